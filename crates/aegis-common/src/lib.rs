@@ -1,4 +1,7 @@
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
@@ -8,24 +11,580 @@ pub struct Config {
     pub http_inspection: HttpInspectionConfig,
     pub metrics: MetricsConfig,
     pub features: FeaturesConfig,
+    #[serde(default)]
+    pub capture: Option<CaptureConfig>,
+    #[serde(default)]
+    pub region_filter: Option<RegionFilterConfig>,
+    #[serde(default)]
+    pub source_policy: Option<SourcePolicyConfig>,
+    #[serde(default)]
+    pub mqtt_policy: Option<MqttPolicyConfig>,
+    #[serde(default)]
+    pub signature_fast_path: Option<SignatureFastPathConfig>,
+    #[serde(default)]
+    pub events: Option<EventsConfig>,
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    #[serde(default)]
+    pub metric_tags: Option<MetricTagsConfig>,
+    #[serde(default)]
+    pub access_control: Option<AccessControlConfig>,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// TLS termination for client connections; backends receive plaintext.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// PEM certificate chain, leaf first.
+    pub cert_path: String,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1).
+    pub key_path: String,
+    /// Max time (ms) a client may take to complete the TLS handshake.
+    #[serde(default = "default_tls_handshake_timeout_ms")]
+    pub handshake_timeout_ms: u64,
+    /// Most TLS handshakes in progress at once; unlimited when unset.
+    #[serde(default)]
+    pub max_concurrent_handshakes: Option<usize>,
+    /// Max time (ms) a connection waits for a handshake slot before it is
+    /// rejected.
+    #[serde(default = "default_tls_handshake_queue_timeout_ms")]
+    pub handshake_queue_timeout_ms: u64,
+}
+
+fn default_tls_handshake_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_tls_handshake_queue_timeout_ms() -> u64 {
+    1_000
+}
+
+/// Static allow / deny lists of client networks, checked at accept.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AccessControlConfig {
+    /// When non-empty, only clients inside one of these networks are
+    /// accepted.
+    #[serde(default)]
+    pub allow_cidrs: Vec<String>,
+    /// Clients inside these networks are refused, even if also allowed.
+    #[serde(default)]
+    pub deny_cidrs: Vec<String>,
+}
+
+/// Allowed values per tag dimension for the tagged metrics. Values outside a
+/// list are reported as `other` and an empty list reports `any`, so the
+/// configuration bounds label cardinality.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MetricTagsConfig {
+    /// Source policy profile names.
+    #[serde(default)]
+    pub profile: Vec<String>,
+    /// Detected protocols: `mqtt`, `http`, `websocket`, `tls`.
+    #[serde(default)]
+    pub protocol: Vec<String>,
+    /// PROXY protocol v2 TLV type whose value tags the connection with the
+    /// load balancer endpoint it came through (e.g. 0xEA, AWS VPC endpoint).
+    #[serde(default)]
+    pub proxy_tlv_type: Option<u8>,
+    /// Values of that TLV reported as the `endpoint` label.
+    #[serde(default)]
+    pub endpoint: Vec<String>,
+}
+
+/// Local operator socket (Unix only).
+#[derive(Debug, Deserialize, Clone)]
+pub struct AdminConfig {
+    /// Path of the Unix socket; created owner-only, replaced if stale.
+    pub socket_path: String,
+}
+
+/// Largest `target_addresses` weight.
+pub const MAX_BACKEND_WEIGHT: u32 = 100;
+
+/// A `target_addresses` entry: a bare address (weight 1), or an address with
+/// a weight relative to the other entries.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum BackendTarget {
+    Address(String),
+    Weighted { address: String, weight: u32 },
+}
+
+impl BackendTarget {
+    pub fn address(&self) -> &str {
+        match self {
+            BackendTarget::Address(address) | BackendTarget::Weighted { address, .. } => address,
+        }
+    }
+
+    pub fn weight(&self) -> u32 {
+        match self {
+            BackendTarget::Address(_) => 1,
+            BackendTarget::Weighted { weight, .. } => *weight,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ProxyConfig {
+    /// `ip:port`, or `unix:<path>` for a Unix domain socket.
     pub listen_address: String,
+    /// `host:port`, or `unix:<path>` for a broker on a Unix domain socket
+    /// (likewise for the other backend addresses).
     pub target_address: String,
+    /// Optional list of interchangeable MQTT brokers, used round-robin in
+    /// place of `target_address`, in proportion to their weights. A connect
+    /// that is refused or times out moves on to the next broker.
+    #[serde(default)]
+    pub target_addresses: Option<Vec<BackendTarget>>,
     /// Optional maximum Remaining Length (in bytes) that will be accepted when
     /// performing full MQTT CONNECT inspection. If absent, callers should use a
     /// sensible default (e.g. 64 * 1024).
     pub max_connect_remaining: Option<usize>,
+    /// Optional maximum time (ms) writing the initial CONNECT frame to the
+    /// backend may take before the connection is dropped. Defaults to 3000.
+    #[serde(default)]
+    pub backend_write_timeout_ms: Option<u64>,
+    /// Optional drain window (seconds) after a shutdown signal. While draining,
+    /// existing sessions keep running and new connections are refused with a
+    /// busy signal (CONNACK / HTTP 503). If absent, shutdown is immediate.
+    #[serde(default)]
+    pub shutdown_drain_secs: Option<u64>,
+    /// Optional protocol dispatch table. When set, detected HTTP and WebSocket
+    /// traffic is routed to its own backend instead of being rejected.
+    #[serde(default)]
+    pub protocol_backends: Option<ProtocolBackends>,
+    /// TLS passthrough routing: server name (SNI) -> backend address. When
+    /// set, a connection opening with a TLS ClientHello is forwarded
+    /// unchanged to the backend for its server name, or to `target_address`
+    /// when the name is missing or unlisted. Names match case-insensitively.
+    #[serde(default)]
+    pub sni_routes: HashMap<String, String>,
+    /// Tag forwarded MQTT 5.0 CONNECTs with this edge's identity as an
+    /// `aegis-edge-id` user property (requires full MQTT inspection).
+    #[serde(default)]
+    pub inject_edge_id: bool,
+    /// Identity injected when `inject_edge_id` is set. Defaults to the hostname.
+    #[serde(default)]
+    pub edge_instance_id: Option<String>,
+    /// Optional cap (ms) on how long the remaining direction may keep flowing
+    /// after the client or backend half-closes its write side.
+    #[serde(default)]
+    pub half_close_grace_ms: Option<u64>,
+    /// Optional application-level write buffer (bytes) on the backend write
+    /// half, batching already-available client data into fewer syscalls.
+    /// Unbuffered when omitted; ignored when splice forwarding is enabled.
+    #[serde(default)]
+    pub backend_write_buffer_bytes: Option<usize>,
+    /// Optional threshold (ms) above which a successful backend connect is
+    /// logged at warn with the client and duration. Defaults to 1000.
+    #[serde(default)]
+    pub slow_backend_connect_ms: Option<u64>,
+    /// What an admitted client gets when no backend can be reached: a bare
+    /// close (the default) or, for MQTT, a "server unavailable" CONNACK first.
+    #[serde(default)]
+    pub backend_unavailable: BackendUnavailableAction,
+    /// Optional max silence (ms) from the client once the session is
+    /// forwarding, to catch dead clients that never send PINGREQ. For MQTT
+    /// clients with a non-zero keep-alive, 1.5x the keep-alive is used
+    /// instead; this value applies when the keep-alive is unknown or 0.
+    #[serde(default)]
+    pub client_idle_timeout_ms: Option<u64>,
+    /// Optional max silence (ms) from the backend once the session is
+    /// forwarding. Subscribers may legitimately see long quiet periods.
+    #[serde(default)]
+    pub backend_idle_timeout_ms: Option<u64>,
+    /// Optional max time (ms) a write to the client or backend may stay
+    /// blocked once the session is forwarding, for peers that stop reading.
+    #[serde(default)]
+    pub write_stall_timeout_ms: Option<u64>,
+    /// Optional maximum length (bytes) of the MQTT client identifier, checked
+    /// under full inspection. Non-UTF-8 identifiers are always rejected.
+    #[serde(default)]
+    pub max_client_id_len: Option<usize>,
+    /// Reject CONNECTs with an empty client identifier that ask to resume a
+    /// session (clean session / clean start not set).
+    #[serde(default)]
+    pub require_client_id_for_persistent_session: bool,
+    /// Optional `TCP_USER_TIMEOUT` (ms) on client and backend sockets of
+    /// forwarded sessions: how long sent data may stay unacknowledged before
+    /// the connection is aborted. Linux only; ignored elsewhere.
+    #[serde(default)]
+    pub tcp_user_timeout_ms: Option<u64>,
+    /// Optional maximum age (seconds, from accept) of a forwarded session.
+    /// Older sessions are closed whatever their activity, so clients
+    /// reconnect and are re-inspected. Unlimited when omitted.
+    #[serde(default)]
+    pub max_connection_lifetime_secs: Option<u64>,
+    /// Optional passive health tracking: backends that keep failing connects
+    /// are skipped for a cooldown instead of being dialled by every client.
+    #[serde(default)]
+    pub backend_circuit: Option<BackendCircuitConfig>,
+    /// Start every backend connection with a PROXY protocol v1 header
+    /// carrying the client's address. The backend must expect it.
+    #[serde(default)]
+    pub send_proxy_protocol: bool,
+    /// Expect every client connection to start with a PROXY protocol v2
+    /// header from an upstream load balancer, and treat the address in it as
+    /// the client. Connections without a valid header are dropped.
+    #[serde(default)]
+    pub accept_proxy_protocol: bool,
+    /// Additional listeners served alongside `listen_address`, e.g. TLS on
+    /// 8883 next to plain MQTT on 1883.
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+}
+
+/// An additional listening socket. Unset fields inherit the top-level
+/// settings, except `tls`: the top-level `tls` section applies to
+/// `listen_address` only.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    /// Value of the `listener` metrics label; `default` names the top-level
+    /// listener.
+    pub name: String,
+    /// `ip:port`, or `unix:<path>` for a Unix domain socket.
+    pub address: String,
+    #[serde(default)]
+    pub target_address: Option<String>,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub accept_proxy_protocol: Option<bool>,
+    #[serde(default)]
+    pub enable_mqtt_inspection: Option<bool>,
+    #[serde(default)]
+    pub enable_mqtt_full_inspection: Option<bool>,
+    #[serde(default)]
+    pub enable_http_inspection: Option<bool>,
+    #[serde(default)]
+    pub enable_slowloris_protection: Option<bool>,
+    #[serde(default)]
+    pub enable_rate_limiter: Option<bool>,
+}
+
+/// Name of the listener bound at `proxy.listen_address`.
+pub const DEFAULT_LISTENER: &str = "default";
+
+/// Circuit breaker per backend address, fed by real connect attempts.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct BackendCircuitConfig {
+    /// Consecutive connect failures within `window_ms` that open the circuit.
+    #[serde(default = "default_circuit_failure_threshold")]
+    pub failure_threshold: u32,
+    #[serde(default = "default_circuit_window_ms")]
+    pub window_ms: u64,
+    /// How long an open circuit skips the backend before one probe connect
+    /// is let through.
+    #[serde(default = "default_circuit_cooldown_ms")]
+    pub cooldown_ms: u64,
+    /// While every MQTT backend's circuit is open, refuse new connections at
+    /// accept instead of inspecting them only to fail at backend connect.
+    /// Off by default: inspection still feeds the security metrics.
+    #[serde(default)]
+    pub reject_at_accept: bool,
+    /// With `reject_at_accept`, answer HTTP with a 503 and MQTT with a
+    /// "server busy" CONNACK before closing, instead of closing silently.
+    #[serde(default)]
+    pub busy_signal: bool,
+}
+
+fn default_circuit_failure_threshold() -> u32 {
+    5
+}
+
+fn default_circuit_window_ms() -> u64 {
+    10_000
+}
+
+fn default_circuit_cooldown_ms() -> u64 {
+    30_000
+}
+
+/// Backend per detected protocol. Protocols without an entry are rejected,
+/// except MQTT which falls back to `target_address`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ProtocolBackends {
+    pub mqtt: Option<String>,
+    pub http: Option<String>,
+    pub websocket: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct LimitConfig {
     pub max_tokens: f64,
     pub refill_rate: f64,
+    /// Algorithm behind the per-IP connection rate limit.
+    #[serde(default)]
+    pub rate_limiter: RateLimiterKind,
+    /// Window for `RateLimiterKind::SlidingWindow`: at most `max_tokens`
+    /// connections per IP within any span of this length.
+    #[serde(default = "default_sliding_window_secs")]
+    pub window_secs: u64,
+    /// Optional prefix length of the IPv4 networks that also share one
+    /// accept bucket, on top of each address's own. A client must pass both.
+    #[serde(default)]
+    pub subnet_prefix_v4: Option<u8>,
+    /// As `subnet_prefix_v4`, for IPv6 (e.g. 64, one customer allocation).
+    #[serde(default)]
+    pub subnet_prefix_v6: Option<u8>,
+    /// Capacity of a subnet bucket. Defaults to `max_tokens`.
+    #[serde(default)]
+    pub subnet_max_tokens: Option<f64>,
+    /// Refill rate of a subnet bucket. Defaults to `refill_rate`.
+    #[serde(default)]
+    pub subnet_refill_rate: Option<f64>,
+    /// Where the token buckets are kept: in this process, or in Redis and
+    /// shared by every instance pointed at the same server.
+    #[serde(default)]
+    pub backend: RateLimitBackendKind,
+    /// `redis://[[user]:password@]host:port[/db]`, for `backend: redis`.
+    #[serde(default)]
+    pub redis_url: Option<String>,
+    /// Max time (ms) a rate-limit decision may wait for Redis before
+    /// `redis_failure_policy` applies.
+    #[serde(default = "default_redis_timeout_ms")]
+    pub redis_timeout_ms: u64,
+    /// Whether clients are admitted or refused while Redis is unreachable.
+    #[serde(default)]
+    pub redis_failure_policy: RedisFailurePolicy,
     pub cleanup_interval_secs: u64,
     pub ip_idle_timeout_secs: u64,
+    /// Optional fraction (0.0-1.0) of the process FD limit above which new
+    /// connections are shed until usage drops. Linux only.
+    #[serde(default)]
+    pub fd_pressure_threshold: Option<f64>,
+    /// How often FD usage is sampled when `fd_pressure_threshold` is set.
+    #[serde(default = "default_fd_check_interval_secs")]
+    pub fd_check_interval_secs: u64,
+    /// Optional aggregate concurrent-connection cap per client subnet.
+    #[serde(default)]
+    pub subnet_cap: Option<SubnetCapConfig>,
+    /// Optional per-IP rate of MQTT sessions sent to the backend, separate
+    /// from the accept rate governed by `max_tokens` / `refill_rate`.
+    #[serde(default)]
+    pub session_rate: Option<SessionRateConfig>,
+    /// Optional ban for clients that open many connections but complete
+    /// inspection on only a few of them.
+    #[serde(default)]
+    pub connect_ratio: Option<ConnectRatioConfig>,
+    /// Optional ban for clients repeating the exact same malformed CONNECT.
+    #[serde(default)]
+    pub repeated_malformed: Option<RepeatedMalformedConfig>,
+    /// Optional cap on one IP's connections that are still being inspected
+    /// or connecting to the backend at the same time.
+    #[serde(default)]
+    pub in_flight_connects: Option<InFlightConnectConfig>,
+    /// Optional cap on one IP's simultaneously open connections, from accept
+    /// until the session ends.
+    #[serde(default)]
+    pub max_concurrent_per_ip: Option<usize>,
+    /// Optional cap on connections being inspected at once, across all
+    /// clients. Connections over it are refused at accept. Global only.
+    #[serde(default)]
+    pub max_concurrent_inspections: Option<usize>,
+    /// Optional file where bans and throttled IPs are saved periodically and
+    /// reloaded from at startup, so a restart does not clear them. Global
+    /// only.
+    #[serde(default)]
+    pub persist_state: Option<PersistStateConfig>,
+}
+
+/// Periodic snapshot of the limiter's security state (connect-ratio and
+/// repeated-malformed bans, IPs out of rate-limit tokens).
+#[derive(Debug, Deserialize, Clone)]
+pub struct PersistStateConfig {
+    pub path: String,
+    #[serde(default = "default_persist_state_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_persist_state_interval_secs() -> u64 {
+    30
+}
+
+/// Token bucket for backend-bound MQTT sessions, consulted after the CONNECT
+/// passed inspection and before the backend connect.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SessionRateConfig {
+    pub max_tokens: f64,
+    pub refill_rate: f64,
+}
+
+/// Per-IP ratio of opened connections to connections that completed
+/// inspection (for MQTT, a valid CONNECT). An IP whose opens outnumber its
+/// completions by more than `max_ratio` within a window is banned.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConnectRatioConfig {
+    /// Length of the counting window.
+    #[serde(default = "default_connect_ratio_window_secs")]
+    pub window_secs: u64,
+    /// Opens within a window before the ratio is evaluated, so a handful of
+    /// failed attempts never trigger a ban.
+    #[serde(default = "default_connect_ratio_min_opens")]
+    pub min_opens: u32,
+    /// Highest tolerated opened / completed ratio.
+    #[serde(default = "default_connect_ratio_max_ratio")]
+    pub max_ratio: f64,
+    /// How long an offending IP is refused at accept.
+    #[serde(default = "default_connect_ratio_ban_secs")]
+    pub ban_secs: u64,
+}
+
+fn default_connect_ratio_window_secs() -> u64 {
+    60
+}
+
+fn default_connect_ratio_min_opens() -> u32 {
+    20
+}
+
+fn default_connect_ratio_max_ratio() -> f64 {
+    4.0
+}
+
+fn default_connect_ratio_ban_secs() -> u64 {
+    300
+}
+
+/// Per-IP tracking of identical malformed CONNECTs. The same malformed bytes
+/// arriving `threshold` times within a window mark a scanner or a stuck
+/// client rather than noise, and the IP is banned.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RepeatedMalformedConfig {
+    #[serde(default = "default_repeated_malformed_threshold")]
+    pub threshold: u32,
+    /// Length of the counting window.
+    #[serde(default = "default_repeated_malformed_window_secs")]
+    pub window_secs: u64,
+    /// Distinct malformed packets remembered per IP; the oldest is forgotten
+    /// when a new one arrives and the set is full.
+    #[serde(default = "default_repeated_malformed_max_hashes")]
+    pub max_hashes_per_ip: usize,
+    /// How long an offending IP is refused at accept.
+    #[serde(default = "default_repeated_malformed_ban_secs")]
+    pub ban_secs: u64,
+}
+
+fn default_repeated_malformed_threshold() -> u32 {
+    5
+}
+
+fn default_repeated_malformed_window_secs() -> u64 {
+    300
+}
+
+fn default_repeated_malformed_max_hashes() -> usize {
+    8
+}
+
+fn default_repeated_malformed_ban_secs() -> u64 {
+    600
+}
+
+/// Per-IP bound on connections between accept and the start of forwarding
+/// (inspection, backend connect, initial CONNECT write). Excess connections
+/// wait for a slot, and are dropped if none frees up within `max_wait_ms`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct InFlightConnectConfig {
+    #[serde(default = "default_in_flight_max_per_ip")]
+    pub max_per_ip: usize,
+    #[serde(default = "default_in_flight_max_wait_ms")]
+    pub max_wait_ms: u64,
+}
+
+fn default_in_flight_max_per_ip() -> usize {
+    2
+}
+
+fn default_in_flight_max_wait_ms() -> u64 {
+    500
+}
+
+/// Concurrent-connection cap shared by every address in a subnet, enforced
+/// alongside (not instead of) the per-IP limits.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SubnetCapConfig {
+    /// Max concurrent connections per subnet.
+    pub max_connections: usize,
+    /// Prefix length grouping IPv4 clients (default /24).
+    #[serde(default = "default_subnet_ipv4_prefix")]
+    pub ipv4_prefix: u8,
+    /// Prefix length grouping IPv6 clients (default /48).
+    #[serde(default = "default_subnet_ipv6_prefix")]
+    pub ipv6_prefix: u8,
+    /// Networks exempt from the cap (e.g. NAT gateways, internal ranges).
+    #[serde(default)]
+    pub exempt_cidrs: Vec<String>,
+}
+
+fn default_subnet_ipv4_prefix() -> u8 {
+    24
+}
+
+fn default_subnet_ipv6_prefix() -> u8 {
+    48
+}
+
+fn default_fd_check_interval_secs() -> u64 {
+    5
+}
+
+fn default_sliding_window_secs() -> u64 {
+    10
+}
+
+fn default_redis_timeout_ms() -> u64 {
+    100
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitBackendKind {
+    /// Buckets local to this process.
+    #[default]
+    Memory,
+    /// Buckets in Redis, updated atomically by a Lua script. Token bucket
+    /// only.
+    Redis,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendUnavailableAction {
+    /// Close the client connection without a reply.
+    #[default]
+    Close,
+    /// Send MQTT clients a CONNACK refusing them with "server unavailable"
+    /// (0x03, or 0x88 for MQTT 5.0), then close. Other protocols are closed.
+    Connack,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RedisFailurePolicy {
+    /// Admit clients without a rate-limit decision.
+    #[default]
+    FailOpen,
+    /// Refuse clients until Redis answers again.
+    FailClosed,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimiterKind {
+    /// Bucket of `max_tokens` refilled at `refill_rate`; allows bursts of a
+    /// full bucket.
+    #[default]
+    TokenBucket,
+    /// At most `max_tokens` connections in any `window_secs`; no bursts
+    /// beyond that, however long the client was quiet before.
+    SlidingWindow,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -41,6 +600,17 @@ pub struct SlowlorisConfig {
     pub mqtt_connect_timeout_ms: u64,
     /// MQTT-specific: max time to receive any other complete MQTT packet (ms)
     pub mqtt_packet_timeout_ms: u64,
+    /// MQTT-specific: max time to receive the CONNECT fixed header byte when
+    /// Slowloris protection is off (ms); with it on, the idle timeouts apply.
+    #[serde(default = "default_mqtt_fixed_header_timeout_ms")]
+    pub mqtt_fixed_header_timeout_ms: u64,
+    /// MQTT-specific: max time to receive each CONNECT Remaining Length byte (ms)
+    #[serde(default = "default_mqtt_remaining_length_byte_timeout_ms")]
+    pub mqtt_remaining_length_byte_timeout_ms: u64,
+    /// MQTT-specific: max time to receive the CONNECT body once its length is
+    /// known (ms)
+    #[serde(default = "default_mqtt_connect_payload_timeout_ms")]
+    pub mqtt_connect_payload_timeout_ms: u64,
 
     /// HTTP-specific: max time to receive complete HTTP request line + headers (ms)
     pub http_request_timeout_ms: u64,
@@ -48,18 +618,90 @@ pub struct SlowlorisConfig {
     pub max_http_header_size: usize,
     /// HTTP-specific: max number of HTTP headers
     pub max_http_header_count: usize,
+
+    /// Optional overall budget (ms) from accept to forwarding start, capping the
+    /// sum of all per-phase timeouts (peek, inspection, backend connect/write).
+    #[serde(default)]
+    pub handshake_deadline_ms: Option<u64>,
+
+    /// MQTT-specific (opt-in, strict): require the complete CONNECT to arrive in
+    /// a single read within this window (ms); fragmented CONNECTs are rejected.
+    /// Only suitable for fleets that always send CONNECT in one TCP segment.
+    #[serde(default)]
+    pub single_segment_connect_timeout_ms: Option<u64>,
+
+    /// Max time (ms) the inspection parsers may spend running for one
+    /// connection, excluding time spent waiting for data. Generous by default;
+    /// only pathological inputs should ever reach it.
+    #[serde(default = "default_inspection_budget_ms")]
+    pub inspection_budget_ms: u64,
+
+    /// Optional max time (ms) a forwarded session may go without bytes in
+    /// either direction before both sides are closed. Unlike the per-direction
+    /// idle timeouts, traffic one way keeps the whole session alive.
+    #[serde(default)]
+    pub proxy_idle_timeout_ms: Option<u64>,
+
+    /// MQTT-specific: optional minimum average rate (bytes/s) at which the
+    /// CONNECT body must arrive, after a one-second grace. Catches senders
+    /// that trickle bytes just inside the idle timeout.
+    #[serde(default)]
+    pub min_throughput_bytes_per_sec: Option<u64>,
+}
+
+fn default_inspection_budget_ms() -> u64 {
+    100
+}
+
+fn default_mqtt_fixed_header_timeout_ms() -> u64 {
+    3000
+}
+
+fn default_mqtt_remaining_length_byte_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_mqtt_connect_payload_timeout_ms() -> u64 {
+    5000
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct HttpInspectionConfig {
     /// Max size of individual HTTP header line (bytes)
     pub max_header_line_size: usize,
+    /// Accept a lone `\n` as a line terminator. When false, bare-LF requests
+    /// are rejected as suspicious instead of being parsed.
+    #[serde(default)]
+    pub allow_bare_lf: bool,
+    /// Valid headers after which parsing stops and the request is classified
+    /// as HTTP without reading the rest. A confident detection, unlike
+    /// `max_header_count` which rejects as Slowloris. Upgrade headers after
+    /// the cut-off are not seen.
+    #[serde(default = "default_max_headers_to_inspect")]
+    pub max_headers_to_inspect: usize,
+}
+
+fn default_max_headers_to_inspect() -> usize {
+    1000
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct MetricsConfig {
     pub enabled: bool,
     pub port: u16,
+    /// Write a final metrics snapshot on graceful shutdown (after draining).
+    #[serde(default)]
+    pub shutdown_snapshot: bool,
+    /// Snapshot destination file; logged when omitted.
+    #[serde(default)]
+    pub shutdown_snapshot_path: Option<String>,
+    /// Log a summary of counter deltas every this many seconds (off if unset).
+    #[serde(default)]
+    pub log_summary_interval_secs: Option<u64>,
+    /// Serve the `/ratelimit` debugging endpoints. Off by default: they list
+    /// client IPs and let anyone reaching the port reset a client's limit.
+    #[serde(default)]
+    pub expose_admin_endpoints: bool,
 }
 
 /// Feature flags to enable or disable proxy protections and subsystems.
@@ -79,4 +721,729 @@ pub struct FeaturesConfig {
     pub enable_ebpf: bool,
     /// Enable ML-based anomaly detection pipeline.
     pub enable_ml: bool,
+    /// Forward admitted sessions with Linux `splice(2)` instead of userspace copies.
+    /// Falls back to the regular copy on other platforms or when splicing fails.
+    #[serde(default)]
+    pub enable_splice_forwarding: bool,
+    /// Emit a per-connection decision trace (checks evaluated and their
+    /// outcomes) as an `aegis_audit` log record. Allocates per connection.
+    #[serde(default)]
+    pub trace_decisions: bool,
+    /// Add the effective per-connection settings (after profile overrides)
+    /// to the decision trace. Implies `trace_decisions`.
+    #[serde(default)]
+    pub trace_effective_config: bool,
+    /// Add per-phase handshake timings (peek, inspection reads, backend
+    /// connect, initial forward) to the decision trace. Implies
+    /// `trace_decisions`.
+    #[serde(default)]
+    pub trace_phase_timings: bool,
+    /// Emit one `aegis_access` record per handled connection: client IP,
+    /// protocol, decision, reason, bytes each way and duration.
+    #[serde(default)]
+    pub access_log: bool,
+    /// Reject CONNECTs without a user name before they reach the broker.
+    /// Needs `enable_mqtt_full_inspection`.
+    #[serde(default)]
+    pub require_username: bool,
+    /// Forward WebSocket upgrades offering the MQTT subprotocol to the MQTT
+    /// backend instead of rejecting them as HTTP. A `protocol_backends`
+    /// websocket entry still takes precedence.
+    #[serde(default)]
+    pub enable_mqtt_websocket: bool,
+    /// Frame the client -> broker stream after the CONNECT and end the
+    /// session on a PUBLISH to a topic refused by `mqtt_policy`'s topic
+    /// lists. Needs `enable_mqtt_full_inspection`; the client -> broker
+    /// direction is then copied in userspace.
+    #[serde(default)]
+    pub enable_publish_inspection: bool,
+}
+
+/// Targeted hex-dump capture of rejected connections for forensic analysis.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CaptureConfig {
+    pub enabled: bool,
+    /// Rejection reasons that trigger a capture: `malformed_connect`,
+    /// `unexpected_packet_type`, `http_detected`, `http_slowloris`,
+    /// `fragmented_connect`.
+    #[serde(default)]
+    pub reasons: Vec<String>,
+    /// Max bytes kept per captured connection.
+    #[serde(default = "default_capture_max_bytes")]
+    pub max_bytes_per_connection: usize,
+    /// Max captures written per minute across all connections.
+    #[serde(default = "default_captures_per_minute")]
+    pub max_captures_per_minute: u32,
+    /// Append captures to this file; captures are logged when absent.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+fn default_capture_max_bytes() -> usize {
+    4096
+}
+
+fn default_captures_per_minute() -> u32 {
+    60
+}
+
+/// Known-good byte prefixes (e.g. a device fleet's fixed CONNECT prefix) that
+/// let a connection skip protocol inspection.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SignatureFastPathConfig {
+    /// Hex-encoded byte prefixes; at most 16, each 4-64 bytes.
+    pub signatures: Vec<String>,
+    /// Fully inspect every Nth matching connection anyway, to catch clients
+    /// spoofing a signature (1 = inspect all).
+    #[serde(default = "default_verify_every")]
+    pub verify_every: u64,
+}
+
+fn default_verify_every() -> u64 {
+    100
+}
+
+/// Connection lifecycle events published to a NATS subject. Requires the
+/// proxy to be built with the `nats-events` feature.
+#[derive(Debug, Deserialize, Clone)]
+pub struct EventsConfig {
+    /// NATS server address (`host:port`, optionally prefixed with `nats://`).
+    pub nats_url: String,
+    pub subject: String,
+    /// Events buffered while the server is slow or unreachable; further
+    /// events are dropped and counted.
+    #[serde(default = "default_event_queue_depth")]
+    pub queue_depth: usize,
+}
+
+fn default_event_queue_depth() -> usize {
+    1024
+}
+
+/// Coarse region filtering from static, operator-supplied CIDR groups.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RegionFilterConfig {
+    pub policy: RegionPolicy,
+    /// Region label -> CIDRs belonging to it (e.g. `eu: ["192.0.2.0/24"]`).
+    #[serde(default)]
+    pub regions: BTreeMap<String, Vec<String>>,
+}
+
+/// How `RegionFilterConfig::regions` is applied.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionPolicy {
+    /// Only clients inside one of the listed regions are accepted.
+    AllowListed,
+    /// Clients inside any listed region are rejected.
+    DenyListed,
+}
+
+/// Policies applied to fully inspected MQTT CONNECT packets.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MqttPolicyConfig {
+    /// Longest keep-alive (seconds) a client may request. A keep-alive of 0
+    /// (never time out) counts as exceeding it.
+    #[serde(default)]
+    pub max_keep_alive_secs: Option<u16>,
+    /// Shortest keep-alive (seconds) a client may request, bounding how often
+    /// it may ping. Without a maximum, a keep-alive of 0 counts as below it.
+    #[serde(default)]
+    pub min_keep_alive_secs: Option<u16>,
+    /// What to do with a CONNECT outside the keep-alive band.
+    #[serde(default)]
+    pub keep_alive_action: KeepAliveAction,
+    /// Send MQTT 5.0 clients a refusing CONNACK with a reason string instead
+    /// of silently closing rejected CONNECTs.
+    #[serde(default)]
+    pub send_connack_on_reject: bool,
+    /// Rejection categories answered with a refusing CONNACK at every
+    /// protocol level that has a code for them, instead of a bare close.
+    #[serde(default)]
+    pub reject_connack_categories: Vec<RejectCategory>,
+    /// Largest packet (bytes, fixed header included) a client may send after
+    /// its CONNECT; larger packets end the session.
+    #[serde(default)]
+    pub max_packet_size: Option<u32>,
+    /// Largest PUBLISH payload (bytes) a client may send; a larger one ends
+    /// the session before it reaches the broker.
+    #[serde(default)]
+    pub max_publish_payload: Option<usize>,
+    /// End the session when the broker sends a packet larger than the
+    /// Maximum Packet Size the (v5) client advertised in its CONNECT.
+    #[serde(default)]
+    pub enforce_client_max_packet_size: bool,
+    /// CONNECT protocol levels accepted (3 = v3.1, 4 = v3.1.1, 5 = v5).
+    /// Every known level is accepted when omitted.
+    #[serde(default)]
+    pub allowed_protocol_levels: Option<Vec<u8>>,
+    /// Topic filters (`+`/`#` wildcards) a client may PUBLISH to, under
+    /// `enable_publish_inspection`. Any topic is allowed when omitted.
+    #[serde(default)]
+    pub publish_allow_topics: Option<Vec<String>>,
+    /// Topic filters a client may not PUBLISH to; these win over the allow
+    /// list.
+    #[serde(default)]
+    pub publish_deny_topics: Vec<String>,
+}
+
+/// Kinds of CONNECT rejection, for choosing which get a CONNACK.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectCategory {
+    /// Malformed CONNECT (bad protocol name, flags, properties or client
+    /// identifier). MQTT 5.0 only: 3.1.1 has no code for it.
+    Malformed,
+    /// Protocol level outside `allowed_protocol_levels`.
+    UnsupportedProtocolVersion,
+    /// Well-formed CONNECT refused by policy (keep-alive, client identifier).
+    /// MQTT 5.0 only: 3.1.1 has no code for it.
+    PolicyViolation,
+    /// Missing user name under `require_username`.
+    NotAuthorized,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeepAliveAction {
+    /// Drop the connection.
+    #[default]
+    Reject,
+    /// Rewrite the keep-alive down to the maximum and forward the CONNECT.
+    Clamp,
+}
+
+/// Graduated per-source-network policy: each named profile lists the CIDRs it
+/// applies to and overrides selected protections. The longest matching prefix
+/// across all profiles wins.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SourcePolicyConfig {
+    /// Profile applied when no CIDR matches; the global settings apply if omitted.
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, SourceProfile>,
+}
+
+/// Overrides applied on top of the global `features` / `limit` settings.
+/// Unset fields inherit the global value.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SourceProfile {
+    #[serde(default)]
+    pub cidrs: Vec<String>,
+    #[serde(default)]
+    pub enable_mqtt_inspection: Option<bool>,
+    #[serde(default)]
+    pub enable_mqtt_full_inspection: Option<bool>,
+    #[serde(default)]
+    pub enable_http_inspection: Option<bool>,
+    #[serde(default)]
+    pub enable_slowloris_protection: Option<bool>,
+    #[serde(default)]
+    pub enable_rate_limiter: Option<bool>,
+    #[serde(default)]
+    pub max_tokens: Option<f64>,
+    #[serde(default)]
+    pub refill_rate: Option<f64>,
+}
+
+/// Configuration file formats, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Format for the extension of `path`: `.yaml` / `.yml`, `.toml` or `.json`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "toml" => Some(ConfigFormat::Toml),
+            "json" => Some(ConfigFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Error loading the configuration file, carrying enough context (path and,
+/// when the parser reports it, line/column) to fix it without guesswork.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The file extension names no supported format.
+    UnsupportedFormat { path: PathBuf },
+    Parse {
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A value that parses but the proxy cannot run with.
+    Invalid { field: String, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "cannot read config file {}: {}", path.display(), source)
+            }
+            ConfigError::UnsupportedFormat { path } => write!(
+                f,
+                "unsupported config file {}: expected a .yaml, .yml, .toml or .json extension",
+                path.display()
+            ),
+            // The parsers' messages already name the field and, when known,
+            // the line and column.
+            ConfigError::Parse { path, source, .. } => {
+                write!(f, "invalid config file {}: {}", path.display(), source)
+            }
+            ConfigError::Invalid { field, reason } => write!(f, "{}: {}", field, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(&**source),
+            ConfigError::UnsupportedFormat { .. } | ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Parses configuration text in the format named by the extension of `path`,
+/// attributing errors to `path`.
+pub fn parse_config(text: &str, path: &Path) -> Result<Config, ConfigError> {
+    let format = ConfigFormat::from_path(path).ok_or_else(|| ConfigError::UnsupportedFormat {
+        path: path.to_path_buf(),
+    })?;
+    let parse_error = |location: Option<(usize, usize)>,
+                       source: Box<dyn std::error::Error + Send + Sync>| {
+        ConfigError::Parse {
+            path: path.to_path_buf(),
+            line: location.map(|(line, _)| line),
+            column: location.map(|(_, column)| column),
+            source,
+        }
+    };
+    match format {
+        ConfigFormat::Yaml => serde_yaml::from_str(text).map_err(|source| {
+            let location = source.location().map(|l| (l.line(), l.column()));
+            parse_error(location, source.into())
+        }),
+        ConfigFormat::Toml => toml::from_str(text).map_err(|source| {
+            let location = source.span().map(|span| line_column(text, span.start));
+            parse_error(location, source.into())
+        }),
+        ConfigFormat::Json => serde_json::from_str(text).map_err(|source| {
+            let location = (source.line() > 0).then(|| (source.line(), source.column()));
+            parse_error(location, source.into())
+        }),
+    }
+}
+
+/// 1-based line and column of byte `offset` in `text`.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Reads and parses the configuration file at `path` (YAML, TOML or JSON, by
+/// extension).
+pub fn load_config(path: impl AsRef<Path>) -> Result<Config, ConfigError> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_config(&text, path)
+}
+
+/// Problems found by `Config::validate`, collected rather than returned on
+/// the first one.
+struct Problems(Vec<ConfigError>);
+
+impl Problems {
+    fn require(&mut self, ok: bool, field: impl Into<String>, reason: impl Into<String>) {
+        if !ok {
+            self.0.push(ConfigError::Invalid {
+                field: field.into(),
+                reason: reason.into(),
+            });
+        }
+    }
+
+    fn positive(&mut self, field: &str, value: u64) {
+        self.require(value > 0, field, "must be greater than 0");
+    }
+
+    fn positive_if_set(&mut self, field: &str, value: Option<u64>) {
+        if let Some(value) = value {
+            self.positive(field, value);
+        }
+    }
+
+    fn listen_address(&mut self, field: impl Into<String>, value: &str) {
+        self.require(
+            value.parse::<std::net::SocketAddr>().is_ok() || unix_socket_path(value).is_some(),
+            field,
+            format!("expected ip:port or unix:<path>, got {:?}", value),
+        );
+    }
+
+    /// Backend addresses may name a host (`broker:1883`) as well as an IP.
+    fn host_port(&mut self, field: impl Into<String>, value: &str) {
+        self.require(
+            is_host_port(value),
+            field,
+            format!("expected host:port or unix:<path>, got {:?}", value),
+        );
+    }
+}
+
+/// The socket path of a `unix:<path>` address; `None` for TCP addresses.
+pub fn unix_socket_path(address: &str) -> Option<&str> {
+    address
+        .strip_prefix("unix:")
+        .filter(|path| !path.is_empty())
+}
+
+fn is_host_port(value: &str) -> bool {
+    if value.parse::<std::net::SocketAddr>().is_ok() || unix_socket_path(value).is_some() {
+        return true;
+    }
+    match value.rsplit_once(':') {
+        Some((host, port)) => {
+            !host.is_empty()
+                && !host.contains(|c: char| c == ':' || c.is_whitespace())
+                && port.parse::<u16>().is_ok()
+        }
+        None => false,
+    }
+}
+
+impl Config {
+    /// Checks the ranges and addresses deserialization accepts but the proxy
+    /// cannot run with, reporting every problem found rather than the first.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut problems = Problems(Vec::new());
+
+        let proxy = &self.proxy;
+        problems.listen_address("proxy.listen_address", &proxy.listen_address);
+        problems.host_port("proxy.target_address", &proxy.target_address);
+        let mut names = std::collections::HashSet::from([DEFAULT_LISTENER]);
+        for (i, listener) in proxy.listeners.iter().enumerate() {
+            problems.require(
+                !listener.name.is_empty() && names.insert(&listener.name),
+                format!("proxy.listeners[{}].name", i),
+                format!("must be non-empty and unique, got {:?}", listener.name),
+            );
+            problems.listen_address(format!("proxy.listeners[{}].address", i), &listener.address);
+            if let Some(target) = &listener.target_address {
+                let field = format!("proxy.listeners[{}].target_address", i);
+                problems.require(
+                    proxy.target_addresses.is_none(),
+                    field.clone(),
+                    "cannot be combined with proxy.target_addresses, which every listener uses",
+                );
+                problems.host_port(field, target);
+            }
+        }
+        for (i, target) in proxy.target_addresses.iter().flatten().enumerate() {
+            problems.host_port(format!("proxy.target_addresses[{}]", i), target.address());
+            problems.require(
+                (1..=MAX_BACKEND_WEIGHT).contains(&target.weight()),
+                format!("proxy.target_addresses[{}].weight", i),
+                format!("must be between 1 and {}", MAX_BACKEND_WEIGHT),
+            );
+        }
+        if let Some(backends) = &proxy.protocol_backends {
+            for (name, backend) in [
+                ("mqtt", &backends.mqtt),
+                ("http", &backends.http),
+                ("websocket", &backends.websocket),
+            ] {
+                if let Some(backend) = backend {
+                    problems.host_port(format!("proxy.protocol_backends.{}", name), backend);
+                }
+            }
+        }
+        for (name, backend) in &proxy.sni_routes {
+            problems.host_port(format!("proxy.sni_routes.{}", name), backend);
+        }
+        problems.require(
+            proxy.max_connect_remaining != Some(0),
+            "proxy.max_connect_remaining",
+            "must be greater than 0",
+        );
+        problems.positive_if_set(
+            "proxy.backend_write_timeout_ms",
+            proxy.backend_write_timeout_ms,
+        );
+        problems.positive_if_set("proxy.client_idle_timeout_ms", proxy.client_idle_timeout_ms);
+        problems.positive_if_set("proxy.write_stall_timeout_ms", proxy.write_stall_timeout_ms);
+        problems.positive_if_set(
+            "proxy.backend_idle_timeout_ms",
+            proxy.backend_idle_timeout_ms,
+        );
+        problems.positive_if_set(
+            "proxy.max_connection_lifetime_secs",
+            proxy.max_connection_lifetime_secs,
+        );
+
+        let limit = &self.limit;
+        problems.require(
+            limit.max_tokens.is_finite() && limit.max_tokens > 0.0,
+            "limit.max_tokens",
+            format!("must be greater than 0 (got {})", limit.max_tokens),
+        );
+        problems.require(
+            limit.refill_rate.is_finite() && limit.refill_rate >= 0.0,
+            "limit.refill_rate",
+            format!("must not be negative (got {})", limit.refill_rate),
+        );
+        // A bucket that never refills locks clients out for good.
+        problems.require(
+            !(self.features.enable_rate_limiter
+                && limit.rate_limiter == RateLimiterKind::TokenBucket
+                && limit.refill_rate == 0.0),
+            "limit.refill_rate",
+            "must be greater than 0 when the rate limiter is enabled",
+        );
+        problems.positive("limit.window_secs", limit.window_secs);
+        if limit.subnet_prefix_v4.is_some() || limit.subnet_prefix_v6.is_some() {
+            problems.require(
+                limit.rate_limiter == RateLimiterKind::TokenBucket,
+                "limit.rate_limiter",
+                "must be token_bucket when subnet rate limiting is configured",
+            );
+        }
+        for (field, prefix, max) in [
+            ("limit.subnet_prefix_v4", limit.subnet_prefix_v4, 32),
+            ("limit.subnet_prefix_v6", limit.subnet_prefix_v6, 128),
+        ] {
+            if let Some(prefix) = prefix {
+                problems.require(
+                    (1..=max).contains(&prefix),
+                    field,
+                    format!("must be between 1 and {} (got {})", max, prefix),
+                );
+            }
+        }
+        if let Some(max_tokens) = limit.subnet_max_tokens {
+            problems.require(
+                max_tokens.is_finite() && max_tokens > 0.0,
+                "limit.subnet_max_tokens",
+                format!("must be greater than 0 (got {})", max_tokens),
+            );
+        }
+        if let Some(refill_rate) = limit.subnet_refill_rate {
+            problems.require(
+                refill_rate.is_finite() && refill_rate > 0.0,
+                "limit.subnet_refill_rate",
+                format!("must be greater than 0 (got {})", refill_rate),
+            );
+        }
+        if limit.backend == RateLimitBackendKind::Redis {
+            problems.require(
+                limit.redis_url.is_some(),
+                "limit.redis_url",
+                "must be set when limit.backend is redis",
+            );
+            problems.require(
+                limit.rate_limiter == RateLimiterKind::TokenBucket,
+                "limit.rate_limiter",
+                "must be token_bucket when limit.backend is redis",
+            );
+            problems.positive("limit.redis_timeout_ms", limit.redis_timeout_ms);
+        }
+        problems.positive("limit.cleanup_interval_secs", limit.cleanup_interval_secs);
+        problems.positive("limit.ip_idle_timeout_secs", limit.ip_idle_timeout_secs);
+        problems.positive("limit.fd_check_interval_secs", limit.fd_check_interval_secs);
+        if let Some(threshold) = limit.fd_pressure_threshold {
+            problems.require(
+                threshold > 0.0 && threshold <= 1.0,
+                "limit.fd_pressure_threshold",
+                format!("must be a fraction in (0, 1] (got {})", threshold),
+            );
+        }
+
+        let sl = &self.slowloris_protection;
+        problems.positive(
+            "slowloris_protection.first_packet_timeout_ms",
+            sl.first_packet_timeout_ms,
+        );
+        problems.positive(
+            "slowloris_protection.packet_idle_timeout_ms",
+            sl.packet_idle_timeout_ms,
+        );
+        problems.positive(
+            "slowloris_protection.connection_timeout_ms",
+            sl.connection_timeout_ms,
+        );
+        problems.positive(
+            "slowloris_protection.mqtt_connect_timeout_ms",
+            sl.mqtt_connect_timeout_ms,
+        );
+        problems.positive(
+            "slowloris_protection.mqtt_packet_timeout_ms",
+            sl.mqtt_packet_timeout_ms,
+        );
+        problems.positive(
+            "slowloris_protection.mqtt_fixed_header_timeout_ms",
+            sl.mqtt_fixed_header_timeout_ms,
+        );
+        problems.positive(
+            "slowloris_protection.mqtt_remaining_length_byte_timeout_ms",
+            sl.mqtt_remaining_length_byte_timeout_ms,
+        );
+        problems.positive(
+            "slowloris_protection.mqtt_connect_payload_timeout_ms",
+            sl.mqtt_connect_payload_timeout_ms,
+        );
+        problems.positive(
+            "slowloris_protection.http_request_timeout_ms",
+            sl.http_request_timeout_ms,
+        );
+        problems.positive(
+            "slowloris_protection.inspection_budget_ms",
+            sl.inspection_budget_ms,
+        );
+        problems.positive_if_set(
+            "slowloris_protection.handshake_deadline_ms",
+            sl.handshake_deadline_ms,
+        );
+        problems.positive_if_set(
+            "slowloris_protection.single_segment_connect_timeout_ms",
+            sl.single_segment_connect_timeout_ms,
+        );
+        problems.positive_if_set(
+            "slowloris_protection.proxy_idle_timeout_ms",
+            sl.proxy_idle_timeout_ms,
+        );
+        problems.positive_if_set(
+            "slowloris_protection.min_throughput_bytes_per_sec",
+            sl.min_throughput_bytes_per_sec,
+        );
+        problems.require(
+            sl.max_http_header_count > 0,
+            "slowloris_protection.max_http_header_count",
+            "must be greater than 0",
+        );
+        problems.require(
+            sl.max_http_header_size > 0,
+            "slowloris_protection.max_http_header_size",
+            "must be greater than 0",
+        );
+        problems.require(
+            (1..=sl.max_http_header_size).contains(&self.http_inspection.max_header_line_size),
+            "http_inspection.max_header_line_size",
+            format!(
+                "must be between 1 and slowloris_protection.max_http_header_size ({})",
+                sl.max_http_header_size
+            ),
+        );
+        problems.require(
+            self.http_inspection.max_headers_to_inspect > 0,
+            "http_inspection.max_headers_to_inspect",
+            "must be greater than 0",
+        );
+
+        problems.require(
+            !self.metrics.enabled || self.metrics.port != 0,
+            "metrics.port",
+            "must be greater than 0 when metrics are enabled",
+        );
+        let tls_configs = std::iter::once(("tls".to_string(), &self.tls)).chain(
+            proxy
+                .listeners
+                .iter()
+                .enumerate()
+                .map(|(i, listener)| (format!("proxy.listeners[{}].tls", i), &listener.tls)),
+        );
+        for (prefix, tls) in tls_configs {
+            if let Some(tls) = tls.as_ref().filter(|tls| tls.enabled) {
+                problems.positive(
+                    &format!("{}.handshake_timeout_ms", prefix),
+                    tls.handshake_timeout_ms,
+                );
+                problems.positive_if_set(
+                    &format!("{}.max_concurrent_handshakes", prefix),
+                    tls.max_concurrent_handshakes.map(|max| max as u64),
+                );
+                problems.positive(
+                    &format!("{}.handshake_queue_timeout_ms", prefix),
+                    tls.handshake_queue_timeout_ms,
+                );
+            }
+        }
+
+        if problems.0.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.0)
+        }
+    }
+
+    /// The configuration each listener runs with, by listener name: the
+    /// top-level listener first, then `proxy.listeners` with their overrides
+    /// applied. The returned configurations list no further listeners.
+    pub fn listener_configs(&self) -> Vec<(String, Config)> {
+        let mut base = self.clone();
+        base.proxy.listeners = Vec::new();
+        let mut configs = Vec::with_capacity(1 + self.proxy.listeners.len());
+        for listener in &self.proxy.listeners {
+            let mut config = base.clone();
+            config.proxy.listen_address = listener.address.clone();
+            if let Some(target) = &listener.target_address {
+                config.proxy.target_address = target.clone();
+            }
+            config.tls = listener.tls.clone();
+            let features = &mut config.features;
+            for (value, setting) in [
+                (
+                    &mut config.proxy.accept_proxy_protocol,
+                    listener.accept_proxy_protocol,
+                ),
+                (
+                    &mut features.enable_mqtt_inspection,
+                    listener.enable_mqtt_inspection,
+                ),
+                (
+                    &mut features.enable_mqtt_full_inspection,
+                    listener.enable_mqtt_full_inspection,
+                ),
+                (
+                    &mut features.enable_http_inspection,
+                    listener.enable_http_inspection,
+                ),
+                (
+                    &mut features.enable_slowloris_protection,
+                    listener.enable_slowloris_protection,
+                ),
+                (
+                    &mut features.enable_rate_limiter,
+                    listener.enable_rate_limiter,
+                ),
+            ] {
+                if let Some(setting) = setting {
+                    *value = setting;
+                }
+            }
+            configs.push((listener.name.clone(), config));
+        }
+        configs.insert(0, (DEFAULT_LISTENER.to_string(), base));
+        configs
+    }
 }
@@ -0,0 +1,343 @@
+use aegis_common::{load_config, parse_config, BackendTarget, ConfigError, ConfigFormat};
+use std::path::Path;
+
+#[test]
+fn shipped_config_parses() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../config/aegis_config.yaml");
+    load_config(&path).expect("shipped config should parse");
+}
+
+#[test]
+fn parse_error_names_file_line_and_field() {
+    let yaml = "proxy:\n  listen_address: \"0.0.0.0:8080\"\n  target_adress: \"127.0.0.1:1883\"\n";
+    let err = parse_config(yaml, Path::new("config/test.yaml")).unwrap_err();
+
+    match &err {
+        ConfigError::Parse { line, .. } => assert_eq!(*line, Some(2)),
+        other => panic!("expected parse error, got {:?}", other),
+    }
+    let message = err.to_string();
+    assert!(message.contains("config/test.yaml"), "{}", message);
+    assert!(message.contains("target_address"), "{}", message);
+    assert!(message.contains("line 2"), "{}", message);
+}
+
+#[test]
+fn missing_file_names_the_path() {
+    let err = load_config("does/not/exist.yaml").unwrap_err();
+    assert!(matches!(err, ConfigError::Io { .. }));
+    assert!(err.to_string().contains("does/not/exist.yaml"));
+}
+
+fn shipped_config() -> aegis_common::Config {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../config/aegis_config.yaml");
+    load_config(&path).expect("shipped config should parse")
+}
+
+fn invalid_fields(config: &aegis_common::Config) -> Vec<String> {
+    match config.validate() {
+        Ok(()) => Vec::new(),
+        Err(problems) => problems
+            .into_iter()
+            .map(|problem| match problem {
+                ConfigError::Invalid { field, .. } => field,
+                other => panic!("expected invalid value, got {:?}", other),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn shipped_config_validates() {
+    assert_eq!(invalid_fields(&shipped_config()), Vec::<String>::new());
+}
+
+#[test]
+fn connect_read_timeouts_default_to_the_former_constants() {
+    let sl = shipped_config().slowloris_protection;
+    assert_eq!(sl.mqtt_fixed_header_timeout_ms, 3000);
+    assert_eq!(sl.mqtt_remaining_length_byte_timeout_ms, 1000);
+    assert_eq!(sl.mqtt_connect_payload_timeout_ms, 5000);
+}
+
+#[test]
+fn every_invalid_value_is_reported_at_once() {
+    let mut config = shipped_config();
+    config.limit.max_tokens = 0.0;
+    config.limit.refill_rate = -1.0;
+    config.slowloris_protection.first_packet_timeout_ms = 0;
+    config.slowloris_protection.max_http_header_count = 0;
+    config.proxy.listen_address = "localhost".to_string();
+    assert_eq!(
+        invalid_fields(&config),
+        [
+            "proxy.listen_address",
+            "limit.max_tokens",
+            "limit.refill_rate",
+            "slowloris_protection.first_packet_timeout_ms",
+            "slowloris_protection.max_http_header_count",
+        ]
+    );
+}
+
+#[test]
+fn unix_socket_addresses_are_accepted() {
+    let mut config = shipped_config();
+    config.proxy.listen_address = "unix:/run/aegis/aegis.sock".to_string();
+    config.proxy.target_address = "unix:/run/mosquitto/mosquitto.sock".to_string();
+    config.proxy.target_addresses = Some(vec![BackendTarget::Address("unix:".to_string())]);
+    assert_eq!(invalid_fields(&config), ["proxy.target_addresses[0]"]);
+}
+
+#[test]
+fn listeners_inherit_top_level_settings_except_tls() {
+    let yaml = "
+- name: tls
+  address: \"0.0.0.0:8883\"
+  enable_http_inspection: false
+  tls:
+    enabled: true
+    cert_path: cert.pem
+    key_path: key.pem
+- name: internal
+  address: \"unix:/run/aegis.sock\"
+  target_address: \"unix:/run/mosquitto.sock\"
+";
+    let mut config = shipped_config();
+    config.proxy.listeners = serde_yaml::from_str(yaml).unwrap();
+    config.features.enable_http_inspection = true;
+    config.tls = None;
+    assert!(invalid_fields(&config).is_empty());
+
+    let listeners = config.listener_configs();
+    let names: Vec<&str> = listeners.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["default", "tls", "internal"]);
+    let (_, tls) = &listeners[1];
+    assert_eq!(tls.proxy.listen_address, "0.0.0.0:8883");
+    assert_eq!(tls.proxy.target_address, config.proxy.target_address);
+    assert!(!tls.features.enable_http_inspection);
+    assert!(tls.tls.as_ref().is_some_and(|tls| tls.enabled));
+    let (_, internal) = &listeners[2];
+    assert_eq!(internal.proxy.target_address, "unix:/run/mosquitto.sock");
+    assert!(internal.features.enable_http_inspection);
+    assert!(internal.tls.is_none());
+    assert!(listeners
+        .iter()
+        .all(|(_, view)| view.proxy.listeners.is_empty()));
+
+    config.proxy.listeners[1].name = "tls".to_string();
+    config.proxy.listeners[1].address = "localhost".to_string();
+    assert_eq!(
+        invalid_fields(&config),
+        ["proxy.listeners[1].name", "proxy.listeners[1].address"]
+    );
+}
+
+#[test]
+fn listener_target_cannot_be_combined_with_a_backend_pool() {
+    let mut config = shipped_config();
+    config.proxy.listeners = serde_yaml::from_str(
+        "
+- name: internal
+  address: \"unix:/run/aegis.sock\"
+  target_address: \"unix:/run/mosquitto.sock\"
+",
+    )
+    .unwrap();
+    assert!(invalid_fields(&config).is_empty());
+
+    config.proxy.target_addresses = Some(vec![BackendTarget::Address("broker-a:1883".to_string())]);
+    assert_eq!(
+        invalid_fields(&config),
+        ["proxy.listeners[0].target_address"]
+    );
+}
+
+#[test]
+fn tls_handshake_limits_must_be_positive() {
+    let mut config = shipped_config();
+    config.tls = serde_yaml::from_str(
+        "
+enabled: true
+cert_path: cert.pem
+key_path: key.pem
+max_concurrent_handshakes: 64
+",
+    )
+    .unwrap();
+    let tls = config.tls.as_ref().unwrap();
+    assert_eq!(tls.handshake_queue_timeout_ms, 1000);
+    assert!(invalid_fields(&config).is_empty());
+
+    let tls = config.tls.as_mut().unwrap();
+    tls.max_concurrent_handshakes = Some(0);
+    tls.handshake_queue_timeout_ms = 0;
+    assert_eq!(
+        invalid_fields(&config),
+        [
+            "tls.max_concurrent_handshakes",
+            "tls.handshake_queue_timeout_ms"
+        ]
+    );
+}
+
+#[test]
+fn redis_rate_limiting_needs_a_url_and_the_token_bucket() {
+    let mut config = shipped_config();
+    config.limit.backend = aegis_common::RateLimitBackendKind::Redis;
+    config.limit.rate_limiter = aegis_common::RateLimiterKind::SlidingWindow;
+    assert_eq!(
+        invalid_fields(&config),
+        ["limit.redis_url", "limit.rate_limiter"]
+    );
+    config.limit.redis_url = Some("redis://127.0.0.1:6379".to_string());
+    config.limit.rate_limiter = aegis_common::RateLimiterKind::TokenBucket;
+    assert!(invalid_fields(&config).is_empty());
+}
+
+#[test]
+fn subnet_rate_limits_need_a_valid_prefix_and_the_token_bucket() {
+    let mut config = shipped_config();
+    config.limit.subnet_prefix_v4 = Some(33);
+    config.limit.subnet_prefix_v6 = Some(0);
+    config.limit.subnet_max_tokens = Some(0.0);
+    assert_eq!(
+        invalid_fields(&config),
+        [
+            "limit.subnet_prefix_v4",
+            "limit.subnet_prefix_v6",
+            "limit.subnet_max_tokens"
+        ]
+    );
+    config.limit.subnet_prefix_v4 = Some(24);
+    config.limit.subnet_prefix_v6 = Some(64);
+    config.limit.subnet_max_tokens = Some(20.0);
+    assert!(invalid_fields(&config).is_empty());
+    config.limit.rate_limiter = aegis_common::RateLimiterKind::SlidingWindow;
+    assert_eq!(invalid_fields(&config), ["limit.rate_limiter"]);
+}
+
+#[test]
+fn backend_addresses_may_name_hosts() {
+    let mut config = shipped_config();
+    config.proxy.target_address = "broker.internal:1883".to_string();
+    config.proxy.target_addresses = Some(
+        ["[::1]:1883", "10.0.0.1", "::1:1883"]
+            .map(|address| BackendTarget::Address(address.to_string()))
+            .to_vec(),
+    );
+    assert_eq!(
+        invalid_fields(&config),
+        ["proxy.target_addresses[1]", "proxy.target_addresses[2]"]
+    );
+}
+
+#[test]
+fn backend_addresses_may_carry_weights() {
+    let mut config = shipped_config();
+    config.proxy.target_addresses = serde_yaml::from_str(
+        r#"
+- "broker-1:1883"
+- address: "broker-2:1883"
+  weight: 3
+- address: "broker-3:1883"
+  weight: 0
+"#,
+    )
+    .unwrap();
+    let targets = config.proxy.target_addresses.as_ref().unwrap();
+    assert_eq!(
+        targets
+            .iter()
+            .map(BackendTarget::weight)
+            .collect::<Vec<_>>(),
+        [1, 3, 0]
+    );
+    assert_eq!(targets[1].address(), "broker-2:1883");
+    assert_eq!(
+        invalid_fields(&config),
+        ["proxy.target_addresses[2].weight"]
+    );
+}
+
+#[test]
+fn invalid_value_message_names_the_field() {
+    let mut config = shipped_config();
+    config.limit.refill_rate = -1.0;
+    let problems = config.validate().unwrap_err();
+    assert_eq!(
+        problems[0].to_string(),
+        "limit.refill_rate: must not be negative (got -1)"
+    );
+}
+
+/// The shipped YAML config converted to another format and written to a
+/// temporary file with `extension`.
+fn shipped_config_as(extension: &str) -> std::path::PathBuf {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../config/aegis_config.yaml");
+    let value: serde_yaml::Value =
+        serde_yaml::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let text = match extension {
+        "toml" => toml::to_string(&toml::Value::try_from(value).unwrap()).unwrap(),
+        "json" => serde_json::to_string_pretty(&value).unwrap(),
+        other => panic!("no conversion to {}", other),
+    };
+    let out =
+        std::env::temp_dir().join(format!("aegis-config-{}.{}", std::process::id(), extension));
+    std::fs::write(&out, text).unwrap();
+    out
+}
+
+#[test]
+fn config_loads_from_toml_and_json() {
+    let yaml = shipped_config();
+    for extension in ["toml", "json"] {
+        let path = shipped_config_as(extension);
+        let config = load_config(&path).unwrap_or_else(|e| panic!("{}: {}", extension, e));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.proxy.listen_address, yaml.proxy.listen_address);
+        assert_eq!(config.limit.max_tokens, yaml.limit.max_tokens);
+        assert_eq!(
+            config.slowloris_protection.first_packet_timeout_ms,
+            yaml.slowloris_protection.first_packet_timeout_ms
+        );
+    }
+}
+
+#[test]
+fn format_follows_the_extension() {
+    assert_eq!(
+        ConfigFormat::from_path(Path::new("a.yml")),
+        Some(ConfigFormat::Yaml)
+    );
+    assert_eq!(
+        ConfigFormat::from_path(Path::new("a.TOML")),
+        Some(ConfigFormat::Toml)
+    );
+    assert_eq!(
+        ConfigFormat::from_path(Path::new("a.json")),
+        Some(ConfigFormat::Json)
+    );
+    assert_eq!(ConfigFormat::from_path(Path::new("a.ini")), None);
+    assert_eq!(ConfigFormat::from_path(Path::new("config")), None);
+
+    let err = parse_config("", Path::new("config/aegis.ini")).unwrap_err();
+    assert!(matches!(err, ConfigError::UnsupportedFormat { .. }));
+    assert!(err.to_string().contains("config/aegis.ini"), "{}", err);
+}
+
+#[test]
+fn toml_and_json_errors_name_the_line() {
+    let toml = "[proxy]\nlisten_address = \"0.0.0.0:8080\"\ntarget_address = 1883\n";
+    match parse_config(toml, Path::new("config/test.toml")).unwrap_err() {
+        ConfigError::Parse { line, .. } => assert_eq!(line, Some(3)),
+        other => panic!("expected parse error, got {:?}", other),
+    }
+    let json = "{\n  \"proxy\": {\n    \"listen_address\": 8080\n  }\n}";
+    let err = parse_config(json, Path::new("config/test.json")).unwrap_err();
+    match &err {
+        ConfigError::Parse { line, .. } => assert_eq!(*line, Some(3)),
+        other => panic!("expected parse error, got {:?}", other),
+    }
+    assert!(err.to_string().contains("config/test.json"), "{}", err);
+}
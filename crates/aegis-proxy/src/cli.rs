@@ -0,0 +1,46 @@
+//! Command-line arguments.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// Configuration file read at startup, unless `--config` or `AEGIS_CONFIG`
+/// names another.
+pub const CONFIG_PATH: &str = "config/aegis_config.yaml";
+
+pub const USAGE: &str = "usage: aegis-proxy [--config <path>]
+
+  -c, --config <path>  configuration file (.yaml, .yml, .toml or .json);
+                       defaults to $AEGIS_CONFIG, then config/aegis_config.yaml
+  -h, --help           print this help";
+
+/// Path of the configuration file: `--config` if given in `args` (program
+/// name excluded), else `env` (the value of `AEGIS_CONFIG`) if non-empty,
+/// else `CONFIG_PATH`. The extension picks the format.
+///
+/// Returns `Ok(None)` when `--help` was asked for, and the usage error
+/// otherwise.
+pub fn config_path(
+    mut args: impl Iterator<Item = OsString>,
+    env: Option<OsString>,
+) -> Result<Option<PathBuf>, String> {
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        let arg_str = arg.to_string_lossy();
+        if arg_str == "-h" || arg_str == "--help" {
+            return Ok(None);
+        } else if arg_str == "-c" || arg_str == "--config" {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a path", arg_str))?;
+            path = Some(PathBuf::from(value));
+        } else if let Some(value) = arg_str.strip_prefix("--config=") {
+            path = Some(PathBuf::from(value));
+        } else {
+            return Err(format!("unexpected argument '{}'", arg_str));
+        }
+    }
+    Ok(Some(path.unwrap_or_else(|| {
+        env.filter(|value| !value.is_empty())
+            .map_or_else(|| PathBuf::from(CONFIG_PATH), PathBuf::from)
+    })))
+}
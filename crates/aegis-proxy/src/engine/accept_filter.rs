@@ -0,0 +1,57 @@
+//! Pre-accept connection filter hook.
+//!
+//! Embedders can install one filter for the process with
+//! [`set_accept_filter`]. The accept loop consults it for every new
+//! connection, after the FD-pressure and region checks and before rate
+//! limiting, so custom logic (an external reputation list, a maintenance
+//! switch) can drop or wave through clients without forking the loop.
+//!
+//! The filter runs on the accept hot path, inline with `accept()`: it must be
+//! fast and must not block. Anything slow (lookups over the network, disk)
+//! belongs in a background task that refreshes state the filter only reads.
+
+use once_cell::sync::OnceCell;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// What the filter wants done with a new connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptDecision {
+    /// Admit the connection, exempt from the per-source rate checks (rate
+    /// limit, connect ratio, repeated-malformed bans). Capacity caps such as
+    /// the subnet cap and inspection still apply.
+    Accept,
+    /// Close the connection immediately.
+    Reject,
+    /// No opinion: the proxy's own checks decide.
+    Defer,
+}
+
+/// A pre-accept filter; see the module docs for its constraints.
+pub type AcceptFilter = Arc<dyn Fn(SocketAddr) -> AcceptDecision + Send + Sync>;
+
+static ACCEPT_FILTER: OnceCell<AcceptFilter> = OnceCell::new();
+
+/// The default filter: defers every connection to the proxy's own checks.
+pub fn no_op() -> AcceptFilter {
+    Arc::new(|_| AcceptDecision::Defer)
+}
+
+/// Installs the process-wide filter. Only one filter can be installed; a
+/// second call returns the rejected filter.
+pub fn set_accept_filter(filter: AcceptFilter) -> Result<(), AcceptFilter> {
+    ACCEPT_FILTER.set(filter)
+}
+
+/// Whether a filter has been installed.
+pub fn is_installed() -> bool {
+    ACCEPT_FILTER.get().is_some()
+}
+
+/// Asks the installed filter about `addr`; `Defer` when none is installed.
+pub fn decide(addr: SocketAddr) -> AcceptDecision {
+    match ACCEPT_FILTER.get() {
+        Some(filter) => filter(addr),
+        None => AcceptDecision::Defer,
+    }
+}
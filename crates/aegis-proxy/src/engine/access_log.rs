@@ -0,0 +1,157 @@
+//! Opt-in access log: one `aegis_access` record per handled connection.
+//!
+//! Admission and inspection report their outcome in scattered log lines. With
+//! `access_log` enabled each connection is also summarized in a single event
+//! with stable field names (`client_ip`, `protocol`, `decision`, `reason`,
+//! `bytes_in`, `bytes_out`, `duration_ms`), one JSON line under the
+//! production subscriber, for feeding a SIEM.
+//!
+//! The decision and reason are filled in by the connection's `DecisionTrace`:
+//! the reason of a rejection is the check that was still open when the trace
+//! ended, under the same names the decision trace uses. Handles are cheap to
+//! clone and share one entry; a disabled handle records nothing.
+
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::info;
+
+#[derive(Debug)]
+struct Entry {
+    started: Instant,
+    client: Option<IpAddr>,
+    protocol: Option<&'static str>,
+    admitted: bool,
+    reason: Option<&'static str>,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+/// Summary of one connection, emitted once with `emit`.
+#[derive(Debug, Clone, Default)]
+pub struct AccessLog {
+    inner: Option<Arc<Mutex<Entry>>>,
+}
+
+impl AccessLog {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            inner: enabled.then(|| {
+                Arc::new(Mutex::new(Entry {
+                    started: Instant::now(),
+                    client: None,
+                    protocol: None,
+                    admitted: false,
+                    reason: None,
+                    bytes_in: 0,
+                    bytes_out: 0,
+                }))
+            }),
+        }
+    }
+
+    /// A handle that records nothing.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Records the client address (after any PROXY protocol header).
+    pub fn client(&self, ip: IpAddr) {
+        self.update(|entry| entry.client = Some(ip));
+    }
+
+    /// Records the protocol detected so far; later calls refine it.
+    pub fn protocol(&self, protocol: &'static str) {
+        self.update(|entry| entry.protocol = Some(protocol));
+    }
+
+    /// Records the admission verdict and, for a rejection, the check that
+    /// rejected the connection.
+    pub fn decide(&self, admitted: bool, reason: Option<&'static str>) {
+        self.update(|entry| {
+            entry.admitted = admitted;
+            entry.reason = reason;
+        });
+    }
+
+    /// Adds bytes forwarded in `direction` (`"upstream"` is client -> backend).
+    pub fn add_bytes(&self, direction: &str, n: u64) {
+        self.update(|entry| match direction {
+            "upstream" => entry.bytes_in += n,
+            _ => entry.bytes_out += n,
+        });
+    }
+
+    /// The recorded fields except the duration, as `key=value` pairs.
+    pub fn render(&self) -> String {
+        let Some(inner) = &self.inner else {
+            return String::new();
+        };
+        let entry = lock(inner);
+        format!(
+            "client_ip={} protocol={} decision={} reason={} bytes_in={} bytes_out={}",
+            entry.client_ip(),
+            entry.protocol(),
+            entry.decision(),
+            entry.reason(),
+            entry.bytes_in,
+            entry.bytes_out
+        )
+    }
+
+    /// Writes the `aegis_access` record.
+    pub fn emit(&self) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let entry = lock(inner);
+        info!(
+            target: "aegis_access",
+            client_ip = %entry.client_ip(),
+            protocol = entry.protocol(),
+            decision = entry.decision(),
+            reason = entry.reason(),
+            bytes_in = entry.bytes_in,
+            bytes_out = entry.bytes_out,
+            duration_ms = entry.started.elapsed().as_millis() as u64,
+            "Connection access"
+        );
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut Entry)) {
+        if let Some(inner) = &self.inner {
+            apply(&mut lock(inner));
+        }
+    }
+}
+
+impl Entry {
+    fn client_ip(&self) -> String {
+        self.client
+            .map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+    }
+
+    fn protocol(&self) -> &'static str {
+        self.protocol.unwrap_or("unknown")
+    }
+
+    fn decision(&self) -> &'static str {
+        if self.admitted {
+            "allowed"
+        } else {
+            "rejected"
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        self.reason.unwrap_or("none")
+    }
+}
+
+fn lock(inner: &Mutex<Entry>) -> std::sync::MutexGuard<'_, Entry> {
+    inner.lock().unwrap_or_else(|e| e.into_inner())
+}
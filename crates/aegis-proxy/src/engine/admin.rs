@@ -0,0 +1,136 @@
+//! Operator commands over a local Unix socket.
+//!
+//! The socket speaks a line protocol: one command per line, one reply line per
+//! command. Access is controlled by file permissions (the socket is created
+//! owner-only), so it must live in a directory only operators can reach.
+//!
+//! Commands:
+//! - `kill <ip|cidr>`: close every live connection from the given address or
+//!   network, mid-session included. Replies `killed <n>`.
+//! - `drain-backend <addr>`: stop sending new sessions to one of the
+//!   `target_addresses` brokers, keeping its current sessions. Replies
+//!   `draining <addr>`.
+//! - `undrain-backend <addr>`: send it new sessions again. Replies
+//!   `undrained <addr>`.
+
+use crate::engine::backend::BackendSelector;
+use crate::engine::registry;
+use ipnet::IpNet;
+use std::net::IpAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+/// Longest command line accepted; longer lines close the admin connection.
+const MAX_COMMAND_LEN: usize = 256;
+
+/// Runs one admin command against the backend pool, if any, and returns its
+/// reply (without newline).
+pub fn execute(command: &str, backends: Option<&BackendSelector>) -> String {
+    let mut words = command.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("kill"), Some(target), None) => match parse_target(target) {
+            Some(net) => {
+                let killed = registry::kill_matching(&net);
+                crate::metrics::ADMIN_KILLS.inc_by(killed as u64);
+                warn!(target = %net, killed, "Admin killed connections");
+                format!("killed {}", killed)
+            }
+            None => format!("error: invalid address or CIDR '{}'", target),
+        },
+        (Some("kill"), _, _) => "error: usage: kill <ip|cidr>".to_string(),
+        (Some(verb @ ("drain-backend" | "undrain-backend")), Some(target), None) => {
+            let Some(backends) = backends else {
+                return "error: no target_addresses configured".to_string();
+            };
+            let draining = verb == "drain-backend";
+            if !backends.set_draining(target, draining) {
+                return format!("error: unknown backend '{}'", target);
+            }
+            warn!(target = %target, draining, "Admin changed backend draining");
+            if draining {
+                format!("draining {}", target)
+            } else {
+                format!("undrained {}", target)
+            }
+        }
+        (Some(verb @ ("drain-backend" | "undrain-backend")), _, _) => {
+            format!("error: usage: {} <addr>", verb)
+        }
+        (Some(other), _, _) => format!("error: unknown command '{}'", other),
+        (None, _, _) => "error: empty command".to_string(),
+    }
+}
+
+/// A bare address is treated as a single-host network.
+fn parse_target(target: &str) -> Option<IpNet> {
+    target
+        .parse::<IpNet>()
+        .map(|net| net.trunc())
+        .or_else(|_| target.parse::<IpAddr>().map(IpNet::from))
+        .ok()
+}
+
+/// Serves the admin socket at `path` until `shutdown` is cancelled. A stale
+/// socket file left by a previous run is replaced.
+pub async fn run_admin_socket(
+    path: String,
+    backends: Option<Arc<BackendSelector>>,
+    shutdown: CancellationToken,
+) {
+    let _ = std::fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(path = %path, error = %e, "Could not bind admin socket");
+            return;
+        }
+    };
+    if let Err(e) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)) {
+        warn!(path = %path, error = %e, "Could not restrict admin socket permissions");
+    }
+    info!(path = %path, "Admin socket listening");
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve_admin_client(stream, backends.clone()));
+                }
+                Err(e) => warn!(error = %e, "Admin socket accept failed"),
+            },
+            _ = shutdown.cancelled() => break,
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+}
+
+async fn serve_admin_client(stream: UnixStream, backends: Option<Arc<BackendSelector>>) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let mut bounded = (&mut lines).take(MAX_COMMAND_LEN as u64 + 1);
+        match bounded.read_line(&mut line).await {
+            Ok(0) => return,
+            Ok(_) if line.len() > MAX_COMMAND_LEN => return,
+            Ok(_) => {}
+            Err(e) => {
+                debug!(error = %e, "Admin client read failed");
+                return;
+            }
+        }
+        let reply = execute(line.trim(), backends.as_deref());
+        if write
+            .write_all(format!("{}\n", reply).as_bytes())
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
@@ -0,0 +1,517 @@
+//! Backend connects and classification of their failures.
+//!
+//! Not every failed connect means the same thing. A refused connection or a
+//! timeout usually means the broker is down or overloaded and will come back,
+//! while a name that does not resolve is almost always a configuration error
+//! that retrying only hammers the resolver with. Failures are classified once,
+//! here, so resilience logic (retries, breakers) and metrics can act on the
+//! class instead of treating every error alike.
+
+use crate::engine::splice::SpliceSocket;
+use aegis_common::BackendCircuitConfig;
+use dashmap::DashMap;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+/// Why a backend connect failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendErrorClass {
+    /// The backend actively refused the connection (nothing listening).
+    Refused,
+    /// No answer within the connect timeout (backend slow or overloaded).
+    Timeout,
+    /// The backend name did not resolve; likely a configuration error.
+    Dns,
+    /// No route to the backend host or network.
+    Unreachable,
+    /// Any other I/O error.
+    Other,
+    /// Not attempted: every candidate backend's circuit is open.
+    CircuitOpen,
+}
+
+impl BackendErrorClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackendErrorClass::Refused => "refused",
+            BackendErrorClass::Timeout => "timeout",
+            BackendErrorClass::Dns => "dns",
+            BackendErrorClass::Unreachable => "unreachable",
+            BackendErrorClass::Other => "other",
+            BackendErrorClass::CircuitOpen => "circuit_open",
+        }
+    }
+
+    /// Whether trying the same backend again soon may succeed. DNS failures
+    /// are not retried: they rarely fix themselves within a handshake.
+    pub fn is_retryable(self) -> bool {
+        match self {
+            BackendErrorClass::Refused
+            | BackendErrorClass::Timeout
+            | BackendErrorClass::Unreachable => true,
+            BackendErrorClass::Dns | BackendErrorClass::Other | BackendErrorClass::CircuitOpen => {
+                false
+            }
+        }
+    }
+
+    /// Classifies an error returned by a TCP connect to a resolved address.
+    pub fn of_connect_error(error: &io::Error) -> Self {
+        match error.kind() {
+            // NotFound: a Unix socket path with nothing bound to it.
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::NotFound => {
+                BackendErrorClass::Refused
+            }
+            io::ErrorKind::TimedOut => BackendErrorClass::Timeout,
+            io::ErrorKind::HostUnreachable | io::ErrorKind::NetworkUnreachable => {
+                BackendErrorClass::Unreachable
+            }
+            _ => BackendErrorClass::Other,
+        }
+    }
+}
+
+/// A failed backend connect with its class.
+#[derive(Debug)]
+pub struct BackendError {
+    pub class: BackendErrorClass,
+    pub source: Option<io::Error>,
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(e) => write!(f, "backend connect failed ({}): {}", self.class.as_str(), e),
+            None => write!(f, "backend connect failed ({})", self.class.as_str()),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_ref()
+            .map(|e| e as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// A connected backend: TCP, or a Unix domain socket for a `unix:<path>`
+/// target.
+#[derive(Debug)]
+pub enum BackendStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+impl BackendStream {
+    /// The TCP socket, for socket options; `None` for a Unix socket.
+    pub fn tcp(&self) -> Option<&TcpStream> {
+        match self {
+            BackendStream::Tcp(stream) => Some(stream),
+            #[cfg(unix)]
+            BackendStream::Unix(_) => None,
+        }
+    }
+
+    pub fn into_split(self) -> (BackendReader, BackendWriter) {
+        match self {
+            BackendStream::Tcp(stream) => {
+                let (read, write) = stream.into_split();
+                (BackendReader::Tcp(read), BackendWriter::Tcp(write))
+            }
+            #[cfg(unix)]
+            BackendStream::Unix(stream) => {
+                let (read, write) = stream.into_split();
+                (BackendReader::Unix(read), BackendWriter::Unix(write))
+            }
+        }
+    }
+}
+
+/// Read half of a `BackendStream`.
+pub enum BackendReader {
+    Tcp(OwnedReadHalf),
+    #[cfg(unix)]
+    Unix(tokio::net::unix::OwnedReadHalf),
+}
+
+/// Write half of a `BackendStream`.
+pub enum BackendWriter {
+    Tcp(OwnedWriteHalf),
+    #[cfg(unix)]
+    Unix(tokio::net::unix::OwnedWriteHalf),
+}
+
+impl AsyncRead for BackendReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendReader::Tcp(read) => Pin::new(read).poll_read(cx, buf),
+            #[cfg(unix)]
+            BackendReader::Unix(read) => Pin::new(read).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for BackendWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            BackendWriter::Tcp(write) => Pin::new(write).poll_write(cx, buf),
+            #[cfg(unix)]
+            BackendWriter::Unix(write) => Pin::new(write).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendWriter::Tcp(write) => Pin::new(write).poll_flush(cx),
+            #[cfg(unix)]
+            BackendWriter::Unix(write) => Pin::new(write).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendWriter::Tcp(write) => Pin::new(write).poll_shutdown(cx),
+            #[cfg(unix)]
+            BackendWriter::Unix(write) => Pin::new(write).poll_shutdown(cx),
+        }
+    }
+}
+
+// Unix sockets are always copied in userspace.
+impl SpliceSocket for BackendReader {
+    fn splice_socket(&self) -> Option<&TcpStream> {
+        match self {
+            BackendReader::Tcp(read) => read.splice_socket(),
+            #[cfg(unix)]
+            BackendReader::Unix(_) => None,
+        }
+    }
+}
+
+impl SpliceSocket for BackendWriter {
+    fn splice_socket(&self) -> Option<&TcpStream> {
+        match self {
+            BackendWriter::Tcp(write) => write.splice_socket(),
+            #[cfg(unix)]
+            BackendWriter::Unix(_) => None,
+        }
+    }
+}
+
+/// Connects to `target` within `limit`: a `unix:<path>` socket, or else a
+/// TCP `host:port`, resolution included. Resolution is done separately from
+/// the connect so that a name lookup failure is reported as `Dns` rather than
+/// a generic I/O error.
+pub async fn connect(target: &str, limit: Duration) -> Result<BackendStream, BackendError> {
+    if let Some(path) = aegis_common::unix_socket_path(target) {
+        return connect_unix(path, limit).await;
+    }
+    let attempt = async {
+        let addrs: Vec<_> = lookup_host(target)
+            .await
+            .map_err(|e| BackendError {
+                class: BackendErrorClass::Dns,
+                source: Some(e),
+            })?
+            .collect();
+        if addrs.is_empty() {
+            return Err(BackendError {
+                class: BackendErrorClass::Dns,
+                source: None,
+            });
+        }
+        TcpStream::connect(&addrs[..])
+            .await
+            .map(BackendStream::Tcp)
+            .map_err(|e| BackendError {
+                class: BackendErrorClass::of_connect_error(&e),
+                source: Some(e),
+            })
+    };
+    match timeout(limit, attempt).await {
+        Ok(result) => result,
+        Err(_) => Err(BackendError {
+            class: BackendErrorClass::Timeout,
+            source: None,
+        }),
+    }
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: &str, limit: Duration) -> Result<BackendStream, BackendError> {
+    match timeout(limit, tokio::net::UnixStream::connect(path)).await {
+        Ok(Ok(stream)) => Ok(BackendStream::Unix(stream)),
+        Ok(Err(e)) => Err(BackendError {
+            class: BackendErrorClass::of_connect_error(&e),
+            source: Some(e),
+        }),
+        Err(_) => Err(BackendError {
+            class: BackendErrorClass::Timeout,
+            source: None,
+        }),
+    }
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_path: &str, _limit: Duration) -> Result<BackendStream, BackendError> {
+    Err(BackendError {
+        class: BackendErrorClass::Other,
+        source: Some(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix domain sockets are not supported on this platform",
+        )),
+    })
+}
+
+/// Weighted round-robin rotation over interchangeable backends.
+pub struct BackendSelector {
+    targets: Vec<String>,
+    /// Index of the first target for each connection in one cycle: every
+    /// target as many times as its weight, interleaved.
+    schedule: Vec<usize>,
+    next: AtomicUsize,
+    /// Per target: left out of new sessions (admin `drain-backend`).
+    draining: Vec<AtomicBool>,
+}
+
+impl BackendSelector {
+    /// Equally weighted targets. Returns `None` when `targets` is empty.
+    pub fn new(targets: Vec<String>) -> Option<Self> {
+        Self::weighted(targets.into_iter().map(|target| (target, 1)).collect())
+    }
+
+    /// Targets with their weights (a weight of 0 counts as 1). Returns `None`
+    /// when `targets` is empty.
+    pub fn weighted(targets: Vec<(String, u32)>) -> Option<Self> {
+        if targets.is_empty() {
+            return None;
+        }
+        let weights: Vec<i64> = targets.iter().map(|(_, w)| i64::from(*w.max(&1))).collect();
+        let total: i64 = weights.iter().sum();
+        // Smooth weighted round-robin: heavier targets come up more often
+        // without running back to back.
+        let mut current = vec![0i64; weights.len()];
+        let mut schedule = Vec::with_capacity(total as usize);
+        for _ in 0..total {
+            for (credit, weight) in current.iter_mut().zip(&weights) {
+                *credit += weight;
+            }
+            let best = (0..current.len())
+                .rev()
+                .max_by_key(|&i| current[i])
+                .unwrap_or(0);
+            current[best] -= total;
+            schedule.push(best);
+        }
+        Some(Self {
+            draining: targets.iter().map(|_| AtomicBool::new(false)).collect(),
+            targets: targets.into_iter().map(|(target, _)| target).collect(),
+            schedule,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn targets(&self) -> &[String] {
+        &self.targets
+    }
+
+    /// Every target once, starting with the next one in the rotation. A
+    /// connection tries them in this order, so consecutive connections start
+    /// on different backends and a failing backend falls through to the rest.
+    /// Draining targets are left out, unless every target is draining.
+    pub fn rotation(&self) -> impl Iterator<Item = &str> + '_ {
+        let len = self.targets.len();
+        let turn = self.next.fetch_add(1, Ordering::Relaxed) % self.schedule.len();
+        let start = self.schedule[turn];
+        let all_draining = self.draining.iter().all(|d| d.load(Ordering::Relaxed));
+        (0..len)
+            .map(move |i| (start + i) % len)
+            .filter(move |&i| all_draining || !self.draining[i].load(Ordering::Relaxed))
+            .map(move |i| self.targets[i].as_str())
+    }
+
+    /// Stops (or resumes) picking `target` for new sessions; sessions already
+    /// on it are left alone. Returns `false` if `target` is not in the pool.
+    pub fn set_draining(&self, target: &str, draining: bool) -> bool {
+        let Some(index) = self.targets.iter().position(|t| t == target) else {
+            return false;
+        };
+        self.draining[index].store(draining, Ordering::Relaxed);
+        crate::metrics::BACKEND_DRAINING
+            .with_label_values(&[target])
+            .set(i64::from(draining));
+        true
+    }
+
+    pub fn is_draining(&self, target: &str) -> bool {
+        self.targets
+            .iter()
+            .position(|t| t == target)
+            .is_some_and(|index| self.draining[index].load(Ordering::Relaxed))
+    }
+}
+
+/// Circuit state of one backend.
+#[derive(Debug, Clone, Copy)]
+enum Circuit {
+    /// Connects are attempted; counts consecutive failures since the first
+    /// one in the current window.
+    Closed {
+        failures: u32,
+        window_start: Instant,
+    },
+    /// Connects are skipped until the cooldown ends.
+    Open { until: Instant },
+    /// One probe connect is in flight; others are skipped until it reports,
+    /// or until a cooldown passes without a report.
+    Probing { since: Instant },
+}
+
+/// Passive health tracking per backend address, fed by the outcome of real
+/// connects (see `BackendCircuitConfig`).
+pub struct BackendHealth {
+    failure_threshold: u32,
+    window: Duration,
+    cooldown: Duration,
+    circuits: DashMap<String, Circuit>,
+}
+
+impl BackendHealth {
+    pub fn new(config: &BackendCircuitConfig) -> Self {
+        Self {
+            failure_threshold: config.failure_threshold.max(1),
+            window: Duration::from_millis(config.window_ms),
+            cooldown: Duration::from_millis(config.cooldown_ms),
+            circuits: DashMap::new(),
+        }
+    }
+
+    /// Whether a connect to `target` should be attempted now. Once an open
+    /// circuit's cooldown has elapsed, exactly one caller gets `true` (the
+    /// probe) until that probe reports its outcome.
+    pub fn allow(&self, target: &str) -> bool {
+        let Some(mut circuit) = self.circuits.get_mut(target) else {
+            return true;
+        };
+        let now = Instant::now();
+        if self.skipping(&circuit, now) {
+            return false;
+        }
+        if !matches!(*circuit, Circuit::Closed { .. }) {
+            *circuit = Circuit::Probing { since: now };
+            info!(backend = %target, "Backend cooldown over; probing");
+        }
+        true
+    }
+
+    /// Whether connects to a backend in `circuit` are currently skipped.
+    fn skipping(&self, circuit: &Circuit, now: Instant) -> bool {
+        match *circuit {
+            Circuit::Closed { .. } => false,
+            Circuit::Open { until } => now < until,
+            // A probe that never reported (its connection was cancelled)
+            // stops blocking others after a cooldown.
+            Circuit::Probing { since } => now < since + self.cooldown,
+        }
+    }
+
+    /// Whether every one of `targets` would be skipped right now: all
+    /// circuits open and no probe due. A connection accepted now could not
+    /// reach any of them.
+    pub fn all_skipped<'a>(&self, targets: impl IntoIterator<Item = &'a str>) -> bool {
+        let now = Instant::now();
+        targets.into_iter().all(|target| {
+            self.circuits
+                .get(target)
+                .is_some_and(|circuit| self.skipping(&circuit, now))
+        })
+    }
+
+    /// Whether `target`'s circuit is currently open (connects are skipped).
+    pub fn is_open(&self, target: &str) -> bool {
+        self.circuits
+            .get(target)
+            .is_some_and(|c| !matches!(*c, Circuit::Closed { .. }))
+    }
+
+    pub fn record_success(&self, target: &str) {
+        let previous = self.circuits.insert(
+            target.to_string(),
+            Circuit::Closed {
+                failures: 0,
+                window_start: Instant::now(),
+            },
+        );
+        if !matches!(previous, None | Some(Circuit::Closed { .. })) {
+            info!(backend = %target, "Backend circuit closed");
+        }
+        crate::metrics::BACKEND_HEALTHY
+            .with_label_values(&[target])
+            .set(1);
+    }
+
+    pub fn record_failure(&self, target: &str) {
+        let now = Instant::now();
+        let mut circuit = self
+            .circuits
+            .entry(target.to_string())
+            .or_insert(Circuit::Closed {
+                failures: 0,
+                window_start: now,
+            });
+        let opens = match *circuit {
+            Circuit::Closed {
+                failures,
+                window_start,
+            } => {
+                let (failures, window_start) = if now.duration_since(window_start) > self.window {
+                    (1, now)
+                } else {
+                    (failures + 1, window_start)
+                };
+                *circuit = Circuit::Closed {
+                    failures,
+                    window_start,
+                };
+                failures >= self.failure_threshold
+            }
+            // A failed probe (or a straggler from before the circuit opened)
+            // restarts the cooldown.
+            Circuit::Probing { .. } => true,
+            Circuit::Open { .. } => false,
+        };
+        if opens {
+            *circuit = Circuit::Open {
+                until: now + self.cooldown,
+            };
+            drop(circuit);
+            warn!(
+                backend = %target,
+                cooldown_ms = self.cooldown.as_millis() as u64,
+                "Backend circuit opened after repeated connect failures"
+            );
+            crate::metrics::BACKEND_HEALTHY
+                .with_label_values(&[target])
+                .set(0);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Targeted packet capture for rejected connections.
+//!
+//! When a connection is rejected for one of the configured reasons, the bytes
+//! we inspected are hex-dumped to a capture sink for offline analysis. Only
+//! flagged connections are ever captured, and both the per-connection size and
+//! the global capture rate are bounded so an attacker cannot turn the feature
+//! into a disk- or log-filling amplifier.
+//!
+//! Captures are handed to a background writer over a bounded channel, so the
+//! connection path never blocks on file I/O; when the channel is full the
+//! capture is dropped and counted.
+
+use aegis_common::CaptureConfig;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Pending captures buffered between connection tasks and the writer.
+const CAPTURE_QUEUE_DEPTH: usize = 256;
+
+/// Length of the capture rate-limit window.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// A single captured rejection, ready to be written out.
+struct CaptureRecord {
+    timestamp_secs: u64,
+    client: String,
+    reason: &'static str,
+    total_len: usize,
+    bytes: Vec<u8>,
+}
+
+impl CaptureRecord {
+    fn hex(&self) -> String {
+        self.bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Entry point used by the connection handler to submit captures.
+pub struct PacketCapture {
+    reasons: HashSet<String>,
+    max_bytes: usize,
+    max_per_window: u32,
+    window: Mutex<(Instant, u32)>,
+    tx: mpsc::Sender<CaptureRecord>,
+}
+
+impl PacketCapture {
+    /// Builds the capture front-end and the writer future that drains it.
+    ///
+    /// The writer should be spawned by the caller; it finishes once every
+    /// `PacketCapture` handle has been dropped.
+    pub fn new(
+        config: &CaptureConfig,
+    ) -> (Self, impl std::future::Future<Output = ()> + Send + 'static) {
+        let (tx, rx) = mpsc::channel(CAPTURE_QUEUE_DEPTH);
+        let capture = Self {
+            reasons: config.reasons.iter().cloned().collect(),
+            max_bytes: config.max_bytes_per_connection,
+            max_per_window: config.max_captures_per_minute,
+            window: Mutex::new((Instant::now(), 0)),
+            tx,
+        };
+        (capture, run_writer(rx, config.path.clone()))
+    }
+
+    /// Capture `bytes` if `reason` is a configured trigger and the global
+    /// capture budget allows it.
+    pub fn record(&self, reason: &'static str, client: &str, bytes: &[u8]) {
+        if bytes.is_empty() || !self.reasons.contains(reason) {
+            return;
+        }
+        if !self.take_budget() {
+            crate::metrics::CAPTURES_DROPPED.inc();
+            return;
+        }
+
+        let record = CaptureRecord {
+            timestamp_secs: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            client: client.to_string(),
+            reason,
+            total_len: bytes.len(),
+            bytes: bytes[..bytes.len().min(self.max_bytes)].to_vec(),
+        };
+        if self.tx.try_send(record).is_err() {
+            crate::metrics::CAPTURES_DROPPED.inc();
+        }
+    }
+
+    fn take_budget(&self) -> bool {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        if window.0.elapsed() >= RATE_WINDOW {
+            *window = (Instant::now(), 0);
+        }
+        if window.1 >= self.max_per_window {
+            return false;
+        }
+        window.1 += 1;
+        true
+    }
+}
+
+/// Drains capture records to the configured file, or to the log if no file
+/// is configured (or it cannot be opened).
+async fn run_writer(mut rx: mpsc::Receiver<CaptureRecord>, path: Option<String>) {
+    let mut file = match &path {
+        Some(p) => match tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(p)
+            .await
+        {
+            Ok(f) => Some(f),
+            Err(e) => {
+                warn!(path = %p, error = %e, "Could not open capture file; logging captures instead");
+                None
+            }
+        },
+        None => None,
+    };
+
+    while let Some(record) = rx.recv().await {
+        match file.as_mut() {
+            Some(f) => {
+                let line = format!(
+                    "{} client={} reason={} len={} captured={} hex={}\n",
+                    record.timestamp_secs,
+                    record.client,
+                    record.reason,
+                    record.total_len,
+                    record.bytes.len(),
+                    record.hex()
+                );
+                // Flush per record: captures are rare and must survive a crash.
+                let written = match f.write_all(line.as_bytes()).await {
+                    Ok(()) => f.flush().await,
+                    Err(e) => Err(e),
+                };
+                match written {
+                    Ok(()) => crate::metrics::CAPTURES_WRITTEN.inc(),
+                    Err(e) => warn!(error = %e, "Failed writing packet capture"),
+                }
+            }
+            None => {
+                info!(
+                    target: "aegis_capture",
+                    client = %record.client,
+                    reason = record.reason,
+                    len = record.total_len,
+                    captured = record.bytes.len(),
+                    hex = %record.hex(),
+                    "Packet capture"
+                );
+                crate::metrics::CAPTURES_WRITTEN.inc();
+            }
+        }
+    }
+}
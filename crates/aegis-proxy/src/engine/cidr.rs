@@ -0,0 +1,157 @@
+//! CIDR matching shared by the IP-based admission filters.
+//!
+//! Lists are small and parsed once at startup, so a linear scan over the
+//! networks is cheaper than maintaining a prefix tree. Addresses are
+//! canonicalised first, so an IPv4 client seen on a dual-stack listener as
+//! `::ffff:a.b.c.d` still matches IPv4 entries.
+
+use aegis_common::{AccessControlConfig, RegionFilterConfig, RegionPolicy};
+use ipnet::IpNet;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+
+/// A set of IPv4/IPv6 networks.
+#[derive(Debug, Clone, Default)]
+pub struct CidrSet {
+    nets: Vec<IpNet>,
+}
+
+impl CidrSet {
+    /// Parses a list of CIDRs; bare addresses are treated as host routes.
+    pub fn parse<S: AsRef<str>>(entries: &[S]) -> Result<Self, String> {
+        let nets = entries
+            .iter()
+            .map(|entry| {
+                let entry = entry.as_ref().trim();
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("invalid CIDR '{}'", entry))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { nets })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.nets.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Prefix length of the most specific network containing `ip`.
+    pub fn longest_match(&self, ip: IpAddr) -> Option<u8> {
+        let ip = ip.to_canonical();
+        self.nets
+            .iter()
+            .filter(|net| net.contains(&ip))
+            .map(|net| net.prefix_len())
+            .max()
+    }
+
+    /// The most specific network containing `ip`.
+    pub fn most_specific(&self, ip: IpAddr) -> Option<IpNet> {
+        let ip = ip.to_canonical();
+        self.nets
+            .iter()
+            .filter(|net| net.contains(&ip))
+            .max_by_key(|net| net.prefix_len())
+            .copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nets.is_empty()
+    }
+}
+
+/// Why `AccessControl` refused a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclRejection {
+    /// Inside this `deny_cidrs` network.
+    Denied(IpNet),
+    /// Outside every `allow_cidrs` network.
+    NotAllowed,
+}
+
+impl fmt::Display for AclRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AclRejection::Denied(net) => write!(f, "deny {}", net),
+            AclRejection::NotAllowed => write!(f, "not in allow list"),
+        }
+    }
+}
+
+/// Allow / deny lists built from `AccessControlConfig`.
+#[derive(Debug, Clone)]
+pub struct AccessControl {
+    allow: CidrSet,
+    deny: CidrSet,
+}
+
+impl AccessControl {
+    pub fn from_config(config: &AccessControlConfig) -> Result<Self, String> {
+        Ok(Self {
+            allow: CidrSet::parse(&config.allow_cidrs)
+                .map_err(|e| format!("access_control.allow_cidrs: {}", e))?,
+            deny: CidrSet::parse(&config.deny_cidrs)
+                .map_err(|e| format!("access_control.deny_cidrs: {}", e))?,
+        })
+    }
+
+    /// Deny takes precedence; an empty allow list admits everyone else.
+    pub fn check(&self, ip: IpAddr) -> Result<(), AclRejection> {
+        if let Some(net) = self.deny.most_specific(ip) {
+            return Err(AclRejection::Denied(net));
+        }
+        if !self.allow.is_empty() && !self.allow.contains(ip) {
+            return Err(AclRejection::NotAllowed);
+        }
+        Ok(())
+    }
+}
+
+/// Label reported for clients outside every region under `AllowListed`.
+pub const UNLISTED_REGION: &str = "unlisted";
+
+/// Region filter built from `RegionFilterConfig`.
+#[derive(Debug, Clone)]
+pub struct RegionFilter {
+    policy: RegionPolicy,
+    regions: BTreeMap<String, CidrSet>,
+}
+
+impl RegionFilter {
+    pub fn from_config(config: &RegionFilterConfig) -> Result<Self, String> {
+        let regions = config
+            .regions
+            .iter()
+            .map(|(label, cidrs)| {
+                CidrSet::parse(cidrs)
+                    .map(|set| (label.clone(), set))
+                    .map_err(|e| format!("region '{}': {}", label, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            policy: config.policy,
+            regions,
+        })
+    }
+
+    /// First region containing `ip`, if any.
+    pub fn region_of(&self, ip: IpAddr) -> Option<&str> {
+        self.regions
+            .iter()
+            .find(|(_, set)| set.contains(ip))
+            .map(|(label, _)| label.as_str())
+    }
+
+    /// Returns `Err(label)` with the region to meter when `ip` must be rejected.
+    pub fn check(&self, ip: IpAddr) -> Result<(), &str> {
+        match (self.policy, self.region_of(ip)) {
+            (RegionPolicy::AllowListed, Some(_)) => Ok(()),
+            (RegionPolicy::AllowListed, None) => Err(UNLISTED_REGION),
+            (RegionPolicy::DenyListed, Some(label)) => Err(label),
+            (RegionPolicy::DenyListed, None) => Ok(()),
+        }
+    }
+}
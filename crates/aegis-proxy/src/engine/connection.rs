@@ -1,15 +1,91 @@
+use crate::engine::accept_filter::{self, AcceptDecision};
+use crate::engine::access_log::AccessLog;
+use crate::engine::backend::{
+    self, BackendError, BackendErrorClass, BackendHealth, BackendReader, BackendSelector,
+    BackendStream, BackendWriter,
+};
+use crate::engine::capture::PacketCapture;
+use crate::engine::cidr::{AccessControl, RegionFilter};
 use crate::engine::http::{inspect_http, looks_like_http, HttpInspectionResult};
-use crate::engine::slowloris::read_with_idle_timeout;
-use crate::parser::mqtt::{self, MqttPacketType};
-use aegis_common::SlowlorisConfig;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
-use tokio::net::{tcp::OwnedWriteHalf, TcpStream};
-use tokio::time::{timeout, Duration};
+use crate::engine::limiter::{
+    acquire_in_flight, check_connect_ratio, check_rate_limit, check_session_rate, malformed_banned,
+    record_connect_completed, record_malformed, try_acquire_concurrent, InspectionSlot,
+    SubnetLimiter,
+};
+use crate::engine::policy::{Profile, SourcePolicy};
+use crate::engine::proxy_protocol::{self, ProxyError};
+use crate::engine::registry;
+use crate::engine::signature::{FastPath, SignatureSet};
+use crate::engine::slowloris::{
+    read_with_idle_timeout, read_with_throughput_floor, ActivityReader, BudgetExceeded,
+    InspectionBudget, SessionActivity, ThroughputTooLow, TimeoutReader, TimeoutWriter,
+    WriteStalled,
+};
+use crate::engine::sockopt::set_tcp_user_timeout;
+use crate::engine::splice::{splice_copy, SpliceSocket};
+use crate::engine::tags::ConnectionTags;
+use crate::engine::topics::TopicRules;
+use crate::engine::trace::DecisionTrace;
+use crate::metrics::RejectReason;
+use crate::parser::mqtt::{
+    self, ConnackRefusal, MqttPacketType, PacketSizeTracker, PublishError, PublishTracker,
+};
+use crate::parser::tls;
+use aegis_common::{
+    HttpInspectionConfig, InFlightConnectConfig, KeepAliveAction, LimitConfig, MqttPolicyConfig,
+    ProtocolBackends, RejectCategory, RepeatedMalformedConfig, SessionRateConfig, SlowlorisConfig,
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter, ReadBuf};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration, Instant};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
 
 pub static ACTIVE_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
 
+/// Set once graceful shutdown begins. New connections accepted while draining
+/// are refused with a protocol-appropriate signal instead of being proxied.
+pub static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Consecutive backend connect failures since the last successful connect.
+pub static BACKEND_CONNECT_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Consecutive backend connect failures after which `/health` reports degraded.
+pub const BACKEND_DEGRADED_THRESHOLD: usize = 3;
+
+/// True while the backend looks unreachable (the proxy itself is fine).
+pub fn backend_degraded() -> bool {
+    BACKEND_CONNECT_FAILURES.load(Ordering::Relaxed) >= BACKEND_DEGRADED_THRESHOLD
+}
+
+/// How long a draining rejection waits for the client's first bytes.
+const DRAIN_REJECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a refused client gets to accept its CONNACK before we close.
+const REJECT_CONNACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the signature fast path waits for the first bytes. Fleet devices
+/// send their CONNECT right away; slower clients take the normal path.
+const FAST_PATH_PEEK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Bytes peeked to classify the first packet. Detection only needs a prefix
+/// (an HTTP method and space, or an MQTT fixed header, Remaining Length and
+/// protocol name: at most 11 bytes), so a larger packet continuing past the
+/// peek is expected and never inspected from this buffer.
+const FIRST_PACKET_PEEK: usize = 16;
+
+/// Response sent to HTTP clients that connect while draining.
+const HTTP_SERVICE_UNAVAILABLE: &[u8] =
+    b"HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+
 /// Configuration for connection handling behavior.
 pub struct ConnectionConfig {
     pub mqtt_inspect: bool,
@@ -17,7 +93,454 @@ pub struct ConnectionConfig {
     pub http_inspect: bool,
     pub slowloris_protect: bool,
     pub max_connect_remaining: usize,
+    /// Max time (ms) forwarding the initial CONNECT to the backend may take
+    /// in total.
+    pub backend_write_timeout_ms: u64,
+    /// Successful backend connects slower than this (ms) are logged at warn.
+    pub slow_backend_connect_ms: u64,
+    /// Send MQTT clients a "server unavailable" CONNACK when no backend can
+    /// be reached, instead of closing silently.
+    pub backend_unavailable_connack: bool,
     pub slowloris_config: SlowlorisConfig,
+    pub http_inspection: HttpInspectionConfig,
+    /// Policies applied to fully inspected CONNECTs.
+    pub mqtt_policy: MqttPolicyConfig,
+    /// Per-IP rate of MQTT sessions allowed through to the backend.
+    pub session_rate: Option<SessionRateConfig>,
+    /// Ban clients repeating an identical malformed CONNECT, when set.
+    pub repeated_malformed: Option<RepeatedMalformedConfig>,
+    /// Per-IP cap on connections being established at once, when set.
+    pub in_flight_connects: Option<InFlightConnectConfig>,
+    /// Longest MQTT client identifier accepted (bytes), when set.
+    pub max_client_id_len: Option<usize>,
+    /// Reject empty client identifiers on CONNECTs without clean session.
+    pub require_client_id_for_persistent_session: bool,
+    /// Reject CONNECTs without a user name.
+    pub require_username: bool,
+    /// Forward MQTT-over-WebSocket upgrades to the MQTT target when no
+    /// websocket backend is configured.
+    pub mqtt_websocket: bool,
+    /// `TCP_USER_TIMEOUT` for both sockets once forwarding starts.
+    pub tcp_user_timeout: Option<Duration>,
+    /// Brokers used round-robin for MQTT instead of the default target.
+    pub backend_pool: Option<Arc<BackendSelector>>,
+    /// Circuit breaker shared by every connection, when configured.
+    pub backend_health: Option<Arc<BackendHealth>>,
+    /// Bounded tags for the tagged metrics, when `metric_tags` is configured.
+    pub tags: Option<ConnectionTags>,
+    /// Prefix the backend stream with a PROXY protocol v1 header.
+    pub send_proxy_protocol: bool,
+    /// Read a PROXY protocol v2 header first and treat the address it
+    /// carries as the client.
+    pub accept_proxy_protocol: bool,
+    /// Per-source checks deferred from the accept loop until the PROXY
+    /// header names the client.
+    pub deferred_source_checks: Option<SourceChecks>,
+    /// Inspection slot taken at accept; released once forwarding starts.
+    pub inspection_slot: Option<InspectionSlot>,
+    /// Cap on open connections per client IP, when set.
+    pub max_concurrent_per_ip: Option<usize>,
+    /// Process shutdown; aborts the initial forward to a backend that is not
+    /// reading.
+    pub shutdown: Option<CancellationToken>,
+    /// Known-good prefixes that skip inspection, when configured.
+    pub fast_path: Option<Arc<SignatureSet>>,
+    /// Forward the admitted session with `splice(2)` (Linux) instead of `io::copy`.
+    pub splice_forwarding: bool,
+    /// Optional protocol -> backend dispatch table (see `ProtocolBackends`).
+    pub protocol_backends: Option<ProtocolBackends>,
+    /// TLS server name -> backend table for TLS passthrough, when configured.
+    /// Keys are lowercased without a trailing dot.
+    pub sni_routes: Option<Arc<HashMap<String, String>>>,
+    /// PUBLISH topic rules enforced on the client -> broker stream after a
+    /// fully inspected CONNECT (`enable_publish_inspection`).
+    pub publish_topics: Option<Arc<TopicRules>>,
+    /// Edge identity added to v5 CONNECTs as a user property, when enabled.
+    pub edge_instance_id: Option<String>,
+    /// Targeted capture of rejected connections, when enabled.
+    pub capture: Option<Arc<PacketCapture>>,
+    /// Application-level write buffer (bytes) on the client -> backend path.
+    pub backend_write_buffer: Option<usize>,
+    /// How long the surviving direction may keep flowing after one side
+    /// half-closes; `None` waits until it ends on its own.
+    pub half_close_grace: Option<Duration>,
+    /// Max silence from the client (client -> backend) during the session.
+    pub client_idle_timeout: Option<Duration>,
+    /// Max silence from the backend (backend -> client) during the session.
+    pub backend_idle_timeout: Option<Duration>,
+    /// Max time a write to either peer may stay blocked during the session.
+    pub write_stall_timeout: Option<Duration>,
+    /// Max age of a session, measured from accept, regardless of activity.
+    pub max_connection_lifetime: Option<Duration>,
+    /// Add per-phase handshake timings to the decision trace. The phase
+    /// histograms are recorded either way.
+    pub phase_timings: bool,
+    /// Admission decision trace (no-op unless `trace_decisions` is enabled).
+    pub trace: DecisionTrace,
+    /// Per-connection access record, emitted when `handle_connection` ends
+    /// (no-op unless `access_log` is enabled).
+    pub access_log: AccessLog,
+    /// Name of the listener that accepted the connection: the `listener`
+    /// label of the rejection and backend metrics.
+    pub listener: String,
+}
+
+/// User property key carrying the edge identity to the broker.
+const EDGE_ID_PROPERTY: &str = "aegis-edge-id";
+
+/// Per-source admission checks the accept loop cannot run: all of them when
+/// the client address comes from a PROXY protocol header, and the rate limit
+/// when its buckets are in Redis, so the accept loop never waits on it.
+#[derive(Debug, Clone)]
+pub struct SourceChecks {
+    pub limit: Arc<LimitConfig>,
+    /// Whether the per-IP token bucket applies (`enable_rate_limiter`).
+    pub rate_limit: bool,
+    /// Whether the connect-ratio and repeated-malformed bans still apply.
+    pub bans: bool,
+    /// Address-keyed checks the accept loop skipped because it only saw the
+    /// load balancer; `None` unless `accept_proxy_protocol` is set.
+    pub proxied: Option<ProxiedChecks>,
+}
+
+/// Checks keyed on the client address that, behind a PROXY protocol load
+/// balancer, run on the decoded header instead of the socket peer.
+#[derive(Debug, Clone)]
+pub struct ProxiedChecks {
+    pub region_filter: Option<Arc<RegionFilter>>,
+    pub access_control: Option<Arc<AccessControl>>,
+    /// Whether to consult the installed `accept_filter` hook.
+    pub accept_filter: bool,
+    /// Picks the client's profile; `limit` and the inspection settings were
+    /// provisionally chosen for the load balancer.
+    pub source_policy: Arc<SourcePolicy>,
+    pub subnet_limiter: Option<Arc<SubnetLimiter>>,
+}
+
+/// A client connection the handler can inspect and forward: a plain socket,
+/// or a TLS session terminated by the proxy (`tls::TlsClient`). Inspection
+/// only ever sees the bytes this stream yields.
+pub trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    type ReadHalf: SpliceSocket + AsyncRead + Unpin + Send;
+    type WriteHalf: SpliceSocket + AsyncWrite + Unpin + Send;
+
+    /// The TCP socket underneath (addresses, socket options); `None` for a
+    /// Unix domain socket, whose peers have no address.
+    fn socket(&self) -> Option<&TcpStream>;
+
+    /// Copies bytes from the front of the stream into `buf` without
+    /// consuming them. Waits for at least one byte; `Ok(0)` means EOF.
+    fn peek(&mut self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send;
+
+    /// Splits the stream for forwarding once inspection is done.
+    fn into_split(self) -> (Self::ReadHalf, Self::WriteHalf);
+}
+
+impl ClientStream for TcpStream {
+    type ReadHalf = OwnedReadHalf;
+    type WriteHalf = OwnedWriteHalf;
+
+    fn socket(&self) -> Option<&TcpStream> {
+        Some(self)
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        TcpStream::peek(self, buf).await
+    }
+
+    fn into_split(self) -> (OwnedReadHalf, OwnedWriteHalf) {
+        TcpStream::into_split(self)
+    }
+}
+
+/// Protocol identified for a connection before it is routed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DetectedProtocol {
+    Mqtt,
+    Http,
+    WebSocket,
+    /// TLS ClientHello, forwarded unchanged when SNI routing is configured.
+    Tls,
+}
+
+impl DetectedProtocol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DetectedProtocol::Mqtt => "mqtt",
+            DetectedProtocol::Http => "http",
+            DetectedProtocol::WebSocket => "websocket",
+            DetectedProtocol::Tls => "tls",
+        }
+    }
+}
+
+impl ConnectionConfig {
+    /// Switches to `profile`'s inspection settings and limits, once the PROXY
+    /// header names the client the accept loop could not see.
+    fn apply_profile(&mut self, profile: &Profile) {
+        let features = &profile.features;
+        self.mqtt_inspect = features.enable_mqtt_inspection;
+        self.mqtt_full_inspect = features.enable_mqtt_full_inspection;
+        self.http_inspect = features.enable_http_inspection;
+        self.slowloris_protect = features.enable_slowloris_protection;
+        self.session_rate = profile.limit.session_rate.clone();
+        self.repeated_malformed = profile.limit.repeated_malformed.clone();
+        self.in_flight_connects = profile.limit.in_flight_connects.clone();
+        self.max_concurrent_per_ip = profile.limit.max_concurrent_per_ip;
+        if let Some(tags) = &mut self.tags {
+            tags.set_profile(&profile.name);
+        }
+    }
+
+    /// Hand the inspected bytes of a rejected connection to the capture sink.
+    fn capture(&self, reason: &'static str, client: &str, bytes: &[u8]) {
+        if let Some(capture) = &self.capture {
+            capture.record(reason, client, bytes);
+        }
+    }
+
+    /// Settings this connection runs under, as `key=value` pairs, for the
+    /// decision trace.
+    pub fn effective_summary(&self) -> String {
+        fn opt<T: std::fmt::Display>(value: Option<T>) -> String {
+            value.map_or_else(|| "none".to_string(), |v| v.to_string())
+        }
+        let sl = &self.slowloris_config;
+        format!(
+            "mqtt_inspect={} mqtt_full_inspect={} http_inspect={} mqtt_websocket={} \
+             slowloris_protect={} \
+             first_packet_timeout_ms={} packet_idle_timeout_ms={} connection_timeout_ms={} \
+             mqtt_connect_timeout_ms={} mqtt_fixed_header_timeout_ms={} \
+             mqtt_remaining_length_byte_timeout_ms={} mqtt_connect_payload_timeout_ms={} \
+             handshake_deadline_ms={} inspection_budget_ms={} \
+             min_throughput_bytes_per_sec={} \
+             max_connect_remaining={} \
+             max_header_line_size={} min_keep_alive_secs={} max_keep_alive_secs={} \
+             backend_write_timeout_ms={} \
+             slow_backend_connect_ms={} backend_unavailable_connack={} \
+             splice_forwarding={} backend_write_buffer={} half_close_grace_ms={} \
+             client_idle_timeout_ms={} backend_idle_timeout_ms={} proxy_idle_timeout_ms={} \
+             write_stall_timeout_ms={} \
+             in_flight_max_per_ip={} max_concurrent_per_ip={} max_client_id_len={} \
+             tcp_user_timeout_ms={} max_connection_lifetime_secs={} sni_routes={} \
+             publish_inspection={}",
+            self.mqtt_inspect,
+            self.mqtt_full_inspect,
+            self.http_inspect,
+            self.mqtt_websocket,
+            self.slowloris_protect,
+            sl.first_packet_timeout_ms,
+            sl.packet_idle_timeout_ms,
+            sl.connection_timeout_ms,
+            sl.mqtt_connect_timeout_ms,
+            sl.mqtt_fixed_header_timeout_ms,
+            sl.mqtt_remaining_length_byte_timeout_ms,
+            sl.mqtt_connect_payload_timeout_ms,
+            opt(sl.handshake_deadline_ms),
+            sl.inspection_budget_ms,
+            opt(sl.min_throughput_bytes_per_sec),
+            self.max_connect_remaining,
+            self.http_inspection.max_header_line_size,
+            opt(self.mqtt_policy.min_keep_alive_secs),
+            opt(self.mqtt_policy.max_keep_alive_secs),
+            self.backend_write_timeout_ms,
+            self.slow_backend_connect_ms,
+            self.backend_unavailable_connack,
+            self.splice_forwarding,
+            opt(self.backend_write_buffer),
+            opt(self.half_close_grace.map(|d| d.as_millis())),
+            opt(self.client_idle_timeout.map(|d| d.as_millis())),
+            opt(self.backend_idle_timeout.map(|d| d.as_millis())),
+            opt(sl.proxy_idle_timeout_ms),
+            opt(self.write_stall_timeout.map(|d| d.as_millis())),
+            opt(self.in_flight_connects.as_ref().map(|c| c.max_per_ip)),
+            opt(self.max_concurrent_per_ip),
+            opt(self.max_client_id_len),
+            opt(self.tcp_user_timeout.map(|d| d.as_millis())),
+            opt(self.max_connection_lifetime.map(|d| d.as_secs())),
+            self.sni_routes.as_ref().map_or(0, |routes| routes.len()),
+            self.publish_topics.is_some(),
+        )
+    }
+
+    /// Records a finished handshake phase that began at `started`.
+    fn phase_done(&self, phase: &'static str, started: Instant) {
+        let elapsed = started.elapsed();
+        crate::metrics::HANDSHAKE_PHASE_SECONDS
+            .with_label_values(&[phase])
+            .observe(elapsed.as_secs_f64());
+        if self.phase_timings {
+            self.trace.record_phase(phase, elapsed);
+        }
+    }
+
+    /// Backend mapped to `protocol` in the dispatch table, if any.
+    fn backend_for(&self, protocol: DetectedProtocol) -> Option<String> {
+        let backends = self.protocol_backends.as_ref()?;
+        match protocol {
+            DetectedProtocol::Mqtt => backends.mqtt.clone(),
+            DetectedProtocol::Http => backends.http.clone(),
+            DetectedProtocol::WebSocket => backends.websocket.clone(),
+            // Passthrough TLS is routed by `sni_routes`, not the table.
+            DetectedProtocol::Tls => None,
+        }
+    }
+}
+
+/// Reader adapter that keeps a copy of every byte consumed through it, so
+/// bytes read during inspection can be replayed to the chosen backend.
+struct RecordingReader<'a, R> {
+    inner: &'a mut R,
+    recorded: Vec<u8>,
+}
+
+impl<'a, R> RecordingReader<'a, R> {
+    fn new(inner: &'a mut R) -> Self {
+        Self {
+            inner,
+            recorded: Vec::new(),
+        }
+    }
+
+    fn into_recorded(self) -> Vec<u8> {
+        self.recorded
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for RecordingReader<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let res = Pin::new(&mut *this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = res {
+            this.recorded.extend_from_slice(&buf.filled()[before..]);
+        }
+        res
+    }
+}
+
+/// Reader adapter that frames the MQTT stream passing through it and fails
+/// the read once a packet breaks one of its checks.
+struct PacketInspector<'a, R> {
+    inner: &'a mut R,
+    checks: PacketChecks,
+    direction: &'static str,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for PacketInspector<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let res = Pin::new(&mut *this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = res {
+            if let Err(reason) = this.checks.observe(&buf.filled()[before..], this.direction) {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, reason)));
+            }
+        }
+        res
+    }
+}
+
+/// Per-packet checks on one direction of the forwarded session. Each tracker
+/// frames the stream itself, so both must start at a packet boundary.
+struct PacketChecks {
+    /// Packet size limit.
+    size: Option<PacketSizeTracker>,
+    /// PUBLISH topic rules and payload limit (client -> broker).
+    publish: Option<(PublishTracker, Option<Arc<TopicRules>>)>,
+    /// Listener the rejections are counted under.
+    listener: String,
+}
+
+impl PacketChecks {
+    fn new(listener: &str) -> Self {
+        Self {
+            size: None,
+            publish: None,
+            listener: listener.to_string(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.size.is_none() && self.publish.is_none()
+    }
+
+    /// Feeds the next bytes in `direction`. A failed check is logged and
+    /// counted here; the error ends the session.
+    fn observe(&mut self, bytes: &[u8], direction: &'static str) -> Result<(), &'static str> {
+        if let Some(tracker) = &mut self.size {
+            if let Err(e) = tracker.observe(bytes) {
+                crate::metrics::OVERSIZED_PACKET_REJECTIONS
+                    .with_label_values(&[direction])
+                    .inc();
+                crate::metrics::record_rejection(RejectReason::OversizedPacket, &self.listener);
+                warn!(direction, error = ?e, "Dropping session: MQTT packet over size limit");
+                return Err("MQTT packet exceeds maximum packet size");
+            }
+        }
+        if let Some((tracker, rules)) = &mut self.publish {
+            let permits = |topic: &str| rules.as_ref().is_none_or(|rules| rules.permits(topic));
+            match tracker.observe(bytes, permits) {
+                Ok(()) => {}
+                Err(PublishError::Denied(topic)) => {
+                    crate::metrics::record_rejection(
+                        RejectReason::PublishTopicDenied,
+                        &self.listener,
+                    );
+                    warn!(direction, topic = %topic, "Dropping session: PUBLISH to a refused topic");
+                    return Err("PUBLISH to a refused topic");
+                }
+                Err(PublishError::PayloadTooLarge(len)) => {
+                    crate::metrics::OVERSIZED_PUBLISH.inc();
+                    crate::metrics::record_rejection(
+                        RejectReason::OversizedPublish,
+                        &self.listener,
+                    );
+                    warn!(
+                        direction,
+                        len, "Dropping session: PUBLISH payload over size limit"
+                    );
+                    return Err("PUBLISH payload exceeds maximum size");
+                }
+                Err(PublishError::Malformed) => {
+                    crate::metrics::record_rejection(
+                        RejectReason::MalformedPublish,
+                        &self.listener,
+                    );
+                    warn!(direction, "Dropping session: malformed PUBLISH");
+                    return Err("malformed PUBLISH");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Packet checks for the forwarded session, per direction.
+struct PacketLimits {
+    /// Client -> broker: `max_packet_size`, PUBLISH topic rules and
+    /// `max_publish_payload`.
+    upstream: PacketChecks,
+    /// Broker -> client: the client's advertised Maximum Packet Size.
+    downstream: PacketChecks,
+}
+
+impl PacketLimits {
+    /// No checks in either direction until the CONNECT sets them.
+    fn new(listener: &str) -> Self {
+        Self {
+            upstream: PacketChecks::new(listener),
+            downstream: PacketChecks::new(listener),
+        }
+    }
 }
 
 struct ProxyConnectionGuard;
@@ -35,19 +558,342 @@ impl Drop for ProxyConnectionGuard {
     }
 }
 
-/// Read one byte (fixed header) from the client with timeout.
-async fn read_fixed_header(
-    source: &mut TcpStream,
+/// Copies one direction of the session and half-closes the writer on EOF, so
+/// the opposite direction keeps flowing.
+///
+/// With `write_buffer`, writes go through a `BufWriter` of that capacity so
+/// data that is already available is batched into fewer syscalls; `io::copy`
+/// flushes whenever the reader has nothing more to give, so sparse traffic is
+/// not delayed. Splicing bypasses userspace and ignores the buffer.
+///
+/// When `checks` holds a packet size limit or PUBLISH topic and payload
+/// rules, every byte must be framed, so the direction is copied in userspace
+/// even when splicing is enabled.
+async fn pump<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    direction: &'static str,
+    splice: bool,
+    write_buffer: Option<usize>,
+    checks: PacketChecks,
+    timeouts: PumpTimeouts,
+) -> io::Result<u64>
+where
+    R: SpliceSocket + AsyncRead + Unpin,
+    W: SpliceSocket + AsyncWrite + Unpin,
+{
+    // Splicing bypasses userspace, so packet checks and timeouts all need
+    // the copy path.
+    let res = if !checks.is_empty() {
+        let mut inspected = PacketInspector {
+            inner: reader,
+            checks,
+            direction,
+        };
+        copy_userspace(&mut inspected, writer, write_buffer, timeouts).await
+    } else if splice && timeouts.is_empty() {
+        splice_copy(reader, writer).await
+    } else {
+        copy_userspace(reader, writer, write_buffer, timeouts).await
+    };
+    let n = match res {
+        Ok(n) => n,
+        Err(e) => {
+            if WriteStalled::is(&e) {
+                debug!(direction, "Session write stalled; peer stopped reading");
+            } else if e.kind() == io::ErrorKind::TimedOut {
+                debug!(direction, "Session idle timeout");
+                crate::metrics::SESSION_IDLE_TIMEOUTS
+                    .with_label_values(&[direction])
+                    .inc();
+            }
+            return Err(e);
+        }
+    };
+    crate::metrics::FORWARDED_BYTES.inc_by(n);
+    match direction {
+        "upstream" => crate::metrics::BYTES_CLIENT_TO_BACKEND.inc_by(n),
+        _ => crate::metrics::BYTES_BACKEND_TO_CLIENT.inc_by(n),
+    }
+    writer.shutdown().await?;
+    Ok(n)
+}
+
+/// Timeouts on one direction of a forwarded session.
+#[derive(Debug, Clone, Copy, Default)]
+struct PumpTimeouts {
+    /// Max silence from the reader.
+    idle: Option<Duration>,
+    /// Max time a write may stay blocked on the writer.
+    write_stall: Option<Duration>,
+}
+
+impl PumpTimeouts {
+    fn is_empty(&self) -> bool {
+        self.idle.is_none() && self.write_stall.is_none()
+    }
+}
+
+async fn copy_userspace<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    write_buffer: Option<usize>,
+    timeouts: PumpTimeouts,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    match timeouts.write_stall {
+        Some(stall) => {
+            let mut writer = TimeoutWriter::new(writer, stall);
+            copy_idle(reader, &mut writer, write_buffer, timeouts.idle).await
+        }
+        None => copy_idle(reader, writer, write_buffer, timeouts.idle).await,
+    }
+}
+
+async fn copy_idle<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    write_buffer: Option<usize>,
+    idle: Option<Duration>,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    match idle {
+        Some(idle) => {
+            copy_buffered(&mut TimeoutReader::new(reader, idle), writer, write_buffer).await
+        }
+        None => copy_buffered(reader, writer, write_buffer).await,
+    }
+}
+
+async fn copy_buffered<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    write_buffer: Option<usize>,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    match write_buffer {
+        Some(capacity) => {
+            let mut buffered = BufWriter::with_capacity(capacity, &mut *writer);
+            io::copy(reader, &mut buffered).await
+        }
+        None => io::copy(reader, writer).await,
+    }
+}
+
+/// Client idle timeout for a forwarded session. When one is configured and
+/// the CONNECT carried a non-zero keep-alive, it becomes 1.5x the keep-alive
+/// (the grace MQTT gives a silent client), so a client that pings on its own
+/// schedule is never cut off; otherwise the configured value applies.
+fn session_client_idle(configured: Option<Duration>, keep_alive: Option<u16>) -> Option<Duration> {
+    let configured = configured?;
+    match keep_alive {
+        Some(secs) if secs > 0 => Some(Duration::from_millis(u64::from(secs) * 1500)),
+        _ => Some(configured),
+    }
+}
+
+/// Session-wide idle timeout: applies only under slowloris protection.
+fn session_proxy_idle(config: &ConnectionConfig) -> Option<Duration> {
+    if !config.slowloris_protect {
+        return None;
+    }
+    config
+        .slowloris_config
+        .proxy_idle_timeout_ms
+        .map(Duration::from_millis)
+}
+
+/// Forwards both directions until each has ended.
+///
+/// A clean EOF on one side only half-closes the peer (e.g. a client that sends
+/// CONNECT and shuts down its write half still receives retained messages);
+/// an error in either direction tears the whole session down, as does a
+/// session with no bytes either way for `proxy_idle_timeout_ms`.
+async fn forward_session<CR, CW>(
+    source: (&mut CR, &mut CW),
+    target: (&mut BackendReader, &mut BackendWriter),
+    config: &ConnectionConfig,
+    limits: PacketLimits,
+    client_idle: Option<Duration>,
+) where
+    CR: SpliceSocket + AsyncRead + Unpin + Send,
+    CW: SpliceSocket + AsyncWrite + Unpin + Send,
+{
+    let proxy_idle = session_proxy_idle(config);
+    let activity = proxy_idle.map(|_| SessionActivity::new());
+    let mut source_read = ActivityReader::new(source.0, activity.as_ref());
+    let mut target_read = ActivityReader::new(target.0, activity.as_ref());
+    // Splicing would bypass the activity readers.
+    let splice = config.splice_forwarding && proxy_idle.is_none();
+    let idle_watch = async {
+        match (&activity, proxy_idle) {
+            (Some(activity), Some(window)) => activity.idle(window).await,
+            _ => std::future::pending().await,
+        }
+    };
+
+    let upstream = pump(
+        &mut source_read,
+        target.1,
+        "upstream",
+        splice,
+        config.backend_write_buffer,
+        limits.upstream,
+        PumpTimeouts {
+            idle: client_idle,
+            write_stall: config.write_stall_timeout,
+        },
+    );
+    let downstream = pump(
+        &mut target_read,
+        source.1,
+        "downstream",
+        splice,
+        None,
+        limits.downstream,
+        PumpTimeouts {
+            idle: config.backend_idle_timeout,
+            write_stall: config.write_stall_timeout,
+        },
+    );
+    tokio::pin!(upstream, downstream, idle_watch);
+
+    // The directions differ in type once the client side is not a plain
+    // socket, so the one still running is awaited as a trait object.
+    // Only directions that end cleanly report a byte count, as for
+    // `aegis_forwarded_bytes_total`.
+    let (closed_by, remaining): (_, Pin<&mut (dyn Future<Output = io::Result<u64>> + Send)>) = tokio::select! {
+        res = &mut upstream => match res {
+            Ok(n) => {
+                config.access_log.add_bytes("upstream", n);
+                ("client", downstream.as_mut())
+            }
+            Err(_) => return,
+        },
+        res = &mut downstream => match res {
+            Ok(n) => {
+                config.access_log.add_bytes("downstream", n);
+                ("backend", upstream.as_mut())
+            }
+            Err(_) => return,
+        },
+        _ = &mut idle_watch => return idle_disconnect(proxy_idle),
+    };
+    let remaining_direction = match closed_by {
+        "client" => "downstream",
+        _ => "upstream",
+    };
+
+    debug!(closed_by, "Half-close, waiting for the other direction");
+    let drain = async {
+        let res = match config.half_close_grace {
+            Some(grace) => match timeout(grace, remaining).await {
+                Ok(res) => res,
+                Err(_) => {
+                    debug!(closed_by, "Half-close grace elapsed");
+                    return;
+                }
+            },
+            None => remaining.await,
+        };
+        if let Ok(n) = res {
+            config.access_log.add_bytes(remaining_direction, n);
+        }
+    };
+    tokio::select! {
+        _ = drain => {}
+        _ = &mut idle_watch => idle_disconnect(proxy_idle),
+    }
+}
+
+fn idle_disconnect(window: Option<Duration>) {
+    debug!(
+        idle_ms = window.map_or(0, |w| w.as_millis() as u64),
+        "Session idle in both directions; closing"
+    );
+    crate::metrics::IDLE_DISCONNECTS.inc();
+}
+
+/// Overall accept -> forwarding-start budget shared by every handshake phase.
+///
+/// Each phase caps its own timeout with `cap`, so the total setup time can
+/// never exceed the deadline however the client paces its bytes. If the
+/// handler bails out after the deadline passed without reaching `complete`,
+/// the drop records a `HANDSHAKE_DEADLINE` rejection.
+struct HandshakeDeadline {
+    deadline: Option<Instant>,
+    client: String,
+    listener: String,
+    completed: bool,
+}
+
+impl HandshakeDeadline {
+    fn new(limit: Option<Duration>, client: &str, listener: &str) -> Self {
+        Self {
+            deadline: limit.map(|d| Instant::now() + d),
+            client: client.to_string(),
+            listener: listener.to_string(),
+            completed: false,
+        }
+    }
+
+    /// Shrink a phase timeout to whatever remains of the overall budget.
+    fn cap(&self, phase: Duration) -> Duration {
+        match self.deadline {
+            Some(deadline) => phase.min(deadline.saturating_duration_since(Instant::now())),
+            None => phase,
+        }
+    }
+
+    /// Whether the overall budget is used up.
+    fn expired(&self) -> bool {
+        matches!(self.deadline, Some(deadline) if Instant::now() >= deadline)
+    }
+
+    /// Mark the handshake as finished; forwarding is about to start.
+    fn complete(&mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for HandshakeDeadline {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        if self.expired() {
+            warn!(client = %self.client, "Handshake deadline exceeded");
+            crate::metrics::HANDSHAKE_DEADLINE_REJECTIONS.inc();
+            crate::metrics::record_rejection(RejectReason::HandshakeDeadline, &self.listener);
+        }
+    }
+}
+
+/// Read one byte (fixed header) from the client within `limit`.
+async fn read_fixed_header<R: AsyncRead + Unpin>(
+    source: &mut R,
+    limit: Duration,
+    deadline: &HandshakeDeadline,
+    listener: &str,
 ) -> Result<u8, Box<dyn std::error::Error + Send + Sync>> {
     let mut fixed = [0u8; 1];
-    match timeout(Duration::from_secs(3), source.read_exact(&mut fixed)).await {
+    match timeout(deadline.cap(limit), source.read_exact(&mut fixed)).await {
         Ok(Ok(_)) => Ok(fixed[0]),
         Ok(Err(e)) => {
-            crate::metrics::PROTOCOL_REJECTIONS.inc();
+            crate::metrics::record_rejection(RejectReason::FixedHeaderRead, listener);
             Err(Box::new(e))
         }
         Err(_) => {
-            crate::metrics::PROTOCOL_REJECTIONS.inc();
+            crate::metrics::record_rejection(RejectReason::FixedHeaderRead, listener);
             Err("timeout reading fixed header".into())
         }
     }
@@ -55,110 +901,526 @@ async fn read_fixed_header(
 
 /// Read Remaining Length bytes from the client, returning the bytes and decoded length.
 /// The caller provides `max_allowed` to guard against excessively large Remaining Lengths
-/// (prevents large allocations during CONNECT inspection).
-async fn read_remaining_length(
-    source: &mut TcpStream,
+/// (prevents large allocations during CONNECT inspection), and `per_byte` bounds the
+/// wait for each byte.
+async fn read_remaining_length<R: AsyncRead + Unpin>(
+    source: &mut R,
     max_allowed: usize,
+    per_byte: Duration,
+    deadline: &HandshakeDeadline,
+    listener: &str,
 ) -> Result<(Vec<u8>, usize), Box<dyn std::error::Error + Send + Sync>> {
     let mut rl_bytes: Vec<u8> = Vec::with_capacity(4);
     for _ in 0..4 {
         let mut b = [0u8; 1];
-        match timeout(Duration::from_secs(1), source.read_exact(&mut b)).await {
+        match timeout(deadline.cap(per_byte), source.read_exact(&mut b)).await {
             Ok(Ok(_)) => {
                 rl_bytes.push(b[0]);
                 match mqtt::decode_remaining_length(&rl_bytes) {
                     Ok((v, _used)) => {
                         // Enforce maximum allowed remaining length for CONNECT inspection.
                         if v > max_allowed {
-                            crate::metrics::PROTOCOL_REJECTIONS.inc();
+                            crate::metrics::record_rejection(
+                                RejectReason::RemainingLengthTooLarge,
+                                listener,
+                            );
                             warn!(
                                 "Rejected CONNECT: remaining length {} exceeds max allowed {}",
                                 v, max_allowed
                             );
                             return Err("remaining length too large".into());
                         }
+                        crate::metrics::CONNECT_REMAINING_LENGTH.observe(v as f64);
                         return Ok((rl_bytes, v));
                     }
                     Err("Incomplete") => continue,
                     Err(_) => {
-                        crate::metrics::PROTOCOL_REJECTIONS.inc();
+                        crate::metrics::record_rejection(
+                            RejectReason::MalformedRemainingLength,
+                            listener,
+                        );
                         return Err("malformed remaining length".into());
                     }
                 }
             }
             Ok(Err(e)) => {
-                crate::metrics::PROTOCOL_REJECTIONS.inc();
+                crate::metrics::record_rejection(RejectReason::RemainingLengthRead, listener);
                 return Err(Box::new(e));
             }
             Err(_) => {
-                crate::metrics::PROTOCOL_REJECTIONS.inc();
+                crate::metrics::record_rejection(RejectReason::RemainingLengthRead, listener);
                 return Err("timeout reading remaining length".into());
             }
         }
     }
-    crate::metrics::PROTOCOL_REJECTIONS.inc();
+    crate::metrics::record_rejection(RejectReason::MalformedRemainingLength, listener);
     Err("incomplete remaining length".into())
 }
 
-/// Read `len` bytes of payload from the client with timeout.
-async fn read_payload(
-    source: &mut TcpStream,
+/// Read `len` bytes of payload from the client within `limit`, and at no less
+/// than `min_throughput` bytes per second when set.
+async fn read_payload<R: AsyncRead + Unpin>(
+    source: &mut R,
     len: usize,
+    limit: Duration,
+    deadline: &HandshakeDeadline,
+    min_throughput: Option<u64>,
+    listener: &str,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
     if len == 0 {
         return Ok(Vec::new());
     }
     let mut payload = vec![0u8; len];
-    match timeout(Duration::from_secs(5), source.read_exact(&mut payload)).await {
+    let limit = deadline.cap(limit);
+    if let Some(floor) = min_throughput {
+        return match read_with_throughput_floor(source, &mut payload, limit, limit, floor).await {
+            Ok(n) if n == len => Ok(payload),
+            Ok(_) => {
+                crate::metrics::record_rejection(RejectReason::ConnectPayloadRead, listener);
+                Err("EOF reading payload".into())
+            }
+            Err(e) if ThroughputTooLow::is(&e) => {
+                warn!(
+                    floor,
+                    "CONNECT payload below the minimum throughput (Slowloris)"
+                );
+                crate::metrics::record_rejection(RejectReason::ConnectThroughputTooLow, listener);
+                Err(Box::new(e))
+            }
+            Err(e) => {
+                crate::metrics::record_rejection(RejectReason::ConnectPayloadRead, listener);
+                Err(Box::new(e))
+            }
+        };
+    }
+    match timeout(limit, source.read_exact(&mut payload)).await {
         Ok(Ok(_)) => Ok(payload),
         Ok(Err(e)) => {
-            crate::metrics::PROTOCOL_REJECTIONS.inc();
+            crate::metrics::record_rejection(RejectReason::ConnectPayloadRead, listener);
             Err(Box::new(e))
         }
         Err(_) => {
-            crate::metrics::PROTOCOL_REJECTIONS.inc();
+            crate::metrics::record_rejection(RejectReason::ConnectPayloadRead, listener);
             Err("timeout reading payload".into())
         }
     }
 }
 
-/// Minimal CONNECT variable-header validation.
-fn validate_connect_variable_header(payload: &[u8]) -> bool {
-    payload.len() >= 6 && payload[0] == 0x00 && payload[1] == 0x04 && &payload[2..6] == b"MQTT"
+/// A CONNECT received complete in a single read.
+struct SingleSegmentConnect {
+    /// The exact CONNECT frame (fixed header, Remaining Length, body).
+    frame: Vec<u8>,
+    /// The CONNECT body (variable header + payload).
+    payload: Vec<u8>,
+    /// Any bytes the client sent after the CONNECT in the same segment.
+    trailing: Vec<u8>,
+}
+
+enum SingleSegmentError {
+    /// The CONNECT did not arrive complete in one read; carries the bytes
+    /// that did.
+    Fragmented(&'static str, Vec<u8>),
+    /// The segment is not an acceptable CONNECT at all.
+    Protocol(&'static str),
 }
 
-/// Connect to backend broker with timeout.
+/// Read one TLS handshake record, expected to hold the ClientHello.
+///
+/// The record is returned whole, header included, so it can be replayed to
+/// the backend unchanged.
+async fn read_client_hello<R: AsyncRead + Unpin>(source: &mut R) -> Result<Vec<u8>, &'static str> {
+    let mut record = vec![0u8; tls::RECORD_HEADER_LEN];
+    source
+        .read_exact(&mut record)
+        .await
+        .map_err(|_| "EOF before TLS record header")?;
+    let len = tls::handshake_record_len(&record).ok_or("not a TLS handshake record")?;
+    record.resize(tls::RECORD_HEADER_LEN + len, 0);
+    source
+        .read_exact(&mut record[tls::RECORD_HEADER_LEN..])
+        .await
+        .map_err(|_| "EOF inside TLS ClientHello")?;
+    Ok(record)
+}
+
+/// First read of a single-segment CONNECT; enough for a typical device
+/// CONNECT, grown once the Remaining Length is known.
+const SINGLE_SEGMENT_READ: usize = 512;
+
+/// Read the whole CONNECT with a single read within `window`.
+///
+/// Used by the strict anti-Slowloris mode for device fleets that always send
+/// their CONNECT in one TCP segment: anything that needs a second read is
+/// rejected as fragmented. The first read is small; when it fills up, the
+/// rest of the frame is taken only from what has already arrived.
+async fn read_single_segment_connect<R: AsyncRead + Unpin>(
+    source: &mut R,
+    max_allowed: usize,
+    window: Duration,
+) -> Result<SingleSegmentConnect, SingleSegmentError> {
+    let mut buf = vec![0u8; SINGLE_SEGMENT_READ];
+    let n = match timeout(window, source.read(&mut buf)).await {
+        Ok(Ok(n)) if n > 0 => n,
+        Ok(Ok(_)) => return Err(SingleSegmentError::Protocol("EOF before CONNECT")),
+        Ok(Err(_)) => return Err(SingleSegmentError::Protocol("read error")),
+        Err(_) => {
+            return Err(SingleSegmentError::Fragmented(
+                "no CONNECT within window",
+                Vec::new(),
+            ))
+        }
+    };
+    let filled = n == buf.len();
+    buf.truncate(n);
+
+    if mqtt::inspect_packet(&buf) != MqttPacketType::Connect {
+        return Err(SingleSegmentError::Protocol("expected CONNECT"));
+    }
+    let (remaining, rl_used) = match mqtt::decode_remaining_length(&buf[1..]) {
+        Ok(v) => v,
+        Err("Incomplete") => {
+            return Err(SingleSegmentError::Fragmented(
+                "split remaining length",
+                buf,
+            ))
+        }
+        Err(_) => return Err(SingleSegmentError::Protocol("malformed remaining length")),
+    };
+    if remaining > max_allowed {
+        return Err(SingleSegmentError::Protocol("remaining length too large"));
+    }
+    crate::metrics::CONNECT_REMAINING_LENGTH.observe(remaining as f64);
+    let body_start = 1 + rl_used;
+    let frame_end = body_start + remaining;
+    if filled && buf.len() < frame_end {
+        // The segment was larger than the first read: take the rest of the
+        // frame, but only what is already buffered.
+        let mut read = buf.len();
+        buf.resize(frame_end, 0);
+        while read < frame_end {
+            match timeout(Duration::ZERO, source.read(&mut buf[read..])).await {
+                Ok(Ok(n)) if n > 0 => read += n,
+                _ => break,
+            }
+        }
+        buf.truncate(read);
+    }
+    if buf.len() < frame_end {
+        return Err(SingleSegmentError::Fragmented(
+            "CONNECT split across reads",
+            buf,
+        ));
+    }
+
+    let trailing = buf.split_off(frame_end);
+    let payload = buf[body_start..].to_vec();
+    Ok(SingleSegmentConnect {
+        frame: buf,
+        payload,
+        trailing,
+    })
+}
+
+/// Feeds a rejected malformed CONNECT to the repeat detector, which bans the
+/// client once it keeps sending the same bytes.
+fn note_malformed(
+    peer: Option<SocketAddr>,
+    config: &ConnectionConfig,
+    client: &str,
+    packet: &[u8],
+) {
+    let (Some(repeated), Some(peer)) = (&config.repeated_malformed, peer) else {
+        return;
+    };
+    if let Some(hash) = record_malformed(peer.ip(), packet, repeated) {
+        let hash = format!("{:016x}", hash);
+        warn!(
+            client = %client,
+            hash = %hash,
+            ban_secs = repeated.ban_secs,
+            "Banning client: repeated identical malformed CONNECT"
+        );
+        crate::metrics::REPEATED_MALFORMED
+            .with_label_values(&[hash.as_str()])
+            .inc();
+    }
+}
+
+/// Rejects a connection whose inspection ran out of its time budget.
+fn reject_over_budget(
+    config: &ConnectionConfig,
+    client: &str,
+    budget: &InspectionBudget,
+    inspected: &[u8],
+) {
+    warn!(
+        client = %client,
+        spent_ms = budget.spent().as_millis() as u64,
+        "Inspection exceeded its time budget"
+    );
+    crate::metrics::INSPECTION_BUDGET_EXCEEDED.inc();
+    crate::metrics::record_rejection(RejectReason::InspectionBudget, &config.listener);
+    config.capture("inspection_budget", client, inspected);
+}
+
+/// Meters the CONNECT keep-alive and applies `min_keep_alive_secs` /
+/// `max_keep_alive_secs`, clamping `frame` in place when configured to. A
+/// rejection carries a reason fit for the client.
+fn enforce_keep_alive(
+    frame: &mut [u8],
+    policy: &MqttPolicyConfig,
+    client_peer: &str,
+    listener: &str,
+) -> Result<(), String> {
+    let Some(keep_alive) = mqtt::connect_keep_alive(frame) else {
+        return Ok(());
+    };
+    crate::metrics::CONNECT_KEEP_ALIVE.observe(f64::from(keep_alive));
+
+    let min = policy.min_keep_alive_secs.unwrap_or(1).max(1);
+    let max = policy.max_keep_alive_secs.unwrap_or(u16::MAX);
+    let bounded = if keep_alive == 0 {
+        // Zero ("never time out") is outside the band whichever bound is
+        // set: clamped down to the maximum, or else up to the minimum.
+        policy
+            .max_keep_alive_secs
+            .or(policy.min_keep_alive_secs.map(|_| min))
+    } else if keep_alive < min {
+        Some(min)
+    } else if keep_alive > max {
+        Some(max)
+    } else {
+        None
+    };
+    let Some(bound) = bounded else {
+        return Ok(());
+    };
+
+    match policy.keep_alive_action {
+        KeepAliveAction::Reject => {
+            warn!(client = %client_peer, keep_alive, min, max, "Rejected CONNECT: keep-alive outside limits");
+            crate::metrics::KEEP_ALIVE_ENFORCED
+                .with_label_values(&["reject"])
+                .inc();
+            crate::metrics::record_rejection(RejectReason::KeepAlive, listener);
+            Err(format!(
+                "keep alive must be between {} and {} seconds",
+                min, max
+            ))
+        }
+        KeepAliveAction::Clamp => {
+            debug!(client = %client_peer, keep_alive, bound, "Clamping CONNECT keep-alive");
+            mqtt::set_connect_keep_alive(frame, bound);
+            crate::metrics::KEEP_ALIVE_ENFORCED
+                .with_label_values(&["clamp"])
+                .inc();
+            Ok(())
+        }
+    }
+}
+
+/// Checks the CONNECT client identifier against the configured limits.
+/// `payload` starts right after the fixed header.
+fn check_client_id(
+    payload: &[u8],
+    config: &ConnectionConfig,
+) -> Result<(), (ConnackRefusal, &'static str)> {
+    let Some(client_id) = mqtt::extract_client_id(payload) else {
+        return Err((
+            ConnackRefusal::MalformedPacket,
+            "client identifier missing or not UTF-8",
+        ));
+    };
+    if config
+        .max_client_id_len
+        .is_some_and(|max| client_id.len() > max)
+    {
+        return Err((
+            ConnackRefusal::PolicyViolation,
+            "client identifier too long",
+        ));
+    }
+    if client_id.is_empty()
+        && config.require_client_id_for_persistent_session
+        && mqtt::connect_clean_session(payload) == Some(false)
+    {
+        return Err((
+            ConnackRefusal::PolicyViolation,
+            "empty client identifier requires clean session",
+        ));
+    }
+    Ok(())
+}
+
+/// Sends a CONNACK refusing a client that spoke `protocol_level`, then
+/// closes the stream. MQTT 5.0 clients also get `reason` as a reason string.
+/// Returns whether a CONNACK was written; levels without a code for
+/// `refusal` are only closed.
+pub async fn send_connack<W: AsyncWrite + Unpin>(
+    stream: &mut W,
+    protocol_level: u8,
+    refusal: ConnackRefusal,
+    reason: Option<&str>,
+) -> bool {
+    let sent = match mqtt::encode_connack_with_reason(protocol_level, refusal, reason) {
+        Some(connack) => matches!(
+            timeout(REJECT_CONNACK_TIMEOUT, stream.write_all(&connack)).await,
+            Ok(Ok(()))
+        ),
+        None => false,
+    };
+    let _ = stream.shutdown().await;
+    sent
+}
+
+/// Which `reject_connack_categories` entry covers `refusal`.
+fn reject_category(refusal: ConnackRefusal) -> Option<RejectCategory> {
+    match refusal {
+        ConnackRefusal::MalformedPacket => Some(RejectCategory::Malformed),
+        ConnackRefusal::UnsupportedProtocolVersion => {
+            Some(RejectCategory::UnsupportedProtocolVersion)
+        }
+        ConnackRefusal::PolicyViolation => Some(RejectCategory::PolicyViolation),
+        ConnackRefusal::NotAuthorized => Some(RejectCategory::NotAuthorized),
+        ConnackRefusal::ServerBusy | ConnackRefusal::ServerUnavailable => None,
+    }
+}
+
+/// Tells a refused MQTT client why before the connection is closed: any
+/// client whose rejection category is in `reject_connack_categories`, and
+/// MQTT 5.0 clients under `send_connack_on_reject`. Others are closed
+/// silently.
+async fn send_reject_connack<W: AsyncWrite + Unpin>(
+    source: &mut W,
+    config: &ConnectionConfig,
+    frame: &[u8],
+    refusal: ConnackRefusal,
+    reason: &str,
+) {
+    let policy = &config.mqtt_policy;
+    let level = mqtt::connect_protocol_level(frame);
+    let send = reject_category(refusal)
+        .is_some_and(|category| policy.reject_connack_categories.contains(&category))
+        || (policy.send_connack_on_reject && level == Some(5));
+    if !send {
+        return;
+    }
+    if send_connack(source, level.unwrap_or(4), refusal, Some(reason)).await {
+        crate::metrics::REJECT_CONNACKS_SENT.inc();
+    }
+}
+
+/// With `backend_unavailable: connack`, tells an MQTT client no backend could
+/// be reached before the connection is closed. `consumed` holds what was
+/// already read from the client; when empty, the CONNECT is peeked instead.
+async fn send_unavailable_connack<S: ClientStream>(source: &mut S, consumed: &[u8]) {
+    let mut peek_buf = [0u8; 16];
+    let seen = if consumed.is_empty() {
+        match timeout(REJECT_CONNACK_TIMEOUT, source.peek(&mut peek_buf)).await {
+            Ok(Ok(n)) => &peek_buf[..n],
+            _ => return,
+        }
+    } else {
+        consumed
+    };
+    if mqtt::inspect_packet(seen) != MqttPacketType::Connect {
+        return;
+    }
+    let level = mqtt::connect_protocol_level(seen).unwrap_or(4);
+    send_connack(source, level, ConnackRefusal::ServerUnavailable, None).await;
+}
+
+/// Connect to the first reachable backend of `candidates` with timeout,
+/// warning about connects slower than `slow_threshold`. Only failures that
+/// may clear up on another broker (refused, timeout, unreachable) move on to
+/// the next candidate. Candidates whose circuit is open in `health` are
+/// skipped. Returns the stream and the index of the backend it reached.
 async fn connect_backend(
-    target_addr: &str,
+    candidates: &[String],
     client_peer: &str,
-) -> Result<TcpStream, Box<dyn std::error::Error + Send + Sync>> {
-    debug!(
-        "Attempting backend connect to {} for client {}",
-        target_addr, client_peer
-    );
-    match timeout(Duration::from_secs(5), TcpStream::connect(target_addr)).await {
-        Ok(stream) => {
-            let s = stream?;
-            debug!(
-                "Successfully connected to backend {} for client {}",
-                target_addr, client_peer
-            );
-            Ok(s)
+    deadline: &HandshakeDeadline,
+    slow_threshold: Duration,
+    health: Option<&BackendHealth>,
+    listener: &str,
+) -> Result<(BackendStream, usize), BackendError> {
+    let mut last_error = None;
+    for (index, target_addr) in candidates.iter().enumerate() {
+        if health.is_some_and(|h| !h.allow(target_addr)) {
+            debug!(client = %client_peer, backend = %target_addr, "Skipping backend with open circuit");
+            continue;
         }
-        Err(_) => {
-            warn!(
-                "Could not connect to backend at {} (connect timeout) for client {}",
-                target_addr, client_peer
-            );
-            Err("backend connect timeout".into())
+        debug!(
+            "Attempting backend connect to {} for client {}",
+            target_addr, client_peer
+        );
+        let started = Instant::now();
+        match backend::connect(target_addr, deadline.cap(Duration::from_secs(5))).await {
+            Ok(s) => {
+                let elapsed = started.elapsed();
+                if elapsed >= slow_threshold {
+                    warn!(
+                        client = %client_peer,
+                        backend = %target_addr,
+                        connect_ms = elapsed.as_millis() as u64,
+                        "Slow backend connect"
+                    );
+                } else {
+                    debug!(
+                        "Successfully connected to backend {} for client {}",
+                        target_addr, client_peer
+                    );
+                }
+                if let Some(health) = health {
+                    health.record_success(target_addr);
+                }
+                return Ok((s, index));
+            }
+            Err(e) => {
+                crate::metrics::BACKEND_ERRORS
+                    .with_label_values(&[e.class.as_str(), listener])
+                    .inc();
+                if let Some(health) = health {
+                    health.record_failure(target_addr);
+                }
+                let last = index + 1 == candidates.len();
+                if last || !e.class.is_retryable() || deadline.expired() {
+                    return Err(e);
+                }
+                warn!(
+                    client = %client_peer,
+                    backend = %target_addr,
+                    class = e.class.as_str(),
+                    "Backend connect failed; trying the next backend"
+                );
+                last_error = Some(e);
+            }
         }
     }
+    // Every remaining candidate was skipped for an open circuit.
+    Err(last_error.unwrap_or_else(|| {
+        crate::metrics::BACKEND_ERRORS
+            .with_label_values(&[BackendErrorClass::CircuitOpen.as_str(), listener])
+            .inc();
+        BackendError {
+            class: BackendErrorClass::CircuitOpen,
+            source: None,
+        }
+    }))
 }
 
+/// Size of the writes that replay the initial bytes to the backend.
+const INITIAL_FORWARD_CHUNK: usize = 4096;
+
 /// Forward initial bytes (already-consumed CONNECT frame) to backend.
+///
+/// The bytes go out in `INITIAL_FORWARD_CHUNK` writes so a backend that
+/// stops reading is noticed part-way (with the count already written), the
+/// whole replay is bounded by `write_timeout`, and `shutdown` aborts it.
 async fn forward_initial_bytes(
-    target_write: &mut OwnedWriteHalf,
+    target_write: &mut BackendWriter,
     initial_bytes: &[u8],
+    write_timeout: Duration,
+    shutdown: Option<&CancellationToken>,
     target_addr: &str,
     client_peer: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -178,13 +1440,38 @@ async fn forward_initial_bytes(
         client_peer,
         preview
     );
-    match timeout(
-        Duration::from_secs(3),
-        target_write.write_all(initial_bytes),
-    )
-    .await
-    {
-        Ok(Ok(_)) => {
+    let mut written = 0;
+    let replay = async {
+        let mut writer = TimeoutWriter::new(&mut *target_write, write_timeout);
+        for chunk in initial_bytes.chunks(INITIAL_FORWARD_CHUNK) {
+            writer.write_all(chunk).await?;
+            written += chunk.len();
+        }
+        writer.flush().await
+    };
+    let cancelled = async {
+        match shutdown {
+            Some(token) => token.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+    let result = tokio::select! {
+        result = timeout(write_timeout, replay) => {
+            result.unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+        }
+        _ = cancelled => {
+            debug!(
+                "Shutdown while forwarding initial bytes to backend {} for client {} ({}/{} written)",
+                target_addr,
+                client_peer,
+                written,
+                initial_bytes.len()
+            );
+            return Err("shutdown while writing initial bytes".into());
+        }
+    };
+    match result {
+        Ok(()) => {
             debug!(
                 "Successfully forwarded {} initial bytes to backend {} for client {}",
                 initial_bytes.len(),
@@ -193,122 +1480,633 @@ async fn forward_initial_bytes(
             );
             Ok(())
         }
-        Ok(Err(e)) => {
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => {
             warn!(
-                "Error writing initial CONNECT bytes to backend {} for client {}: {}",
-                target_addr, client_peer, e
+                "Timeout writing initial CONNECT bytes to backend {} for client {} ({}/{} written)",
+                target_addr,
+                client_peer,
+                written,
+                initial_bytes.len()
             );
             debug!(
                 "Initial bytes length: {}, preview: {}",
                 initial_bytes.len(),
                 preview
             );
-            Err(Box::new(e))
+            Err("timeout writing initial bytes".into())
         }
-        Err(_) => {
+        Err(e) => {
             warn!(
-                "Timeout writing initial CONNECT bytes to backend {} for client {}",
-                target_addr, client_peer
+                "Error writing initial CONNECT bytes to backend {} for client {} ({}/{} written): {}",
+                target_addr,
+                client_peer,
+                written,
+                initial_bytes.len(),
+                e
             );
             debug!(
                 "Initial bytes length: {}, preview: {}",
                 initial_bytes.len(),
                 preview
             );
-            Err("timeout writing initial bytes".into())
+            Err(Box::new(e))
         }
     }
 }
 
+/// Refuse a connection accepted during the drain window with a busy signal
+/// (see `send_busy_signal`).
+pub async fn reject_while_draining<S: ClientStream>(source: S) {
+    crate::metrics::DRAINING_REJECTIONS.inc();
+    send_busy_signal(source).await;
+}
+
+/// Tell a refused client the server is busy, then close.
+///
+/// Peeks the first bytes to pick a signal clients understand: a 503 for HTTP,
+/// a "server busy" CONNACK for MQTT. Anything else is simply closed.
+pub async fn send_busy_signal<S: ClientStream>(mut source: S) {
+    let mut peek_buf = [0u8; 16];
+    let n = match timeout(DRAIN_REJECT_TIMEOUT, source.peek(&mut peek_buf)).await {
+        Ok(Ok(n)) => n,
+        _ => return,
+    };
+    let seen = &peek_buf[..n];
+
+    let signal = if looks_like_http(seen) {
+        HTTP_SERVICE_UNAVAILABLE.to_vec()
+    } else if mqtt::inspect_packet(seen) == MqttPacketType::Connect {
+        let level = mqtt::connect_protocol_level(seen).unwrap_or(4);
+        match mqtt::encode_connack(level, ConnackRefusal::ServerBusy) {
+            Some(connack) => connack,
+            None => return,
+        }
+    } else {
+        return;
+    };
+
+    let _ = timeout(DRAIN_REJECT_TIMEOUT, source.write_all(&signal)).await;
+    let _ = source.shutdown().await;
+}
+
 /// Handle a single client connection. Supports optional MQTT inspection (lightweight or full),
 /// HTTP inspection, and Slowloris protection.
-pub async fn handle_connection(
-    mut source: TcpStream,
+///
+/// The connection is listed in the live-connection registry while it runs and
+/// is closed early if an operator kills it. Its access record, when enabled,
+/// is written once it ends, however it ends.
+pub async fn handle_connection<S: ClientStream>(
+    source: S,
     target_addr: String,
     config: ConnectionConfig,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let client_peer = source
-        .peer_addr()
-        .map(|a| a.to_string())
-        .unwrap_or_else(|_| "<unknown>".to_string());
+    let access_log = config.access_log.clone();
+    if let Some(Ok(addr)) = source.socket().map(TcpStream::peer_addr) {
+        access_log.client(addr.ip());
+    }
+    // `config` (and with it the decision trace, which records the verdict)
+    // is dropped by the time this returns.
+    let result = admit_and_serve(source, target_addr, config).await;
+    access_log.emit();
+    result
+}
+
+async fn admit_and_serve<S: ClientStream>(
+    mut source: S,
+    target_addr: String,
+    mut config: ConnectionConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let peer = if config.accept_proxy_protocol {
+        config.trace.check("proxy_protocol");
+        let wait = Duration::from_millis(config.slowloris_config.first_packet_timeout_ms);
+        let tlv_type = config
+            .tags
+            .as_ref()
+            .and_then(ConnectionTags::proxy_tlv_type);
+        match read_proxy_header(&mut source, wait, tlv_type).await {
+            Ok((client, endpoint)) => {
+                if let Some(endpoint) = endpoint {
+                    config.trace.pass("proxy_tlv", endpoint.as_str());
+                    if let Some(tags) = &mut config.tags {
+                        tags.set_endpoint(&endpoint);
+                    }
+                }
+                // No client address (LOCAL): the load balancer speaking for
+                // itself.
+                client.or_else(|| socket_peer(&source))
+            }
+            Err(e) => {
+                warn!(peer = ?socket_peer(&source), error = %e, "Rejected connection: bad PROXY protocol header");
+                crate::metrics::PROXY_PROTOCOL_REJECTIONS.inc();
+                crate::metrics::record_rejection(RejectReason::ProxyProtocol, &config.listener);
+                return Ok(());
+            }
+        }
+    } else {
+        socket_peer(&source)
+    };
+    if let Some(peer) = peer {
+        config.access_log.client(peer.ip());
+    }
+    // Held until this function returns, whichever way the connection ends.
+    let mut _subnet_slot = None;
+    if let (Some(mut checks), Some(peer)) = (config.deferred_source_checks.take(), peer) {
+        let proxied = checks.proxied.take();
+        if let Some(proxied) = &proxied {
+            match admit_proxied(peer, proxied, &mut config) {
+                Some(client_checks) => checks = client_checks,
+                None => return Ok(()),
+            }
+        }
+        if !admit_source(peer.ip(), &checks, &config).await {
+            return Ok(());
+        }
+        if let Some(limiter) = proxied.as_ref().and_then(|p| p.subnet_limiter.as_ref()) {
+            config.trace.check("subnet_cap");
+            match limiter.try_acquire(peer.ip()) {
+                Some(slot) => _subnet_slot = Some(slot),
+                None => {
+                    crate::metrics::SUBNET_CAP_REJECTIONS.inc();
+                    debug!(client_ip = %peer.ip(), "Rejected: subnet connection cap reached");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    let Some(peer) = peer else {
+        return serve_connection(source, None, target_addr, config).await;
+    };
+    // Held until this function returns, whichever way the connection ends.
+    let _concurrency = match config.max_concurrent_per_ip {
+        Some(max) => {
+            config.trace.check("concurrent_per_ip");
+            match try_acquire_concurrent(peer.ip(), max) {
+                Some(slot) => Some(slot),
+                None => {
+                    crate::metrics::CONCURRENCY_REJECTIONS.inc();
+                    crate::metrics::record_rejection(
+                        RejectReason::ConcurrentPerIp,
+                        &config.listener,
+                    );
+                    warn!(client_ip = %peer.ip(), max, "Rejected: too many open connections from IP");
+                    return Ok(());
+                }
+            }
+        }
+        None => None,
+    };
+    let registration = registry::register(peer.ip());
+    tokio::select! {
+        result = serve_connection(source, Some(peer), target_addr, config) => result,
+        _ = registration.token().cancelled() => {
+            info!(client = %peer, "Connection killed by operator");
+            Ok(())
+        }
+    }
+}
+
+/// The address the client connected from; `None` over a Unix socket.
+fn socket_peer<S: ClientStream>(source: &S) -> Option<SocketAddr> {
+    source.socket()?.peer_addr().ok()
+}
+
+/// Reads and decodes the PROXY protocol v2 header in front of the client
+/// stream: the client address, `None` when the header carried none (LOCAL),
+/// and the value of the `tlv_type` TLV as a label, when asked for and present.
+async fn read_proxy_header<R: AsyncRead + Unpin>(
+    source: &mut R,
+    wait: Duration,
+    tlv_type: Option<u8>,
+) -> Result<(Option<SocketAddr>, Option<String>), ProxyError> {
+    let read = async {
+        let mut header = vec![0u8; proxy_protocol::V2_FIXED_LEN];
+        source
+            .read_exact(&mut header)
+            .await
+            .map_err(|_| ProxyError::Incomplete)?;
+        header.resize(proxy_protocol::v2_header_len(&header)?, 0);
+        source
+            .read_exact(&mut header[proxy_protocol::V2_FIXED_LEN..])
+            .await
+            .map_err(|_| ProxyError::Incomplete)?;
+        let client = match proxy_protocol::parse_proxy_v2(&header) {
+            Ok((client, _)) => Some(client),
+            Err(ProxyError::Local { .. }) => None,
+            Err(e) => return Err(e),
+        };
+        let endpoint = match tlv_type {
+            Some(kind) => proxy_protocol::find_tlv(&header, kind)?.map(proxy_protocol::tlv_label),
+            None => None,
+        };
+        Ok((client, endpoint))
+    };
+    timeout(wait, read)
+        .await
+        .unwrap_or(Err(ProxyError::Incomplete))
+}
+
+/// Runs the address-keyed accept checks on the client the PROXY header
+/// named (region filter, access control, accept filter) and switches to its profile. Returns
+/// the per-source checks for that profile, or `None` when rejected.
+fn admit_proxied(
+    peer: SocketAddr,
+    proxied: &ProxiedChecks,
+    config: &mut ConnectionConfig,
+) -> Option<SourceChecks> {
+    if let Some(filter) = &proxied.region_filter {
+        config.trace.check("region_filter");
+        if let Err(region) = filter.check(peer.ip()) {
+            crate::metrics::REGION_REJECTIONS
+                .with_label_values(&[region])
+                .inc();
+            debug!(client_ip = %peer.ip(), region = region, "Rejected by region filter");
+            return None;
+        }
+    } else {
+        config.trace.skip("region_filter");
+    }
+
+    if let Some(acl) = &proxied.access_control {
+        config.trace.check("access_control");
+        if let Err(rule) = acl.check(peer.ip()) {
+            crate::metrics::ACL_REJECTIONS.inc();
+            debug!(client_ip = %peer.ip(), rule = %rule, "Rejected by access control");
+            return None;
+        }
+    } else {
+        config.trace.skip("access_control");
+    }
+
+    let exempt = if proxied.accept_filter {
+        config.trace.check("accept_filter");
+        match accept_filter::decide(peer) {
+            AcceptDecision::Reject => {
+                crate::metrics::ACCEPT_FILTER_REJECTIONS.inc();
+                debug!(client_ip = %peer.ip(), "Rejected by accept filter");
+                return None;
+            }
+            decision => decision == AcceptDecision::Accept,
+        }
+    } else {
+        config.trace.skip("accept_filter");
+        false
+    };
+
+    let profile = proxied.source_policy.select(peer.ip());
+    crate::metrics::POLICY_PROFILE_MATCHES
+        .with_label_values(&[profile.name.as_str()])
+        .inc();
+    config.trace.pass("source_profile", profile.name.as_str());
+    config.apply_profile(profile);
+    Some(SourceChecks {
+        limit: Arc::clone(&profile.limit),
+        rate_limit: profile.features.enable_rate_limiter && !exempt,
+        bans: !exempt,
+        proxied: None,
+    })
+}
+
+/// Runs the per-source checks deferred from the accept loop (rate limit,
+/// connect ratio, repeated-malformed ban).
+async fn admit_source(ip: IpAddr, checks: &SourceChecks, config: &ConnectionConfig) -> bool {
+    if checks.rate_limit {
+        config.trace.check("rate_limit");
+        if !check_rate_limit(ip, &checks.limit).await {
+            crate::metrics::REJECTED_CONNECTIONS.inc();
+            warn!(client_ip = %ip, "Rate limit exceeded");
+            return false;
+        }
+    }
+    if !checks.bans {
+        return true;
+    }
+    if let Some(ratio) = &checks.limit.connect_ratio {
+        config.trace.check("connect_ratio");
+        if !check_connect_ratio(ip, ratio) {
+            crate::metrics::CONNECT_RATIO_REJECTIONS.inc();
+            debug!(client_ip = %ip, "Rejected: banned for connect ratio");
+            return false;
+        }
+    }
+    if checks.limit.repeated_malformed.is_some() {
+        config.trace.check("repeated_malformed");
+        if malformed_banned(ip) {
+            crate::metrics::REPEATED_MALFORMED_REJECTIONS.inc();
+            debug!(client_ip = %ip, "Rejected: banned for repeated malformed CONNECTs");
+            return false;
+        }
+    }
+    true
+}
+
+async fn serve_connection<S: ClientStream>(
+    mut source: S,
+    peer: Option<SocketAddr>,
+    target_addr: String,
+    mut config: ConnectionConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let accepted_at = Instant::now();
+    let client_peer = peer.map_or_else(|| "<unknown>".to_string(), |a| a.to_string());
+
+    let mut deadline = HandshakeDeadline::new(
+        config
+            .slowloris_config
+            .handshake_deadline_ms
+            .map(Duration::from_millis),
+        &client_peer,
+        &config.listener,
+    );
+
+    // Held until forwarding starts, so one IP's simultaneous reconnects are
+    // inspected and connected a few at a time.
+    let in_flight = match (&config.in_flight_connects, peer) {
+        (Some(in_flight), Some(peer)) => {
+            config.trace.check("in_flight_connects");
+            // Time spent waiting still counts toward the handshake deadline.
+            match acquire_in_flight(peer.ip(), in_flight).await {
+                Some(permit) => Some(permit),
+                None => {
+                    warn!(client = %client_peer, "Rejected connection: too many in-flight CONNECTs from this IP");
+                    crate::metrics::IN_FLIGHT_CONNECT_REJECTIONS.inc();
+                    crate::metrics::record_rejection(
+                        RejectReason::InFlightConnects,
+                        &config.listener,
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        _ => {
+            config.trace.skip("in_flight_connects");
+            None
+        }
+    };
 
     let mut initial_bytes: Vec<u8> = Vec::new();
+    // Protocol and backend chosen by detection, when it is not plain MQTT.
+    let mut routed: Option<(DetectedProtocol, String)> = None;
+    // Packet size framing, set up once a CONNECT has been fully parsed.
+    let mut packet_limits = PacketLimits::new(&config.listener);
+    // Keep-alive the client asked for (after any clamping), when parsed.
+    let mut keep_alive: Option<u16> = None;
+
+    // TLS passthrough: the ClientHello is read whole, routed by its server
+    // name (or to the default target) and replayed to the backend unchanged.
+    if let Some(routes) = config.sni_routes.clone() {
+        config.trace.check("sni_route");
+        let window = deadline.cap(Duration::from_millis(
+            config.slowloris_config.first_packet_timeout_ms,
+        ));
+        let mut first = [0u8; 1];
+        if let Ok(Ok(1)) = timeout(window, source.peek(&mut first)).await {
+            if first[0] == tls::CONTENT_TYPE_HANDSHAKE {
+                config.access_log.protocol(DetectedProtocol::Tls.as_str());
+                let started = Instant::now();
+                let hello = timeout(window, read_client_hello(&mut source)).await;
+                config.phase_done("tls_client_hello", started);
+                let record = match hello {
+                    Ok(Ok(record)) => record,
+                    Ok(Err(reason)) => {
+                        warn!(client = %client_peer, reason = reason, "Rejected TLS ClientHello");
+                        crate::metrics::record_rejection(
+                            RejectReason::MalformedClientHello,
+                            &config.listener,
+                        );
+                        return Ok(());
+                    }
+                    Err(_) => {
+                        warn!(client = %client_peer, "Timeout reading TLS ClientHello");
+                        crate::metrics::record_rejection(
+                            RejectReason::ClientHelloTimeout,
+                            &config.listener,
+                        );
+                        return Ok(());
+                    }
+                };
+                let server_name = tls::parse_tls_client_hello(&record);
+                let backend = server_name
+                    .as_deref()
+                    .and_then(|name| routes.get(name))
+                    .cloned();
+                debug!(client = %client_peer, sni = ?server_name, matched = backend.is_some(), "TLS passthrough");
+                initial_bytes = record;
+                routed = Some((
+                    DetectedProtocol::Tls,
+                    backend.unwrap_or_else(|| target_addr.clone()),
+                ));
+            }
+        }
+    }
+
+    let fast_path = match &config.fast_path {
+        Some(signatures) if routed.is_none() => {
+            config.trace.check("signature_fast_path");
+            let mut peek_buf = [0u8; crate::engine::signature::MAX_SIGNATURE_LEN];
+            let len = signatures.max_len();
+            match timeout(FAST_PATH_PEEK_TIMEOUT, source.peek(&mut peek_buf[..len])).await {
+                Ok(Ok(n)) => match signatures.classify(&peek_buf[..n]) {
+                    FastPath::Hit => {
+                        crate::metrics::SIGNATURE_FAST_PATH
+                            .with_label_values(&["hit"])
+                            .inc();
+                        true
+                    }
+                    FastPath::Verify => {
+                        crate::metrics::SIGNATURE_FAST_PATH
+                            .with_label_values(&["verified"])
+                            .inc();
+                        false
+                    }
+                    FastPath::Miss => false,
+                },
+                _ => false,
+            }
+        }
+        _ => false,
+    };
 
-    if config.slowloris_protect {
-        let first_packet_timeout =
-            Duration::from_millis(config.slowloris_config.first_packet_timeout_ms);
-        let mut peek_buf = [0u8; 16];
-        let n = match timeout(first_packet_timeout, source.peek(&mut peek_buf)).await {
+    // Peek/read interleaving: every peek below is non-consuming and the
+    // handler is the socket's only reader, so later reads always start at the
+    // first byte the client sent. Anything consumed before the backend is
+    // chosen (HTTP inspection via `RecordingReader`, the MQTT CONNECT frame)
+    // is kept in `initial_bytes` and replayed ahead of the forwarded stream;
+    // nothing read during inspection is ever dropped or reordered.
+    let mut budget = InspectionBudget::new(Duration::from_millis(
+        config.slowloris_config.inspection_budget_ms,
+    ));
+    if config.slowloris_protect && !fast_path && routed.is_none() {
+        config.trace.check("first_packet");
+        let first_packet_timeout = deadline.cap(Duration::from_millis(
+            config.slowloris_config.first_packet_timeout_ms,
+        ));
+        let mut peek_buf = [0u8; FIRST_PACKET_PEEK];
+        let started = Instant::now();
+        let peeked = timeout(first_packet_timeout, source.peek(&mut peek_buf)).await;
+        config.phase_done("peek", started);
+        let n = match peeked {
             Ok(Ok(n)) if n > 0 => n,
             Ok(Ok(_)) => {
                 warn!(client = %client_peer, "Connection closed before sending data");
-                crate::metrics::SLOWLORIS_REJECTIONS.inc();
+                crate::metrics::record_rejection(RejectReason::ClosedBeforeData, &config.listener);
                 return Ok(());
             }
             Ok(Err(e)) => {
                 warn!(client = %client_peer, error = %e, "Error peeking first packet");
-                crate::metrics::SLOWLORIS_REJECTIONS.inc();
+                crate::metrics::record_rejection(RejectReason::FirstPacketError, &config.listener);
                 return Ok(());
             }
             Err(_) => {
                 warn!(client = %client_peer, "First packet timeout - no data received within {}ms",
                     config.slowloris_config.first_packet_timeout_ms);
-                crate::metrics::SLOWLORIS_REJECTIONS.inc();
+                crate::metrics::record_rejection(
+                    RejectReason::FirstPacketTimeout,
+                    &config.listener,
+                );
                 return Ok(());
             }
         };
 
         debug!(client = %client_peer, "Received first {} bytes within timeout", n);
+        if n == FIRST_PACKET_PEEK {
+            // Typical for large CONNECTs; the rest is read by full inspection.
+            crate::metrics::FIRST_PACKET_PEEK_FILLED.inc();
+        }
 
         if config.http_inspect && looks_like_http(&peek_buf[..n]) {
+            config.trace.check("http_inspection");
+            config.access_log.protocol(DetectedProtocol::Http.as_str());
             info!(client = %client_peer, "HTTP protocol detected - inspecting for Slowloris");
 
-            let http_timeout =
-                Duration::from_millis(config.slowloris_config.http_request_timeout_ms);
+            let http_timeout = deadline.cap(Duration::from_millis(
+                config.slowloris_config.http_request_timeout_ms,
+            ));
             let idle_timeout =
                 Duration::from_millis(config.slowloris_config.packet_idle_timeout_ms);
 
-            match inspect_http(
-                &mut source,
-                http_timeout,
-                idle_timeout,
-                config.slowloris_config.max_http_header_size,
-                config.slowloris_config.max_http_header_count,
-                8192,
-            )
-            .await
-            {
-                Ok(HttpInspectionResult::HttpDetected) => {
-                    info!(client = %client_peer, "Valid HTTP request detected - rejecting (wrong protocol for MQTT broker)");
-                    crate::metrics::HTTP_REJECTIONS.inc();
+            let mut recorder = RecordingReader::new(&mut source);
+            let started = Instant::now();
+            let result = budget
+                .run(inspect_http(
+                    &mut recorder,
+                    http_timeout,
+                    idle_timeout,
+                    config.slowloris_config.max_http_header_size,
+                    config.slowloris_config.max_http_header_count,
+                    &config.http_inspection,
+                ))
+                .await;
+            config.phase_done("http_detection", started);
+            let consumed = recorder.into_recorded();
+            let Ok(result) = result else {
+                reject_over_budget(&config, &client_peer, &budget, &consumed);
+                return Ok(());
+            };
+
+            let mut mqtt_websocket = false;
+            let protocol = match result {
+                Ok(HttpInspectionResult::HttpDetected) => DetectedProtocol::Http,
+                Ok(HttpInspectionResult::HttpConnectTunnel(tunnel)) => {
+                    // Never routed, even with an HTTP backend configured.
+                    warn!(client = %client_peer, target = %tunnel, "Rejected HTTP CONNECT tunnel request");
+                    crate::metrics::record_rejection(
+                        RejectReason::HttpConnectTunnel,
+                        &config.listener,
+                    );
+                    config.capture("http_detected", &client_peer, &consumed);
                     return Ok(());
                 }
+                Ok(HttpInspectionResult::WebSocketUpgrade) => DetectedProtocol::WebSocket,
+                Ok(HttpInspectionResult::MqttWebSocket) => {
+                    mqtt_websocket = config.mqtt_websocket;
+                    DetectedProtocol::WebSocket
+                }
                 Ok(HttpInspectionResult::SlowlorisDetected(reason)) => {
-                    warn!(client = %client_peer, reason = %reason, "Slowloris attack detected on HTTP");
-                    crate::metrics::SLOWLORIS_REJECTIONS.inc();
+                    warn!(client = %client_peer, reason = reason.as_str(), "Slowloris attack detected on HTTP");
+                    crate::metrics::record_rejection(reason, &config.listener);
+                    config.capture("http_slowloris", &client_peer, &consumed);
+                    return Ok(());
+                }
+                Ok(HttpInspectionResult::BareLineFeed) => {
+                    warn!(client = %client_peer, "Rejected HTTP request with bare LF line endings");
+                    crate::metrics::HTTP_BARE_LF_REJECTIONS.inc();
+                    crate::metrics::record_rejection(RejectReason::HttpBareLf, &config.listener);
+                    config.capture("http_bare_lf", &client_peer, &consumed);
                     return Ok(());
                 }
                 Ok(HttpInspectionResult::NotHttp) => {
+                    // The request line has already been consumed. An HTTP
+                    // method's first byte is never an MQTT CONNECT, so under
+                    // MQTT inspection this is a protocol violation; otherwise
+                    // the consumed bytes are replayed to the backend.
+                    if config.mqtt_inspect {
+                        warn!(client = %client_peer, "Neither HTTP nor an MQTT CONNECT");
+                        crate::metrics::record_rejection(
+                            RejectReason::NotHttpOrMqtt,
+                            &config.listener,
+                        );
+                        config.capture("unexpected_packet_type", &client_peer, &consumed);
+                        return Ok(());
+                    }
                     debug!(client = %client_peer, "Quick HTTP check was false positive, proceeding");
+                    DetectedProtocol::Mqtt
                 }
                 Err(e) => {
                     warn!(client = %client_peer, error = %e, "Error during HTTP inspection");
-                    crate::metrics::SLOWLORIS_REJECTIONS.inc();
+                    crate::metrics::record_rejection(RejectReason::HttpReadError, &config.listener);
+                    config.capture("http_slowloris", &client_peer, &consumed);
                     return Ok(());
                 }
+            };
+
+            if protocol == DetectedProtocol::Mqtt {
+                initial_bytes = consumed;
+            } else {
+                config.trace.check("protocol_backend");
+                match config.backend_for(protocol) {
+                    Some(backend) => {
+                        initial_bytes = consumed;
+                        routed = Some((protocol, backend));
+                    }
+                    None if mqtt_websocket => {
+                        debug!(client = %client_peer, "MQTT over WebSocket upgrade; forwarding to the MQTT target");
+                        initial_bytes = consumed;
+                        let mqtt_target = config
+                            .backend_for(DetectedProtocol::Mqtt)
+                            .unwrap_or_else(|| target_addr.clone());
+                        routed = Some((protocol, mqtt_target));
+                    }
+                    None => {
+                        info!(client = %client_peer, "Valid HTTP request detected - rejecting (wrong protocol for MQTT broker)");
+                        crate::metrics::record_rejection(
+                            RejectReason::HttpDetected,
+                            &config.listener,
+                        );
+                        config.capture("http_detected", &client_peer, &consumed);
+                        return Ok(());
+                    }
+                }
             }
         }
     }
 
     // MQTT-specific overlay
-    if config.mqtt_inspect {
+    if routed.is_some() {
+        // Non-MQTT traffic routed to its own backend; MQTT inspection does not apply.
+        config.trace.skip("mqtt_inspection");
+    } else if fast_path {
+        config.trace.skip("mqtt_inspection");
+        debug!(client = %client_peer, "Signature fast path; forwarding without inspection");
+    } else if config.mqtt_inspect {
         if config.mqtt_full_inspect {
+            config.trace.check("mqtt_connect");
+            config.access_log.protocol(DetectedProtocol::Mqtt.as_str());
             // Apply MQTT CONNECT timeout if Slowloris protection enabled
-            let connect_timeout = if config.slowloris_protect {
+            let connect_timeout = deadline.cap(if config.slowloris_protect {
                 Duration::from_millis(config.slowloris_config.mqtt_connect_timeout_ms)
             } else {
                 Duration::from_secs(30) // Default fallback
-            };
+            });
 
             let idle_timeout = if config.slowloris_protect {
                 Duration::from_millis(config.slowloris_config.packet_idle_timeout_ms)
@@ -316,84 +2114,359 @@ pub async fn handle_connection(
                 Duration::from_secs(10) // Default fallback
             };
 
-            // Read fixed header with idle timeout
-            let fixed_byte = if config.slowloris_protect {
-                let mut buf = [0u8; 1];
-                match read_with_idle_timeout(&mut source, &mut buf, idle_timeout, connect_timeout)
-                    .await
-                {
-                    Ok(1) => buf[0],
-                    Ok(_) => {
-                        warn!(client = %client_peer, "EOF while reading MQTT fixed header");
-                        crate::metrics::PROTOCOL_REJECTIONS.inc();
+            let min_throughput = config
+                .slowloris_config
+                .min_throughput_bytes_per_sec
+                .filter(|_| config.slowloris_protect);
+
+            // Bytes the client pipelined after its CONNECT in the same segment.
+            let mut trailing: Vec<u8> = Vec::new();
+
+            let payload = if let Some(window_ms) =
+                config.slowloris_config.single_segment_connect_timeout_ms
+            {
+                let window = deadline.cap(Duration::from_millis(window_ms));
+                let started = Instant::now();
+                let segment = budget
+                    .run(read_single_segment_connect(
+                        &mut source,
+                        config.max_connect_remaining,
+                        window,
+                    ))
+                    .await;
+                config.phase_done("connect_segment", started);
+                match segment {
+                    Ok(Ok(segment)) => {
+                        initial_bytes = segment.frame;
+                        trailing = segment.trailing;
+                        segment.payload
+                    }
+                    Ok(Err(SingleSegmentError::Fragmented(reason, read))) => {
+                        warn!(client = %client_peer, reason = reason, "Rejected fragmented CONNECT");
+                        crate::metrics::FRAGMENTED_CONNECT_REJECTIONS.inc();
+                        crate::metrics::record_rejection(
+                            RejectReason::FragmentedConnect,
+                            &config.listener,
+                        );
+                        config.capture("fragmented_connect", &client_peer, &read);
                         return Ok(());
                     }
-                    Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                        warn!(client = %client_peer, "Timeout reading MQTT fixed header (Slowloris)");
-                        crate::metrics::SLOWLORIS_REJECTIONS.inc();
+                    Ok(Err(SingleSegmentError::Protocol(reason))) => {
+                        warn!(client = %client_peer, reason = reason, "Rejected CONNECT segment");
+                        crate::metrics::record_rejection(
+                            RejectReason::ConnectSegment,
+                            &config.listener,
+                        );
                         return Ok(());
                     }
-                    Err(_) => {
-                        crate::metrics::PROTOCOL_REJECTIONS.inc();
+                    Err(BudgetExceeded) => {
+                        reject_over_budget(&config, &client_peer, &budget, &[]);
                         return Ok(());
                     }
                 }
             } else {
-                match read_fixed_header(&mut source).await {
-                    Ok(b) => b,
+                // Read fixed header with idle timeout
+                let started = Instant::now();
+                let fixed_byte = if config.slowloris_protect {
+                    let mut buf = [0u8; 1];
+                    match read_with_idle_timeout(
+                        &mut source,
+                        &mut buf,
+                        idle_timeout,
+                        connect_timeout,
+                    )
+                    .await
+                    {
+                        Ok(1) => buf[0],
+                        Ok(_) => {
+                            warn!(client = %client_peer, "EOF while reading MQTT fixed header");
+                            crate::metrics::record_rejection(
+                                RejectReason::FixedHeaderRead,
+                                &config.listener,
+                            );
+                            return Ok(());
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                            warn!(client = %client_peer, "Timeout reading MQTT fixed header (Slowloris)");
+                            crate::metrics::record_rejection(
+                                RejectReason::FixedHeaderTimeout,
+                                &config.listener,
+                            );
+                            return Ok(());
+                        }
+                        Err(_) => {
+                            crate::metrics::record_rejection(
+                                RejectReason::FixedHeaderRead,
+                                &config.listener,
+                            );
+                            return Ok(());
+                        }
+                    }
+                } else {
+                    let limit =
+                        Duration::from_millis(config.slowloris_config.mqtt_fixed_header_timeout_ms);
+                    match read_fixed_header(&mut source, limit, &deadline, &config.listener).await {
+                        Ok(b) => b,
+                        Err(_) => return Ok(()),
+                    }
+                };
+                config.phase_done("mqtt_fixed_header", started);
+                initial_bytes.push(fixed_byte);
+
+                let packet_type = mqtt::inspect_packet(&[fixed_byte]);
+                if packet_type != MqttPacketType::Connect {
+                    warn!(client = %client_peer, "Dropped: Expected CONNECT, detected {:?}", packet_type);
+                    crate::metrics::record_rejection(
+                        RejectReason::UnexpectedPacketType,
+                        &config.listener,
+                    );
+                    config.capture("unexpected_packet_type", &client_peer, &initial_bytes);
+                    return Ok(());
+                }
+
+                // Read remaining length (pass configured cap)
+                let started = Instant::now();
+                let remaining = read_remaining_length(
+                    &mut source,
+                    config.max_connect_remaining,
+                    Duration::from_millis(
+                        config
+                            .slowloris_config
+                            .mqtt_remaining_length_byte_timeout_ms,
+                    ),
+                    &deadline,
+                    &config.listener,
+                )
+                .await;
+                config.phase_done("remaining_length", started);
+                let (rl_bytes, remaining_len) = match remaining {
+                    Ok(v) => v,
                     Err(_) => return Ok(()),
+                };
+                initial_bytes.extend_from_slice(&rl_bytes);
+
+                // Read payload
+                let started = Instant::now();
+                let payload = budget
+                    .run(read_payload(
+                        &mut source,
+                        remaining_len,
+                        Duration::from_millis(
+                            config.slowloris_config.mqtt_connect_payload_timeout_ms,
+                        ),
+                        &deadline,
+                        min_throughput,
+                        &config.listener,
+                    ))
+                    .await;
+                config.phase_done("payload", started);
+                let payload = match payload {
+                    Ok(Ok(p)) => p,
+                    Ok(Err(_)) => return Ok(()),
+                    Err(BudgetExceeded) => {
+                        reject_over_budget(&config, &client_peer, &budget, &initial_bytes);
+                        return Ok(());
+                    }
+                };
+                if !payload.is_empty() {
+                    initial_bytes.extend_from_slice(&payload);
                 }
+                payload
             };
-            initial_bytes.push(fixed_byte);
 
-            let packet_type = mqtt::inspect_packet(&[fixed_byte]);
-            if packet_type != MqttPacketType::Connect {
-                warn!(client = %client_peer, "Dropped: Expected CONNECT, detected {:?}", packet_type);
-                crate::metrics::PROTOCOL_REJECTIONS.inc();
+            // Validate minimal CONNECT variable header
+            if !mqtt::validate_connect_variable_header(&payload) {
+                warn!(client = %client_peer, "Malformed CONNECT: invalid protocol name/version or too short");
+                crate::metrics::record_rejection(RejectReason::MalformedConnect, &config.listener);
+                config.capture("malformed_connect", &client_peer, &initial_bytes);
+                note_malformed(peer, &config, &client_peer, &initial_bytes);
+                send_reject_connack(
+                    &mut source,
+                    &config,
+                    &initial_bytes,
+                    ConnackRefusal::MalformedPacket,
+                    "malformed CONNECT: invalid protocol name",
+                )
+                .await;
                 return Ok(());
             }
 
-            // Read remaining length (pass configured cap)
-            let (rl_bytes, remaining_len) =
-                match read_remaining_length(&mut source, config.max_connect_remaining).await {
-                    Ok(v) => v,
-                    Err(_) => return Ok(()),
-                };
-            initial_bytes.extend_from_slice(&rl_bytes);
+            let level = mqtt::connect_protocol_level(&initial_bytes).unwrap_or(0);
+            let allowed = config
+                .mqtt_policy
+                .allowed_protocol_levels
+                .as_deref()
+                .unwrap_or(mqtt::KNOWN_PROTOCOL_LEVELS);
+            if !allowed.contains(&level) {
+                warn!(client = %client_peer, level, "Rejected CONNECT: unsupported protocol level");
+                crate::metrics::PROTOCOL_LEVEL_REJECTIONS
+                    .with_label_values(&[&level.to_string()])
+                    .inc();
+                crate::metrics::record_rejection(
+                    RejectReason::UnsupportedProtocolLevel,
+                    &config.listener,
+                );
+                config.capture("unsupported_protocol_level", &client_peer, &initial_bytes);
+                send_reject_connack(
+                    &mut source,
+                    &config,
+                    &initial_bytes,
+                    ConnackRefusal::UnsupportedProtocolVersion,
+                    "unsupported protocol level",
+                )
+                .await;
+                return Ok(());
+            }
+
+            let flags = mqtt::connect_flags_byte(&payload)
+                .map_or(Err("connect flags missing"), mqtt::validate_connect_flags);
+            if let Err(reason) = flags {
+                warn!(client = %client_peer, reason, "Malformed CONNECT: invalid connect flags");
+                crate::metrics::record_rejection(
+                    RejectReason::InvalidConnectFlags,
+                    &config.listener,
+                );
+                config.capture("malformed_connect", &client_peer, &initial_bytes);
+                note_malformed(peer, &config, &client_peer, &initial_bytes);
+                send_reject_connack(
+                    &mut source,
+                    &config,
+                    &initial_bytes,
+                    ConnackRefusal::MalformedPacket,
+                    reason,
+                )
+                .await;
+                return Ok(());
+            }
 
-            // Read payload
-            let payload = match read_payload(&mut source, remaining_len).await {
-                Ok(p) => p,
-                Err(_) => return Ok(()),
+            let properties = match mqtt::parse_connect_properties(&payload) {
+                Ok(properties) => properties,
+                Err(e) => {
+                    warn!(client = %client_peer, error = %e, "Malformed CONNECT: invalid properties");
+                    crate::metrics::record_rejection(
+                        RejectReason::InvalidConnectProperties,
+                        &config.listener,
+                    );
+                    config.capture("malformed_connect", &client_peer, &initial_bytes);
+                    note_malformed(peer, &config, &client_peer, &initial_bytes);
+                    send_reject_connack(
+                        &mut source,
+                        &config,
+                        &initial_bytes,
+                        ConnackRefusal::MalformedPacket,
+                        "malformed CONNECT: invalid properties",
+                    )
+                    .await;
+                    return Ok(());
+                }
             };
-            if !payload.is_empty() {
-                initial_bytes.extend_from_slice(&payload);
+            debug!(client = %client_peer, ?properties, "CONNECT properties");
+
+            if let Err((refusal, reason)) = check_client_id(&payload, &config) {
+                warn!(client = %client_peer, reason, "Rejected CONNECT: client identifier");
+                crate::metrics::record_rejection(RejectReason::ClientId, &config.listener);
+                send_reject_connack(&mut source, &config, &initial_bytes, refusal, reason).await;
+                return Ok(());
             }
 
-            // Validate minimal CONNECT variable header
-            if !validate_connect_variable_header(&payload) {
-                warn!(client = %client_peer, "Malformed CONNECT: invalid protocol name/version or too short");
-                crate::metrics::PROTOCOL_REJECTIONS.inc();
+            let username = mqtt::extract_username(&payload);
+            if config.require_username && username.is_none() {
+                warn!(client = %client_peer, "Rejected CONNECT: no user name");
+                crate::metrics::AUTH_REJECTIONS.inc();
+                crate::metrics::record_rejection(RejectReason::UsernameRequired, &config.listener);
+                send_reject_connack(
+                    &mut source,
+                    &config,
+                    &initial_bytes,
+                    ConnackRefusal::NotAuthorized,
+                    "user name required",
+                )
+                .await;
+                return Ok(());
+            }
+            debug!(client = %client_peer, username, "CONNECT user name");
+
+            if let Err(reason) = enforce_keep_alive(
+                &mut initial_bytes,
+                &config.mqtt_policy,
+                &client_peer,
+                &config.listener,
+            ) {
+                send_reject_connack(
+                    &mut source,
+                    &config,
+                    &initial_bytes,
+                    ConnackRefusal::PolicyViolation,
+                    &reason,
+                )
+                .await;
                 return Ok(());
             }
+            keep_alive = mqtt::connect_keep_alive(&initial_bytes);
+
+            // Framing starts right after the CONNECT, so any pipelined bytes
+            // are checked before they are forwarded.
+            let max_payload = config.mqtt_policy.max_publish_payload;
+            let publish = (config.publish_topics.is_some() || max_payload.is_some()).then(|| {
+                let v5 = mqtt::connect_protocol_level(&initial_bytes) == Some(5);
+                (
+                    PublishTracker::new(v5, max_payload),
+                    config.publish_topics.clone(),
+                )
+            });
+            packet_limits.upstream.size = config
+                .mqtt_policy
+                .max_packet_size
+                .map(PacketSizeTracker::new);
+            packet_limits.upstream.publish = publish;
+            if packet_limits
+                .upstream
+                .observe(&trailing, "upstream")
+                .is_err()
+            {
+                warn!(client = %client_peer, "Rejected pipelined MQTT packet after CONNECT");
+                return Ok(());
+            }
+            if config.mqtt_policy.enforce_client_max_packet_size {
+                packet_limits.downstream.size =
+                    mqtt::connect_maximum_packet_size(&initial_bytes).map(PacketSizeTracker::new);
+            }
+
+            if let Some(edge_id) = &config.edge_instance_id {
+                if let Some(tagged) =
+                    mqtt::inject_user_property(&initial_bytes, EDGE_ID_PROPERTY, edge_id)
+                {
+                    initial_bytes = tagged;
+                }
+            }
+            initial_bytes.extend_from_slice(&trailing);
 
             debug!(
                 "Verified full MQTT CONNECT frame. Forwarding to {}",
                 target_addr
             );
         } else {
+            config.trace.check("mqtt_first_byte");
+            config.access_log.protocol(DetectedProtocol::Mqtt.as_str());
             // Lightweight inspection: peek the first byte
             let mut buffer = [0u8; 1];
-            let peek_res = timeout(Duration::from_secs(3), source.peek(&mut buffer)).await;
+            let peek_res = timeout(
+                deadline.cap(Duration::from_secs(3)),
+                source.peek(&mut buffer),
+            )
+            .await;
             if peek_res.is_err() {
                 warn!(client = %client_peer, "Connection timed out waiting for MQTT data");
-                crate::metrics::PROTOCOL_REJECTIONS.inc();
+                crate::metrics::record_rejection(RejectReason::FirstByteTimeout, &config.listener);
                 return Ok(());
             }
             let packet_type = mqtt::inspect_packet(&buffer);
             if packet_type != MqttPacketType::Connect {
                 warn!(client = %client_peer, "Dropped: Expected CONNECT, detected {:?}", packet_type);
-                crate::metrics::PROTOCOL_REJECTIONS.inc();
+                crate::metrics::record_rejection(
+                    RejectReason::UnexpectedPacketType,
+                    &config.listener,
+                );
                 return Ok(());
             }
             debug!(
@@ -402,6 +2475,7 @@ pub async fn handle_connection(
             );
         }
     } else {
+        config.trace.skip("mqtt_inspection");
         debug!(
             "MQTT inspection disabled; forwarding connection to {}",
             target_addr
@@ -410,35 +2484,184 @@ pub async fn handle_connection(
 
     // client_peer already captured earlier for logging at inspection-time
 
+    // Only the default MQTT target is replaced by the pool; dispatch-table
+    // entries name one backend each.
+    let pooled = routed.is_none() && config.backend_for(DetectedProtocol::Mqtt).is_none();
+    let (protocol, target_addr) = match routed {
+        Some(route) => route,
+        // MQTT falls back to the default target when the table has no entry.
+        None => (
+            DetectedProtocol::Mqtt,
+            config
+                .backend_for(DetectedProtocol::Mqtt)
+                .unwrap_or(target_addr),
+        ),
+    };
+    crate::metrics::ROUTING_DECISIONS
+        .with_label_values(&[protocol.as_str()])
+        .inc();
+    config.access_log.protocol(protocol.as_str());
+    if config.trace.is_enabled() {
+        config
+            .trace
+            .pass("route", format!("{}->{}", protocol.as_str(), target_addr));
+    }
+
+    // Inspection is complete: the client is no longer a dangling open.
+    if let Some(peer) = peer {
+        record_connect_completed(peer.ip());
+    }
+
+    // Sessions cost the broker far more than rejected sockets, so they get
+    // their own budget, spent only once the client is otherwise admitted.
+    if let Some(session_rate) = config
+        .session_rate
+        .as_ref()
+        .filter(|_| protocol == DetectedProtocol::Mqtt)
+    {
+        config.trace.check("session_rate");
+        if let Some(peer) = peer {
+            if !check_session_rate(peer.ip(), session_rate) {
+                warn!(client = %client_peer, "Rejected MQTT session: session rate limit exceeded");
+                crate::metrics::SESSION_RATE_REJECTIONS.inc();
+                crate::metrics::record_rejection(RejectReason::SessionRate, &config.listener);
+                return Ok(());
+            }
+        }
+    } else {
+        config.trace.skip("session_rate");
+    }
+
     // Connect to backend
-    let target = match connect_backend(&target_addr, &client_peer).await {
-        Ok(s) => s,
-        Err(_) => return Ok(()),
+    config.trace.check("backend_connect");
+    let started = Instant::now();
+    let mut candidates: Vec<String> = match &config.backend_pool {
+        Some(pool) if pooled => pool.rotation().map(str::to_string).collect(),
+        _ => vec![target_addr],
+    };
+    let target = connect_backend(
+        &candidates,
+        &client_peer,
+        &deadline,
+        Duration::from_millis(config.slow_backend_connect_ms),
+        config.backend_health.as_deref(),
+        &config.listener,
+    )
+    .await;
+    config.phase_done("backend_connect", started);
+    let (target, target_addr) = match target {
+        Ok((s, reached)) => {
+            BACKEND_CONNECT_FAILURES.store(0, Ordering::Relaxed);
+            let target_addr = candidates.swap_remove(reached);
+            if config.backend_pool.is_some() && pooled {
+                crate::metrics::BACKEND_SELECTIONS
+                    .with_label_values(&[target_addr.as_str()])
+                    .inc();
+            }
+            (s, target_addr)
+        }
+        Err(e) => {
+            // The client passed every check; only the backend let it down.
+            BACKEND_CONNECT_FAILURES.fetch_add(1, Ordering::Relaxed);
+            crate::metrics::BACKEND_UNAVAILABLE
+                .with_label_values(&[config.listener.as_str()])
+                .inc();
+            warn!(
+                client = %client_peer,
+                backend = %candidates.join(","),
+                class = e.class.as_str(),
+                retryable = e.class.is_retryable(),
+                error = %e,
+                "Admitted connection dropped: backend unavailable"
+            );
+            if config.backend_unavailable_connack {
+                send_unavailable_connack(&mut source, &initial_bytes).await;
+            }
+            return Ok(());
+        }
     };
 
     let _guard = ProxyConnectionGuard::new();
 
+    if let Some(user_timeout) = config.tcp_user_timeout {
+        for (side, stream) in [("client", source.socket()), ("backend", target.tcp())] {
+            let Some(stream) = stream else {
+                continue;
+            };
+            if let Err(e) = set_tcp_user_timeout(stream, user_timeout) {
+                debug!(client = %client_peer, side, error = %e, "Could not set TCP_USER_TIMEOUT");
+            }
+        }
+    }
+
+    if config.send_proxy_protocol {
+        let local = source.socket().and_then(|socket| socket.local_addr().ok());
+        let header = proxy_protocol::v1_header(peer, local);
+        initial_bytes.splice(0..0, header.into_bytes());
+    }
+
     let (mut source_read, mut source_write) = source.into_split();
     let (mut target_read, mut target_write) = target.into_split();
 
     // Forward initial bytes if present
-    if let Err(e) = forward_initial_bytes(
+    config.trace.check("forward_initial_bytes");
+    let started = Instant::now();
+    let forwarded = forward_initial_bytes(
         &mut target_write,
         &initial_bytes,
+        deadline.cap(Duration::from_millis(config.backend_write_timeout_ms)),
+        config.shutdown.as_ref(),
         &target_addr,
         &client_peer,
     )
-    .await
-    {
+    .await;
+    config.phase_done("initial_forward", started);
+    if let Err(e) = forwarded {
         warn!(client = %client_peer, reason = %e, "Failed forwarding initial bytes to backend");
         return Ok(());
     }
+    config
+        .access_log
+        .add_bytes("upstream", initial_bytes.len() as u64);
+
+    deadline.complete();
+    drop(in_flight);
+    drop(config.inspection_slot.take());
+    config.trace.admit();
+    if let Some(tags) = &config.tags {
+        let labels = tags.labels(protocol.as_str());
+        crate::metrics::TAGGED_SESSIONS
+            .with_label_values(&labels)
+            .inc();
+        crate::metrics::TAGGED_HANDSHAKE_SECONDS
+            .with_label_values(&labels)
+            .observe(accepted_at.elapsed().as_secs_f64());
+    }
 
     // Start bidirectional copying between client and backend
-    let _ = tokio::select! {
-        res = io::copy(&mut source_read, &mut target_write) => res,
-        res = io::copy(&mut target_read, &mut source_write) => res,
-    };
+    let client_idle = session_client_idle(config.client_idle_timeout, keep_alive);
+    let session = forward_session(
+        (&mut source_read, &mut source_write),
+        (&mut target_read, &mut target_write),
+        &config,
+        packet_limits,
+        client_idle,
+    );
+    match config.max_connection_lifetime {
+        Some(lifetime) => {
+            let remaining = lifetime.saturating_sub(accepted_at.elapsed());
+            if timeout(remaining, session).await.is_err() {
+                info!(
+                    client = %client_peer,
+                    lifetime_secs = lifetime.as_secs(),
+                    "Closing session that reached its maximum lifetime"
+                );
+                crate::metrics::LIFETIME_DISCONNECTS.inc();
+            }
+        }
+        None => session.await,
+    }
+    config.trace.closed();
 
     debug!("Connection closed.");
     Ok(())
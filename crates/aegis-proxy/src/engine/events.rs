@@ -0,0 +1,112 @@
+//! Connection lifecycle events published to NATS.
+//!
+//! Each decision trace record (admitted / rejected with the failing check)
+//! and each session close is published as a JSON message, so security
+//! pipelines can react in real time. Connection tasks only `try_send` into a
+//! bounded queue: when the server is slow or unreachable the queue absorbs
+//! bursts, then events are dropped and counted; the hot path never waits.
+//!
+//! The publisher speaks the NATS text protocol directly (`CONNECT`, `PUB`,
+//! `PING`/`PONG`) and reconnects with backoff.
+
+use aegis_common::EventsConfig;
+use once_cell::sync::OnceCell;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// Longest pause between reconnect attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+static SINK: OnceCell<mpsc::Sender<Vec<u8>>> = OnceCell::new();
+
+/// Installs the global event queue and returns the publisher future, which
+/// the caller should spawn. Only the first call installs a queue.
+pub fn init(config: &EventsConfig) -> impl std::future::Future<Output = ()> + Send + 'static {
+    let (tx, rx) = mpsc::channel(config.queue_depth.max(1));
+    let _ = SINK.set(tx);
+    let address = config
+        .nats_url
+        .trim_start_matches("nats://")
+        .trim_end_matches('/')
+        .to_string();
+    run_publisher(rx, address, config.subject.clone())
+}
+
+/// Queues one event; a no-op when events are not configured.
+pub fn publish(event: &str, client: &str, fields: serde_json::Value) {
+    let Some(tx) = SINK.get() else {
+        return;
+    };
+    let mut record = serde_json::json!({ "event": event, "client": client });
+    if let (Some(record), serde_json::Value::Object(fields)) = (record.as_object_mut(), fields) {
+        record.extend(fields);
+    }
+    if tx.try_send(record.to_string().into_bytes()).is_err() {
+        crate::metrics::EVENTS_DROPPED.inc();
+    }
+}
+
+async fn run_publisher(mut rx: mpsc::Receiver<Vec<u8>>, address: String, subject: String) {
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        match TcpStream::connect(&address).await {
+            Ok(stream) => {
+                info!(server = %address, subject = %subject, "Connected to NATS event bus");
+                backoff = Duration::from_secs(1);
+                match publish_until_error(stream, &mut rx, &subject).await {
+                    Ok(()) => return, // every sender is gone
+                    Err(e) => {
+                        warn!(server = %address, error = %e, "NATS event bus connection lost")
+                    }
+                }
+            }
+            Err(e) => debug!(server = %address, error = %e, "NATS event bus unreachable"),
+        }
+        // Events keep queueing (then dropping) while we wait.
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn publish_until_error(
+    stream: TcpStream,
+    rx: &mut mpsc::Receiver<Vec<u8>>,
+    subject: &str,
+) -> std::io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    write
+        .write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"aegisgate\"}\r\n")
+        .await?;
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(payload) = event else {
+                    return Ok(());
+                };
+                let mut frame = format!("PUB {} {}\r\n", subject, payload.len()).into_bytes();
+                frame.extend_from_slice(&payload);
+                frame.extend_from_slice(b"\r\n");
+                write.write_all(&frame).await?;
+                crate::metrics::EVENTS_PUBLISHED.inc();
+            }
+            line = lines.next_line() => match line? {
+                Some(line) if line.starts_with("PING") => write.write_all(b"PONG\r\n").await?,
+                Some(line) if line.starts_with("-ERR") => {
+                    warn!(error = %line, "NATS server reported an error");
+                }
+                Some(_) => {}
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "server closed the connection",
+                    ))
+                }
+            },
+        }
+    }
+}
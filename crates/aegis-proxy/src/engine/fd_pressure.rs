@@ -0,0 +1,73 @@
+//! File-descriptor pressure safety valve.
+//!
+//! Every proxied session holds two sockets, so a flood can push the process
+//! toward its `RLIMIT_NOFILE` limit, at which point `accept()` starts failing
+//! in ways that are hard to reason about. A background task samples the open
+//! FD count on a timer (never per connection) and raises `FD_PRESSURE` while
+//! usage is above the configured fraction of the limit; the accept loop sheds
+//! new connections until usage drops again.
+//!
+//! Sampling relies on `/proc/self/fd`, so the feature is Linux-only and is a
+//! no-op elsewhere.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Raised while open FDs are above the configured fraction of the limit.
+pub static FD_PRESSURE: AtomicBool = AtomicBool::new(false);
+
+/// Returns `(open, limit)` for this process, or `None` if unavailable.
+#[cfg(target_os = "linux")]
+pub fn fd_usage() -> Option<(usize, usize)> {
+    let open = std::fs::read_dir("/proc/self/fd").ok()?.count();
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid, writable rlimit struct.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+    Some((open, limit.rlim_cur as usize))
+}
+
+/// Returns `(open, limit)` for this process, or `None` if unavailable.
+#[cfg(not(target_os = "linux"))]
+pub fn fd_usage() -> Option<(usize, usize)> {
+    None
+}
+
+/// Periodically samples FD usage and toggles `FD_PRESSURE` around `threshold`
+/// (a fraction of the soft limit, e.g. 0.9).
+pub async fn start_fd_monitor(threshold: f64, check_interval: Duration) {
+    if fd_usage().is_none() {
+        warn!("FD pressure monitoring unavailable on this platform; feature disabled");
+        return;
+    }
+
+    let mut interval = tokio::time::interval(check_interval);
+    loop {
+        interval.tick().await;
+        let Some((open, limit)) = fd_usage() else {
+            continue;
+        };
+        crate::metrics::OPEN_FDS.set(open as i64);
+
+        let under_pressure = limit > 0 && open as f64 >= limit as f64 * threshold;
+        let was = FD_PRESSURE.swap(under_pressure, Ordering::SeqCst);
+        if under_pressure && !was {
+            warn!(
+                open_fds = open,
+                fd_limit = limit,
+                "FD pressure: shedding new connections"
+            );
+        } else if !under_pressure && was {
+            info!(
+                open_fds = open,
+                fd_limit = limit,
+                "FD pressure relieved: accepting connections"
+            );
+        }
+    }
+}
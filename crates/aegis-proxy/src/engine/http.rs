@@ -20,6 +20,8 @@
 //! - Enforce size limits (total headers, per-header, header count)
 //! - Reject if any limit exceeded
 
+use crate::metrics::RejectReason;
+use aegis_common::HttpInspectionConfig;
 use std::io;
 use std::time::Duration;
 use tokio::io::AsyncRead;
@@ -38,10 +40,34 @@ const MAX_REQUEST_LINE_SIZE: usize = 8192;
 pub enum HttpInspectionResult {
     /// Valid HTTP request detected (should be rejected - wrong protocol)
     HttpDetected,
+    /// Valid `CONNECT host:port` request asking for a tunnel, with its target.
+    /// Told apart from other requests so it is logged and counted on its own.
+    HttpConnectTunnel(String),
+    /// Valid HTTP request carrying `Upgrade: websocket`
+    WebSocketUpgrade,
+    /// WebSocket upgrade that offers an MQTT subprotocol
+    /// (`Sec-WebSocket-Protocol: mqtt`, or a legacy `mqttv3.1`)
+    MqttWebSocket,
     /// Not HTTP traffic
     NotHttp,
-    /// Slowloris attack detected (timeout or size limit exceeded)
-    SlowlorisDetected(String),
+    /// Slowloris attack detected (timeout or size limit exceeded), with the
+    /// reason it is counted under
+    SlowlorisDetected(RejectReason),
+    /// HTTP request using bare `\n` line endings while they are not allowed.
+    /// A known smuggling/evasion technique, reported rather than left to
+    /// time out as Slowloris.
+    BareLineFeed,
+}
+
+/// Outcome of reading one line.
+enum LineRead {
+    /// Line without its terminator.
+    Line(String),
+    /// EOF before any byte of the line.
+    Eof,
+    /// A lone `\n` ended the line while bare LF is not allowed; carries the
+    /// text read before it.
+    BareLf(String),
 }
 
 /// Parsed HTTP request line
@@ -50,6 +76,8 @@ struct RequestLine {
     method: String,
     uri: String,
     version: String,
+    /// `host:port` a CONNECT request asks to be tunnelled to.
+    tunnel_target: Option<String>,
 }
 
 /// Header struct removed — it was unused. Kept out-of-band to avoid dead_code warning.
@@ -61,7 +89,8 @@ struct RequestLine {
 /// * `idle_timeout` - Idle timeout between bytes
 /// * `max_header_size` - Maximum total size of all headers
 /// * `max_header_count` - Maximum number of headers
-/// * `max_header_line_size` - Maximum size of a single header line
+/// * `options` - Per-line size limit, bare-LF handling and the early
+///   classification cap (`max_headers_to_inspect`)
 ///
 /// # Returns
 /// * `HttpInspectionResult` indicating detection outcome
@@ -71,7 +100,7 @@ pub async fn inspect_http<R>(
     idle_timeout: Duration,
     max_header_size: usize,
     max_header_count: usize,
-    max_header_line_size: usize,
+    options: &HttpInspectionConfig,
 ) -> io::Result<HttpInspectionResult>
 where
     R: AsyncRead + Unpin,
@@ -84,7 +113,7 @@ where
             idle_timeout,
             max_header_size,
             max_header_count,
-            max_header_line_size,
+            options,
         ),
     )
     .await
@@ -92,7 +121,7 @@ where
         Ok(Ok(result)) => Ok(result),
         Ok(Err(e)) => Err(e),
         Err(_) => Ok(HttpInspectionResult::SlowlorisDetected(
-            "total request timeout exceeded".to_string(),
+            RejectReason::HttpRequestTimeout,
         )),
     }
 }
@@ -103,13 +132,27 @@ async fn parse_http_request<R>(
     idle_timeout: Duration,
     max_header_size: usize,
     max_header_count: usize,
-    max_header_line_size: usize,
+    options: &HttpInspectionConfig,
 ) -> io::Result<HttpInspectionResult>
 where
     R: AsyncRead + Unpin,
 {
+    let allow_bare_lf = options.allow_bare_lf;
     // Parse request line
-    let _request_line = match parse_request_line(reader, idle_timeout).await? {
+    let line =
+        match read_line_with_timeout(reader, idle_timeout, MAX_REQUEST_LINE_SIZE, allow_bare_lf)
+            .await?
+        {
+            LineRead::Line(line) => line,
+            LineRead::Eof => return Ok(HttpInspectionResult::NotHttp),
+            LineRead::BareLf(line) => {
+                return Ok(match parse_request_line(&line) {
+                    Some(_) => HttpInspectionResult::BareLineFeed,
+                    None => HttpInspectionResult::NotHttp,
+                })
+            }
+        };
+    let request_line = match parse_request_line(&line) {
         Some(line) => line,
         None => return Ok(HttpInspectionResult::NotHttp),
     };
@@ -117,23 +160,33 @@ where
     // Parse headers
     let mut total_header_bytes = 0;
     let mut header_count = 0;
+    let mut websocket_upgrade = false;
+    let mut mqtt_subprotocol = false;
 
     loop {
         // Check header count limit
         if header_count >= max_header_count {
             return Ok(HttpInspectionResult::SlowlorisDetected(
-                "max header count exceeded".to_string(),
+                RejectReason::HttpHeaderCountExceeded,
             ));
         }
 
         // Parse next header line
-        let line = match read_line_with_timeout(reader, idle_timeout, max_header_line_size).await? {
-            Some(line) => line,
-            None => {
+        let line = match read_line_with_timeout(
+            reader,
+            idle_timeout,
+            options.max_header_line_size,
+            allow_bare_lf,
+        )
+        .await?
+        {
+            LineRead::Line(line) => line,
+            LineRead::Eof => {
                 return Ok(HttpInspectionResult::SlowlorisDetected(
-                    "incomplete headers (EOF)".to_string(),
+                    RejectReason::HttpIncompleteHeaders,
                 ))
             }
+            LineRead::BareLf(_) => return Ok(HttpInspectionResult::BareLineFeed),
         };
 
         total_header_bytes += line.len() + 2; // +2 for \r\n
@@ -141,7 +194,7 @@ where
         // Check total header size
         if total_header_bytes > max_header_size {
             return Ok(HttpInspectionResult::SlowlorisDetected(
-                "max header size exceeded".to_string(),
+                RejectReason::HttpHeaderSizeExceeded,
             ));
         }
 
@@ -151,17 +204,48 @@ where
         }
 
         // Validate header format (must contain ':')
-        if !line.contains(':') {
+        let Some((name, value)) = line.split_once(':') else {
             return Ok(HttpInspectionResult::SlowlorisDetected(
-                "malformed header line".to_string(),
+                RejectReason::HttpMalformedHeader,
             ));
+        };
+
+        if name.trim().eq_ignore_ascii_case("upgrade")
+            && value.trim().eq_ignore_ascii_case("websocket")
+        {
+            websocket_upgrade = true;
+        }
+        if name.trim().eq_ignore_ascii_case("sec-websocket-protocol")
+            && value.split(',').any(is_mqtt_subprotocol)
+        {
+            mqtt_subprotocol = true;
         }
 
         header_count += 1;
+
+        // Seen enough to be sure it is HTTP; the rest is left unread.
+        if header_count >= options.max_headers_to_inspect {
+            crate::metrics::HTTP_EARLY_CLASSIFICATIONS.inc();
+            break;
+        }
     }
 
     // Valid HTTP request detected
-    Ok(HttpInspectionResult::HttpDetected)
+    if let Some(target) = request_line.tunnel_target {
+        Ok(HttpInspectionResult::HttpConnectTunnel(target))
+    } else if websocket_upgrade && mqtt_subprotocol {
+        Ok(HttpInspectionResult::MqttWebSocket)
+    } else if websocket_upgrade {
+        Ok(HttpInspectionResult::WebSocketUpgrade)
+    } else {
+        Ok(HttpInspectionResult::HttpDetected)
+    }
+}
+
+/// Whether one offered WebSocket subprotocol is MQTT (`mqtt`, `mqttv3.1`).
+fn is_mqtt_subprotocol(token: &str) -> bool {
+    let token = token.trim();
+    token.eq_ignore_ascii_case("mqtt") || token.eq_ignore_ascii_case("mqttv3.1")
 }
 
 /// Parses HTTP request line (e.g., "GET /path HTTP/1.1")
@@ -169,22 +253,11 @@ where
 /// Returns:
 /// * `Some(RequestLine)` if valid HTTP request line detected
 /// * `None` if not HTTP (doesn't start with known method)
-async fn parse_request_line<R>(
-    reader: &mut R,
-    idle_timeout: Duration,
-) -> io::Result<Option<RequestLine>>
-where
-    R: AsyncRead + Unpin,
-{
-    let line = match read_line_with_timeout(reader, idle_timeout, MAX_REQUEST_LINE_SIZE).await? {
-        Some(line) => line,
-        None => return Ok(None),
-    };
-
+fn parse_request_line(line: &str) -> Option<RequestLine> {
     // Parse "METHOD URI VERSION"
     let parts: Vec<&str> = line.split_whitespace().collect();
     if parts.len() != 3 {
-        return Ok(None);
+        return None;
     }
 
     let method = parts[0];
@@ -193,31 +266,52 @@ where
 
     // Check if method is valid HTTP method
     if !HTTP_METHODS.contains(&method) {
-        return Ok(None);
+        return None;
     }
 
     // Check if version starts with "HTTP/"
     if !version.starts_with("HTTP/") {
-        return Ok(None);
+        return None;
     }
 
-    Ok(Some(RequestLine {
+    // A CONNECT names its target in authority form, `host:port`; one that
+    // does not is an ordinary (if odd) request.
+    let tunnel_target = (method == "CONNECT")
+        .then_some(uri)
+        .filter(|uri| is_authority_form(uri))
+        .map(str::to_string);
+
+    Some(RequestLine {
         method: method.to_string(),
         uri: uri.to_string(),
         version: version.to_string(),
-    }))
+        tunnel_target,
+    })
+}
+
+/// Whether `uri` is `host:port`, with no scheme or path.
+fn is_authority_form(uri: &str) -> bool {
+    match uri.rsplit_once(':') {
+        Some((host, port)) => {
+            !host.is_empty() && !host.contains('/') && port.parse::<u16>().is_ok()
+        }
+        None => false,
+    }
 }
 
-/// Reads a line (terminated by \r\n) with timeout and size limit.
+/// Reads a line (terminated by \r\n, or also a lone \n when `allow_bare_lf`)
+/// with timeout and size limit.
 ///
 /// Returns:
-/// * `Some(String)` - Line without \r\n terminator
-/// * `None` - EOF reached before reading anything
+/// * `Line` - Line without its terminator
+/// * `Eof` - EOF reached before reading anything
+/// * `BareLf` - A lone \n was seen while bare LF is not allowed
 async fn read_line_with_timeout<R>(
     reader: &mut R,
     idle_timeout: Duration,
     max_line_size: usize,
-) -> io::Result<Option<String>>
+    allow_bare_lf: bool,
+) -> io::Result<LineRead>
 where
     R: AsyncRead + Unpin,
 {
@@ -244,7 +338,7 @@ where
             Ok(Ok(0)) => {
                 // EOF
                 if line.is_empty() {
-                    return Ok(None);
+                    return Ok(LineRead::Eof);
                 } else {
                     // Incomplete line
                     return Err(io::Error::new(
@@ -271,13 +365,22 @@ where
             line.pop();
             break;
         }
+        if current_byte == b'\n' {
+            if allow_bare_lf {
+                break;
+            }
+            return line_to_string(line).map(LineRead::BareLf);
+        }
 
         line.push(current_byte);
         prev_byte = current_byte;
     }
 
+    line_to_string(line).map(LineRead::Line)
+}
+
+fn line_to_string(line: Vec<u8>) -> io::Result<String> {
     String::from_utf8(line)
-        .map(Some)
         .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8 in line"))
 }
 
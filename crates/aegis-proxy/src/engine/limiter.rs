@@ -1,9 +1,22 @@
-use aegis_common::LimitConfig;
+use crate::engine::cidr::CidrSet;
+use aegis_common::{
+    ConnectRatioConfig, InFlightConnectConfig, LimitConfig, RateLimitBackendKind, RateLimiterKind,
+    RedisFailurePolicy, RepeatedMalformedConfig, SessionRateConfig, SubnetCapConfig,
+};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
+use ipnet::IpNet;
 use once_cell::sync::Lazy;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io;
 use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tracing::{debug, info, warn};
 
 pub struct TokenBucket {
@@ -13,9 +26,100 @@ pub struct TokenBucket {
 
 pub static IP_TRACKER: Lazy<DashMap<IpAddr, TokenBucket>> = Lazy::new(DashMap::new);
 
-pub fn check_rate_limit(addr: IpAddr, config: &LimitConfig) -> bool {
-    let mut entry = IP_TRACKER.entry(addr).or_insert_with(|| TokenBucket {
-        tokens: config.max_tokens,
+/// Per-network accept buckets, when `subnet_prefix_v4` / `subnet_prefix_v6`
+/// are set.
+pub static SUBNET_TRACKER: Lazy<DashMap<IpNet, TokenBucket>> = Lazy::new(DashMap::new);
+
+/// Per-IP accept times within the current window, oldest first, for
+/// `RateLimiterKind::SlidingWindow`. Holds at most `max_tokens` entries per IP.
+pub static WINDOW_TRACKER: Lazy<DashMap<IpAddr, VecDeque<Instant>>> = Lazy::new(DashMap::new);
+
+/// Per-IP buckets for backend-bound MQTT sessions (see `SessionRateConfig`).
+pub static SESSION_TRACKER: Lazy<DashMap<IpAddr, TokenBucket>> = Lazy::new(DashMap::new);
+
+/// Key under which `addr` is tracked by the per-IP limiters.
+///
+/// IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`, seen on dual-stack
+/// listeners) are folded into their IPv4 form so a client shares one bucket
+/// however it reached us. IPv6 zone/scope ids are not part of `IpAddr` and are
+/// dropped deliberately: a link-local address is keyed the same on every
+/// interface, which is stable and errs toward limiting.
+pub fn client_key(addr: IpAddr) -> IpAddr {
+    addr.to_canonical()
+}
+
+/// Opened vs completed connections for one IP in the current window.
+pub struct ConnectRatio {
+    window_start: Instant,
+    opened: u32,
+    completed: u32,
+    banned_until: Option<Instant>,
+}
+
+/// Per-IP open/complete counts (see `ConnectRatioConfig`).
+pub static CONNECT_RATIO_TRACKER: Lazy<DashMap<IpAddr, ConnectRatio>> = Lazy::new(DashMap::new);
+
+/// Recent malformed CONNECTs from one IP, by content hash.
+pub struct MalformedHistory {
+    window_start: Instant,
+    /// `(hash, count)`, oldest first; bounded by `max_hashes_per_ip`.
+    seen: Vec<(u64, u32)>,
+    banned_until: Option<Instant>,
+}
+
+/// Per-IP malformed packet history (see `RepeatedMalformedConfig`).
+pub static MALFORMED_TRACKER: Lazy<DashMap<IpAddr, MalformedHistory>> = Lazy::new(DashMap::new);
+
+/// Per-IP slots for connections still being established (see
+/// `InFlightConnectConfig`).
+pub static IN_FLIGHT_TRACKER: Lazy<DashMap<IpAddr, Arc<Semaphore>>> = Lazy::new(DashMap::new);
+
+/// Open connections per IP (see `max_concurrent_per_ip`).
+pub static CONCURRENT_TRACKER: Lazy<DashMap<IpAddr, AtomicUsize>> = Lazy::new(DashMap::new);
+
+/// One open connection counted against its IP; released on drop.
+pub struct ConcurrencySlot {
+    addr: IpAddr,
+}
+
+impl Drop for ConcurrencySlot {
+    fn drop(&mut self) {
+        CONCURRENT_TRACKER.remove_if(&self.addr, |_, open| {
+            open.fetch_sub(1, Ordering::SeqCst) == 1
+        });
+    }
+}
+
+/// Counts a connection from `addr` against `max` open connections per IP.
+/// `None` means the IP is at its cap.
+pub fn try_acquire_concurrent(addr: IpAddr, max: usize) -> Option<ConcurrencySlot> {
+    let addr = client_key(addr);
+    let previous = CONCURRENT_TRACKER
+        .entry(addr)
+        .or_insert_with(|| AtomicUsize::new(0))
+        .fetch_add(1, Ordering::SeqCst);
+    // Dropping the slot on rejection undoes the increment.
+    let slot = ConcurrencySlot { addr };
+    (previous < max).then_some(slot)
+}
+
+/// Open connections currently counted for `addr`.
+pub fn concurrent_connections(addr: IpAddr) -> usize {
+    CONCURRENT_TRACKER
+        .get(&client_key(addr))
+        .map_or(0, |open| open.load(Ordering::SeqCst))
+}
+
+/// Refills `key`'s bucket and takes a token if one is available.
+/// Returns whether it was taken, with the token counts before and after.
+fn take_token<K: Hash + Eq>(
+    tracker: &DashMap<K, TokenBucket>,
+    key: K,
+    max_tokens: f64,
+    refill_rate: f64,
+) -> (bool, f64, f64) {
+    let mut entry = tracker.entry(key).or_insert_with(|| TokenBucket {
+        tokens: max_tokens,
         last_refill: Instant::now(),
     });
 
@@ -23,23 +127,475 @@ pub fn check_rate_limit(addr: IpAddr, config: &LimitConfig) -> bool {
     let elapsed = now.duration_since(entry.last_refill).as_secs_f64();
 
     let old_tokens = entry.tokens;
-    entry.tokens = (entry.tokens + elapsed * config.refill_rate).min(config.max_tokens);
+    entry.tokens = (entry.tokens + elapsed * refill_rate).min(max_tokens);
     entry.last_refill = now;
 
-    if entry.tokens >= 1.0 {
+    let allowed = entry.tokens >= 1.0;
+    if allowed {
         entry.tokens -= 1.0;
-        debug!(
-            "IP {}: {:.2} -> {:.2} (Allowed)",
-            addr, old_tokens, entry.tokens
+    }
+    (allowed, old_tokens, entry.tokens)
+}
+
+/// Admits `addr` if it had fewer than `max` accepts within the last `window`,
+/// recording this one. Returns whether it was admitted and the accepts counted
+/// in the window.
+fn take_window_slot(addr: IpAddr, max: f64, window: Duration) -> (bool, usize) {
+    let now = Instant::now();
+    let mut accepts = WINDOW_TRACKER.entry(client_key(addr)).or_default();
+    while accepts
+        .front()
+        .is_some_and(|at| now.duration_since(*at) >= window)
+    {
+        accepts.pop_front();
+    }
+    let allowed = (accepts.len() as f64) < max.floor();
+    if allowed {
+        accepts.push_back(now);
+    }
+    (allowed, accepts.len())
+}
+
+/// Storage for the accept token buckets (`limit.backend`).
+pub trait RateLimitBackend {
+    /// Refills the bucket of `key`, a single address or a whole network, and
+    /// takes a token if one is available. Returns whether it was taken and
+    /// the tokens left; an error means no decision could be made.
+    fn take_token(
+        &self,
+        key: IpNet,
+        max_tokens: f64,
+        refill_rate: f64,
+        config: &LimitConfig,
+    ) -> impl Future<Output = io::Result<(bool, f64)>> + Send;
+}
+
+/// Buckets in this process (`IP_TRACKER`, `SUBNET_TRACKER`).
+pub struct MemoryBackend;
+
+impl RateLimitBackend for MemoryBackend {
+    async fn take_token(
+        &self,
+        key: IpNet,
+        max_tokens: f64,
+        refill_rate: f64,
+        _config: &LimitConfig,
+    ) -> io::Result<(bool, f64)> {
+        let (allowed, _, tokens) = if is_host(&key) {
+            take_token(&IP_TRACKER, key.addr(), max_tokens, refill_rate)
+        } else {
+            take_token(&SUBNET_TRACKER, key, max_tokens, refill_rate)
+        };
+        Ok((allowed, tokens))
+    }
+}
+
+fn is_host(key: &IpNet) -> bool {
+    key.prefix_len() == key.max_prefix_len()
+}
+
+/// How a bucket key is written in logs and Redis: the bare address for a
+/// single client, `network/prefix` for a subnet.
+pub fn bucket_name(key: &IpNet) -> String {
+    if is_host(key) {
+        key.addr().to_string()
+    } else {
+        key.to_string()
+    }
+}
+
+/// The network whose shared bucket `addr` also draws from, when
+/// `subnet_prefix_v4` / `subnet_prefix_v6` is set for its address family.
+pub fn rate_limit_subnet(addr: IpAddr, config: &LimitConfig) -> Option<IpNet> {
+    let addr = client_key(addr);
+    let prefix = match addr {
+        IpAddr::V4(_) => config.subnet_prefix_v4?,
+        IpAddr::V6(_) => config.subnet_prefix_v6?,
+    };
+    // Prefix lengths are validated with the configuration.
+    IpNet::new(addr, prefix).ok().map(|net| net.trunc())
+}
+
+/// Takes an accept token for `addr`, and one from its subnet's bucket when
+/// subnet limiting is configured; the connection needs both. Only the Redis
+/// backend ever waits; when it cannot decide, `redis_failure_policy` does.
+pub async fn check_rate_limit(addr: IpAddr, config: &LimitConfig) -> bool {
+    if config.rate_limiter == RateLimiterKind::SlidingWindow {
+        let (allowed, in_window) = take_window_slot(
+            addr,
+            config.max_tokens,
+            Duration::from_secs(config.window_secs),
         );
-        true
+        if allowed {
+            crate::metrics::RATE_LIMIT_ALLOWED.inc();
+            debug!("IP {}: {} in window (Allowed)", addr, in_window);
+        } else {
+            warn!(
+                "IP {}: Rate limit hit. {} in window (Dropped)",
+                addr, in_window
+            );
+        }
+        return allowed;
+    }
+    let host = IpNet::from(client_key(addr));
+    if !take_from_bucket(host, config.max_tokens, config.refill_rate, config).await {
+        return false;
+    }
+    // Only clients within their own limit draw from the shared bucket, so one
+    // throttled address does not drain it for its neighbours.
+    let allowed = match rate_limit_subnet(addr, config) {
+        Some(subnet) => {
+            take_from_bucket(
+                subnet,
+                config.subnet_max_tokens.unwrap_or(config.max_tokens),
+                config.subnet_refill_rate.unwrap_or(config.refill_rate),
+                config,
+            )
+            .await
+        }
+        None => true,
+    };
+    if allowed {
+        crate::metrics::RATE_LIMIT_ALLOWED.inc();
+    }
+    allowed
+}
+
+async fn take_from_bucket(
+    key: IpNet,
+    max_tokens: f64,
+    refill_rate: f64,
+    config: &LimitConfig,
+) -> bool {
+    let taken = match config.backend {
+        RateLimitBackendKind::Memory => {
+            MemoryBackend
+                .take_token(key, max_tokens, refill_rate, config)
+                .await
+        }
+        RateLimitBackendKind::Redis => match crate::engine::redis_limiter::installed() {
+            Some(redis) => redis.take_token(key, max_tokens, refill_rate, config).await,
+            None => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "redis rate limiting is not initialized",
+            )),
+        },
+    };
+    let scope = if is_host(&key) { "IP" } else { "Subnet" };
+    let name = bucket_name(&key);
+    let (allowed, tokens) = match taken {
+        Ok(taken) => taken,
+        Err(e) => {
+            crate::metrics::RATE_LIMIT_BACKEND_ERRORS.inc();
+            let admit = config.redis_failure_policy == RedisFailurePolicy::FailOpen;
+            debug!(bucket = %name, error = %e, admit, "No rate limit decision from Redis");
+            return admit;
+        }
+    };
+    if is_host(&key) {
+        crate::metrics::RATE_LIMIT_TOKENS_REMAINING.observe(tokens);
+    }
+    if allowed {
+        debug!("{} {}: {:.2} left (Allowed)", scope, name, tokens);
     } else {
         warn!(
-            "IP {}: Rate limit hit. Tokens: {:.2} (Dropped)",
-            addr, entry.tokens
+            "{} {}: Rate limit hit. Tokens: {:.2} (Dropped)",
+            scope, name, tokens
         );
-        false
     }
+    allowed
+}
+
+/// One client's accept bucket as last updated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketState {
+    pub addr: IpAddr,
+    /// Tokens left at the last refill; refills are applied lazily on the
+    /// next accept, so this can be lower than the client would now get.
+    pub tokens: f64,
+    /// Time since the bucket was last refilled.
+    pub last_refill_age: Duration,
+}
+
+/// Current `IP_TRACKER` contents, most throttled first.
+pub fn rate_limit_snapshot() -> Vec<BucketState> {
+    let now = Instant::now();
+    let mut buckets: Vec<BucketState> = IP_TRACKER
+        .iter()
+        .map(|entry| BucketState {
+            addr: *entry.key(),
+            tokens: entry.tokens,
+            last_refill_age: now.duration_since(entry.last_refill),
+        })
+        .collect();
+    buckets.sort_by(|a, b| a.tokens.total_cmp(&b.tokens).then(a.addr.cmp(&b.addr)));
+    buckets
+}
+
+/// Forgets the accept history of `addr` under either rate limiter, so its
+/// next connection starts from a full bucket. Returns whether anything was
+/// tracked.
+pub fn reset_rate_limit(addr: IpAddr) -> bool {
+    let addr = client_key(addr);
+    let bucket = IP_TRACKER.remove(&addr).is_some();
+    let window = WINDOW_TRACKER.remove(&addr).is_some();
+    if bucket || window {
+        info!(client_ip = %addr, "Rate limit state reset");
+    }
+    bucket || window
+}
+
+/// Takes a session token for `addr`; `false` means the session is over rate.
+pub fn check_session_rate(addr: IpAddr, config: &SessionRateConfig) -> bool {
+    let (allowed, _, tokens) = take_token(
+        &SESSION_TRACKER,
+        client_key(addr),
+        config.max_tokens,
+        config.refill_rate,
+    );
+    if !allowed {
+        debug!(client_ip = %addr, tokens, "Session rate limit hit");
+    }
+    allowed
+}
+
+/// Counts a newly opened connection from `addr`; `false` means the IP is
+/// banned, either already or because this open skewed its ratio too far.
+pub fn check_connect_ratio(addr: IpAddr, config: &ConnectRatioConfig) -> bool {
+    let now = Instant::now();
+    let window = Duration::from_secs(config.window_secs);
+    let mut entry = CONNECT_RATIO_TRACKER
+        .entry(client_key(addr))
+        .or_insert_with(|| ConnectRatio {
+            window_start: now,
+            opened: 0,
+            completed: 0,
+            banned_until: None,
+        });
+
+    if let Some(until) = entry.banned_until {
+        if now < until {
+            return false;
+        }
+        entry.banned_until = None;
+    }
+    if now.duration_since(entry.window_start) >= window {
+        entry.window_start = now;
+        entry.opened = 0;
+        entry.completed = 0;
+    }
+
+    entry.opened = entry.opened.saturating_add(1);
+    // Connections still in inspection count as incomplete; `min_opens` keeps
+    // that from mattering for ordinary clients.
+    if entry.opened >= config.min_opens
+        && f64::from(entry.opened) > config.max_ratio * f64::from(entry.completed.max(1))
+    {
+        warn!(
+            client_ip = %addr,
+            opened = entry.opened,
+            completed = entry.completed,
+            ban_secs = config.ban_secs,
+            "Banning client: too many connections without a completed CONNECT"
+        );
+        crate::metrics::CONNECT_RATIO_BANS.inc();
+        entry.banned_until = Some(now + Duration::from_secs(config.ban_secs));
+        return false;
+    }
+    true
+}
+
+/// Counts a connection from `addr` that completed inspection. A no-op for
+/// IPs that are not tracked.
+pub fn record_connect_completed(addr: IpAddr) {
+    if let Some(mut entry) = CONNECT_RATIO_TRACKER.get_mut(&client_key(addr)) {
+        entry.completed = entry.completed.saturating_add(1);
+    }
+}
+
+/// Takes one of `addr`'s establishment slots, waiting up to `max_wait_ms`
+/// for one to free up. `None` means the wait timed out. The slot is released
+/// when the permit is dropped.
+pub async fn acquire_in_flight(
+    addr: IpAddr,
+    config: &InFlightConnectConfig,
+) -> Option<OwnedSemaphorePermit> {
+    let slots = IN_FLIGHT_TRACKER
+        .entry(client_key(addr))
+        .or_insert_with(|| Arc::new(Semaphore::new(config.max_per_ip)))
+        .clone();
+    if let Ok(permit) = Arc::clone(&slots).try_acquire_owned() {
+        return Some(permit);
+    }
+
+    let started = Instant::now();
+    let permit = tokio::time::timeout(
+        Duration::from_millis(config.max_wait_ms),
+        slots.acquire_owned(),
+    )
+    .await;
+    crate::metrics::IN_FLIGHT_CONNECT_WAIT.observe(started.elapsed().as_secs_f64());
+    match permit {
+        Ok(Ok(permit)) => Some(permit),
+        // The semaphore is never closed; treat it like a timeout anyway.
+        _ => None,
+    }
+}
+
+/// Stable hash identifying a malformed packet across connections.
+pub fn packet_hash(packet: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    packet.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Records a rejected malformed packet from `addr`. Returns the packet hash
+/// when this repeat crossed the threshold and the IP was banned.
+pub fn record_malformed(
+    addr: IpAddr,
+    packet: &[u8],
+    config: &RepeatedMalformedConfig,
+) -> Option<u64> {
+    let now = Instant::now();
+    let hash = packet_hash(packet);
+    let mut entry = MALFORMED_TRACKER
+        .entry(client_key(addr))
+        .or_insert_with(|| MalformedHistory {
+            window_start: now,
+            seen: Vec::new(),
+            banned_until: None,
+        });
+
+    if now.duration_since(entry.window_start) >= Duration::from_secs(config.window_secs) {
+        entry.window_start = now;
+        entry.seen.clear();
+    }
+
+    let count = match entry.seen.iter_mut().find(|(h, _)| *h == hash) {
+        Some((_, count)) => {
+            *count = count.saturating_add(1);
+            *count
+        }
+        None => {
+            if entry.seen.len() >= config.max_hashes_per_ip.max(1) {
+                entry.seen.remove(0);
+            }
+            entry.seen.push((hash, 1));
+            1
+        }
+    };
+
+    if count < config.threshold {
+        return None;
+    }
+    entry.banned_until = Some(now + Duration::from_secs(config.ban_secs));
+    entry.seen.retain(|(h, _)| *h != hash);
+    Some(hash)
+}
+
+/// Whether `addr` is currently banned for repeating a malformed packet.
+pub fn malformed_banned(addr: IpAddr) -> bool {
+    MALFORMED_TRACKER
+        .get(&client_key(addr))
+        .and_then(|entry| entry.banned_until)
+        .is_some_and(|until| Instant::now() < until)
+}
+
+/// Per-IP limiter state worth keeping across restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeldState {
+    /// Banned by the connect-ratio check until the given time.
+    ConnectRatioBan(IpAddr, SystemTime),
+    /// Banned for repeated malformed packets until the given time.
+    MalformedBan(IpAddr, SystemTime),
+    /// Out of connection rate-limit tokens.
+    Throttled(IpAddr),
+}
+
+/// Wall-clock time of `until`, for state that outlives the process.
+fn wall_clock(until: Instant, now: Instant) -> SystemTime {
+    SystemTime::now() + until.saturating_duration_since(now)
+}
+
+/// Active bans and throttled IPs. Transient buckets and ban-free histories
+/// are left out to keep the snapshot small.
+pub fn export_held_state() -> Vec<HeldState> {
+    let now = Instant::now();
+    let mut held = Vec::new();
+    for entry in CONNECT_RATIO_TRACKER.iter() {
+        if let Some(until) = entry.banned_until.filter(|until| *until > now) {
+            held.push(HeldState::ConnectRatioBan(
+                *entry.key(),
+                wall_clock(until, now),
+            ));
+        }
+    }
+    for entry in MALFORMED_TRACKER.iter() {
+        if let Some(until) = entry.banned_until.filter(|until| *until > now) {
+            held.push(HeldState::MalformedBan(
+                *entry.key(),
+                wall_clock(until, now),
+            ));
+        }
+    }
+    for entry in IP_TRACKER.iter() {
+        if entry.tokens < 1.0 {
+            held.push(HeldState::Throttled(*entry.key()));
+        }
+    }
+    held
+}
+
+/// Reinstates `held` state; bans that expired meanwhile are skipped.
+/// Throttled IPs start from an empty bucket. Returns how many entries were
+/// restored.
+pub fn import_held_state(held: &[HeldState]) -> usize {
+    let now = Instant::now();
+    let wall_now = SystemTime::now();
+    let remaining = |until: &SystemTime| until.duration_since(wall_now).ok();
+    let mut restored = 0;
+    for state in held {
+        match state {
+            HeldState::ConnectRatioBan(addr, until) => {
+                let Some(left) = remaining(until) else {
+                    continue;
+                };
+                CONNECT_RATIO_TRACKER
+                    .entry(client_key(*addr))
+                    .or_insert_with(|| ConnectRatio {
+                        window_start: now,
+                        opened: 0,
+                        completed: 0,
+                        banned_until: None,
+                    })
+                    .banned_until = Some(now + left);
+            }
+            HeldState::MalformedBan(addr, until) => {
+                let Some(left) = remaining(until) else {
+                    continue;
+                };
+                MALFORMED_TRACKER
+                    .entry(client_key(*addr))
+                    .or_insert_with(|| MalformedHistory {
+                        window_start: now,
+                        seen: Vec::new(),
+                        banned_until: None,
+                    })
+                    .banned_until = Some(now + left);
+            }
+            HeldState::Throttled(addr) => {
+                IP_TRACKER.insert(
+                    client_key(*addr),
+                    TokenBucket {
+                        tokens: 0.0,
+                        last_refill: now,
+                    },
+                );
+            }
+        }
+        restored += 1;
+    }
+    restored
 }
 
 pub async fn start_cleanup_task(config: Arc<LimitConfig>) {
@@ -52,8 +608,28 @@ pub async fn start_cleanup_task(config: Arc<LimitConfig>) {
         let initial_size = IP_TRACKER.len();
 
         IP_TRACKER.retain(|_, bucket| now.duration_since(bucket.last_refill) < timeout);
+        SESSION_TRACKER.retain(|_, bucket| now.duration_since(bucket.last_refill) < timeout);
+        SUBNET_TRACKER.retain(|_, bucket| now.duration_since(bucket.last_refill) < timeout);
+        // Accepts still inside a window must outlive a shorter idle timeout.
+        let window = Duration::from_secs(config.window_secs).max(timeout);
+        WINDOW_TRACKER.retain(|_, accepts| {
+            accepts
+                .back()
+                .is_some_and(|at| now.duration_since(*at) < window)
+        });
+        MALFORMED_TRACKER.retain(|_, history| {
+            history.banned_until.is_some_and(|until| until > now)
+                || now.duration_since(history.window_start) < timeout
+        });
+        // Outstanding permits and waiters hold their own handle.
+        IN_FLIGHT_TRACKER.retain(|_, slots| Arc::strong_count(slots) > 1);
+        CONNECT_RATIO_TRACKER.retain(|_, ratio| {
+            ratio.banned_until.is_some_and(|until| until > now)
+                || now.duration_since(ratio.window_start) < timeout
+        });
 
         let final_size = IP_TRACKER.len();
+        crate::metrics::TRACKED_IPS.set(final_size as i64);
         if initial_size != final_size {
             info!(
                 "Cleanup: GC removed {} inactive IPs.",
@@ -62,3 +638,158 @@ pub async fn start_cleanup_task(config: Arc<LimitConfig>) {
         }
     }
 }
+
+/// Aggregate concurrent-connection cap per client subnet.
+///
+/// Per-IP limits do not stop a cooperative flood spread across one network
+/// block, so concurrent connections are also counted per masked prefix.
+#[derive(Debug)]
+pub struct SubnetLimiter {
+    max_connections: usize,
+    ipv4_prefix: u8,
+    ipv6_prefix: u8,
+    exempt: CidrSet,
+    counts: Arc<DashMap<IpNet, usize>>,
+}
+
+/// Holds one connection slot in its subnet; released on drop.
+pub struct SubnetSlot {
+    subnet: Option<IpNet>,
+    counts: Arc<DashMap<IpNet, usize>>,
+}
+
+impl SubnetLimiter {
+    pub fn from_config(config: &SubnetCapConfig) -> Result<Self, String> {
+        if config.max_connections == 0 {
+            return Err("subnet_cap: max_connections must be at least 1".to_string());
+        }
+        if config.ipv4_prefix > 32 || config.ipv6_prefix > 128 {
+            return Err(format!(
+                "subnet_cap: invalid prefix lengths /{} (IPv4), /{} (IPv6)",
+                config.ipv4_prefix, config.ipv6_prefix
+            ));
+        }
+        let exempt =
+            CidrSet::parse(&config.exempt_cidrs).map_err(|e| format!("subnet_cap: {}", e))?;
+        Ok(Self {
+            max_connections: config.max_connections,
+            ipv4_prefix: config.ipv4_prefix,
+            ipv6_prefix: config.ipv6_prefix,
+            exempt,
+            counts: Arc::new(DashMap::new()),
+        })
+    }
+
+    /// Subnet `addr` is counted under.
+    pub fn subnet_of(&self, addr: IpAddr) -> IpNet {
+        let addr = client_key(addr);
+        let prefix = match addr {
+            IpAddr::V4(_) => self.ipv4_prefix,
+            IpAddr::V6(_) => self.ipv6_prefix,
+        };
+        // Prefix lengths are validated in `from_config`.
+        IpNet::new(addr, prefix)
+            .map(|net| net.trunc())
+            .unwrap_or_else(|_| IpNet::from(addr))
+    }
+
+    /// Takes a slot for `addr`, or returns `None` if its subnet is at the cap.
+    pub fn try_acquire(&self, addr: IpAddr) -> Option<SubnetSlot> {
+        if self.exempt.contains(addr) {
+            return Some(SubnetSlot {
+                subnet: None,
+                counts: Arc::clone(&self.counts),
+            });
+        }
+
+        let subnet = self.subnet_of(addr);
+        let mut count = self.counts.entry(subnet).or_insert(0);
+        if *count >= self.max_connections {
+            warn!(client_ip = %addr, subnet = %subnet, "Subnet connection cap reached");
+            return None;
+        }
+        *count += 1;
+        Some(SubnetSlot {
+            subnet: Some(subnet),
+            counts: Arc::clone(&self.counts),
+        })
+    }
+
+    /// Current concurrent connections counted for `addr`'s subnet.
+    pub fn active_in_subnet(&self, addr: IpAddr) -> usize {
+        self.counts
+            .get(&self.subnet_of(addr))
+            .map(|count| *count)
+            .unwrap_or(0)
+    }
+}
+
+impl Drop for SubnetSlot {
+    fn drop(&mut self) {
+        if let Some(subnet) = self.subnet {
+            if let Entry::Occupied(mut entry) = self.counts.entry(subnet) {
+                *entry.get_mut() -= 1;
+                if *entry.get() == 0 {
+                    entry.remove();
+                }
+            }
+        }
+    }
+}
+
+/// Cap on connections in the inspection phase (accept until forwarding
+/// starts) at once, across all clients. Inspecting connections hold read
+/// buffers and timers, so this bounds what a Slowloris flood can tie up.
+/// Occupancy is metered even without a cap.
+pub struct InspectionLimiter {
+    max: Option<usize>,
+    active: Arc<AtomicUsize>,
+}
+
+/// One connection in inspection; released on drop.
+pub struct InspectionSlot {
+    active: Arc<AtomicUsize>,
+}
+
+impl InspectionLimiter {
+    /// `None` meters occupancy without limiting it.
+    pub fn from_config(max: Option<usize>) -> Result<Self, String> {
+        if max == Some(0) {
+            return Err("limit: max_concurrent_inspections must be at least 1".to_string());
+        }
+        Ok(Self {
+            max,
+            active: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn is_capped(&self) -> bool {
+        self.max.is_some()
+    }
+
+    /// Takes a slot, or returns `None` if inspection is at the cap.
+    pub fn try_acquire(&self) -> Option<InspectionSlot> {
+        let max = self.max.unwrap_or(usize::MAX);
+        self.active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                (active < max).then_some(active + 1)
+            })
+            .ok()?;
+        crate::metrics::INSPECTIONS_IN_PROGRESS.inc();
+        Some(InspectionSlot {
+            active: Arc::clone(&self.active),
+        })
+    }
+
+    /// Connections currently in inspection.
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for InspectionSlot {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        crate::metrics::INSPECTIONS_IN_PROGRESS.dec();
+    }
+}
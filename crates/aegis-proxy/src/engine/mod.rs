@@ -1,4 +1,28 @@
+pub mod accept_filter;
+pub mod access_log;
+#[cfg(unix)]
+pub mod admin;
+pub mod backend;
+pub mod capture;
+pub mod cidr;
 pub mod connection;
+#[cfg(feature = "nats-events")]
+pub mod events;
+pub mod fd_pressure;
 pub mod http;
 pub mod limiter;
+pub mod persist;
+pub mod policy;
+pub mod proxy_protocol;
+pub mod redis_limiter;
+pub mod registry;
+pub mod signature;
 pub mod slowloris;
+pub mod sockopt;
+pub mod splice;
+pub mod tags;
+pub mod tls;
+pub mod topics;
+pub mod trace;
+#[cfg(unix)]
+pub mod unix;
@@ -0,0 +1,144 @@
+//! Limiter state that survives restarts (see `persist_state`).
+//!
+//! Bans live in memory, so without this a deploy or crash gives every banned
+//! client a clean slate. The state is written as a small text file, one entry
+//! per line after a version header:
+//!
+//! ```text
+//! # aegis limiter state v1
+//! connect_ratio_ban 203.0.113.7 1767225600
+//! malformed_ban 2001:db8::1 1767225900
+//! throttled 198.51.100.2
+//! ```
+//!
+//! Ban lines end with the Unix time (seconds) the ban lifts. The file is
+//! replaced atomically (write then rename), and a file that does not parse is
+//! ignored as a whole rather than half-applied.
+
+use crate::engine::limiter::{export_held_state, import_held_state, HeldState};
+use aegis_common::PersistStateConfig;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+const HEADER: &str = "# aegis limiter state v1";
+
+/// Renders `held` in the state file format.
+pub fn encode(held: &[HeldState]) -> String {
+    let mut out = format!("{}\n", HEADER);
+    for state in held {
+        let line = match state {
+            HeldState::ConnectRatioBan(addr, until) => {
+                format!("connect_ratio_ban {} {}", addr, unix_secs(*until))
+            }
+            HeldState::MalformedBan(addr, until) => {
+                format!("malformed_ban {} {}", addr, unix_secs(*until))
+            }
+            HeldState::Throttled(addr) => format!("throttled {}", addr),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Parses a state file; any unrecognised line fails the whole file.
+pub fn decode(text: &str) -> Result<Vec<HeldState>, String> {
+    let mut lines = text.lines();
+    if lines.next() != Some(HEADER) {
+        return Err("missing or unsupported header".to_string());
+    }
+    let mut held = Vec::new();
+    for (number, line) in lines.enumerate().map(|(i, l)| (i + 2, l)) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let addr = |word: &str| {
+            word.parse::<IpAddr>()
+                .map_err(|_| format!("line {}: invalid address '{}'", number, word))
+        };
+        let until = |word: &str| {
+            word.parse::<u64>()
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+                .map_err(|_| format!("line {}: invalid time '{}'", number, word))
+        };
+        let state = match words.as_slice() {
+            ["connect_ratio_ban", ip, secs] => HeldState::ConnectRatioBan(addr(ip)?, until(secs)?),
+            ["malformed_ban", ip, secs] => HeldState::MalformedBan(addr(ip)?, until(secs)?),
+            ["throttled", ip] => HeldState::Throttled(addr(ip)?),
+            _ => return Err(format!("line {}: unrecognised entry", number)),
+        };
+        held.push(state);
+    }
+    Ok(held)
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Restores the state saved at `path`. A missing or corrupt file starts
+/// fresh; returns how many entries were restored.
+pub fn load(path: &Path) -> usize {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!(path = %path.display(), "No saved limiter state; starting fresh");
+            return 0;
+        }
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "Could not read saved limiter state; starting fresh");
+            return 0;
+        }
+    };
+    match decode(&text) {
+        Ok(held) => {
+            let restored = import_held_state(&held);
+            info!(path = %path.display(), restored, "Restored limiter state");
+            restored
+        }
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "Saved limiter state is corrupt; starting fresh");
+            0
+        }
+    }
+}
+
+/// Writes the current state to `path`, replacing it atomically. Returns how
+/// many entries were written.
+pub fn save(path: &Path) -> io::Result<usize> {
+    let held = export_held_state();
+    let mut staging = path.as_os_str().to_owned();
+    staging.push(".tmp");
+    std::fs::write(&staging, encode(&held))?;
+    std::fs::rename(&staging, path)?;
+    Ok(held.len())
+}
+
+/// Saves the state every `interval_secs` until `shutdown` is cancelled, then
+/// once more so the final bans are kept.
+pub async fn run_persistence(config: PersistStateConfig, shutdown: CancellationToken) {
+    let path = Path::new(&config.path);
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+    // The first tick completes immediately; nothing has changed since load.
+    ticker.tick().await;
+    loop {
+        let stopping = tokio::select! {
+            _ = ticker.tick() => false,
+            _ = shutdown.cancelled() => true,
+        };
+        match save(path) {
+            Ok(entries) => debug!(path = %config.path, entries, "Saved limiter state"),
+            Err(e) => warn!(path = %config.path, error = %e, "Could not save limiter state"),
+        }
+        if stopping {
+            break;
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Per-source-network policy profiles.
+//!
+//! Operators group client networks (internal, partners, unknown, ...) and give
+//! each group its own protection profile. Profiles are resolved against the
+//! global `features` / `limit` settings once at startup, so the per-connection
+//! work is a longest-prefix match over a handful of CIDR sets.
+
+use crate::engine::cidr::CidrSet;
+use aegis_common::{
+    FeaturesConfig, LimitConfig, RateLimiterKind, SourcePolicyConfig, SourceProfile,
+};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Name reported for connections handled with the global settings.
+pub const GLOBAL_PROFILE: &str = "default";
+
+/// A fully resolved protection profile.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub features: FeaturesConfig,
+    pub limit: Arc<LimitConfig>,
+}
+
+impl Profile {
+    fn resolve(
+        name: &str,
+        overrides: &SourceProfile,
+        features: &FeaturesConfig,
+        limit: &LimitConfig,
+    ) -> Result<Self, String> {
+        let mut features = features.clone();
+        let flags = [
+            (
+                &mut features.enable_mqtt_inspection,
+                overrides.enable_mqtt_inspection,
+            ),
+            (
+                &mut features.enable_mqtt_full_inspection,
+                overrides.enable_mqtt_full_inspection,
+            ),
+            (
+                &mut features.enable_http_inspection,
+                overrides.enable_http_inspection,
+            ),
+            (
+                &mut features.enable_slowloris_protection,
+                overrides.enable_slowloris_protection,
+            ),
+            (
+                &mut features.enable_rate_limiter,
+                overrides.enable_rate_limiter,
+            ),
+        ];
+        for (flag, value) in flags {
+            if let Some(value) = value {
+                *flag = value;
+            }
+        }
+
+        let mut limit = limit.clone();
+        if let Some(max_tokens) = overrides.max_tokens {
+            limit.max_tokens = max_tokens;
+        }
+        if let Some(refill_rate) = overrides.refill_rate {
+            limit.refill_rate = refill_rate;
+        }
+
+        // A bucket that never refills locks every client out for good once its
+        // first `max_tokens` connections are spent, which looks like a hang.
+        if features.enable_rate_limiter
+            && limit.rate_limiter == RateLimiterKind::TokenBucket
+            && (limit.refill_rate.is_nan() || limit.refill_rate <= 0.0)
+        {
+            return Err(format!(
+                "refill_rate must be greater than 0 when the rate limiter is enabled (got {})",
+                limit.refill_rate
+            ));
+        }
+        if features.enable_rate_limiter
+            && limit.rate_limiter == RateLimiterKind::SlidingWindow
+            && limit.window_secs == 0
+        {
+            return Err(
+                "window_secs must be at least 1 for the sliding_window rate limiter".to_string(),
+            );
+        }
+        if let Some(session_rate) = &limit.session_rate {
+            if session_rate.refill_rate.is_nan() || session_rate.refill_rate <= 0.0 {
+                return Err(format!(
+                    "session_rate.refill_rate must be greater than 0 (got {})",
+                    session_rate.refill_rate
+                ));
+            }
+        }
+        if let Some(repeated) = &limit.repeated_malformed {
+            if repeated.threshold < 2 || repeated.window_secs == 0 {
+                return Err(
+                    "repeated_malformed.threshold must be at least 2 and window_secs at least 1"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(in_flight) = &limit.in_flight_connects {
+            if in_flight.max_per_ip == 0 {
+                return Err("in_flight_connects.max_per_ip must be at least 1".to_string());
+            }
+        }
+        if let Some(connect_ratio) = &limit.connect_ratio {
+            if connect_ratio.window_secs == 0 || connect_ratio.min_opens == 0 {
+                return Err(
+                    "connect_ratio.window_secs and min_opens must be at least 1".to_string()
+                );
+            }
+            if connect_ratio.max_ratio.is_nan() || connect_ratio.max_ratio < 1.0 {
+                return Err(format!(
+                    "connect_ratio.max_ratio must be at least 1.0 (got {})",
+                    connect_ratio.max_ratio
+                ));
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            features,
+            limit: Arc::new(limit),
+        })
+    }
+}
+
+/// Selects the profile for each client address.
+#[derive(Debug, Clone)]
+pub struct SourcePolicy {
+    profiles: Vec<(CidrSet, Profile)>,
+    fallback: Profile,
+}
+
+impl SourcePolicy {
+    /// Builds the policy; with no `config` every client gets the global settings.
+    pub fn from_config(
+        config: Option<&SourcePolicyConfig>,
+        features: &FeaturesConfig,
+        limit: &LimitConfig,
+    ) -> Result<Self, String> {
+        let global = Profile::resolve(GLOBAL_PROFILE, &SourceProfile::default(), features, limit)
+            .map_err(|e| format!("limit: {}", e))?;
+        let Some(config) = config else {
+            return Ok(Self {
+                profiles: Vec::new(),
+                fallback: global,
+            });
+        };
+
+        let mut profiles = Vec::with_capacity(config.profiles.len());
+        for (name, overrides) in &config.profiles {
+            let cidrs = CidrSet::parse(&overrides.cidrs)
+                .map_err(|e| format!("source policy profile '{}': {}", name, e))?;
+            let profile = Profile::resolve(name, overrides, features, limit)
+                .map_err(|e| format!("source policy profile '{}': {}", name, e))?;
+            profiles.push((cidrs, profile));
+        }
+
+        let fallback = match &config.default_profile {
+            Some(name) => profiles
+                .iter()
+                .find(|(_, profile)| &profile.name == name)
+                .map(|(_, profile)| profile.clone())
+                .ok_or_else(|| {
+                    format!("source policy default_profile '{}' is not defined", name)
+                })?,
+            None => global,
+        };
+
+        Ok(Self { profiles, fallback })
+    }
+
+    /// Profile for `ip`: the longest matching prefix wins, ties go to the
+    /// first profile by name, and unmatched clients get the default profile.
+    pub fn select(&self, ip: IpAddr) -> &Profile {
+        let mut best: Option<(u8, &Profile)> = None;
+        for (cidrs, profile) in &self.profiles {
+            if let Some(len) = cidrs.longest_match(ip) {
+                if best.is_none_or(|(best_len, _)| len > best_len) {
+                    best = Some((len, profile));
+                }
+            }
+        }
+        best.map_or(&self.fallback, |(_, profile)| profile)
+    }
+
+    /// Names of every profile a client can be assigned, the default included.
+    pub fn profile_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .profiles
+            .iter()
+            .map(|(_, profile)| profile.name.as_str())
+            .collect();
+        if !names.contains(&self.fallback.name.as_str()) {
+            names.push(&self.fallback.name);
+        }
+        names
+    }
+
+    /// Whether any profile (or the default) uses per-IP rate limiting.
+    pub fn rate_limiting_enabled(&self) -> bool {
+        self.fallback.features.enable_rate_limiter
+            || self
+                .profiles
+                .iter()
+                .any(|(_, profile)| profile.features.enable_rate_limiter)
+    }
+}
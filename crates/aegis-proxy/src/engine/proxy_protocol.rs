@@ -0,0 +1,185 @@
+//! PROXY protocol support.
+//!
+//! Backends behind the proxy only see the proxy's own address. With
+//! `send_proxy_protocol`, every backend connection starts with a PROXY
+//! protocol v1 line naming the real client, as understood by most brokers
+//! and load balancers (e.g. `PROXY TCP4 192.0.2.1 198.51.100.1 56324 8080`).
+//!
+//! In the other direction, with `accept_proxy_protocol` the proxy expects
+//! the binary v2 header an L4 load balancer puts in front of each client
+//! stream, and takes the client address from it.
+
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// The 12 bytes every PROXY protocol v2 header starts with.
+pub const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Length of the v2 header before the address block.
+pub const V2_FIXED_LEN: usize = 16;
+
+/// Longest v2 header accepted (TLVs included). The spec asks senders to fit
+/// the header in 536 bytes, the minimum TCP MSS.
+pub const V2_MAX_LEN: usize = 536;
+
+/// Most TLVs read from one v2 header; a header with more is malformed.
+pub const V2_MAX_TLVS: usize = 32;
+
+/// Why a v2 header did not yield a client address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyError {
+    /// More bytes are needed to decode the header.
+    Incomplete,
+    /// Not a valid v2 header.
+    Malformed(&'static str),
+    /// A valid header without a client address to use: the LOCAL command
+    /// (the load balancer's own health checks) or an address family other
+    /// than IPv4/IPv6. The TCP peer address stands; `header_len` bytes are
+    /// still to be skipped.
+    Local { header_len: usize },
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::Incomplete => write!(f, "incomplete PROXY header"),
+            ProxyError::Malformed(reason) => write!(f, "malformed PROXY header: {}", reason),
+            ProxyError::Local { .. } => write!(f, "PROXY header carries no client address"),
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+/// Total length of the v2 header starting `buf`, from its fixed part.
+pub fn v2_header_len(buf: &[u8]) -> Result<usize, ProxyError> {
+    if buf.len() < V2_FIXED_LEN {
+        return Err(ProxyError::Incomplete);
+    }
+    if buf[..12] != V2_SIGNATURE {
+        return Err(ProxyError::Malformed("missing v2 signature"));
+    }
+    if buf[12] >> 4 != 2 {
+        return Err(ProxyError::Malformed("unsupported version"));
+    }
+    let len = V2_FIXED_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if len > V2_MAX_LEN {
+        return Err(ProxyError::Malformed("header too long"));
+    }
+    Ok(len)
+}
+
+/// Decodes the PROXY protocol v2 header at the start of `buf`, returning the
+/// client (source) address and the header length to skip.
+pub fn parse_proxy_v2(buf: &[u8]) -> Result<(SocketAddr, usize), ProxyError> {
+    let header_len = v2_header_len(buf)?;
+    let Some(header) = buf.get(..header_len) else {
+        return Err(ProxyError::Incomplete);
+    };
+    match header[12] & 0x0f {
+        0x0 => return Err(ProxyError::Local { header_len }),
+        0x1 => {}
+        _ => return Err(ProxyError::Malformed("unknown command")),
+    }
+    let family = header[13];
+    let addresses = &header[V2_FIXED_LEN..];
+    let source = match family {
+        // TCP over IPv4: source, destination address; source, destination port.
+        0x11 => {
+            let block = addresses
+                .get(..12)
+                .ok_or(ProxyError::Malformed("IPv4 address block too short"))?;
+            let ip = Ipv4Addr::new(block[0], block[1], block[2], block[3]);
+            SocketAddr::new(ip.into(), u16::from_be_bytes([block[8], block[9]]))
+        }
+        // TCP over IPv6.
+        0x21 => {
+            let block = addresses
+                .get(..36)
+                .ok_or(ProxyError::Malformed("IPv6 address block too short"))?;
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&block[..16]);
+            let ip = Ipv6Addr::from(octets);
+            SocketAddr::new(ip.into(), u16::from_be_bytes([block[32], block[33]]))
+        }
+        // UNSPEC and Unix sockets carry no IP address.
+        0x00 | 0x31 | 0x32 => return Err(ProxyError::Local { header_len }),
+        _ => return Err(ProxyError::Malformed("unsupported address family")),
+    };
+    Ok((source, header_len))
+}
+
+/// Finds the value of the first TLV of `kind` in the v2 header `header`
+/// (as returned whole by `v2_header_len`). Other types are skipped. The TLV
+/// block must be well formed and hold at most `V2_MAX_TLVS` entries.
+pub fn find_tlv(header: &[u8], kind: u8) -> Result<Option<&[u8]>, ProxyError> {
+    let header_len = v2_header_len(header)?;
+    let header = header.get(..header_len).ok_or(ProxyError::Incomplete)?;
+    let addresses_len = match header[13] {
+        0x11 | 0x12 => 12,
+        0x21 | 0x22 => 36,
+        0x31 | 0x32 => 216,
+        _ => 0,
+    };
+    let mut tlvs = header
+        .get(V2_FIXED_LEN + addresses_len..)
+        .ok_or(ProxyError::Malformed("address block too short"))?;
+    let mut found = None;
+    let mut count = 0;
+    while !tlvs.is_empty() {
+        count += 1;
+        if count > V2_MAX_TLVS {
+            return Err(ProxyError::Malformed("too many TLVs"));
+        }
+        let [tlv_kind, hi, lo, rest @ ..] = tlvs else {
+            return Err(ProxyError::Malformed("truncated TLV"));
+        };
+        let len = u16::from_be_bytes([*hi, *lo]) as usize;
+        let value = rest
+            .get(..len)
+            .ok_or(ProxyError::Malformed("truncated TLV"))?;
+        if *tlv_kind == kind && found.is_none() {
+            found = Some(value);
+        }
+        tlvs = &rest[len..];
+    }
+    Ok(found)
+}
+
+/// A TLV value as a label: UTF-8 (lossy) without control characters, such
+/// as the subtype byte in front of an AWS VPC endpoint ID, cut to 64 chars.
+pub fn tlv_label(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(64)
+        .collect()
+}
+
+/// Builds the PROXY protocol v1 header for a connection from `source` to
+/// `destination`. Falls back to `PROXY UNKNOWN` when either address is
+/// unavailable or the two are of different families.
+pub fn v1_header(source: Option<SocketAddr>, destination: Option<SocketAddr>) -> String {
+    let (source, destination) = match (source, destination) {
+        (Some(source), Some(destination)) => (canonical(source), canonical(destination)),
+        _ => return "PROXY UNKNOWN\r\n".to_string(),
+    };
+    let family = match (source, destination) {
+        (SocketAddr::V4(_), SocketAddr::V4(_)) => "TCP4",
+        (SocketAddr::V6(_), SocketAddr::V6(_)) => "TCP6",
+        _ => return "PROXY UNKNOWN\r\n".to_string(),
+    };
+    format!(
+        "PROXY {} {} {} {} {}\r\n",
+        family,
+        source.ip(),
+        destination.ip(),
+        source.port(),
+        destination.port()
+    )
+}
+
+/// Reports IPv4-mapped IPv6 addresses (dual-stack listeners) as IPv4.
+fn canonical(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
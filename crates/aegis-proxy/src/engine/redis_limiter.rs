@@ -0,0 +1,341 @@
+//! Token buckets shared through Redis (`limit.backend: redis`).
+//!
+//! Every instance pointed at the same server draws from one bucket per
+//! client, so a client spread across a fleet is limited as a whole. Each
+//! decision is one run of a Lua script: atomic on the server and timed by the
+//! server's clock, so instances need not agree on the time. A bucket expires
+//! after `ip_idle_timeout_secs` without connections. Needs Redis 5 or later.
+//!
+//! The client speaks RESP directly over a small pool of connections. A
+//! connection that fails is dropped and the next decision opens a new one.
+
+use crate::engine::limiter::{bucket_name, RateLimitBackend};
+use aegis_common::LimitConfig;
+use ipnet::IpNet;
+use once_cell::sync::OnceCell;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+
+/// Most connections open to the server at once.
+const MAX_CONNECTIONS: usize = 16;
+
+/// Buckets are hashes at this prefix followed by the client address, or the
+/// network for a subnet bucket.
+pub const KEY_PREFIX: &str = "aegis:rate:";
+
+/// Longest bulk reply accepted; decisions are a few bytes.
+const MAX_BULK: usize = 64 * 1024;
+
+/// KEYS[1]: bucket; ARGV: max tokens, refill rate (tokens/s), expiry (ms).
+/// Returns `{allowed, tokens left}`.
+const SCRIPT: &str = r#"
+if redis.replicate_commands then redis.replicate_commands() end
+local max_tokens = tonumber(ARGV[1])
+local refill_rate = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'refilled')
+local tokens = tonumber(bucket[1]) or max_tokens
+local refilled = tonumber(bucket[2]) or now
+tokens = math.min(max_tokens, tokens + math.max(0, now - refilled) * refill_rate)
+local allowed = 0
+if tokens >= 1 then
+  tokens = tokens - 1
+  allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'refilled', tostring(now))
+redis.call('PEXPIRE', KEYS[1], ARGV[3])
+return {allowed, tostring(tokens)}
+"#;
+
+static BACKEND: OnceCell<RedisBackend> = OnceCell::new();
+
+/// Installs the process-wide Redis backend used by `check_rate_limit`. Only
+/// the first call installs one.
+pub fn init(config: &LimitConfig) -> Result<(), String> {
+    let backend = RedisBackend::from_config(config)?;
+    let _ = BACKEND.set(backend);
+    Ok(())
+}
+
+/// The backend installed by `init`, if any.
+pub fn installed() -> Option<&'static RedisBackend> {
+    BACKEND.get()
+}
+
+pub struct RedisBackend {
+    address: String,
+    /// `AUTH` arguments: optional user name, then password.
+    auth: Option<(Option<String>, String)>,
+    db: Option<u32>,
+    idle: Mutex<Vec<Connection>>,
+    slots: Semaphore,
+}
+
+impl RedisBackend {
+    pub fn from_config(config: &LimitConfig) -> Result<Self, String> {
+        let url = config
+            .redis_url
+            .as_deref()
+            .ok_or("limit.redis_url is not set")?;
+        let rest = url
+            .strip_prefix("redis://")
+            .ok_or_else(|| format!("invalid redis_url {:?}: expected redis://", url))?;
+        let (auth, rest) = match rest.rsplit_once('@') {
+            Some((userinfo, rest)) => {
+                let auth = match userinfo.split_once(':') {
+                    Some((user, password)) => {
+                        ((!user.is_empty()).then(|| user.to_string()), password)
+                    }
+                    None => (None, userinfo),
+                };
+                (Some((auth.0, auth.1.to_string())), rest)
+            }
+            None => (None, rest),
+        };
+        let (address, db) = match rest.split_once('/') {
+            Some((address, "")) => (address, None),
+            Some((address, db)) => {
+                let db = db
+                    .parse()
+                    .map_err(|_| format!("invalid redis_url {:?}: bad database", url))?;
+                (address, Some(db))
+            }
+            None => (rest, None),
+        };
+        if address.is_empty() {
+            return Err(format!("invalid redis_url {:?}: missing host", url));
+        }
+        let has_port = address.rsplit_once(':').is_some_and(|(host, port)| {
+            port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']'))
+        });
+        let address = if has_port {
+            address.to_string()
+        } else {
+            format!("{}:6379", address)
+        };
+        Ok(Self {
+            address,
+            auth,
+            db,
+            idle: Mutex::new(Vec::new()),
+            slots: Semaphore::new(MAX_CONNECTIONS),
+        })
+    }
+
+    /// The server address, `host:port`.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    async fn take(
+        &self,
+        key: IpNet,
+        max_tokens: f64,
+        refill_rate: f64,
+        expiry_ms: u64,
+    ) -> io::Result<(bool, f64)> {
+        let _slot = self.slots.acquire().await.map_err(io::Error::other)?;
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let mut conn = match idle {
+            Some(conn) => conn,
+            None => Connection::open(self).await?,
+        };
+
+        let key = format!("{}{}", KEY_PREFIX, bucket_name(&key));
+        let max_tokens = max_tokens.to_string();
+        let refill_rate = refill_rate.to_string();
+        let expiry = expiry_ms.to_string();
+        let args = [
+            b"1".as_slice(),
+            key.as_bytes(),
+            max_tokens.as_bytes(),
+            refill_rate.as_bytes(),
+            expiry.as_bytes(),
+        ];
+        let sha = conn.sha.clone();
+        let reply = match conn.call(b"EVALSHA", sha.as_bytes(), &args).await? {
+            // The script cache was flushed, or this is a failed-over server.
+            Reply::Error(e) if e.starts_with("NOSCRIPT") => {
+                conn.call(b"EVAL", SCRIPT.as_bytes(), &args).await?
+            }
+            reply => reply,
+        };
+        let decision = match reply.ok()? {
+            Reply::Array(items) => match items.as_slice() {
+                [Reply::Integer(allowed), Reply::Bulk(Some(tokens))] => std::str::from_utf8(tokens)
+                    .ok()
+                    .and_then(|tokens| tokens.parse::<f64>().ok())
+                    .map(|tokens| (*allowed == 1, tokens)),
+                _ => None,
+            },
+            _ => None,
+        };
+        let decision =
+            decision.ok_or_else(|| invalid("unexpected reply to the rate-limit script"))?;
+        self.idle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(conn);
+        Ok(decision)
+    }
+}
+
+impl RateLimitBackend for RedisBackend {
+    async fn take_token(
+        &self,
+        key: IpNet,
+        max_tokens: f64,
+        refill_rate: f64,
+        config: &LimitConfig,
+    ) -> io::Result<(bool, f64)> {
+        let wait = Duration::from_millis(config.redis_timeout_ms);
+        let expiry_ms = config.ip_idle_timeout_secs.max(1) * 1000;
+        timeout(wait, self.take(key, max_tokens, refill_rate, expiry_ms))
+            .await
+            .unwrap_or_else(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no answer from redis in time",
+                ))
+            })
+    }
+}
+
+/// One RESP reply; arrays hold only scalars, which is all the script returns.
+#[derive(Debug)]
+enum Reply {
+    /// `+OK` and the like; the text is not needed.
+    Status,
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    /// The reply, or an error reply turned into an `io::Error`.
+    fn ok(self) -> io::Result<Self> {
+        match self {
+            Reply::Error(e) => Err(io::Error::other(e)),
+            reply => Ok(reply),
+        }
+    }
+}
+
+struct Connection {
+    stream: BufReader<TcpStream>,
+    /// SHA1 of `SCRIPT`, as loaded on this connection's server.
+    sha: String,
+}
+
+impl Connection {
+    async fn open(backend: &RedisBackend) -> io::Result<Self> {
+        let stream = TcpStream::connect(&backend.address).await?;
+        stream.set_nodelay(true)?;
+        let mut conn = Connection {
+            stream: BufReader::new(stream),
+            sha: String::new(),
+        };
+        if let Some((user, password)) = &backend.auth {
+            let reply = match user {
+                Some(user) => {
+                    conn.call(b"AUTH", user.as_bytes(), &[password.as_bytes()])
+                        .await?
+                }
+                None => conn.call(b"AUTH", password.as_bytes(), &[]).await?,
+            };
+            reply.ok()?;
+        }
+        if let Some(db) = backend.db {
+            conn.call(b"SELECT", db.to_string().as_bytes(), &[])
+                .await?
+                .ok()?;
+        }
+        conn.sha = match conn
+            .call(b"SCRIPT", b"LOAD", &[SCRIPT.as_bytes()])
+            .await?
+            .ok()?
+        {
+            Reply::Bulk(Some(sha)) => {
+                String::from_utf8(sha).map_err(|_| invalid("bad script hash"))?
+            }
+            _ => return Err(invalid("unexpected reply to SCRIPT LOAD")),
+        };
+        Ok(conn)
+    }
+
+    /// Sends one command and reads its reply.
+    async fn call(&mut self, command: &[u8], first: &[u8], rest: &[&[u8]]) -> io::Result<Reply> {
+        let mut frame = format!("*{}\r\n", 2 + rest.len()).into_bytes();
+        for arg in [command, first].iter().chain(rest) {
+            frame.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            frame.extend_from_slice(arg);
+            frame.extend_from_slice(b"\r\n");
+        }
+        self.stream.get_mut().write_all(&frame).await?;
+
+        let line = self.read_line().await?;
+        let Some(count) = line.strip_prefix('*') else {
+            return self.read_scalar(line).await;
+        };
+        let count: i64 = count.parse().map_err(|_| invalid("bad array length"))?;
+        let mut items = Vec::new();
+        for _ in 0..count.max(0) {
+            let line = self.read_line().await?;
+            if line.starts_with('*') {
+                return Err(invalid("nested array reply"));
+            }
+            items.push(self.read_scalar(line).await?);
+        }
+        Ok(Reply::Array(items))
+    }
+
+    async fn read_scalar(&mut self, line: String) -> io::Result<Reply> {
+        let (kind, value) = line.split_at(line.len().min(1));
+        match kind {
+            "+" => Ok(Reply::Status),
+            "-" => Ok(Reply::Error(value.to_string())),
+            ":" => value
+                .parse()
+                .map(Reply::Integer)
+                .map_err(|_| invalid("bad integer reply")),
+            "$" => {
+                let len: i64 = value.parse().map_err(|_| invalid("bad bulk length"))?;
+                if len < 0 {
+                    return Ok(Reply::Bulk(None));
+                }
+                let len = len as usize;
+                if len > MAX_BULK {
+                    return Err(invalid("bulk reply too large"));
+                }
+                let mut bulk = vec![0u8; len + 2];
+                self.stream.read_exact(&mut bulk).await?;
+                bulk.truncate(len);
+                Ok(Reply::Bulk(Some(bulk)))
+            }
+            _ => Err(invalid("unknown reply type")),
+        }
+    }
+
+    /// One CRLF-terminated line, without the terminator.
+    async fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "redis closed the connection",
+            ));
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,75 @@
+//! Registry of live client connections.
+//!
+//! Every connection handled by `handle_connection` registers its client IP and
+//! a cancellation token for as long as it runs, from inspection through the
+//! forwarded session. Operator actions (see `admin`) use it to close running
+//! sessions without restarting the proxy.
+
+use crate::engine::limiter::client_key;
+use dashmap::DashMap;
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio_util::sync::CancellationToken;
+
+struct LiveConnection {
+    client: IpAddr,
+    cancel: CancellationToken,
+}
+
+static LIVE_CONNECTIONS: Lazy<DashMap<u64, LiveConnection>> = Lazy::new(DashMap::new);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A connection's entry in the registry; removed on drop.
+pub struct Registration {
+    id: u64,
+    cancel: CancellationToken,
+}
+
+impl Registration {
+    /// Token cancelled when an operator kills this connection.
+    pub fn token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        LIVE_CONNECTIONS.remove(&self.id);
+    }
+}
+
+/// Registers a connection from `client` for the lifetime of the returned
+/// handle.
+pub fn register(client: IpAddr) -> Registration {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let cancel = CancellationToken::new();
+    LIVE_CONNECTIONS.insert(
+        id,
+        LiveConnection {
+            client: client_key(client),
+            cancel: cancel.clone(),
+        },
+    );
+    Registration { id, cancel }
+}
+
+/// Cancels every live connection whose client is inside `net`; returns how
+/// many were cancelled.
+pub fn kill_matching(net: &IpNet) -> usize {
+    let mut killed = 0;
+    for entry in LIVE_CONNECTIONS.iter() {
+        if net.contains(&entry.client) && !entry.cancel.is_cancelled() {
+            entry.cancel.cancel();
+            killed += 1;
+        }
+    }
+    killed
+}
+
+/// Number of registered connections.
+pub fn live_count() -> usize {
+    LIVE_CONNECTIONS.len()
+}
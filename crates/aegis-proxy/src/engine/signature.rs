@@ -0,0 +1,104 @@
+//! Signature fast path for homogeneous device fleets.
+//!
+//! Fleets that always open with an identical CONNECT prefix pay for full
+//! inspection on every connection for no benefit. Operators can list such
+//! prefixes; a connection whose first bytes match one skips HTTP and MQTT
+//! inspection and is forwarded directly. Accept-time checks (rate limits,
+//! subnet caps, ...) still apply. A sampled fraction of matches is inspected
+//! anyway, so a client copying a signature cannot count on skipping checks.
+
+use aegis_common::SignatureFastPathConfig;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Most signatures that may be configured.
+pub const MAX_SIGNATURES: usize = 16;
+
+/// Shortest and longest accepted signature, in bytes.
+pub const MIN_SIGNATURE_LEN: usize = 4;
+pub const MAX_SIGNATURE_LEN: usize = 64;
+
+/// What to do with a connection after matching its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FastPath {
+    /// Matched; skip inspection.
+    Hit,
+    /// Matched, but sampled for full inspection.
+    Verify,
+    /// No signature matched.
+    Miss,
+}
+
+pub struct SignatureSet {
+    signatures: Vec<Vec<u8>>,
+    verify_every: u64,
+    matches: AtomicU64,
+}
+
+impl SignatureSet {
+    pub fn from_config(config: &SignatureFastPathConfig) -> Result<Self, String> {
+        if config.signatures.is_empty() || config.signatures.len() > MAX_SIGNATURES {
+            return Err(format!(
+                "signature_fast_path: between 1 and {} signatures are required",
+                MAX_SIGNATURES
+            ));
+        }
+        if config.verify_every == 0 {
+            return Err("signature_fast_path: verify_every must be at least 1".to_string());
+        }
+        let signatures = config
+            .signatures
+            .iter()
+            .map(|hex| parse_signature(hex))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            signatures,
+            verify_every: config.verify_every,
+            matches: AtomicU64::new(0),
+        })
+    }
+
+    /// Bytes to peek to be able to match every signature.
+    pub fn max_len(&self) -> usize {
+        self.signatures.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    /// Classifies a connection by its first bytes.
+    pub fn classify(&self, first_bytes: &[u8]) -> FastPath {
+        if !self
+            .signatures
+            .iter()
+            .any(|signature| first_bytes.starts_with(signature))
+        {
+            return FastPath::Miss;
+        }
+        let n = self.matches.fetch_add(1, Ordering::Relaxed) + 1;
+        if n.is_multiple_of(self.verify_every) {
+            FastPath::Verify
+        } else {
+            FastPath::Hit
+        }
+    }
+}
+
+fn parse_signature(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("signature_fast_path: odd-length hex '{}'", hex));
+    }
+    let bytes = digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok())
+                .ok_or_else(|| format!("signature_fast_path: invalid hex '{}'", hex))
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    if !(MIN_SIGNATURE_LEN..=MAX_SIGNATURE_LEN).contains(&bytes.len()) {
+        return Err(format!(
+            "signature_fast_path: signature '{}' must be {}-{} bytes",
+            hex, MIN_SIGNATURE_LEN, MAX_SIGNATURE_LEN
+        ));
+    }
+    Ok(bytes)
+}
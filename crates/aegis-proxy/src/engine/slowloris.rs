@@ -13,25 +13,36 @@
 //! 2. **Protocol-specific overlays**: MQTT CONNECT timeout, HTTP request timeout
 //!
 //! ## Usage
-//! Wrap a `TcpStream` with `TimeoutReader` to enforce idle timeouts on all reads.
+//! Wrap a `TcpStream` with `TimeoutReader` to enforce idle timeouts on all reads,
+//! and a write half with `TimeoutWriter` to bound how long a write may stall.
+//! Run parsing futures through an `InspectionBudget` to bound the time the
+//! parsers themselves spend on one connection. Once forwarding, wrap both
+//! readers in an `ActivityReader` sharing one `SessionActivity` to notice a
+//! session that has gone quiet in both directions.
 
+use crate::engine::splice::SpliceSocket;
 use pin_project_lite::pin_project;
+use std::future::Future;
 use std::io;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::task::{Context, Poll};
 use std::time::Duration;
-use tokio::io::{AsyncRead, ReadBuf};
-use tokio::time::timeout;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout, Instant, Sleep};
 
 pin_project! {
     /// A wrapper around an AsyncRead that enforces an idle timeout between reads.
     ///
     /// If no data is received within `idle_timeout`, the next read will return
-    /// an error of kind `TimedOut`.
+    /// an error of kind `TimedOut`. The timer starts when the inner reader
+    /// first returns `Pending` and is cleared whenever a read completes.
     pub struct TimeoutReader<R> {
         #[pin]
         inner: R,
         idle_timeout: Duration,
+        idle: Option<Pin<Box<Sleep>>>,
     }
 }
 
@@ -45,6 +56,7 @@ impl<R> TimeoutReader<R> {
         Self {
             inner,
             idle_timeout,
+            idle: None,
         }
     }
 
@@ -61,7 +73,216 @@ impl<R: AsyncRead> AsyncRead for TimeoutReader<R> {
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
         let this = self.project();
-        this.inner.poll_read(cx, buf)
+        match this.inner.poll_read(cx, buf) {
+            Poll::Ready(res) => {
+                *this.idle = None;
+                Poll::Ready(res)
+            }
+            Poll::Pending => {
+                let timer = this
+                    .idle
+                    .get_or_insert_with(|| Box::pin(sleep(*this.idle_timeout)));
+                match timer.as_mut().poll(cx) {
+                    Poll::Ready(()) => {
+                        *this.idle = None;
+                        Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "idle timeout exceeded between reads",
+                        )))
+                    }
+                    Poll::Pending => Poll::Pending,
+                }
+            }
+        }
+    }
+}
+
+/// When a forwarded session last moved bytes in either direction.
+pub struct SessionActivity {
+    started: Instant,
+    /// Milliseconds after `started` of the last read that returned data.
+    last_ms: AtomicU64,
+}
+
+impl SessionActivity {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Records that bytes were just read.
+    pub fn touch(&self) {
+        let now = self.started.elapsed().as_millis() as u64;
+        self.last_ms.fetch_max(now, Ordering::Relaxed);
+    }
+
+    /// How long since bytes last moved (or since the session started).
+    pub fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+
+    /// Resolves once no bytes have moved for `window`.
+    pub async fn idle(&self, window: Duration) {
+        loop {
+            let idle = self.idle_for();
+            if idle >= window {
+                return;
+            }
+            sleep(window - idle).await;
+        }
+    }
+}
+
+impl Default for SessionActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A reader that touches a shared `SessionActivity` whenever a read returns
+/// data. Without an activity it is a plain pass-through.
+pub struct ActivityReader<'a, R> {
+    inner: R,
+    activity: Option<&'a SessionActivity>,
+}
+
+impl<'a, R> ActivityReader<'a, R> {
+    pub fn new(inner: R, activity: Option<&'a SessionActivity>) -> Self {
+        Self { inner, activity }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ActivityReader<'_, R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(activity)) = (&res, self.activity) {
+            if buf.filled().len() > before {
+                activity.touch();
+            }
+        }
+        res
+    }
+}
+
+/// Lets the splice data plane reach the socket; splicing bypasses the reader,
+/// so callers must not splice while an activity is attached.
+impl<R: SpliceSocket> SpliceSocket for ActivityReader<'_, R> {
+    fn splice_socket(&self) -> Option<&TcpStream> {
+        self.inner.splice_socket()
+    }
+}
+
+pin_project! {
+    /// A wrapper around an AsyncWrite that fails writes which cannot make
+    /// progress within `write_timeout`.
+    ///
+    /// The timer starts when the inner writer first returns `Pending` and is
+    /// cleared as soon as it accepts bytes, so a slow-but-progressing peer is
+    /// fine while a peer that stops reading yields an error of kind `TimedOut`.
+    pub struct TimeoutWriter<W> {
+        #[pin]
+        inner: W,
+        write_timeout: Duration,
+        stalled: Option<Pin<Box<Sleep>>>,
+    }
+}
+
+impl<W> TimeoutWriter<W> {
+    /// Creates a new `TimeoutWriter` wrapping the given writer.
+    ///
+    /// # Arguments
+    /// * `inner` - The underlying writer (e.g., `OwnedWriteHalf`)
+    /// * `write_timeout` - Maximum duration a write may stay blocked
+    pub fn new(inner: W, write_timeout: Duration) -> Self {
+        Self {
+            inner,
+            write_timeout,
+            stalled: None,
+        }
+    }
+
+    /// Consumes the wrapper and returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// The error inside a `TimedOut` from `TimeoutWriter`, telling a stalled
+/// peer apart from an idle one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteStalled;
+
+impl std::fmt::Display for WriteStalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("write stalled beyond timeout")
+    }
+}
+
+impl std::error::Error for WriteStalled {}
+
+impl WriteStalled {
+    /// Whether `error` is a `TimeoutWriter` stall.
+    pub fn is(error: &io::Error) -> bool {
+        error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<WriteStalled>())
+    }
+}
+
+/// Resolves a pending inner poll against the stall timer.
+fn poll_stall<T>(
+    stalled: &mut Option<Pin<Box<Sleep>>>,
+    write_timeout: Duration,
+    cx: &mut Context<'_>,
+    res: Poll<io::Result<T>>,
+) -> Poll<io::Result<T>> {
+    match res {
+        Poll::Ready(r) => {
+            *stalled = None;
+            Poll::Ready(r)
+        }
+        Poll::Pending => {
+            let timer = stalled.get_or_insert_with(|| Box::pin(sleep(write_timeout)));
+            match timer.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    *stalled = None;
+                    Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, WriteStalled)))
+                }
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<W: AsyncWrite> AsyncWrite for TimeoutWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        let res = this.inner.poll_write(cx, buf);
+        poll_stall(this.stalled, *this.write_timeout, cx, res)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let res = this.inner.poll_flush(cx);
+        poll_stall(this.stalled, *this.write_timeout, cx, res)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let res = this.inner.poll_shutdown(cx);
+        poll_stall(this.stalled, *this.write_timeout, cx, res)
     }
 }
 
@@ -107,6 +328,68 @@ pub async fn read_with_idle_timeout<R>(
     idle_timeout: Duration,
     total_timeout: Duration,
 ) -> io::Result<usize>
+where
+    R: AsyncRead + Unpin,
+{
+    read_paced(reader, buf, idle_timeout, total_timeout, None).await
+}
+
+/// How long a sender gets before `read_with_throughput_floor` starts holding
+/// it to the floor, so the first bytes of a read are not judged alone.
+pub const THROUGHPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// The error inside a `TimedOut` from `read_with_throughput_floor` when the
+/// sender fell below the throughput floor (rather than going idle or running
+/// out of total time).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThroughputTooLow;
+
+impl std::fmt::Display for ThroughputTooLow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("read throughput below minimum")
+    }
+}
+
+impl std::error::Error for ThroughputTooLow {}
+
+impl ThroughputTooLow {
+    /// Whether `error` is a throughput-floor timeout.
+    pub fn is(error: &io::Error) -> bool {
+        error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<ThroughputTooLow>())
+    }
+}
+
+/// Like `read_with_idle_timeout`, but also requires the bytes read so far to
+/// keep up with `min_bytes_per_sec` averaged since the start of the read.
+///
+/// A sender trickling one byte just inside every idle timeout passes the
+/// idle and total checks for a long time; this one fails it as soon as the
+/// cumulative count falls behind `min_bytes_per_sec` after the
+/// `THROUGHPUT_GRACE`, with a `TimedOut` error wrapping `ThroughputTooLow`.
+/// A floor of 0 disables the check.
+pub async fn read_with_throughput_floor<R>(
+    reader: &mut R,
+    buf: &mut [u8],
+    idle_timeout: Duration,
+    total_timeout: Duration,
+    min_bytes_per_sec: u64,
+) -> io::Result<usize>
+where
+    R: AsyncRead + Unpin,
+{
+    let floor = (min_bytes_per_sec > 0).then_some(min_bytes_per_sec);
+    read_paced(reader, buf, idle_timeout, total_timeout, floor).await
+}
+
+async fn read_paced<R>(
+    reader: &mut R,
+    buf: &mut [u8],
+    idle_timeout: Duration,
+    total_timeout: Duration,
+    min_bytes_per_sec: Option<u64>,
+) -> io::Result<usize>
 where
     R: AsyncRead + Unpin,
 {
@@ -124,7 +407,18 @@ where
 
         // Calculate remaining timeout
         let remaining_total = total_timeout - start.elapsed();
-        let effective_timeout = std::cmp::min(idle_timeout, remaining_total);
+        let mut effective_timeout = std::cmp::min(idle_timeout, remaining_total);
+
+        // The time by which the next byte must arrive to stay above the floor.
+        let mut floor_bound = false;
+        if let Some(floor) = min_bytes_per_sec {
+            let due = THROUGHPUT_GRACE + Duration::from_secs_f64(total_read as f64 / floor as f64);
+            let remaining_floor = due.saturating_sub(start.elapsed());
+            if remaining_floor < effective_timeout {
+                effective_timeout = remaining_floor;
+                floor_bound = true;
+            }
+        }
 
         // Read with idle timeout
         let n = match timeout(
@@ -139,6 +433,9 @@ where
             }
             Ok(Ok(n)) => n,
             Ok(Err(e)) => return Err(e),
+            Err(_) if floor_bound => {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, ThroughputTooLow))
+            }
             Err(_) => {
                 return Err(io::Error::new(
                     io::ErrorKind::TimedOut,
@@ -153,6 +450,75 @@ where
     Ok(total_read)
 }
 
+/// Soft cap on the time inspection code spends running for one connection.
+///
+/// Timeouts bound how long a client may take; this bounds what its bytes may
+/// cost us. Only time spent inside polls of the wrapped futures counts (not
+/// time waiting for data), and it is checked at every poll boundary, i.e.
+/// each await in the parser loops. A safety valve against inputs that hit a
+/// slow path in a parser, not a precise CPU accounting.
+#[derive(Debug)]
+pub struct InspectionBudget {
+    limit: Duration,
+    spent: Duration,
+}
+
+/// The inspection budget ran out before the wrapped future finished.
+#[derive(Debug, PartialEq)]
+pub struct BudgetExceeded;
+
+impl InspectionBudget {
+    pub fn new(limit: Duration) -> Self {
+        Self {
+            limit,
+            spent: Duration::ZERO,
+        }
+    }
+
+    /// Time charged so far.
+    pub fn spent(&self) -> Duration {
+        self.spent
+    }
+
+    /// Runs `future`, charging its poll time to this budget.
+    pub fn run<F: Future>(&mut self, future: F) -> Budgeted<'_, F> {
+        Budgeted {
+            inner: future,
+            budget: self,
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by `InspectionBudget::run`.
+    pub struct Budgeted<'a, F> {
+        #[pin]
+        inner: F,
+        budget: &'a mut InspectionBudget,
+    }
+}
+
+impl<F: Future> Future for Budgeted<'_, F> {
+    type Output = Result<F::Output, BudgetExceeded>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if this.budget.spent >= this.budget.limit {
+            return Poll::Ready(Err(BudgetExceeded));
+        }
+        let started = std::time::Instant::now();
+        let res = this.inner.poll(cx);
+        this.budget.spent += started.elapsed();
+        match res {
+            Poll::Ready(output) => Poll::Ready(Ok(output)),
+            Poll::Pending if this.budget.spent >= this.budget.limit => {
+                Poll::Ready(Err(BudgetExceeded))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
 // NOTE: Inline unit tests have been moved to the crate-level `tests/` directory.
 // See: `crates/aegis-proxy/tests/slowloris_tests.rs`
 //
@@ -0,0 +1,74 @@
+//! Socket options applied to proxied connections.
+//!
+//! `TCP_USER_TIMEOUT` bounds how long sent data may stay unacknowledged
+//! before the kernel aborts the connection. It complements the other
+//! timeouts rather than replacing them:
+//! - the idle timeouts (`client_idle_timeout_ms`, `backend_idle_timeout_ms`)
+//!   watch for silence at the application level and fire even when the peer
+//!   is alive but quiet;
+//! - TCP keepalive probes an idle connection, and only after its own, usually
+//!   long, idle period;
+//! - the user timeout fires only while data is in flight and unacknowledged,
+//!   so a broker that vanished with our writes pending is detected in
+//!   seconds instead of after the kernel's retransmission limit (~15 min).
+//!
+//! The option is Linux-only; elsewhere it is a no-op.
+
+use std::io;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// Sets `TCP_USER_TIMEOUT` on `stream` (rounded down to milliseconds).
+#[cfg(target_os = "linux")]
+pub fn set_tcp_user_timeout(stream: &TcpStream, timeout: Duration) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let millis = libc::c_uint::try_from(timeout.as_millis()).unwrap_or(libc::c_uint::MAX);
+    // SAFETY: the descriptor is open for the lifetime of `stream`, and the
+    // option value is a valid c_uint of the length passed.
+    let rc = unsafe {
+        libc::setsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_USER_TIMEOUT,
+            &millis as *const libc::c_uint as *const libc::c_void,
+            std::mem::size_of::<libc::c_uint>() as libc::socklen_t,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Sets `TCP_USER_TIMEOUT` on `stream`; a no-op on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn set_tcp_user_timeout(_stream: &TcpStream, _timeout: Duration) -> io::Result<()> {
+    Ok(())
+}
+
+/// Reads back `TCP_USER_TIMEOUT`; `None` where unsupported.
+#[cfg(target_os = "linux")]
+pub fn tcp_user_timeout(stream: &TcpStream) -> Option<Duration> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut millis: libc::c_uint = 0;
+    let mut len = std::mem::size_of::<libc::c_uint>() as libc::socklen_t;
+    // SAFETY: `millis` and `len` are valid, writable and sized for the option.
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_USER_TIMEOUT,
+            &mut millis as *mut libc::c_uint as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (rc == 0).then(|| Duration::from_millis(millis.into()))
+}
+
+/// Reads back `TCP_USER_TIMEOUT`; `None` where unsupported.
+#[cfg(not(target_os = "linux"))]
+pub fn tcp_user_timeout(_stream: &TcpStream) -> Option<Duration> {
+    None
+}
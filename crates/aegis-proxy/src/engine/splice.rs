@@ -0,0 +1,203 @@
+//! Kernel-side forwarding for the proxy data plane.
+//!
+//! Once a connection has passed inspection, AegisGate no longer needs to look
+//! at the bytes flowing between client and broker. On Linux, `splice(2)` lets
+//! us move those bytes socket -> pipe -> socket without ever copying them into
+//! userspace, which noticeably reduces CPU for throughput-bound sessions.
+//!
+//! ## Strategy
+//! - One pipe per direction acts as the in-kernel buffer
+//! - Readiness is driven by Tokio (`readable()` / `writable()` + `try_io`), so
+//!   the loop stays fully async on non-blocking sockets
+//! - If the kernel refuses to splice (unsupported socket type, old kernel) the
+//!   direction falls back to `tokio::io::copy` before any byte was moved
+//! - On non-Linux targets `splice_copy` is simply `tokio::io::copy`
+//! - Streams whose socket does not carry the forwarded bytes as-is (TLS
+//!   terminated at the proxy) have no `splice_socket` and are copied in
+//!   userspace
+
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+
+/// A stream (or stream half) that may expose the socket underneath it for
+/// splicing.
+pub trait SpliceSocket {
+    /// The socket carrying exactly the bytes read from / written to this
+    /// stream, or `None` when they are transformed on the way (e.g. TLS).
+    fn splice_socket(&self) -> Option<&TcpStream>;
+}
+
+impl SpliceSocket for TcpStream {
+    fn splice_socket(&self) -> Option<&TcpStream> {
+        Some(self)
+    }
+}
+
+impl SpliceSocket for OwnedReadHalf {
+    fn splice_socket(&self) -> Option<&TcpStream> {
+        Some(self.as_ref())
+    }
+}
+
+impl SpliceSocket for OwnedWriteHalf {
+    fn splice_socket(&self) -> Option<&TcpStream> {
+        Some(self.as_ref())
+    }
+}
+
+impl<T> SpliceSocket for ReadHalf<T> {
+    fn splice_socket(&self) -> Option<&TcpStream> {
+        None
+    }
+}
+
+impl<T> SpliceSocket for WriteHalf<T> {
+    fn splice_socket(&self) -> Option<&TcpStream> {
+        None
+    }
+}
+
+impl<T: SpliceSocket> SpliceSocket for &mut T {
+    fn splice_socket(&self) -> Option<&TcpStream> {
+        (**self).splice_socket()
+    }
+}
+
+/// Copies everything from `reader` to `writer`, using `splice(2)` on Linux and
+/// falling back to `tokio::io::copy` elsewhere, when splicing is unsupported,
+/// or when either side has no `splice_socket`.
+///
+/// Returns the total number of bytes forwarded.
+pub async fn splice_copy<R, W>(reader: &mut R, writer: &mut W) -> io::Result<u64>
+where
+    R: SpliceSocket + AsyncRead + Unpin,
+    W: SpliceSocket + AsyncWrite + Unpin,
+{
+    #[cfg(target_os = "linux")]
+    if let (Some(src), Some(dst)) = (reader.splice_socket(), writer.splice_socket()) {
+        match linux::splice_loop(src, dst).await {
+            Ok(n) => return Ok(n),
+            Err(linux::SpliceError::Unsupported(e)) => {
+                tracing::debug!(error = %e, "splice unavailable, falling back to userspace copy");
+            }
+            Err(linux::SpliceError::Io(e)) => return Err(e),
+        }
+    }
+
+    tokio::io::copy(reader, writer).await
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use tokio::io::Interest;
+    use tokio::net::TcpStream;
+
+    /// Maximum bytes moved per `splice(2)` call (matches the default pipe capacity).
+    const SPLICE_CHUNK: usize = 64 * 1024;
+
+    pub(super) enum SpliceError {
+        /// Splicing is not possible for these descriptors; nothing was moved yet.
+        Unsupported(io::Error),
+        /// A genuine I/O error after forwarding started.
+        Io(io::Error),
+    }
+
+    /// A non-blocking pipe used as the in-kernel buffer for one direction.
+    struct Pipe {
+        read: OwnedFd,
+        write: OwnedFd,
+    }
+
+    impl Pipe {
+        fn new() -> io::Result<Self> {
+            let mut fds: [libc::c_int; 2] = [-1; 2];
+            // SAFETY: `fds` is a valid two-element array for pipe2 to fill.
+            let rc = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+            if rc < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: pipe2 succeeded, so both descriptors are open and owned by us.
+            unsafe {
+                Ok(Self {
+                    read: OwnedFd::from_raw_fd(fds[0]),
+                    write: OwnedFd::from_raw_fd(fds[1]),
+                })
+            }
+        }
+    }
+
+    fn splice(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+        // SAFETY: both descriptors are open for the duration of the call and
+        // null offsets are valid for sockets and pipes.
+        let n = unsafe {
+            libc::splice(
+                fd_in,
+                std::ptr::null_mut(),
+                fd_out,
+                std::ptr::null_mut(),
+                len,
+                libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+            )
+        };
+        if n < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    fn is_unsupported(e: &io::Error) -> bool {
+        matches!(
+            e.raw_os_error(),
+            Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP)
+        )
+    }
+
+    pub(super) async fn splice_loop(src: &TcpStream, dst: &TcpStream) -> Result<u64, SpliceError> {
+        let pipe = Pipe::new().map_err(SpliceError::Unsupported)?;
+        let mut total: u64 = 0;
+
+        loop {
+            // socket -> pipe. The pipe is always empty here, so an "unsupported"
+            // error can still be handed back to the userspace fallback.
+            let filled = loop {
+                src.readable().await.map_err(SpliceError::Io)?;
+                match src.try_io(Interest::READABLE, || {
+                    splice(src.as_raw_fd(), pipe.write.as_raw_fd(), SPLICE_CHUNK)
+                }) {
+                    Ok(n) => break n,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) if total == 0 && is_unsupported(&e) => {
+                        return Err(SpliceError::Unsupported(e))
+                    }
+                    Err(e) => return Err(SpliceError::Io(e)),
+                }
+            };
+
+            if filled == 0 {
+                return Ok(total);
+            }
+
+            // pipe -> socket, until the pipe is drained.
+            let mut pending = filled;
+            while pending > 0 {
+                dst.writable().await.map_err(SpliceError::Io)?;
+                match dst.try_io(Interest::WRITABLE, || {
+                    splice(pipe.read.as_raw_fd(), dst.as_raw_fd(), pending)
+                }) {
+                    Ok(n) => {
+                        pending -= n;
+                        total += n as u64;
+                        crate::metrics::SPLICED_BYTES.inc_by(n as u64);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => return Err(SpliceError::Io(e)),
+                }
+            }
+        }
+    }
+}
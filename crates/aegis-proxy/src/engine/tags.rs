@@ -0,0 +1,158 @@
+//! Bounded connection tags for metric labels.
+//!
+//! Connections are tagged along a fixed set of dimensions (source policy
+//! profile, detected protocol, load balancer endpoint from a PROXY protocol
+//! TLV) and a few metrics are broken down by those
+//! tags. Each dimension only reports the values listed in `metric_tags`;
+//! anything else is folded into `other`, and an unlisted dimension reports
+//! `any`. The label cardinality is therefore fixed by the configuration,
+//! which is validated at startup.
+
+use aegis_common::MetricTagsConfig;
+use std::sync::Arc;
+
+/// Label for values not in a dimension's allowed list.
+pub const OTHER: &str = "other";
+/// Label for every value of a dimension with no allowed list.
+pub const ANY: &str = "any";
+/// Most values allowed per dimension.
+pub const MAX_VALUES_PER_DIMENSION: usize = 16;
+
+const PROTOCOLS: &[&str] = &["mqtt", "http", "websocket", "tls"];
+
+/// Allowed tag values per dimension.
+#[derive(Debug)]
+pub struct TagSet {
+    profile: Vec<String>,
+    protocol: Vec<String>,
+    endpoint: Vec<String>,
+    proxy_tlv_type: Option<u8>,
+}
+
+impl TagSet {
+    /// Validates `config` against the values each dimension can take:
+    /// `profiles` (the configured source policy profiles) and the detected
+    /// protocols. Endpoints can be any value, but need `proxy_tlv_type`.
+    pub fn from_config(config: &MetricTagsConfig, profiles: &[&str]) -> Result<Self, String> {
+        validate_dimension("profile", &config.profile, Some(profiles))?;
+        validate_dimension("protocol", &config.protocol, Some(PROTOCOLS))?;
+        validate_dimension("endpoint", &config.endpoint, None)?;
+        if !config.endpoint.is_empty() && config.proxy_tlv_type.is_none() {
+            return Err("metric_tags.endpoint needs metric_tags.proxy_tlv_type".to_string());
+        }
+        Ok(Self {
+            profile: config.profile.clone(),
+            protocol: config.protocol.clone(),
+            endpoint: config.endpoint.clone(),
+            proxy_tlv_type: config.proxy_tlv_type,
+        })
+    }
+
+    /// Upper bound on the label combinations of a tagged metric.
+    pub fn cardinality(&self) -> usize {
+        dimension_size(&self.profile)
+            * dimension_size(&self.protocol)
+            * dimension_size(&self.endpoint)
+    }
+
+    /// PROXY protocol v2 TLV type carrying the endpoint, when configured.
+    pub fn proxy_tlv_type(&self) -> Option<u8> {
+        self.proxy_tlv_type
+    }
+}
+
+fn validate_dimension(
+    dimension: &str,
+    values: &[String],
+    known: Option<&[&str]>,
+) -> Result<(), String> {
+    if values.len() > MAX_VALUES_PER_DIMENSION {
+        return Err(format!(
+            "metric_tags.{}: at most {} values are allowed (got {})",
+            dimension,
+            MAX_VALUES_PER_DIMENSION,
+            values.len()
+        ));
+    }
+    for (i, value) in values.iter().enumerate() {
+        if known.is_some_and(|known| !known.contains(&value.as_str())) {
+            return Err(format!(
+                "metric_tags.{}: '{}' is not one of {:?}",
+                dimension, value, known
+            ));
+        }
+        if values[..i].contains(value) {
+            return Err(format!(
+                "metric_tags.{}: '{}' is listed twice",
+                dimension, value
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Allowed values plus `other`, or just `any` for an unlisted dimension.
+fn dimension_size(values: &[String]) -> usize {
+    if values.is_empty() {
+        1
+    } else {
+        values.len() + 1
+    }
+}
+
+fn label<'a>(allowed: &'a [String], value: &str) -> &'a str {
+    if allowed.is_empty() {
+        return ANY;
+    }
+    allowed
+        .iter()
+        .find(|v| *v == value)
+        .map_or(OTHER, String::as_str)
+}
+
+/// One connection's tags, filled in as inspection learns them.
+#[derive(Debug, Clone)]
+pub struct ConnectionTags {
+    set: Arc<TagSet>,
+    profile: String,
+    /// `other` until a PROXY header names a listed endpoint.
+    endpoint: String,
+}
+
+impl ConnectionTags {
+    pub fn new(set: Arc<TagSet>, profile: &str) -> Self {
+        let profile = label(&set.profile, profile).to_string();
+        let endpoint = label(&set.endpoint, "").to_string();
+        Self {
+            set,
+            profile,
+            endpoint,
+        }
+    }
+
+    /// PROXY protocol v2 TLV type carrying the endpoint, when configured.
+    pub fn proxy_tlv_type(&self) -> Option<u8> {
+        self.set.proxy_tlv_type
+    }
+
+    /// Replaces the profile chosen at accept, for a client the PROXY header
+    /// named.
+    pub fn set_profile(&mut self, profile: &str) {
+        self.profile = label(&self.set.profile, profile).to_string();
+    }
+
+    /// Records the endpoint the PROXY header named.
+    pub fn set_endpoint(&mut self, endpoint: &str) {
+        self.endpoint = label(&self.set.endpoint, endpoint).to_string();
+    }
+
+    /// `[profile, protocol, endpoint]` label values for a connection routed
+    /// as `protocol`.
+    pub fn labels(&self, protocol: &str) -> [&str; 3] {
+        [
+            &self.profile,
+            label(&self.set.protocol, protocol),
+            &self.endpoint,
+        ]
+    }
+}
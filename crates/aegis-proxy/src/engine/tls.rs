@@ -0,0 +1,236 @@
+//! TLS termination for client connections.
+//!
+//! With `tls.enabled`, each accepted socket completes a TLS handshake before
+//! anything reads from it, so inspection sees the decrypted stream and the
+//! backend receives plaintext. The handshake runs in the connection's own
+//! task, bounded by `handshake_timeout_ms`, never on the accept loop. With
+//! `max_concurrent_handshakes`, a handshake first waits for one of a fixed
+//! number of slots, so a handshake flood cannot take every core.
+//!
+//! The socket underneath carries ciphertext, so TLS sessions are always
+//! copied in userspace (never spliced), and peeking is served from a small
+//! buffer of decrypted bytes read ahead of the handler.
+
+use crate::engine::connection::ClientStream;
+use aegis_common::TlsConfig;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{ProtocolVersion, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tracing::debug;
+
+pub use tokio_rustls::TlsAcceptor;
+
+/// Builds the acceptor from the configured certificate chain and key.
+pub fn load_acceptor(config: &TlsConfig) -> Result<TlsAcceptor, String> {
+    let read =
+        |path: &str| std::fs::read(path).map_err(|e| format!("tls: cannot read '{}': {}", path, e));
+    let certs = CertificateDer::pem_slice_iter(&read(&config.cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("tls.cert_path '{}': {}", config.cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!(
+            "tls.cert_path '{}': no certificates found",
+            config.cert_path
+        ));
+    }
+    let key = PrivateKeyDer::from_pem_slice(&read(&config.key_path)?)
+        .map_err(|e| format!("tls.key_path '{}': {}", config.key_path, e))?;
+    let server = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("tls: {}", e))?;
+    Ok(TlsAcceptor::from(Arc::new(server)))
+}
+
+/// Slots for TLS handshakes in progress, shared by a listener's connections.
+#[derive(Clone)]
+pub struct HandshakeLimit {
+    slots: Arc<Semaphore>,
+    /// How long a connection waits for a slot.
+    wait: Duration,
+}
+
+impl HandshakeLimit {
+    pub fn new(max: usize, wait: Duration) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(max)),
+            wait,
+        }
+    }
+
+    /// Waits for a slot, held until the permit is dropped. `None` if no slot
+    /// freed up in time.
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        crate::metrics::TLS_HANDSHAKE_WAITERS.inc();
+        let slot = timeout(self.wait, Arc::clone(&self.slots).acquire_owned()).await;
+        crate::metrics::TLS_HANDSHAKE_WAITERS.dec();
+        slot.ok()?.ok()
+    }
+}
+
+/// What a completed handshake negotiated, for audit and weak-client
+/// visibility.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsParameters {
+    /// `tls1.2`, `tls1.3`, or `other`.
+    pub version: &'static str,
+    /// IANA cipher suite name, e.g. `TLS13_AES_128_GCM_SHA256`.
+    pub cipher: String,
+    pub sni: Option<String>,
+}
+
+/// A client connection with TLS terminated by the proxy.
+pub struct TlsClient {
+    stream: TlsStream<TcpStream>,
+    /// Decrypted bytes read ahead by `peek`, returned before the stream's.
+    peeked: Vec<u8>,
+}
+
+impl TlsClient {
+    /// Completes the server side of the handshake on `socket` within `limit`,
+    /// however slowly the client trickles its handshake messages. Handshakes
+    /// cut off by `limit` and ones that failed are counted apart.
+    pub async fn accept(
+        acceptor: &TlsAcceptor,
+        socket: TcpStream,
+        limit: Duration,
+    ) -> io::Result<Self> {
+        let stream = match timeout(limit, acceptor.accept(socket)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => {
+                crate::metrics::TLS_HANDSHAKE_FAILURES.inc();
+                return Err(e);
+            }
+            Err(_) => {
+                crate::metrics::TLS_HANDSHAKE_TIMEOUTS.inc();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "TLS handshake timed out",
+                ));
+            }
+        };
+        let client = Self {
+            stream,
+            peeked: Vec::new(),
+        };
+        let params = client.parameters();
+        crate::metrics::TLS_CONNECTIONS_BY_VERSION
+            .with_label_values(&[params.version])
+            .inc();
+        debug!(
+            version = params.version,
+            cipher = %params.cipher,
+            sni = params.sni.as_deref().unwrap_or(""),
+            "TLS handshake completed"
+        );
+        Ok(client)
+    }
+
+    /// Version, cipher suite and SNI the handshake settled on.
+    pub fn parameters(&self) -> TlsParameters {
+        let connection = self.stream.get_ref().1;
+        let version = match connection.protocol_version() {
+            Some(ProtocolVersion::TLSv1_2) => "tls1.2",
+            Some(ProtocolVersion::TLSv1_3) => "tls1.3",
+            _ => "other",
+        };
+        let cipher = connection
+            .negotiated_cipher_suite()
+            .and_then(|suite| suite.suite().as_str())
+            .unwrap_or("unknown")
+            .to_string();
+        TlsParameters {
+            version,
+            cipher,
+            sni: connection.server_name().map(str::to_string),
+        }
+    }
+
+    /// `version=.. cipher=.. sni=..`, for the decision trace.
+    pub fn describe(&self) -> String {
+        let params = self.parameters();
+        format!(
+            "version={} cipher={} sni={}",
+            params.version,
+            params.cipher,
+            params.sni.as_deref().unwrap_or("none")
+        )
+    }
+}
+
+impl AsyncRead for TlsClient {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.peeked.is_empty() {
+            let n = this.peeked.len().min(buf.remaining());
+            buf.put_slice(&this.peeked[..n]);
+            this.peeked.drain(..n);
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsClient {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+impl ClientStream for TlsClient {
+    type ReadHalf = ReadHalf<TlsClient>;
+    type WriteHalf = WriteHalf<TlsClient>;
+
+    fn socket(&self) -> Option<&TcpStream> {
+        Some(self.stream.get_ref().0)
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let wanted = buf.len().saturating_sub(self.peeked.len());
+        if wanted > 0 {
+            let mut chunk = vec![0u8; wanted];
+            let read = self.stream.read(&mut chunk);
+            // Like a socket peek: wait for the first byte, then only take
+            // what is already available.
+            let n = if self.peeked.is_empty() {
+                read.await?
+            } else {
+                timeout(Duration::ZERO, read).await.unwrap_or(Ok(0))?
+            };
+            self.peeked.extend_from_slice(&chunk[..n]);
+        }
+        let n = self.peeked.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.peeked[..n]);
+        Ok(n)
+    }
+
+    fn into_split(self) -> (Self::ReadHalf, Self::WriteHalf) {
+        tokio::io::split(self)
+    }
+}
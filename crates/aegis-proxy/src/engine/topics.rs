@@ -0,0 +1,84 @@
+//! PUBLISH topic allow/deny rules (`enable_publish_inspection`).
+//!
+//! Patterns are MQTT topic filters: `+` matches exactly one level and a
+//! trailing `#` matches the parent level and everything below it. As in MQTT,
+//! a pattern starting with a wildcard does not match `$`-prefixed topics.
+
+use aegis_common::MqttPolicyConfig;
+
+/// Topic patterns a client may (not) publish to.
+#[derive(Debug, Clone, Default)]
+pub struct TopicRules {
+    /// When set, a topic must match one of these.
+    allow: Option<Vec<String>>,
+    /// A topic matching one of these is refused, even if allowed.
+    deny: Vec<String>,
+}
+
+impl TopicRules {
+    pub fn from_config(policy: &MqttPolicyConfig) -> Result<Self, String> {
+        let patterns = policy
+            .publish_allow_topics
+            .iter()
+            .flatten()
+            .chain(&policy.publish_deny_topics);
+        for pattern in patterns {
+            if !is_valid_filter(pattern) {
+                return Err(format!("invalid topic pattern: {:?}", pattern));
+            }
+        }
+        Ok(Self {
+            allow: policy.publish_allow_topics.clone(),
+            deny: policy.publish_deny_topics.clone(),
+        })
+    }
+
+    /// Whether a client may publish to `topic`.
+    pub fn permits(&self, topic: &str) -> bool {
+        if self.deny.iter().any(|filter| topic_matches(filter, topic)) {
+            return false;
+        }
+        self.allow
+            .as_ref()
+            .is_none_or(|allow| allow.iter().any(|filter| topic_matches(filter, topic)))
+    }
+}
+
+/// Whether `topic` matches the topic filter `filter`.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && (filter.starts_with('+') || filter.starts_with('#')) {
+        return false;
+    }
+    let mut levels = topic.split('/');
+    for part in filter.split('/') {
+        match part {
+            "#" => return true,
+            "+" => {
+                if levels.next().is_none() {
+                    return false;
+                }
+            }
+            _ => {
+                if levels.next() != Some(part) {
+                    return false;
+                }
+            }
+        }
+    }
+    levels.next().is_none()
+}
+
+/// Non-empty, with `+` and `#` only as whole levels and `#` only last.
+fn is_valid_filter(filter: &str) -> bool {
+    let mut parts = filter.split('/').peekable();
+    while let Some(part) = parts.next() {
+        let wildcard = part.contains(['+', '#']);
+        if wildcard && part != "+" && part != "#" {
+            return false;
+        }
+        if part == "#" && parts.peek().is_some() {
+            return false;
+        }
+    }
+    !filter.is_empty()
+}
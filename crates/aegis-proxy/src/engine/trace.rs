@@ -0,0 +1,269 @@
+//! Per-connection decision trace for debugging admission policy.
+//!
+//! With several policy layers (FD pressure, region filter, source profiles,
+//! rate and subnet limits, inspection, routing) it is hard to tell *why* a
+//! connection was admitted or rejected. When `trace_decisions` is enabled each
+//! connection carries an ordered list of the checks it went through, emitted
+//! as a single `aegis_audit` record once the verdict is known.
+//!
+//! Checks are recorded as they start; starting the next check (or admitting
+//! the connection) marks the previous one as passed. A trace dropped with a
+//! check still open means that check rejected the connection, so rejection
+//! sites need no extra bookkeeping. Disabled traces never allocate.
+//!
+//! A trace with an enabled `AccessLog` attached records its checks even when
+//! the audit record is off, and hands the verdict to the access log.
+
+use crate::engine::access_log::AccessLog;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::info;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Pass,
+    Skip,
+    Reject,
+}
+
+#[derive(Debug)]
+struct Step {
+    check: &'static str,
+    detail: Option<String>,
+    outcome: Option<Outcome>,
+}
+
+#[derive(Debug, Default)]
+struct Steps {
+    steps: Vec<Step>,
+    effective_config: Option<String>,
+    phase_timings: Vec<(&'static str, Duration)>,
+    emitted: bool,
+}
+
+/// Ordered record of the admission checks applied to one connection.
+#[derive(Debug, Default)]
+pub struct DecisionTrace {
+    client: String,
+    inner: Option<Mutex<Steps>>,
+    /// Emit the `aegis_audit` record (and events); off when the trace only
+    /// records for the access log.
+    audit: bool,
+    access_log: AccessLog,
+}
+
+impl DecisionTrace {
+    pub fn new(enabled: bool, client: impl Into<String>) -> Self {
+        Self {
+            client: client.into(),
+            inner: enabled.then(|| Mutex::new(Steps::default())),
+            audit: enabled,
+            access_log: AccessLog::disabled(),
+        }
+    }
+
+    /// Reports the verdict to `access_log`, recording checks for it if the
+    /// trace was disabled.
+    pub fn with_access_log(mut self, access_log: AccessLog) -> Self {
+        if access_log.is_enabled() && self.inner.is_none() {
+            self.inner = Some(Mutex::new(Steps::default()));
+        }
+        self.access_log = access_log;
+        self
+    }
+
+    /// A trace that records nothing.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Starts evaluating `check`, marking the previous check as passed.
+    pub fn check(&self, check: &'static str) {
+        self.push(check, None, None);
+    }
+
+    /// Records a check that was evaluated and passed, with a detail value
+    /// (e.g. the selected profile or route).
+    pub fn pass(&self, check: &'static str, detail: impl Into<String>) {
+        if self.is_enabled() {
+            self.push(check, Some(detail.into()), Some(Outcome::Pass));
+        }
+    }
+
+    /// Records a check that does not apply to this connection.
+    pub fn skip(&self, check: &'static str) {
+        self.push(check, None, Some(Outcome::Skip));
+    }
+
+    /// Attaches the effective connection settings to the emitted record.
+    /// `describe` only runs when the trace is enabled.
+    pub fn record_config(&self, describe: impl FnOnce() -> String) {
+        if let Some(inner) = &self.inner {
+            lock(inner).effective_config = Some(describe());
+        }
+    }
+
+    /// Effective settings attached with `record_config`, if any.
+    pub fn effective_config(&self) -> Option<String> {
+        self.inner
+            .as_ref()
+            .and_then(|inner| lock(inner).effective_config.clone())
+    }
+
+    /// Records how long a handshake phase took, for the emitted record.
+    pub fn record_phase(&self, phase: &'static str, elapsed: Duration) {
+        if let Some(inner) = &self.inner {
+            lock(inner).phase_timings.push((phase, elapsed));
+        }
+    }
+
+    /// Phase timings recorded so far as `phase=<ms>ms` pairs; `None` if none.
+    pub fn phase_timings(&self) -> Option<String> {
+        render_timings(&lock(self.inner.as_ref()?).phase_timings)
+    }
+
+    /// Marks the connection as admitted and emits the trace.
+    pub fn admit(&self) {
+        self.emit("admitted");
+    }
+
+    /// Reports the end of an admitted session to the event bus, if any.
+    pub fn closed(&self) {
+        #[cfg(feature = "nats-events")]
+        if self.audit {
+            crate::engine::events::publish("closed", &self.client, serde_json::json!({}));
+        }
+    }
+
+    /// Ordered `check=outcome` pairs recorded so far.
+    pub fn render(&self) -> String {
+        match &self.inner {
+            Some(inner) => render_steps(&lock(inner).steps),
+            None => String::new(),
+        }
+    }
+
+    fn push(&self, check: &'static str, detail: Option<String>, outcome: Option<Outcome>) {
+        if let Some(inner) = &self.inner {
+            let mut inner = lock(inner);
+            close_open_step(&mut inner.steps, Outcome::Pass);
+            inner.steps.push(Step {
+                check,
+                detail,
+                outcome,
+            });
+        }
+    }
+
+    fn emit(&self, default_verdict: &'static str) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let mut inner = lock(inner);
+        if inner.emitted {
+            return;
+        }
+        inner.emitted = true;
+
+        let verdict = if default_verdict == "admitted" {
+            close_open_step(&mut inner.steps, Outcome::Pass);
+            "admitted"
+        } else if close_open_step(&mut inner.steps, Outcome::Reject) {
+            "rejected"
+        } else {
+            default_verdict
+        };
+        let rejected_by = inner
+            .steps
+            .last()
+            .filter(|_| verdict == "rejected")
+            .map(|step| step.check);
+        self.access_log.decide(verdict == "admitted", rejected_by);
+        if !self.audit {
+            return;
+        }
+        let trace = render_steps(&inner.steps);
+        let timings = render_timings(&inner.phase_timings);
+        #[cfg(feature = "nats-events")]
+        crate::engine::events::publish(
+            verdict,
+            &self.client,
+            serde_json::json!({
+                "trace": trace,
+                "effective_config": inner.effective_config,
+                "phase_timings": timings,
+            }),
+        );
+        // Optional fields are left out of the record when unset.
+        info!(
+            target: "aegis_audit",
+            client = %self.client,
+            verdict = verdict,
+            trace = %trace,
+            effective_config = inner.effective_config.as_deref(),
+            phase_timings = timings.as_deref(),
+            "Connection decision trace"
+        );
+    }
+}
+
+impl Drop for DecisionTrace {
+    fn drop(&mut self) {
+        // A still-open check at this point is the one that ended the connection.
+        self.emit("closed");
+    }
+}
+
+fn lock(inner: &Mutex<Steps>) -> std::sync::MutexGuard<'_, Steps> {
+    inner.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Resolves the last step if it is still open; returns whether it was.
+fn close_open_step(steps: &mut [Step], outcome: Outcome) -> bool {
+    match steps.last_mut() {
+        Some(step) if step.outcome.is_none() => {
+            step.outcome = Some(outcome);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn render_steps(steps: &[Step]) -> String {
+    let mut out = String::new();
+    for step in steps {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        let outcome = match step.outcome {
+            Some(Outcome::Pass) => "pass",
+            Some(Outcome::Skip) => "skip",
+            Some(Outcome::Reject) => "reject",
+            None => "pending",
+        };
+        let _ = write!(out, "{}={}", step.check, outcome);
+        if let Some(detail) = &step.detail {
+            let _ = write!(out, "({})", detail);
+        }
+    }
+    out
+}
+
+fn render_timings(timings: &[(&'static str, Duration)]) -> Option<String> {
+    if timings.is_empty() {
+        return None;
+    }
+    let mut out = String::new();
+    for (phase, elapsed) in timings {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        let _ = write!(out, "{}={:.3}ms", phase, elapsed.as_secs_f64() * 1000.0);
+    }
+    Some(out)
+}
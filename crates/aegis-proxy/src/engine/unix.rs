@@ -0,0 +1,117 @@
+//! Unix domain socket clients (`listen_address: "unix:<path>"`).
+//!
+//! For sidecar deployments where the proxy and its clients share a host.
+//! A Unix peer has no IP address: the accept loop runs its address-based
+//! checks (region filter, access control, source profile) against
+//! `UNIX_PEER`, loopback, and skips the per-IP ones (rate limit, connect
+//! ratio, repeated-malformed bans, per-IP concurrency).
+//!
+//! Tokio's Unix stream cannot peek, so peeked bytes are read ahead into a
+//! small buffer and returned before the stream's, as for TLS clients.
+
+use crate::engine::connection::ClientStream;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf, ReadHalf, WriteHalf};
+use tokio::net::{TcpStream, UnixListener, UnixStream};
+use tokio::time::timeout;
+
+/// The address accept-time checks see for a Unix socket peer.
+pub const UNIX_PEER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+/// Binds a listener at `path`, replacing a stale socket file left by a
+/// previous run.
+pub fn bind(path: &str) -> io::Result<UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
+
+/// A client connected over a Unix domain socket.
+pub struct UnixClient {
+    stream: UnixStream,
+    /// Bytes read ahead by `peek`, returned before the stream's.
+    peeked: Vec<u8>,
+}
+
+impl UnixClient {
+    pub fn new(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            peeked: Vec::new(),
+        }
+    }
+}
+
+impl AsyncRead for UnixClient {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.peeked.is_empty() {
+            let n = this.peeked.len().min(buf.remaining());
+            buf.put_slice(&this.peeked[..n]);
+            this.peeked.drain(..n);
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixClient {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+impl ClientStream for UnixClient {
+    type ReadHalf = ReadHalf<UnixClient>;
+    type WriteHalf = WriteHalf<UnixClient>;
+
+    fn socket(&self) -> Option<&TcpStream> {
+        None
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let wanted = buf.len().saturating_sub(self.peeked.len());
+        if wanted > 0 {
+            let mut chunk = vec![0u8; wanted];
+            let read = self.stream.read(&mut chunk);
+            // Like a socket peek: wait for the first byte, then only take
+            // what is already available.
+            let n = if self.peeked.is_empty() {
+                read.await?
+            } else {
+                timeout(Duration::ZERO, read).await.unwrap_or(Ok(0))?
+            };
+            self.peeked.extend_from_slice(&chunk[..n]);
+        }
+        let n = self.peeked.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.peeked[..n]);
+        Ok(n)
+    }
+
+    fn into_split(self) -> (Self::ReadHalf, Self::WriteHalf) {
+        tokio::io::split(self)
+    }
+}
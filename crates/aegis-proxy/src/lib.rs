@@ -1,6 +1,11 @@
+// The metrics `lazy_static!` block expands one level per static.
+#![recursion_limit = "256"]
+
+pub mod cli;
 pub mod engine;
 pub mod metrics;
 pub mod parser;
+pub mod reload;
 
 pub use crate::metrics::{CONNECTION_GAUGE, REJECTED_CONNECTIONS};
 pub use engine::connection::{handle_connection, ACTIVE_CONNECTIONS};
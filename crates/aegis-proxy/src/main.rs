@@ -1,18 +1,48 @@
-use aegis_common::Config;
-use aegis_proxy::engine::connection::{handle_connection, ConnectionConfig};
-use aegis_proxy::engine::limiter::{check_rate_limit, start_cleanup_task};
+use aegis_common::{
+    load_config, unix_socket_path, BackendUnavailableAction, Config, RateLimitBackendKind,
+};
+use aegis_proxy::cli::{config_path, USAGE};
+use aegis_proxy::engine::accept_filter::{self, AcceptDecision};
+use aegis_proxy::engine::access_log::AccessLog;
+use aegis_proxy::engine::backend::{BackendHealth, BackendSelector};
+use aegis_proxy::engine::capture::PacketCapture;
+use aegis_proxy::engine::cidr::{AccessControl, RegionFilter};
+use aegis_proxy::engine::connection::{
+    backend_degraded, handle_connection, reject_while_draining, send_busy_signal, ConnectionConfig,
+    ProxiedChecks, SourceChecks, ACTIVE_CONNECTIONS, DRAINING,
+};
+use aegis_proxy::engine::fd_pressure::{start_fd_monitor, FD_PRESSURE};
+use aegis_proxy::engine::limiter::{
+    check_connect_ratio, check_rate_limit, malformed_banned, rate_limit_snapshot, reset_rate_limit,
+    start_cleanup_task, InspectionLimiter, SubnetLimiter,
+};
+use aegis_proxy::engine::policy::SourcePolicy;
+use aegis_proxy::engine::signature::SignatureSet;
+use aegis_proxy::engine::tags::{ConnectionTags, TagSet};
+use aegis_proxy::engine::tls::{load_acceptor, HandshakeLimit, TlsClient};
+use aegis_proxy::engine::topics::TopicRules;
+use aegis_proxy::engine::trace::DecisionTrace;
+#[cfg(unix)]
+use aegis_proxy::engine::unix::{self, UnixClient, UNIX_PEER};
 use aegis_proxy::metrics;
+use aegis_proxy::reload;
+use arc_swap::ArcSwap;
 use hyper::{
     service::{make_service_fn, service_fn},
     Body, Request, Response, Server, StatusCode,
 };
+use std::collections::HashMap;
 use std::convert::Infallible;
-use std::fs;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 fn init_production_logging() {
@@ -26,11 +56,24 @@ fn init_production_logging() {
     info!("Production structured logging initialized (JSON)");
 }
 
-/// Handle simple HTTP endpoints for liveness and metrics.
-async fn metrics_handler(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+/// Handle simple HTTP endpoints for liveness and metrics, plus the
+/// rate-limiter endpoints when `admin` is set.
+async fn metrics_handler(req: Request<Body>, admin: bool) -> Result<Response<Body>, Infallible> {
     match req.uri().path() {
+        "/health" if DRAINING.load(Ordering::SeqCst) => {
+            let mut draining = Response::new(Body::from("DRAINING"));
+            *draining.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            Ok(draining)
+        }
+        "/health" if backend_degraded() => {
+            let mut degraded = Response::new(Body::from("DEGRADED: backend unavailable"));
+            *degraded.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            Ok(degraded)
+        }
         "/health" => Ok(Response::new(Body::from("OK"))),
         "/metrics" => Ok(Response::new(Body::from(metrics::render_metrics()))),
+        "/ratelimit" if admin => Ok(json_response(StatusCode::OK, rate_limit_json())),
+        "/ratelimit/reset" if admin => Ok(reset_rate_limit_handler(&req)),
         _ => {
             let mut not_found = Response::new(Body::from("Not Found"));
             *not_found.status_mut() = StatusCode::NOT_FOUND;
@@ -39,104 +82,1155 @@ async fn metrics_handler(req: Request<Body>) -> Result<Response<Body>, Infallibl
     }
 }
 
-async fn run_metrics_server(port: u16) {
+fn json_response(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+/// `IP_TRACKER` as JSON, most throttled client first.
+fn rate_limit_json() -> String {
+    let buckets: Vec<String> = rate_limit_snapshot()
+        .iter()
+        .map(|bucket| {
+            format!(
+                "{{\"ip\":\"{}\",\"tokens\":{:.3},\"last_refill_age_ms\":{}}}",
+                bucket.addr,
+                bucket.tokens,
+                bucket.last_refill_age.as_millis()
+            )
+        })
+        .collect();
+    format!("{{\"buckets\":[{}]}}", buckets.join(","))
+}
+
+/// `POST /ratelimit/reset?ip=<ip>`: clears one client's bucket.
+fn reset_rate_limit_handler(req: &Request<Body>) -> Response<Body> {
+    if req.method() != hyper::Method::POST {
+        let mut not_allowed = Response::new(Body::from("Method Not Allowed"));
+        *not_allowed.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        not_allowed.headers_mut().insert(
+            hyper::header::ALLOW,
+            hyper::header::HeaderValue::from_static("POST"),
+        );
+        return not_allowed;
+    }
+    let query = req.uri().query().unwrap_or("");
+    let ip = url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "ip")
+        .map(|(_, ip)| ip);
+    let Some(addr) = ip.and_then(|ip| ip.parse::<IpAddr>().ok()) else {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            "{\"error\":\"expected ?ip=<address>\"}".to_string(),
+        );
+    };
+    let cleared = reset_rate_limit(addr);
+    json_response(
+        StatusCode::OK,
+        format!("{{\"ip\":\"{}\",\"cleared\":{}}}", addr, cleared),
+    )
+}
+
+async fn run_metrics_server(port: u16, admin: bool, shutdown: CancellationToken) {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     metrics::register_metrics();
 
-    let make_svc =
-        make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(metrics_handler)) });
+    let make_svc = make_service_fn(move |_conn| async move {
+        Ok::<_, Infallible>(service_fn(move |req| metrics_handler(req, admin)))
+    });
 
-    let server = Server::bind(&addr).serve(make_svc);
+    let server = Server::bind(&addr)
+        .serve(make_svc)
+        .with_graceful_shutdown(async move { shutdown.cancelled().await });
 
-    info!(port = port, "Observability server online");
+    info!(
+        port = port,
+        admin_endpoints = admin,
+        "Observability server online"
+    );
 
     if let Err(e) = server.await {
         error!(error = %e, "Observability server failed");
     }
 }
 
+/// How long background tasks get to finish their cleanup after shutdown.
+const TASK_SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Accepted clients queued between the accept loops and the admission checks.
+const ACCEPT_QUEUE: usize = 64;
+
+/// Upper bound on the injected edge identity, keeping CONNECT growth small.
+const MAX_EDGE_ID_LEN: usize = 128;
+
+/// Resolve the edge identity to inject into forwarded CONNECTs, if enabled.
+fn resolve_edge_id(config: &Config) -> Option<String> {
+    if !config.proxy.inject_edge_id {
+        return None;
+    }
+    let mut id = config.proxy.edge_instance_id.clone().unwrap_or_else(|| {
+        hostname::get()
+            .ok()
+            .and_then(|h| h.into_string().ok())
+            .unwrap_or_else(|| "aegisgate".to_string())
+    });
+    if id.len() > MAX_EDGE_ID_LEN {
+        let mut cut = MAX_EDGE_ID_LEN;
+        while !id.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        id.truncate(cut);
+        warn!(edge_id = %id, "Edge instance ID truncated to {} bytes", MAX_EDGE_ID_LEN);
+    }
+    info!(edge_id = %id, "Injecting edge identity into MQTT 5.0 CONNECTs");
+    Some(id)
+}
+
+/// Everything the accept loop derives from one listener's configuration.
+/// Rebuilt as a whole on reload, so a connection never mixes old and new
+/// settings.
+struct LiveConfig {
+    config: Config,
+    source_policy: Arc<SourcePolicy>,
+    tag_set: Option<Arc<TagSet>>,
+    region_filter: Option<Arc<RegionFilter>>,
+    access_control: Option<Arc<AccessControl>>,
+    fast_path: Option<Arc<SignatureSet>>,
+    sni_routes: Option<Arc<HashMap<String, String>>>,
+    publish_topics: Arc<TopicRules>,
+    edge_instance_id: Option<String>,
+}
+
+impl LiveConfig {
+    fn build(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let region_filter = match &config.region_filter {
+            Some(region_cfg) => {
+                let filter = RegionFilter::from_config(region_cfg)?;
+                info!(policy = ?region_cfg.policy, regions = region_cfg.regions.len(), "Region filter enabled");
+                Some(Arc::new(filter))
+            }
+            None => None,
+        };
+
+        let access_control = match &config.access_control {
+            Some(acl_cfg) => {
+                let acl = AccessControl::from_config(acl_cfg)?;
+                info!(
+                    allow = acl_cfg.allow_cidrs.len(),
+                    deny = acl_cfg.deny_cidrs.len(),
+                    "Access control lists enabled"
+                );
+                Some(Arc::new(acl))
+            }
+            None => None,
+        };
+
+        let sni_routes = (!config.proxy.sni_routes.is_empty()).then(|| {
+            let routes: HashMap<String, String> = config
+                .proxy
+                .sni_routes
+                .iter()
+                .map(|(name, backend)| {
+                    let name = name.strip_suffix('.').unwrap_or(name);
+                    (name.to_ascii_lowercase(), backend.clone())
+                })
+                .collect();
+            info!(
+                routes = routes.len(),
+                "TLS passthrough routing by SNI enabled"
+            );
+            Arc::new(routes)
+        });
+
+        let fast_path = match &config.signature_fast_path {
+            Some(fast_path_cfg) => {
+                let signatures = SignatureSet::from_config(fast_path_cfg)?;
+                info!(
+                    signatures = fast_path_cfg.signatures.len(),
+                    verify_every = fast_path_cfg.verify_every,
+                    "Signature fast path enabled"
+                );
+                Some(Arc::new(signatures))
+            }
+            None => None,
+        };
+
+        if config.proxy.backend_write_buffer_bytes.is_some()
+            && config.features.enable_splice_forwarding
+        {
+            warn!("backend_write_buffer_bytes has no effect with splice forwarding enabled");
+        }
+
+        let source_policy = SourcePolicy::from_config(
+            config.source_policy.as_ref(),
+            &config.features,
+            &config.limit,
+        )?;
+        if let Some(policy_cfg) = &config.source_policy {
+            info!(
+                profiles = policy_cfg.profiles.len(),
+                "Source policy profiles enabled"
+            );
+        }
+
+        let tag_set = match &config.metric_tags {
+            Some(tags_cfg) => {
+                let set = TagSet::from_config(tags_cfg, &source_policy.profile_names())?;
+                info!(
+                    max_label_sets = set.cardinality(),
+                    "Tagged connection metrics enabled"
+                );
+                Some(Arc::new(set))
+            }
+            None => None,
+        };
+
+        let publish_topics = Arc::new(TopicRules::from_config(
+            &config.mqtt_policy.clone().unwrap_or_default(),
+        )?);
+        if config.features.enable_publish_inspection && !config.features.enable_mqtt_full_inspection
+        {
+            warn!("enable_publish_inspection has no effect without enable_mqtt_full_inspection");
+        }
+
+        let edge_instance_id = resolve_edge_id(&config);
+        Ok(Self {
+            config,
+            source_policy: Arc::new(source_policy),
+            tag_set,
+            region_filter,
+            access_control,
+            fast_path,
+            sni_routes,
+            publish_topics,
+            edge_instance_id,
+        })
+    }
+}
+
+/// TLS termination is set up once at startup; reject settings that
+/// contradict it, including in a reloaded configuration.
+fn check_tls_compatible(config: &Config, tls_enabled: bool) -> Result<(), String> {
+    if !tls_enabled {
+        return Ok(());
+    }
+    if config.proxy.accept_proxy_protocol {
+        return Err(
+            "tls cannot be combined with proxy.accept_proxy_protocol: the PROXY header \
+             precedes the TLS handshake"
+                .to_string(),
+        );
+    }
+    if !config.proxy.sni_routes.is_empty() {
+        return Err(
+            "tls cannot be combined with proxy.sni_routes: SNI routing forwards the \
+             TLS stream unchanged"
+                .to_string(),
+        );
+    }
+    if unix_socket_path(&config.proxy.listen_address).is_some() {
+        return Err("tls is only supported on a TCP proxy.listen_address".to_string());
+    }
+    Ok(())
+}
+
+/// What a bound listener keeps from startup: TLS and the backend health gate
+/// are not reloaded.
+struct ListenerSettings {
+    /// Value of the `listener` metrics label.
+    name: String,
+    tls: Option<(tokio_rustls::TlsAcceptor, Duration, Option<HandshakeLimit>)>,
+    health_gate: Option<(Arc<BackendHealth>, Vec<String>, bool)>,
+}
+
+/// The proxy's listening socket: TCP, or a Unix domain socket for a
+/// `unix:<path>` listen address.
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+/// A client just accepted, before any TLS handshake.
+enum Accepted {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixClient),
+}
+
+impl Listener {
+    async fn bind(address: &str) -> std::io::Result<Self> {
+        match unix_socket_path(address) {
+            #[cfg(unix)]
+            Some(path) => unix::bind(path).map(Listener::Unix),
+            #[cfg(not(unix))]
+            Some(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this platform",
+            )),
+            None => TcpListener::bind(address).await.map(Listener::Tcp),
+        }
+    }
+
+    /// Removes a Unix listener's socket file; nothing to do for TCP.
+    fn remove_socket_file(&self) {
+        #[cfg(unix)]
+        if let Listener::Unix(listener) = self {
+            let addr = listener.local_addr();
+            if let Some(path) = addr.as_ref().ok().and_then(|addr| addr.as_pathname()) {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    /// Accepts the next client; Unix peers are reported as `UNIX_PEER`.
+    async fn accept(&self) -> std::io::Result<(Accepted, SocketAddr)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (socket, addr) = listener.accept().await?;
+                Ok((Accepted::Tcp(socket), addr))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok((Accepted::Unix(UnixClient::new(stream)), UNIX_PEER))
+            }
+        }
+    }
+}
+
+impl Accepted {
+    /// Whether the client came in over a Unix socket (and has no IP).
+    fn is_local(&self) -> bool {
+        match self {
+            Accepted::Tcp(_) => false,
+            #[cfg(unix)]
+            Accepted::Unix(_) => true,
+        }
+    }
+
+    /// Refuses the client with a busy signal, off the accept loop.
+    fn spawn_busy_signal(self) {
+        match self {
+            Accepted::Tcp(socket) => tokio::spawn(send_busy_signal(socket)),
+            #[cfg(unix)]
+            Accepted::Unix(client) => tokio::spawn(send_busy_signal(client)),
+        };
+    }
+
+    /// Refuses a client accepted during the drain window, off the loop.
+    fn spawn_drain_rejection(self) {
+        match self {
+            Accepted::Tcp(socket) => tokio::spawn(reject_while_draining(socket)),
+            #[cfg(unix)]
+            Accepted::Unix(client) => tokio::spawn(reject_while_draining(client)),
+        };
+    }
+}
+
+/// Accepts clients on `listener` until `shutdown`, handing them to the
+/// admission loop tagged with the listener's index. Removes a Unix socket
+/// file on the way out.
+async fn accept_clients(
+    index: usize,
+    listener: Listener,
+    clients: mpsc::Sender<(usize, Accepted, SocketAddr)>,
+    shutdown: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            res = listener.accept() => {
+                if let Ok((socket, addr)) = res {
+                    if clients.send((index, socket, addr)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            _ = shutdown.cancelled() => break,
+        }
+    }
+    listener.remove_socket_file();
+}
+
+fn tls_enabled(config: &Config) -> bool {
+    config.tls.as_ref().is_some_and(|tls_cfg| tls_cfg.enabled)
+}
+
+/// Re-reads the configuration file and swaps it in if it is valid (see
+/// `aegis_proxy::reload`).
+fn reload_config(path: &Path, live: &ArcSwap<Vec<LiveConfig>>, startup: &Config) {
+    let _ = reload::reload(path, live, startup, |config, running| {
+        check_tls_compatible(&config, tls_enabled(running))?;
+        LiveConfig::build(config).map_err(|e| e.to_string())
+    });
+}
+
+/// Reloads the configuration on every SIGHUP until `shutdown` is cancelled.
+#[cfg(unix)]
+async fn reload_on_sighup(
+    path: PathBuf,
+    live: Arc<ArcSwap<Vec<LiveConfig>>>,
+    startup: Config,
+    shutdown: CancellationToken,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!(error = %e, "Cannot listen for SIGHUP; configuration reload disabled");
+            return;
+        }
+    };
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                info!(path = %path.display(), "SIGHUP received; reloading configuration");
+                reload_config(&path, &live, &startup);
+            }
+            _ = shutdown.cancelled() => break,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = match config_path(
+        std::env::args_os().skip(1),
+        std::env::var_os("AEGIS_CONFIG"),
+    ) {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("aegis-proxy: {}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
     init_production_logging();
 
-    let config_data = fs::read_to_string("config/aegis_config.yaml")?;
-    let config: Config = serde_yaml::from_str(&config_data)?;
+    if !config_path.is_file() {
+        error!(path = %config_path.display(), "Configuration file not found");
+        eprintln!(
+            "aegis-proxy: configuration file {} not found; pass --config <path> or set AEGIS_CONFIG",
+            config_path.display()
+        );
+        std::process::exit(1);
+    }
+    let config = match load_config(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(error = %e, "Failed to load configuration");
+            eprintln!("aegis-proxy: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(problems) = config.validate() {
+        for problem in &problems {
+            error!(error = %problem, "Invalid configuration");
+            eprintln!("aegis-proxy: {}: {}", config_path.display(), problem);
+        }
+        std::process::exit(1);
+    }
 
     let limit_cfg = Arc::new(config.limit.clone());
-    let slowloris_cfg = Arc::new(config.slowloris_protection.clone());
-    let target_addr = config.proxy.target_address.clone();
-    // Configure maximum Remaining Length (bytes) allowed for full CONNECT inspection.
-    // If the YAML omits this value, fall back to a safe default of 64 KiB.
-    let max_connect_remaining = config.proxy.max_connect_remaining.unwrap_or(64 * 1024);
+    let backend_pool = config
+        .proxy
+        .target_addresses
+        .as_ref()
+        .and_then(|targets| {
+            BackendSelector::weighted(
+                targets
+                    .iter()
+                    .map(|target| (target.address().to_string(), target.weight()))
+                    .collect(),
+            )
+        })
+        .map(Arc::new);
+    if let Some(pool) = &backend_pool {
+        info!(backends = ?pool.targets(), "Round-robin across MQTT backends");
+    }
+    let backend_health = config
+        .proxy
+        .backend_circuit
+        .as_ref()
+        .map(|circuit| Arc::new(BackendHealth::new(circuit)));
+    // With `reject_at_accept`, new connections are refused while every
+    // backend an MQTT session could use has its circuit open.
+    let health_gate = |listener: &Config| match (&backend_health, &config.proxy.backend_circuit) {
+        (Some(health), Some(circuit)) if circuit.reject_at_accept => {
+            let mqtt_backend = config
+                .proxy
+                .protocol_backends
+                .as_ref()
+                .and_then(|backends| backends.mqtt.clone());
+            let targets = match (mqtt_backend, &backend_pool) {
+                (Some(mqtt), _) => vec![mqtt],
+                (None, Some(pool)) => pool.targets().to_vec(),
+                (None, None) => vec![listener.proxy.target_address.clone()],
+            };
+            Some((Arc::clone(health), targets, circuit.busy_signal))
+        }
+        _ => None,
+    };
     let master_token = CancellationToken::new();
-    let features = config.features.clone();
+    // Long-lived tasks, joined after cancellation so their cleanup completes.
+    let mut background: Vec<(&'static str, JoinHandle<()>)> = Vec::new();
 
     if config.metrics.enabled {
         let port = config.metrics.port;
-        tokio::spawn(async move {
-            run_metrics_server(port).await;
+        let admin = config.metrics.expose_admin_endpoints;
+        if admin {
+            warn!(
+                port,
+                "Rate-limiter admin endpoints are exposed on the metrics port"
+            );
+        }
+        let server_token = master_token.clone();
+        background.push((
+            "metrics_server",
+            tokio::spawn(run_metrics_server(port, admin, server_token)),
+        ));
+    }
+
+    let capture = match &config.capture {
+        Some(capture_cfg) if capture_cfg.enabled => {
+            let (capture, writer) = PacketCapture::new(capture_cfg);
+            // Ends once every handle is dropped, after writing what is queued.
+            background.push(("capture_writer", tokio::spawn(writer)));
+            info!(reasons = ?capture_cfg.reasons, "Packet capture enabled for flagged connections");
+            Some(Arc::new(capture))
+        }
+        _ => None,
+    };
+
+    let mut listeners = Vec::new();
+    let mut live_configs = Vec::new();
+    for (name, listener_cfg) in config.listener_configs() {
+        check_tls_compatible(&listener_cfg, tls_enabled(&listener_cfg))?;
+        let tls = match &listener_cfg.tls {
+            Some(tls_cfg) if tls_cfg.enabled => {
+                let acceptor = load_acceptor(tls_cfg)?;
+                info!(listener = %name, cert = %tls_cfg.cert_path, "TLS termination enabled");
+                let handshakes = tls_cfg.max_concurrent_handshakes.map(|max| {
+                    HandshakeLimit::new(
+                        max,
+                        Duration::from_millis(tls_cfg.handshake_queue_timeout_ms),
+                    )
+                });
+                Some((
+                    acceptor,
+                    Duration::from_millis(tls_cfg.handshake_timeout_ms),
+                    handshakes,
+                ))
+            }
+            _ => None,
+        };
+        listeners.push(ListenerSettings {
+            health_gate: health_gate(&listener_cfg),
+            name,
+            tls,
         });
+        live_configs.push(LiveConfig::build(listener_cfg)?);
+    }
+
+    // Settings read per connection; replaced wholesale on SIGHUP.
+    let live = Arc::new(ArcSwap::from_pointee(live_configs));
+
+    let subnet_limiter = match &config.limit.subnet_cap {
+        Some(cap_cfg) => {
+            let limiter = SubnetLimiter::from_config(cap_cfg)?;
+            info!(
+                max_connections = cap_cfg.max_connections,
+                ipv4_prefix = cap_cfg.ipv4_prefix,
+                ipv6_prefix = cap_cfg.ipv6_prefix,
+                "Per-subnet connection cap enabled"
+            );
+            Some(Arc::new(limiter))
+        }
+        None => None,
+    };
+
+    let inspection_limiter =
+        InspectionLimiter::from_config(config.limit.max_concurrent_inspections)?;
+    if let Some(max) = config.limit.max_concurrent_inspections {
+        info!(max, "Concurrent inspection cap enabled");
     }
 
-    if features.enable_rate_limiter {
-        let janitor_cfg = Arc::clone(&limit_cfg);
-        let janitor_token = master_token.clone();
-        tokio::spawn(async move {
+    // Always running, since a reload may turn rate limiting on. The cleanup
+    // interval and idle timeout are read once, at startup.
+    let janitor_cfg = Arc::clone(&limit_cfg);
+    let janitor_token = master_token.clone();
+    let janitor = tokio::spawn(async move {
+        tokio::select! {
+            _ = start_cleanup_task(janitor_cfg) => {},
+            _ = janitor_token.cancelled() => {
+                info!("Janitor task shutting down");
+            }
+        }
+    });
+    background.push(("janitor", janitor));
+
+    if config.limit.backend == RateLimitBackendKind::Redis {
+        aegis_proxy::engine::redis_limiter::init(&config.limit)?;
+        info!(
+            policy = ?config.limit.redis_failure_policy,
+            "Rate-limit buckets shared through Redis"
+        );
+    }
+
+    if let Some(persist_cfg) = &config.limit.persist_state {
+        aegis_proxy::engine::persist::load(std::path::Path::new(&persist_cfg.path));
+        background.push((
+            "limiter_state",
+            tokio::spawn(aegis_proxy::engine::persist::run_persistence(
+                persist_cfg.clone(),
+                master_token.clone(),
+            )),
+        ));
+    }
+
+    if let Some(threshold) = config.limit.fd_pressure_threshold {
+        let monitor_token = master_token.clone();
+        let check_interval = Duration::from_secs(config.limit.fd_check_interval_secs);
+        let monitor = tokio::spawn(async move {
             tokio::select! {
-                _ = start_cleanup_task(janitor_cfg) => {},
-                _ = janitor_token.cancelled() => {
-                    info!("Janitor task shutting down");
-                }
+                _ = start_fd_monitor(threshold, check_interval) => {},
+                _ = monitor_token.cancelled() => {}
+            }
+        });
+        background.push(("fd_monitor", monitor));
+    }
+
+    if let Some(secs) = config.metrics.log_summary_interval_secs {
+        let summary_token = master_token.clone();
+        let interval = Duration::from_secs(secs.max(1));
+        let summary = tokio::spawn(async move {
+            tokio::select! {
+                _ = metrics::log_summary_periodically(interval) => {},
+                _ = summary_token.cancelled() => {}
             }
         });
+        background.push(("metrics_summary", summary));
     }
 
-    let listener = TcpListener::bind(&config.proxy.listen_address).await?;
-    info!(listen_addr = %config.proxy.listen_address, "AegisGate started");
+    if let Some(admin_cfg) = &config.admin {
+        #[cfg(unix)]
+        background.push((
+            "admin_socket",
+            tokio::spawn(aegis_proxy::engine::admin::run_admin_socket(
+                admin_cfg.socket_path.clone(),
+                backend_pool.clone(),
+                master_token.clone(),
+            )),
+        ));
+        #[cfg(not(unix))]
+        warn!(path = %admin_cfg.socket_path, "Admin socket is only supported on Unix; ignoring");
+    }
+
+    // Events reuse the decision trace records, so they need traces enabled.
+    let events_enabled = match &config.events {
+        #[cfg(feature = "nats-events")]
+        Some(events_cfg) => {
+            let publisher = aegis_proxy::engine::events::init(events_cfg);
+            let events_token = master_token.clone();
+            let publisher = tokio::spawn(async move {
+                tokio::select! {
+                    _ = publisher => {},
+                    _ = events_token.cancelled() => {}
+                }
+            });
+            background.push(("events_publisher", publisher));
+            info!(server = %events_cfg.nats_url, subject = %events_cfg.subject, "Connection events enabled");
+            true
+        }
+        #[cfg(not(feature = "nats-events"))]
+        Some(_) => {
+            warn!("events configured but this build lacks the nats-events feature; ignoring");
+            false
+        }
+        None => false,
+    };
+
+    #[cfg(unix)]
+    background.push((
+        "config_reload",
+        tokio::spawn(reload_on_sighup(
+            config_path.clone(),
+            Arc::clone(&live),
+            config.clone(),
+            master_token.clone(),
+        )),
+    ));
+
+    // One accept loop per listener, feeding the admission checks below.
+    let (clients_tx, mut clients) = mpsc::channel(ACCEPT_QUEUE);
+    for (index, settings) in listeners.iter().enumerate() {
+        let listen_address = &live.load()[index].config.proxy.listen_address;
+        let listener = Listener::bind(listen_address).await?;
+        info!(listener = %settings.name, listen_addr = %listen_address, "Listening");
+        metrics::register_listener(&settings.name);
+        background.push((
+            "accept_loop",
+            tokio::spawn(accept_clients(
+                index,
+                listener,
+                clients_tx.clone(),
+                master_token.clone(),
+            )),
+        ));
+    }
+    drop(clients_tx);
+    info!(listeners = listeners.len(), "AegisGate started");
 
     loop {
         tokio::select! {
-            res = listener.accept() => {
-                if let Ok((socket, addr)) = res {
-                    let l_cfg = Arc::clone(&limit_cfg);
-                    let sl_cfg = Arc::clone(&slowloris_cfg);
-                    let target = target_addr.clone();
-                    let rate_limiter_enabled = features.enable_rate_limiter;
+            res = clients.recv() => {
+                if let Some((index, socket, addr)) = res {
+                    let ListenerSettings { name: listener_name, tls, health_gate } = &listeners[index];
+                    let current = live.load_full();
+                    let LiveConfig {
+                        config,
+                        source_policy,
+                        tag_set,
+                        region_filter,
+                        access_control,
+                        fast_path,
+                        sni_routes,
+                        publish_topics,
+                        edge_instance_id,
+                    } = &current[index];
+                    let features = &config.features;
+                    // Dropping the trace with a check still open records that
+                    // check as the one that rejected the connection.
+                    let access_log = AccessLog::new(features.access_log);
+                    let trace = DecisionTrace::new(
+                        features.trace_decisions
+                            || features.trace_effective_config
+                            || features.trace_phase_timings
+                            || events_enabled,
+                        addr.to_string(),
+                    )
+                    .with_access_log(access_log.clone());
+
+                    trace.check("fd_pressure");
+                    if FD_PRESSURE.load(Ordering::Relaxed) {
+                        metrics::FD_PRESSURE_REJECTIONS.inc();
+                        debug!(client_ip = %addr.ip(), "Rejected under FD pressure");
+                        drop(socket);
+                        continue;
+                    }
+
+                    if let Some((health, targets, busy_signal)) = health_gate {
+                        trace.check("backend_health");
+                        if health.all_skipped(targets.iter().map(String::as_str)) {
+                            metrics::BACKEND_UNHEALTHY_REJECTIONS
+                                .with_label_values(&[listener_name.as_str()])
+                                .inc();
+                            debug!(client_ip = %addr.ip(), "Rejected: no healthy backend");
+                            if *busy_signal {
+                                socket.spawn_busy_signal();
+                            } else {
+                                drop(socket);
+                            }
+                            continue;
+                        }
+                    } else {
+                        trace.skip("backend_health");
+                    }
+
+                    // Behind a PROXY protocol load balancer `addr` is the
+                    // balancer; checks keyed on the client address wait for
+                    // the header.
+                    let proxied = config.proxy.accept_proxy_protocol;
+
+                    if proxied {
+                        // Checked on the PROXY header's address.
+                    } else if let Some(filter) = &region_filter {
+                        trace.check("region_filter");
+                        if let Err(region) = filter.check(addr.ip()) {
+                            metrics::REGION_REJECTIONS.with_label_values(&[region]).inc();
+                            debug!(client_ip = %addr.ip(), region = region, "Rejected by region filter");
+                            drop(socket);
+                            continue;
+                        }
+                    } else {
+                        trace.skip("region_filter");
+                    }
+
+                    if proxied {
+                        // Checked on the PROXY header's address.
+                    } else if let Some(acl) = &access_control {
+                        trace.check("access_control");
+                        if let Err(rule) = acl.check(addr.ip()) {
+                            metrics::ACL_REJECTIONS.inc();
+                            debug!(client_ip = %addr.ip(), rule = %rule, "Rejected by access control");
+                            drop(socket);
+                            continue;
+                        }
+                    } else {
+                        trace.skip("access_control");
+                    }
+
+                    let exempt = if proxied {
+                        false
+                    } else if accept_filter::is_installed() {
+                        trace.check("accept_filter");
+                        match accept_filter::decide(addr) {
+                            AcceptDecision::Reject => {
+                                metrics::ACCEPT_FILTER_REJECTIONS.inc();
+                                debug!(client_ip = %addr.ip(), "Rejected by accept filter");
+                                drop(socket);
+                                continue;
+                            }
+                            decision => decision == AcceptDecision::Accept,
+                        }
+                    } else {
+                        trace.skip("accept_filter");
+                        false
+                    };
+
+                    // Provisional when proxied: the handler switches to the
+                    // profile of the client the header names.
+                    let profile = source_policy.select(addr.ip());
+                    if !proxied {
+                        metrics::POLICY_PROFILE_MATCHES
+                            .with_label_values(&[profile.name.as_str()])
+                            .inc();
+                        trace.pass("source_profile", profile.name.as_str());
+                    }
+                    let p_features = &profile.features;
+                    let target = config.proxy.target_address.clone();
+                    let rate_limiter_enabled = p_features.enable_rate_limiter;
+                    // Unix peers have no IP to key per-source state on.
+                    let per_source = !exempt && !proxied && !socket.is_local();
+                    // Redis round trips run in the connection task instead.
+                    let shared_buckets = profile.limit.backend == RateLimitBackendKind::Redis;
+
+                    let allowed = if rate_limiter_enabled && per_source && !shared_buckets {
+                        trace.check("rate_limit");
+                        check_rate_limit(addr.ip(), &profile.limit).await
+                    } else {
+                        trace.skip("rate_limit");
+                        true
+                    };
+
+                    match &profile.limit.connect_ratio {
+                        Some(ratio_cfg) if allowed && per_source => {
+                            trace.check("connect_ratio");
+                            if !check_connect_ratio(addr.ip(), ratio_cfg) {
+                                metrics::CONNECT_RATIO_REJECTIONS.inc();
+                                debug!(client_ip = %addr.ip(), "Rejected: banned for connect ratio");
+                                drop(socket);
+                                continue;
+                            }
+                        }
+                        None => trace.skip("connect_ratio"),
+                        Some(_) if !per_source => trace.skip("connect_ratio"),
+                        Some(_) => {}
+                    }
 
-                    let allowed = !rate_limiter_enabled || check_rate_limit(addr.ip(), &l_cfg);
+                    if profile.limit.repeated_malformed.is_some() && allowed && per_source {
+                        trace.check("repeated_malformed");
+                        if malformed_banned(addr.ip()) {
+                            metrics::REPEATED_MALFORMED_REJECTIONS.inc();
+                            debug!(client_ip = %addr.ip(), "Rejected: banned for repeated malformed CONNECTs");
+                            drop(socket);
+                            continue;
+                        }
+                    } else {
+                        trace.skip("repeated_malformed");
+                    }
+
+                    let subnet_slot = match &subnet_limiter {
+                        Some(limiter) if allowed && !proxied => {
+                            trace.check("subnet_cap");
+                            match limiter.try_acquire(addr.ip()) {
+                                Some(slot) => Some(slot),
+                                None => {
+                                    metrics::SUBNET_CAP_REJECTIONS.inc();
+                                    drop(socket);
+                                    continue;
+                                }
+                            }
+                        }
+                        None => {
+                            trace.skip("subnet_cap");
+                            None
+                        }
+                        Some(_) => None,
+                    };
+
+                    let inspection_slot = if allowed {
+                        if inspection_limiter.is_capped() {
+                            trace.check("inspection_cap");
+                        } else {
+                            trace.skip("inspection_cap");
+                        }
+                        match inspection_limiter.try_acquire() {
+                            Some(slot) => Some(slot),
+                            None => {
+                                metrics::INSPECTION_SATURATED.inc();
+                                debug!(client_ip = %addr.ip(), "Rejected: inspection slots saturated");
+                                drop(socket);
+                                continue;
+                            }
+                        }
+                    } else {
+                        None
+                    };
 
                     if allowed {
                         let conn_config = ConnectionConfig {
-                            mqtt_inspect: features.enable_mqtt_inspection,
-                            mqtt_full_inspect: features.enable_mqtt_full_inspection,
-                            http_inspect: features.enable_http_inspection,
-                            slowloris_protect: features.enable_slowloris_protection,
-                            max_connect_remaining,
-                            slowloris_config: (*sl_cfg).clone(),
+                            mqtt_inspect: p_features.enable_mqtt_inspection,
+                            mqtt_full_inspect: p_features.enable_mqtt_full_inspection,
+                            http_inspect: p_features.enable_http_inspection,
+                            slowloris_protect: p_features.enable_slowloris_protection,
+                            // Safe default of 64 KiB for full CONNECT inspection.
+                            max_connect_remaining: config
+                                .proxy
+                                .max_connect_remaining
+                                .unwrap_or(64 * 1024),
+                            backend_write_timeout_ms: config
+                                .proxy
+                                .backend_write_timeout_ms
+                                .unwrap_or(3000),
+                            slow_backend_connect_ms: config
+                                .proxy
+                                .slow_backend_connect_ms
+                                .unwrap_or(1000),
+                            backend_unavailable_connack: config.proxy.backend_unavailable
+                                == BackendUnavailableAction::Connack,
+                            slowloris_config: config.slowloris_protection.clone(),
+                            http_inspection: config.http_inspection.clone(),
+                            mqtt_policy: config.mqtt_policy.clone().unwrap_or_default(),
+                            session_rate: profile.limit.session_rate.clone(),
+                            repeated_malformed: profile.limit.repeated_malformed.clone(),
+                            in_flight_connects: profile.limit.in_flight_connects.clone(),
+                            max_concurrent_per_ip: profile.limit.max_concurrent_per_ip,
+                            inspection_slot,
+                            max_client_id_len: config.proxy.max_client_id_len,
+                            require_client_id_for_persistent_session: config
+                                .proxy
+                                .require_client_id_for_persistent_session,
+                            require_username: p_features.require_username,
+                            mqtt_websocket: p_features.enable_mqtt_websocket,
+                            backend_pool: backend_pool.clone(),
+                            backend_health: backend_health.clone(),
+                            send_proxy_protocol: config.proxy.send_proxy_protocol,
+                            accept_proxy_protocol: config.proxy.accept_proxy_protocol,
+                            shutdown: Some(master_token.clone()),
+                            deferred_source_checks: if proxied {
+                                Some(SourceChecks {
+                                    limit: Arc::clone(&profile.limit),
+                                    rate_limit: rate_limiter_enabled,
+                                    bans: true,
+                                    proxied: Some(ProxiedChecks {
+                                        region_filter: region_filter.clone(),
+                                        access_control: access_control.clone(),
+                                        accept_filter: accept_filter::is_installed(),
+                                        source_policy: Arc::clone(source_policy),
+                                        subnet_limiter: subnet_limiter.clone(),
+                                    }),
+                                })
+                            } else {
+                                (per_source && rate_limiter_enabled && shared_buckets).then(|| {
+                                    SourceChecks {
+                                        limit: Arc::clone(&profile.limit),
+                                        rate_limit: true,
+                                        bans: false,
+                                        proxied: None,
+                                    }
+                                })
+                            },
+                            tags: tag_set
+                                .as_ref()
+                                .map(|set| ConnectionTags::new(Arc::clone(set), &profile.name)),
+                            tcp_user_timeout: config
+                                .proxy
+                                .tcp_user_timeout_ms
+                                .map(Duration::from_millis),
+                            fast_path: fast_path.clone(),
+                            splice_forwarding: p_features.enable_splice_forwarding,
+                            protocol_backends: config.proxy.protocol_backends.clone(),
+                            sni_routes: sni_routes.clone(),
+                            publish_topics: p_features
+                                .enable_publish_inspection
+                                .then(|| Arc::clone(publish_topics)),
+                            edge_instance_id: edge_instance_id.clone(),
+                            capture: capture.clone(),
+                            backend_write_buffer: config.proxy.backend_write_buffer_bytes,
+                            half_close_grace: config
+                                .proxy
+                                .half_close_grace_ms
+                                .map(Duration::from_millis),
+                            client_idle_timeout: config
+                                .proxy
+                                .client_idle_timeout_ms
+                                .map(Duration::from_millis),
+                            backend_idle_timeout: config
+                                .proxy
+                                .backend_idle_timeout_ms
+                                .map(Duration::from_millis),
+                            write_stall_timeout: config
+                                .proxy
+                                .write_stall_timeout_ms
+                                .map(Duration::from_millis),
+                            max_connection_lifetime: config
+                                .proxy
+                                .max_connection_lifetime_secs
+                                .map(Duration::from_secs),
+                            phase_timings: features.trace_phase_timings,
+                            trace,
+                            access_log,
+                            listener: listener_name.clone(),
                         };
+                        if features.trace_effective_config {
+                            conn_config.trace.record_config(|| conn_config.effective_summary());
+                        }
+                        metrics::ACCEPTED_CONNECTIONS.inc();
+                        metrics::LISTENER_ACCEPTED
+                            .with_label_values(&[listener_name.as_str()])
+                            .inc();
+                        let active = metrics::LISTENER_ACTIVE.with_label_values(&[listener_name.as_str()]);
+                        let tls = tls.clone();
                         tokio::spawn(async move {
-                            if let Err(e) = handle_connection(
-                                socket,
-                                target,
-                                conn_config,
-                            ).await {
+                            let _subnet_slot = subnet_slot;
+                            active.inc();
+                            let result = match (socket, tls) {
+                                (Accepted::Tcp(socket), Some((acceptor, limit, handshakes))) => {
+                                    let slot = match &handshakes {
+                                        Some(handshakes) => handshakes.acquire().await.map(Some),
+                                        None => Some(None),
+                                    };
+                                    match slot {
+                                        Some(slot) => {
+                                            // The slot covers the handshake only, whatever its outcome.
+                                            let accepted = TlsClient::accept(&acceptor, socket, limit).await;
+                                            drop(slot);
+                                            match accepted {
+                                                Ok(client) => {
+                                                    if conn_config.trace.is_enabled() {
+                                                        conn_config.trace.pass("tls", client.describe());
+                                                    }
+                                                    handle_connection(client, target, conn_config).await
+                                                }
+                                                Err(e) => {
+                                                    debug!(client_ip = %addr.ip(), error = %e, "TLS handshake failed");
+                                                    Ok(())
+                                                }
+                                            }
+                                        }
+                                        None => {
+                                            metrics::TLS_HANDSHAKE_REJECTIONS.inc();
+                                            debug!(client_ip = %addr.ip(), "Rejected connection: too many TLS handshakes in progress");
+                                            Ok(())
+                                        }
+                                    }
+                                }
+                                (Accepted::Tcp(socket), None) => {
+                                    handle_connection(socket, target, conn_config).await
+                                }
+                                #[cfg(unix)]
+                                (Accepted::Unix(client), _) => {
+                                    handle_connection(client, target, conn_config).await
+                                }
+                            };
+                            active.dec();
+                            if let Err(e) = result {
                                 error!(client_ip = %addr.ip(), error = %e, "Connection error");
                             }
                         });
                     } else {
-                        if config.metrics.enabled {
-                            metrics::REJECTED_CONNECTIONS.inc();
-                        }
-                        warn!(client_ip = %addr.ip(), "Rate limit exceeded");
+                        // Counted even without the Prometheus endpoint, for the
+                        // periodic summary log.
+                        metrics::REJECTED_CONNECTIONS.inc();
+                        warn!(client_ip = %addr.ip(), profile = %profile.name, "Rate limit exceeded");
                     }
                 }
             }
             _ = tokio::signal::ctrl_c() => {
                 info!("Shutdown signal received");
-                master_token.cancel();
                 break;
             }
         }
     }
+
+    // The last reloaded settings decide how this process shuts down.
+    let config = &live.load()[0].config;
+    if let Some(drain_secs) = config.proxy.shutdown_drain_secs {
+        drain(&mut clients, Duration::from_secs(drain_secs)).await;
+    }
+    // Unblocks accept loops waiting on a full queue so they can exit.
+    drop(clients);
+
+    if config.metrics.shutdown_snapshot {
+        metrics::write_shutdown_snapshot(config.metrics.shutdown_snapshot_path.as_deref()).await;
+    }
+
+    master_token.cancel();
+    drop(capture);
+    join_background_tasks(background, TASK_SHUTDOWN_GRACE).await;
     Ok(())
 }
+
+/// Waits for cancelled background tasks to finish, logging any still running
+/// when `grace` runs out (they are abandoned when the process exits).
+async fn join_background_tasks(tasks: Vec<(&'static str, JoinHandle<()>)>, grace: Duration) {
+    let deadline = tokio::time::Instant::now() + grace;
+    for (task, handle) in tasks {
+        match tokio::time::timeout_at(deadline, handle).await {
+            Ok(Ok(())) => debug!(task, "Background task finished"),
+            Ok(Err(e)) => warn!(task, error = %e, "Background task failed"),
+            Err(_) => warn!(
+                task,
+                grace_secs = grace.as_secs(),
+                "Background task did not finish within the shutdown grace"
+            ),
+        }
+    }
+}
+
+/// Keep accepting during the drain window so new clients get a clean busy
+/// signal instead of a connection refusal, until active sessions finish or
+/// the window elapses.
+async fn drain(clients: &mut mpsc::Receiver<(usize, Accepted, SocketAddr)>, window: Duration) {
+    DRAINING.store(true, Ordering::SeqCst);
+    info!(drain_secs = window.as_secs(), "Draining connections");
+
+    let deadline = tokio::time::sleep(window);
+    tokio::pin!(deadline);
+    let mut poll = tokio::time::interval(Duration::from_millis(200));
+
+    loop {
+        tokio::select! {
+            res = clients.recv() => {
+                if let Some((_, socket, addr)) = res {
+                    debug!(client_ip = %addr.ip(), "Refusing connection while draining");
+                    socket.spawn_drain_rejection();
+                }
+            }
+            _ = poll.tick() => {
+                if ACTIVE_CONNECTIONS.load(Ordering::SeqCst) == 0 {
+                    info!("All sessions drained");
+                    break;
+                }
+            }
+            _ = &mut deadline => {
+                warn!(
+                    active = ACTIVE_CONNECTIONS.load(Ordering::SeqCst),
+                    "Drain window elapsed with sessions still active"
+                );
+                break;
+            }
+        }
+    }
+}
@@ -1,7 +1,13 @@
 use crate::engine::connection::ACTIVE_CONNECTIONS;
 use lazy_static::lazy_static;
-use prometheus::{Encoder, Gauge, IntCounter, Registry, TextEncoder};
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Gauge, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
 use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tracing::{info, warn};
 
 lazy_static! {
     pub static ref REGISTRY: Registry = Registry::new();
@@ -15,32 +21,879 @@ lazy_static! {
         "Total number of connections rejected by rate limiting"
     )
     .expect("metric can be created");
-    /// Count of connections rejected due to protocol validation (malformed packets, invalid CONNECT)
-    pub static ref PROTOCOL_REJECTIONS: IntCounter = IntCounter::new(
-        "aegis_protocol_rejections_total",
-        "Total number of connections rejected by protocol (MQTT) validation"
+    /// Connections rejected by protocol, HTTP or Slowloris inspection, by
+    /// `RejectReason` and listener. Replaces `aegis_protocol_rejections_total`,
+    /// `aegis_http_rejections_total` and `aegis_slowloris_rejections_total`.
+    pub static ref REJECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_rejections_total",
+            "Total number of connections rejected by protocol, HTTP or Slowloris inspection, by reason and listener"
+        ),
+        &["reason", "listener"]
     )
     .expect("metric can be created");
-    /// Count of connections rejected due to HTTP detection (wrong protocol)
-    pub static ref HTTP_REJECTIONS: IntCounter = IntCounter::new(
-        "aegis_http_rejections_total",
-        "Total number of connections rejected due to HTTP protocol detection"
+    /// Count of connections whose total handshake exceeded the deadline budget
+    pub static ref HANDSHAKE_DEADLINE_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_handshake_deadline_rejections_total",
+        "Total number of connections rejected for exceeding the overall handshake deadline"
     )
     .expect("metric can be created");
-    /// Count of connections rejected due to Slowloris attack detection
-    pub static ref SLOWLORIS_REJECTIONS: IntCounter = IntCounter::new(
-        "aegis_slowloris_rejections_total",
-        "Total number of connections rejected due to Slowloris attack detection"
+    /// Count of CONNECTs rejected for not arriving in a single segment
+    pub static ref FRAGMENTED_CONNECT_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_fragmented_connect_rejections_total",
+        "Total number of connections rejected because the CONNECT was fragmented"
     )
     .expect("metric can be created");
+    /// Count of connections rejected by the static region filter, by region
+    pub static ref REGION_REJECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_region_rejections_total",
+            "Total number of connections rejected by the region CIDR filter"
+        ),
+        &["region"]
+    )
+    .expect("metric can be created");
+    /// Count of accepted connections by the source policy profile they matched
+    pub static ref POLICY_PROFILE_MATCHES: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_policy_profile_matches_total",
+            "Total number of connections handled under each source policy profile"
+        ),
+        &["profile"]
+    )
+    .expect("metric can be created");
+    /// Count of admitted (fully inspected) connections whose backend connect
+    /// failed, by listener
+    pub static ref BACKEND_UNAVAILABLE: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_backend_unavailable_total",
+            "Total number of admitted connections dropped because the backend could not be reached, by listener"
+        ),
+        &["listener"]
+    )
+    .expect("metric can be created");
+    /// Count of connections rejected because their subnet hit its concurrent cap
+    pub static ref SUBNET_CAP_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_subnet_cap_rejections_total",
+        "Total number of connections rejected by the per-subnet concurrent connection cap"
+    )
+    .expect("metric can be created");
+    /// Count of inspected MQTT sessions refused by the per-IP session rate
+    pub static ref SESSION_RATE_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_session_rate_rejections_total",
+        "Total number of MQTT sessions refused by the per-IP session rate limit"
+    )
+    .expect("metric can be created");
+    /// Count of HTTP requests rejected for bare LF line endings
+    pub static ref HTTP_BARE_LF_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_http_bare_lf_rejections_total",
+        "Total number of HTTP requests rejected for using bare LF line endings"
+    )
+    .expect("metric can be created");
+    /// Connections matching a fast-path signature, by outcome (hit / verified)
+    pub static ref SIGNATURE_FAST_PATH: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_signature_fast_path_total",
+            "Total number of connections matching a fast-path signature, by outcome"
+        ),
+        &["outcome"]
+    )
+    .expect("metric can be created");
+    /// Sessions ended by an oversized MQTT packet, by direction (upstream / downstream)
+    pub static ref OVERSIZED_PACKET_REJECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_oversized_packet_rejections_total",
+            "Total number of sessions ended by an MQTT packet over the size limit, by direction"
+        ),
+        &["direction"]
+    )
+    .expect("metric can be created");
+    /// Sessions ended by a PUBLISH payload over `max_publish_payload`
+    pub static ref OVERSIZED_PUBLISH: IntCounter = IntCounter::new(
+        "aegis_oversized_publish_total",
+        "Total number of sessions ended by a PUBLISH payload over the size limit"
+    )
+    .expect("metric can be created");
+    /// Rate-limit decisions Redis could not make, settled by
+    /// `redis_failure_policy`
+    pub static ref RATE_LIMIT_BACKEND_ERRORS: IntCounter = IntCounter::new(
+        "aegis_rate_limit_backend_errors_total",
+        "Total number of rate-limit decisions that failed because Redis was unreachable or slow"
+    )
+    .expect("metric can be created");
+    /// Connections the rate limiter admitted; with
+    /// `aegis_rejected_connections_total`, every rate-limit decision
+    pub static ref RATE_LIMIT_ALLOWED: IntCounter = IntCounter::new(
+        "aegis_rate_limit_allowed_total",
+        "Total number of connections admitted by rate limiting"
+    )
+    .expect("metric can be created");
+    /// Tokens left in a client's own bucket after each token-bucket decision.
+    /// Mass near zero means clients are close to being throttled.
+    pub static ref RATE_LIMIT_TOKENS_REMAINING: Histogram = Histogram::with_opts(
+        HistogramOpts::new(
+            "aegis_rate_limit_tokens_remaining",
+            "Tokens left in the client's bucket at each token-bucket rate-limit decision"
+        )
+        .buckets(vec![0.0, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 500.0])
+    )
+    .expect("metric can be created");
+    /// Per-IP token buckets held in this process, as of the last cleanup run.
+    /// Always 0 with Redis-backed rate limiting.
+    pub static ref TRACKED_IPS: IntGauge = IntGauge::new(
+        "aegis_tracked_ips",
+        "Number of client IPs with a token bucket in this process"
+    )
+    .expect("metric can be created");
+    /// Connections admitted past the accept-time checks, by listener
+    pub static ref LISTENER_ACCEPTED: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_listener_accepted_connections_total",
+            "Total number of connections admitted past the accept-time checks, by listener"
+        ),
+        &["listener"]
+    )
+    .expect("metric can be created");
+    /// Admitted connections still being handled, by listener
+    pub static ref LISTENER_ACTIVE: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "aegis_listener_active_connections",
+            "Number of admitted connections currently being handled, by listener"
+        ),
+        &["listener"]
+    )
+    .expect("metric can be created");
+    /// Configuration reloads on SIGHUP, by result (applied / rejected)
+    pub static ref CONFIG_RELOADS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_config_reloads_total",
+            "Total number of configuration reloads, by result"
+        ),
+        &["result"]
+    )
+    .expect("metric can be created");
+    /// Connections closed because their TLS handshake failed
+    pub static ref TLS_HANDSHAKE_FAILURES: IntCounter = IntCounter::new(
+        "aegis_tls_handshake_failures_total",
+        "Total number of connections closed after a failed TLS handshake"
+    )
+    .expect("metric can be created");
+    /// Connections closed because the client did not finish its TLS handshake
+    /// within `handshake_timeout_ms`
+    pub static ref TLS_HANDSHAKE_TIMEOUTS: IntCounter = IntCounter::new(
+        "aegis_tls_handshake_timeouts_total",
+        "Total number of connections closed because the TLS handshake did not complete in time"
+    )
+    .expect("metric can be created");
+    /// Completed TLS handshakes by negotiated protocol version
+    pub static ref TLS_CONNECTIONS_BY_VERSION: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_tls_connections_total",
+            "Total number of completed TLS handshakes, by negotiated version (tls1.2, tls1.3, other)"
+        ),
+        &["version"]
+    )
+    .expect("metric can be created");
+    /// Connections refused because no TLS handshake slot freed up in time
+    pub static ref TLS_HANDSHAKE_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_tls_handshake_rejections_total",
+        "Total number of connections refused because too many TLS handshakes were in progress"
+    )
+    .expect("metric can be created");
+    /// Connections waiting for a TLS handshake slot
+    pub static ref TLS_HANDSHAKE_WAITERS: IntGauge = IntGauge::new(
+        "aegis_tls_handshake_waiters",
+        "Number of connections waiting for a TLS handshake slot"
+    )
+    .expect("metric can be created");
+    /// Sessions closed on reaching `max_connection_lifetime_secs`
+    pub static ref LIFETIME_DISCONNECTS: IntCounter = IntCounter::new(
+        "aegis_lifetime_disconnects_total",
+        "Total number of forwarded sessions closed on reaching their maximum lifetime"
+    )
+    .expect("metric can be created");
+    /// Bytes forwarded from clients to backends, counted as each direction ends
+    pub static ref BYTES_CLIENT_TO_BACKEND: IntCounter = IntCounter::new(
+        "aegis_bytes_client_to_backend_total",
+        "Total number of bytes forwarded from clients to backends after the handshake"
+    )
+    .expect("metric can be created");
+    /// Bytes forwarded from backends to clients, counted as each direction ends
+    pub static ref BYTES_BACKEND_TO_CLIENT: IntCounter = IntCounter::new(
+        "aegis_bytes_backend_to_client_total",
+        "Total number of bytes forwarded from backends to clients"
+    )
+    .expect("metric can be created");
+    /// Sessions closed because neither direction moved bytes for the proxy idle timeout
+    pub static ref IDLE_DISCONNECTS: IntCounter = IntCounter::new(
+        "aegis_idle_disconnects_total",
+        "Total number of forwarded sessions closed after no bytes moved in either direction"
+    )
+    .expect("metric can be created");
+    /// Connections refused by the access_control allow / deny lists
+    pub static ref ACL_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_acl_rejections_total",
+        "Total number of connections rejected by the access control lists"
+    )
+    .expect("metric can be created");
+    /// Connections refused at accept while every inspection slot was taken
+    pub static ref INSPECTION_SATURATED: IntCounter = IntCounter::new(
+        "aegis_inspection_saturated_total",
+        "Total number of connections refused at accept because the inspection cap was reached"
+    )
+    .expect("metric can be created");
+    /// Connections currently between accept and the start of forwarding
+    pub static ref INSPECTIONS_IN_PROGRESS: IntGauge = IntGauge::new(
+        "aegis_inspections_in_progress",
+        "Number of connections currently in the inspection phase"
+    )
+    .expect("metric can be created");
+    /// Connections refused because their IP had too many open already
+    pub static ref CONCURRENCY_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_concurrency_rejections_total",
+        "Total number of connections rejected by the per-IP concurrent connection cap"
+    )
+    .expect("metric can be created");
+    /// Connections dropped for a missing or malformed inbound PROXY header
+    pub static ref PROXY_PROTOCOL_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_proxy_protocol_rejections_total",
+        "Total number of connections rejected for a missing or malformed PROXY protocol header"
+    )
+    .expect("metric can be created");
+    /// Admitted sessions by bounded connection tags (see `metric_tags`)
+    pub static ref TAGGED_SESSIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_tagged_sessions_total",
+            "Total number of admitted sessions, by configured profile, protocol and endpoint tags"
+        ),
+        &["profile", "protocol", "endpoint"]
+    )
+    .expect("metric can be created");
+    /// Accept-to-forwarding time of admitted sessions by bounded tags
+    pub static ref TAGGED_HANDSHAKE_SECONDS: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "aegis_tagged_handshake_seconds",
+            "Time from accept to the start of forwarding, by configured profile, protocol and endpoint tags"
+        )
+        .buckets(vec![
+            0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 30.0
+        ]),
+        &["profile", "protocol", "endpoint"]
+    )
+    .expect("metric can be created");
+    /// Connections refused at accept while every backend's circuit was open,
+    /// by listener
+    pub static ref BACKEND_UNHEALTHY_REJECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_backend_unhealthy_rejections_total",
+            "Total number of connections refused at accept while no backend was healthy, by listener"
+        ),
+        &["listener"]
+    )
+    .expect("metric can be created");
+    /// Backend circuit state per target: 1 while connects are attempted, 0
+    /// while the circuit is open
+    pub static ref BACKEND_HEALTHY: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "aegis_backend_healthy",
+            "Whether a backend's circuit is closed (1) or open after repeated connect failures (0)"
+        ),
+        &["target"]
+    )
+    .expect("metric can be created");
+    /// Backends drained through the admin socket
+    pub static ref BACKEND_DRAINING: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "aegis_backend_draining",
+            "Whether a backend is draining (1): skipped for new sessions, existing ones kept"
+        ),
+        &["target"]
+    )
+    .expect("metric can be created");
+    /// Sessions the backend pool sent to each backend
+    pub static ref BACKEND_SELECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_backend_selections_total",
+            "Total number of sessions the backend pool connected to each backend"
+        ),
+        &["backend"]
+    )
+    .expect("metric can be created");
+    /// CONNECTs rejected for missing credentials (`require_username`)
+    pub static ref AUTH_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_auth_rejections_total",
+        "Total number of CONNECTs rejected for missing a user name"
+    )
+    .expect("metric can be created");
+    /// Connections rejected by the embedder's pre-accept filter
+    pub static ref ACCEPT_FILTER_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_accept_filter_rejections_total",
+        "Total number of connections rejected by the pre-accept filter hook"
+    )
+    .expect("metric can be created");
+    /// CONNECTs rejected for a protocol level outside the allowed set, by
+    /// declared level
+    pub static ref PROTOCOL_LEVEL_REJECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_protocol_level_rejections_total",
+            "Total number of CONNECTs rejected for an unsupported protocol level"
+        ),
+        &["level"]
+    )
+    .expect("metric can be created");
+    /// Duration of each handshake phase (peek, HTTP detection, MQTT reads,
+    /// backend connect, initial forward), by phase
+    pub static ref HANDSHAKE_PHASE_SECONDS: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "aegis_handshake_phase_seconds",
+            "Time spent in each connection handshake phase"
+        )
+        .buckets(vec![
+            0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 30.0
+        ]),
+        &["phase"]
+    )
+    .expect("metric can be created");
+    /// Live connections closed by an operator `kill` admin command
+    pub static ref ADMIN_KILLS: IntCounter = IntCounter::new(
+        "aegis_admin_kills_total",
+        "Total number of live connections closed by admin kill commands"
+    )
+    .expect("metric can be created");
+    /// Failed backend connects, by class (refused / timeout / dns /
+    /// unreachable / other) and listener
+    pub static ref BACKEND_ERRORS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_backend_errors_total",
+            "Total number of failed backend connects by error class and listener"
+        ),
+        &["class", "listener"]
+    )
+    .expect("metric can be created");
+    /// How long connections waited for a per-IP in-flight slot (only
+    /// connections that had to wait, including those that gave up)
+    pub static ref IN_FLIGHT_CONNECT_WAIT: Histogram = Histogram::with_opts(
+        HistogramOpts::new(
+            "aegis_in_flight_connect_wait_seconds",
+            "Time connections waited for a per-IP in-flight CONNECT slot"
+        )
+        .buckets(vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5])
+    )
+    .expect("metric can be created");
+    /// Count of connections dropped after waiting too long for a per-IP
+    /// in-flight slot
+    pub static ref IN_FLIGHT_CONNECT_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_in_flight_connect_rejections_total",
+        "Total number of connections rejected by the per-IP in-flight CONNECT limit"
+    )
+    .expect("metric can be created");
+    /// HTTP requests classified after `max_headers_to_inspect` headers
+    pub static ref HTTP_EARLY_CLASSIFICATIONS: IntCounter = IntCounter::new(
+        "aegis_http_early_classifications_total",
+        "Total number of HTTP requests classified before all headers were read"
+    )
+    .expect("metric can be created");
+    /// IPs banned for repeating an identical malformed CONNECT, by packet hash
+    pub static ref REPEATED_MALFORMED: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_repeated_malformed_total",
+            "Total number of IPs banned for repeating an identical malformed CONNECT"
+        ),
+        &["hash"]
+    )
+    .expect("metric can be created");
+    /// Connections refused at accept because their IP is banned for malformed repeats
+    pub static ref REPEATED_MALFORMED_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_repeated_malformed_rejections_total",
+        "Total number of connections rejected from IPs banned for repeated malformed CONNECTs"
+    )
+    .expect("metric can be created");
+    /// Sessions ended because one direction stayed idle too long
+    pub static ref SESSION_IDLE_TIMEOUTS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_session_idle_timeouts_total",
+            "Total number of forwarded sessions ended by a per-direction idle timeout"
+        ),
+        &["direction"]
+    )
+    .expect("metric can be created");
+    /// Connections whose inspection ran out of its time budget
+    pub static ref INSPECTION_BUDGET_EXCEEDED: IntCounter = IntCounter::new(
+        "aegis_inspection_budget_exceeded_total",
+        "Total number of connections rejected because inspection exceeded its time budget"
+    )
+    .expect("metric can be created");
+    /// First-packet peeks that filled the peek buffer (packet continues past it)
+    pub static ref FIRST_PACKET_PEEK_FILLED: IntCounter = IntCounter::new(
+        "aegis_first_packet_peek_filled_total",
+        "Total number of first-packet peeks that filled the peek buffer before the packet ended"
+    )
+    .expect("metric can be created");
+    /// IPs banned for opening far more connections than they complete
+    pub static ref CONNECT_RATIO_BANS: IntCounter = IntCounter::new(
+        "aegis_connect_ratio_bans_total",
+        "Total number of IPs banned for a skewed opened/completed connection ratio"
+    )
+    .expect("metric can be created");
+    /// Connections refused at accept because their IP is banned
+    pub static ref CONNECT_RATIO_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_connect_ratio_rejections_total",
+        "Total number of connections rejected from IPs banned for a skewed connection ratio"
+    )
+    .expect("metric can be created");
+    /// Connection events published to the event bus
+    pub static ref EVENTS_PUBLISHED: IntCounter = IntCounter::new(
+        "aegis_events_published_total",
+        "Total number of connection events published to the event bus"
+    )
+    .expect("metric can be created");
+    /// Connection events dropped because the event queue was full
+    pub static ref EVENTS_DROPPED: IntCounter = IntCounter::new(
+        "aegis_events_dropped_total",
+        "Total number of connection events dropped due to event bus backpressure"
+    )
+    .expect("metric can be created");
+    /// Count of connections shed at accept because FD usage was too high
+    pub static ref FD_PRESSURE_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_fd_pressure_rejections_total",
+        "Total number of connections rejected while file-descriptor usage was high"
+    )
+    .expect("metric can be created");
+    /// Open file descriptors, as last sampled by the FD pressure monitor
+    pub static ref OPEN_FDS: IntGauge = IntGauge::new(
+        "aegis_open_fds",
+        "Number of open file descriptors held by the proxy process"
+    )
+    .expect("metric can be created");
+    /// Count of connections refused with a busy signal during graceful shutdown
+    pub static ref DRAINING_REJECTIONS: IntCounter = IntCounter::new(
+        "aegis_draining_rejections_total",
+        "Total number of connections refused while the proxy was draining"
+    )
+    .expect("metric can be created");
+    /// Count of routed connections by detected protocol (mqtt, http, websocket, tls)
+    pub static ref ROUTING_DECISIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_routing_decisions_total",
+            "Total number of connections routed to a backend, by detected protocol"
+        ),
+        &["protocol"]
+    )
+    .expect("metric can be created");
+    /// Packet captures written for flagged connections
+    pub static ref CAPTURES_WRITTEN: IntCounter = IntCounter::new(
+        "aegis_packet_captures_total",
+        "Total number of packet captures written for flagged connections"
+    )
+    .expect("metric can be created");
+    /// Packet captures skipped because the capture budget or queue was exhausted
+    pub static ref CAPTURES_DROPPED: IntCounter = IntCounter::new(
+        "aegis_packet_captures_dropped_total",
+        "Total number of packet captures dropped by rate limiting or backpressure"
+    )
+    .expect("metric can be created");
+    /// Decoded Remaining Length of inspected CONNECTs (full inspection only).
+    /// Buckets cover typical CONNECT sizes up to the default 64 KiB cap.
+    pub static ref CONNECT_REMAINING_LENGTH: Histogram = Histogram::with_opts(
+        HistogramOpts::new(
+            "aegis_connect_remaining_length_bytes",
+            "Remaining Length of inspected MQTT CONNECT packets"
+        )
+        .buckets(vec![
+            16.0, 32.0, 64.0, 128.0, 256.0, 512.0, 1024.0, 4096.0, 16384.0, 65536.0
+        ])
+    )
+    .expect("metric can be created");
+    /// Keep-alive requested by inspected MQTT CONNECT packets
+    pub static ref CONNECT_KEEP_ALIVE: Histogram = Histogram::with_opts(
+        HistogramOpts::new(
+            "aegis_connect_keep_alive_seconds",
+            "Keep-alive requested by inspected MQTT CONNECT packets (0 = disabled)"
+        )
+        .buckets(vec![
+            0.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0, 7200.0, 21600.0, 65535.0
+        ])
+    )
+    .expect("metric can be created");
+    /// CONNECTs outside the keep-alive limits, by action taken (reject / clamp)
+    pub static ref KEEP_ALIVE_ENFORCED: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "aegis_keep_alive_enforced_total",
+            "Total number of CONNECTs outside the keep-alive limits, by action taken"
+        ),
+        &["action"]
+    )
+    .expect("metric can be created");
+    /// Refusing CONNACKs sent to rejected MQTT clients (with a reason string
+    /// for MQTT 5.0)
+    pub static ref REJECT_CONNACKS_SENT: IntCounter = IntCounter::new(
+        "aegis_reject_connacks_sent_total",
+        "Total number of refusing CONNACKs sent to rejected MQTT clients"
+    )
+    .expect("metric can be created");
+    /// Connections that passed the accept-time checks and were handed off
+    pub static ref ACCEPTED_CONNECTIONS: IntCounter = IntCounter::new(
+        "aegis_accepted_connections_total",
+        "Total number of connections admitted past the accept-time checks"
+    )
+    .expect("metric can be created");
+    /// Bytes forwarded in both directions, counted as each direction ends
+    pub static ref FORWARDED_BYTES: IntCounter = IntCounter::new(
+        "aegis_forwarded_bytes_total",
+        "Total number of bytes forwarded between clients and backends"
+    )
+    .expect("metric can be created");
+    /// Bytes forwarded through the kernel `splice(2)` data plane (Linux only)
+    pub static ref SPLICED_BYTES: IntCounter = IntCounter::new(
+        "aegis_spliced_bytes_total",
+        "Total number of bytes forwarded via splice(2) without userspace copies"
+    )
+    .expect("metric can be created");
+}
+
+/// Which of the former per-kind counters a `RejectReason` belonged to; kept
+/// for the periodic summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectClass {
+    Protocol,
+    Http,
+    Slowloris,
+    /// Reasons the summary reports in a field of their own.
+    Itemized,
+}
+
+/// Why inspection rejected a connection: the `reason` label of
+/// `aegis_rejections_total`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// Closed before sending any data.
+    ClosedBeforeData,
+    /// Reading the first packet failed.
+    FirstPacketError,
+    /// No data within `first_packet_timeout_ms`.
+    FirstPacketTimeout,
+    /// HTTP request line and headers not complete in time.
+    HttpRequestTimeout,
+    HttpHeaderCountExceeded,
+    HttpHeaderSizeExceeded,
+    /// EOF in the middle of the HTTP headers.
+    HttpIncompleteHeaders,
+    /// HTTP header line without a `:`.
+    HttpMalformedHeader,
+    /// Reading the HTTP request failed.
+    HttpReadError,
+    /// A well-formed HTTP request with no backend to route it to.
+    HttpDetected,
+    /// An HTTP `CONNECT host:port` tunnel request.
+    HttpConnectTunnel,
+    /// The MQTT fixed header did not arrive within the idle timeout.
+    FixedHeaderTimeout,
+    /// EOF or a read error on the MQTT fixed header.
+    FixedHeaderRead,
+    /// First packet is neither HTTP nor an MQTT CONNECT.
+    NotHttpOrMqtt,
+    /// First MQTT packet is not a CONNECT.
+    UnexpectedPacketType,
+    /// No first byte within the lightweight check's window.
+    FirstByteTimeout,
+    RemainingLengthTooLarge,
+    MalformedRemainingLength,
+    /// EOF, read error or timeout on the Remaining Length.
+    RemainingLengthRead,
+    /// EOF, read error or timeout on the CONNECT body.
+    ConnectPayloadRead,
+    /// CONNECT body arriving slower than `min_throughput_bytes_per_sec`.
+    ConnectThroughputTooLow,
+    /// Single-segment CONNECT that is not a valid CONNECT.
+    ConnectSegment,
+    /// Invalid protocol name / version or too short.
+    MalformedConnect,
+    UnsupportedProtocolLevel,
+    /// Malformed MQTT 5.0 CONNECT properties.
+    InvalidConnectProperties,
+    /// Reserved Connect Flags bit set or an illegal will QoS / flag mix.
+    InvalidConnectFlags,
+    /// Client identifier refused by policy.
+    ClientId,
+    /// TLS handshake record too short or malformed (SNI routing).
+    MalformedClientHello,
+    /// TLS ClientHello not complete in time (SNI routing).
+    ClientHelloTimeout,
+    /// PUBLISH to a topic refused by the topic lists.
+    PublishTopicDenied,
+    /// PUBLISH with a truncated or non-UTF-8 topic name, or a bad Remaining
+    /// Length, seen by PUBLISH inspection.
+    MalformedPublish,
+    /// CONNECT keep-alive outside the configured limits.
+    KeepAlive,
+    /// CONNECT without a user name while `require_username` is set.
+    UsernameRequired,
+    /// HTTP request with bare LF line endings.
+    HttpBareLf,
+    /// CONNECT split across several TCP segments.
+    FragmentedConnect,
+    /// Handshake not complete within `handshake_deadline_ms`.
+    HandshakeDeadline,
+    /// MQTT packet over the maximum packet size.
+    OversizedPacket,
+    /// PUBLISH payload over `max_publish_payload`.
+    OversizedPublish,
+    /// Missing or malformed PROXY protocol header.
+    ProxyProtocol,
+    /// Client IP already at `max_concurrent_per_ip` open connections.
+    ConcurrentPerIp,
+    /// Client IP already at its in-flight CONNECT limit.
+    InFlightConnects,
+    /// MQTT session over the per-IP session rate.
+    SessionRate,
+    /// Inspection exceeded `inspection_budget_ms`.
+    InspectionBudget,
+}
+
+impl RejectReason {
+    pub const ALL: [RejectReason; 43] = [
+        RejectReason::ClosedBeforeData,
+        RejectReason::FirstPacketError,
+        RejectReason::FirstPacketTimeout,
+        RejectReason::HttpRequestTimeout,
+        RejectReason::HttpHeaderCountExceeded,
+        RejectReason::HttpHeaderSizeExceeded,
+        RejectReason::HttpIncompleteHeaders,
+        RejectReason::HttpMalformedHeader,
+        RejectReason::HttpReadError,
+        RejectReason::HttpDetected,
+        RejectReason::HttpConnectTunnel,
+        RejectReason::FixedHeaderTimeout,
+        RejectReason::FixedHeaderRead,
+        RejectReason::NotHttpOrMqtt,
+        RejectReason::UnexpectedPacketType,
+        RejectReason::FirstByteTimeout,
+        RejectReason::RemainingLengthTooLarge,
+        RejectReason::MalformedRemainingLength,
+        RejectReason::RemainingLengthRead,
+        RejectReason::ConnectPayloadRead,
+        RejectReason::ConnectThroughputTooLow,
+        RejectReason::ConnectSegment,
+        RejectReason::MalformedConnect,
+        RejectReason::UnsupportedProtocolLevel,
+        RejectReason::InvalidConnectProperties,
+        RejectReason::InvalidConnectFlags,
+        RejectReason::ClientId,
+        RejectReason::MalformedClientHello,
+        RejectReason::ClientHelloTimeout,
+        RejectReason::PublishTopicDenied,
+        RejectReason::MalformedPublish,
+        RejectReason::KeepAlive,
+        RejectReason::UsernameRequired,
+        RejectReason::HttpBareLf,
+        RejectReason::FragmentedConnect,
+        RejectReason::HandshakeDeadline,
+        RejectReason::OversizedPacket,
+        RejectReason::OversizedPublish,
+        RejectReason::ProxyProtocol,
+        RejectReason::ConcurrentPerIp,
+        RejectReason::InFlightConnects,
+        RejectReason::SessionRate,
+        RejectReason::InspectionBudget,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RejectReason::ClosedBeforeData => "closed_before_data",
+            RejectReason::FirstPacketError => "first_packet_error",
+            RejectReason::FirstPacketTimeout => "first_packet_timeout",
+            RejectReason::HttpRequestTimeout => "http_request_timeout",
+            RejectReason::HttpHeaderCountExceeded => "http_header_count_exceeded",
+            RejectReason::HttpHeaderSizeExceeded => "http_header_size_exceeded",
+            RejectReason::HttpIncompleteHeaders => "http_incomplete_headers",
+            RejectReason::HttpMalformedHeader => "http_malformed_header",
+            RejectReason::HttpReadError => "http_read_error",
+            RejectReason::HttpDetected => "http_detected",
+            RejectReason::HttpConnectTunnel => "http_connect_tunnel",
+            RejectReason::FixedHeaderTimeout => "fixed_header_timeout",
+            RejectReason::FixedHeaderRead => "fixed_header_read",
+            RejectReason::NotHttpOrMqtt => "not_http_or_mqtt",
+            RejectReason::UnexpectedPacketType => "unexpected_packet_type",
+            RejectReason::FirstByteTimeout => "first_byte_timeout",
+            RejectReason::RemainingLengthTooLarge => "remaining_length_too_large",
+            RejectReason::MalformedRemainingLength => "malformed_remaining_length",
+            RejectReason::RemainingLengthRead => "remaining_length_read",
+            RejectReason::ConnectPayloadRead => "connect_payload_read",
+            RejectReason::ConnectThroughputTooLow => "connect_throughput_too_low",
+            RejectReason::ConnectSegment => "connect_segment",
+            RejectReason::MalformedConnect => "malformed_connect",
+            RejectReason::UnsupportedProtocolLevel => "unsupported_protocol_level",
+            RejectReason::InvalidConnectProperties => "invalid_connect_properties",
+            RejectReason::InvalidConnectFlags => "invalid_connect_flags",
+            RejectReason::ClientId => "client_id",
+            RejectReason::MalformedClientHello => "malformed_tls_client_hello",
+            RejectReason::ClientHelloTimeout => "tls_client_hello_timeout",
+            RejectReason::PublishTopicDenied => "publish_topic_denied",
+            RejectReason::MalformedPublish => "malformed_publish",
+            RejectReason::KeepAlive => "keep_alive",
+            RejectReason::UsernameRequired => "username_required",
+            RejectReason::HttpBareLf => "http_bare_lf",
+            RejectReason::FragmentedConnect => "fragmented_connect",
+            RejectReason::HandshakeDeadline => "handshake_deadline",
+            RejectReason::OversizedPacket => "oversized_packet",
+            RejectReason::OversizedPublish => "oversized_publish",
+            RejectReason::ProxyProtocol => "proxy_protocol",
+            RejectReason::ConcurrentPerIp => "concurrent_per_ip",
+            RejectReason::InFlightConnects => "in_flight_connects",
+            RejectReason::SessionRate => "session_rate",
+            RejectReason::InspectionBudget => "inspection_budget",
+        }
+    }
+
+    pub fn class(self) -> RejectClass {
+        match self {
+            RejectReason::HttpDetected | RejectReason::HttpConnectTunnel => RejectClass::Http,
+            RejectReason::ClosedBeforeData
+            | RejectReason::FirstPacketError
+            | RejectReason::FirstPacketTimeout
+            | RejectReason::HttpRequestTimeout
+            | RejectReason::HttpHeaderCountExceeded
+            | RejectReason::HttpHeaderSizeExceeded
+            | RejectReason::HttpIncompleteHeaders
+            | RejectReason::HttpMalformedHeader
+            | RejectReason::HttpReadError
+            | RejectReason::FixedHeaderTimeout
+            | RejectReason::ConnectThroughputTooLow
+            | RejectReason::ClientHelloTimeout => RejectClass::Slowloris,
+            RejectReason::UsernameRequired
+            | RejectReason::HttpBareLf
+            | RejectReason::FragmentedConnect
+            | RejectReason::HandshakeDeadline
+            | RejectReason::ProxyProtocol
+            | RejectReason::ConcurrentPerIp
+            | RejectReason::InFlightConnects
+            | RejectReason::SessionRate
+            | RejectReason::InspectionBudget => RejectClass::Itemized,
+            _ => RejectClass::Protocol,
+        }
+    }
+}
+
+/// Counts a connection on `listener` rejected for `reason`.
+pub fn record_rejection(reason: RejectReason, listener: &str) {
+    REJECTIONS
+        .with_label_values(&[reason.as_str(), listener])
+        .inc();
+}
+
+/// Exports every per-listener series for `listener` from the start, so rates
+/// work before the first rejection of each kind.
+pub fn register_listener(listener: &str) {
+    for reason in RejectReason::ALL {
+        REJECTIONS.with_label_values(&[reason.as_str(), listener]);
+    }
+    BACKEND_UNAVAILABLE.with_label_values(&[listener]);
+    BACKEND_UNHEALTHY_REJECTIONS.with_label_values(&[listener]);
+}
+
+/// Sum of `counters` across every label value.
+fn total(counters: &IntCounterVec) -> u64 {
+    counters
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|m| m.get_counter().value() as u64)
+        .sum()
+}
+
+/// Rejections so far across every reason of `class` and every listener.
+fn rejections_of(class: RejectClass) -> u64 {
+    let in_class = |reason: &str| {
+        RejectReason::ALL
+            .iter()
+            .any(|r| r.as_str() == reason && r.class() == class)
+    };
+    REJECTIONS
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .filter(|m| {
+            m.get_label()
+                .iter()
+                .any(|label| label.name() == "reason" && in_class(label.value()))
+        })
+        .map(|m| m.get_counter().value() as u64)
+        .sum()
 }
 
 pub fn register_metrics() {
     let _ = REGISTRY.register(Box::new(CONNECTION_GAUGE.clone()));
     let _ = REGISTRY.register(Box::new(REJECTED_CONNECTIONS.clone()));
-    let _ = REGISTRY.register(Box::new(PROTOCOL_REJECTIONS.clone()));
-    let _ = REGISTRY.register(Box::new(HTTP_REJECTIONS.clone()));
-    let _ = REGISTRY.register(Box::new(SLOWLORIS_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(HTTP_BARE_LF_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(SPLICED_BYTES.clone()));
+    let _ = REGISTRY.register(Box::new(ACCEPTED_CONNECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(FORWARDED_BYTES.clone()));
+    let _ = REGISTRY.register(Box::new(DRAINING_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(HANDSHAKE_DEADLINE_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(FRAGMENTED_CONNECT_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(CONNECT_REMAINING_LENGTH.clone()));
+    let _ = REGISTRY.register(Box::new(CONNECT_KEEP_ALIVE.clone()));
+    let _ = REGISTRY.register(Box::new(KEEP_ALIVE_ENFORCED.clone()));
+    let _ = REGISTRY.register(Box::new(REJECT_CONNACKS_SENT.clone()));
+    let _ = REGISTRY.register(Box::new(OVERSIZED_PACKET_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(OVERSIZED_PUBLISH.clone()));
+    let _ = REGISTRY.register(Box::new(RATE_LIMIT_BACKEND_ERRORS.clone()));
+    let _ = REGISTRY.register(Box::new(RATE_LIMIT_ALLOWED.clone()));
+    let _ = REGISTRY.register(Box::new(RATE_LIMIT_TOKENS_REMAINING.clone()));
+    let _ = REGISTRY.register(Box::new(TRACKED_IPS.clone()));
+    let _ = REGISTRY.register(Box::new(LISTENER_ACCEPTED.clone()));
+    let _ = REGISTRY.register(Box::new(LISTENER_ACTIVE.clone()));
+    let _ = REGISTRY.register(Box::new(FD_PRESSURE_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(REGION_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(SUBNET_CAP_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(SESSION_RATE_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(BACKEND_UNAVAILABLE.clone()));
+    let _ = REGISTRY.register(Box::new(POLICY_PROFILE_MATCHES.clone()));
+    let _ = REGISTRY.register(Box::new(OPEN_FDS.clone()));
+    let _ = REGISTRY.register(Box::new(ROUTING_DECISIONS.clone()));
+    let _ = REGISTRY.register(Box::new(SIGNATURE_FAST_PATH.clone()));
+    let _ = REGISTRY.register(Box::new(CAPTURES_WRITTEN.clone()));
+    let _ = REGISTRY.register(Box::new(CAPTURES_DROPPED.clone()));
+    let _ = REGISTRY.register(Box::new(HTTP_EARLY_CLASSIFICATIONS.clone()));
+    let _ = REGISTRY.register(Box::new(IN_FLIGHT_CONNECT_WAIT.clone()));
+    let _ = REGISTRY.register(Box::new(IN_FLIGHT_CONNECT_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(BACKEND_ERRORS.clone()));
+    let _ = REGISTRY.register(Box::new(ADMIN_KILLS.clone()));
+    let _ = REGISTRY.register(Box::new(HANDSHAKE_PHASE_SECONDS.clone()));
+    let _ = REGISTRY.register(Box::new(PROTOCOL_LEVEL_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(ACCEPT_FILTER_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(AUTH_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(BACKEND_HEALTHY.clone()));
+    let _ = REGISTRY.register(Box::new(BACKEND_DRAINING.clone()));
+    let _ = REGISTRY.register(Box::new(BACKEND_SELECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(BACKEND_UNHEALTHY_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(TAGGED_SESSIONS.clone()));
+    let _ = REGISTRY.register(Box::new(TAGGED_HANDSHAKE_SECONDS.clone()));
+    let _ = REGISTRY.register(Box::new(PROXY_PROTOCOL_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(CONCURRENCY_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(INSPECTION_SATURATED.clone()));
+    let _ = REGISTRY.register(Box::new(INSPECTIONS_IN_PROGRESS.clone()));
+    let _ = REGISTRY.register(Box::new(ACL_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(IDLE_DISCONNECTS.clone()));
+    let _ = REGISTRY.register(Box::new(BYTES_CLIENT_TO_BACKEND.clone()));
+    let _ = REGISTRY.register(Box::new(BYTES_BACKEND_TO_CLIENT.clone()));
+    let _ = REGISTRY.register(Box::new(LIFETIME_DISCONNECTS.clone()));
+    let _ = REGISTRY.register(Box::new(TLS_HANDSHAKE_FAILURES.clone()));
+    let _ = REGISTRY.register(Box::new(TLS_HANDSHAKE_TIMEOUTS.clone()));
+    let _ = REGISTRY.register(Box::new(TLS_CONNECTIONS_BY_VERSION.clone()));
+    let _ = REGISTRY.register(Box::new(TLS_HANDSHAKE_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(TLS_HANDSHAKE_WAITERS.clone()));
+    let _ = REGISTRY.register(Box::new(CONFIG_RELOADS.clone()));
+    let _ = REGISTRY.register(Box::new(REPEATED_MALFORMED.clone()));
+    let _ = REGISTRY.register(Box::new(REPEATED_MALFORMED_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(SESSION_IDLE_TIMEOUTS.clone()));
+    let _ = REGISTRY.register(Box::new(INSPECTION_BUDGET_EXCEEDED.clone()));
+    let _ = REGISTRY.register(Box::new(FIRST_PACKET_PEEK_FILLED.clone()));
+    let _ = REGISTRY.register(Box::new(CONNECT_RATIO_BANS.clone()));
+    let _ = REGISTRY.register(Box::new(CONNECT_RATIO_REJECTIONS.clone()));
+    let _ = REGISTRY.register(Box::new(EVENTS_PUBLISHED.clone()));
+    let _ = REGISTRY.register(Box::new(EVENTS_DROPPED.clone()));
 }
 
 fn update_metrics() {
@@ -61,3 +914,334 @@ pub fn render_metrics() -> String {
 
     String::from_utf8(buffer).unwrap_or_else(|_| "# Error: Invalid UTF8".to_string())
 }
+
+/// Writes a final Prometheus text snapshot for post-mortem analysis: to `path`
+/// when set, otherwise (or if the file cannot be written) to the log.
+pub async fn write_shutdown_snapshot(path: Option<&str>) {
+    let snapshot = render_metrics();
+    if let Some(path) = path {
+        match tokio::fs::write(path, &snapshot).await {
+            Ok(()) => {
+                info!(path = %path, "Wrote final metrics snapshot");
+                return;
+            }
+            Err(e) => {
+                warn!(path = %path, error = %e, "Could not write metrics snapshot; logging it instead")
+            }
+        }
+    }
+    info!(target: "aegis_metrics", snapshot = %snapshot, "Final metrics snapshot");
+}
+
+/// Counter values reported by the periodic metrics summary.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CounterTotals {
+    pub accepted: u64,
+    pub bytes_forwarded: u64,
+    pub rejected_rate_limit: u64,
+    pub rejected_protocol: u64,
+    pub rejected_http: u64,
+    pub rejected_http_bare_lf: u64,
+    pub rejected_slowloris: u64,
+    pub rejected_handshake_deadline: u64,
+    pub rejected_fragmented_connect: u64,
+    pub rejected_region: u64,
+    pub rejected_subnet_cap: u64,
+    pub rejected_session_rate: u64,
+    pub rejected_connect_ratio: u64,
+    pub rejected_inspection_budget: u64,
+    pub rejected_repeated_malformed: u64,
+    pub rejected_in_flight: u64,
+    pub rejected_accept_filter: u64,
+    pub rejected_auth: u64,
+    pub rejected_backend_unhealthy: u64,
+    pub rejected_proxy_protocol: u64,
+    pub rejected_concurrency: u64,
+    pub rejected_inspection_saturated: u64,
+    pub rejected_acl: u64,
+    pub rejected_tls_handshake: u64,
+    pub rejected_fd_pressure: u64,
+    pub rejected_draining: u64,
+    pub backend_unavailable: u64,
+}
+
+impl CounterTotals {
+    /// Current values of the summarized counters.
+    pub fn now() -> Self {
+        Self {
+            accepted: ACCEPTED_CONNECTIONS.get(),
+            bytes_forwarded: FORWARDED_BYTES.get(),
+            rejected_rate_limit: REJECTED_CONNECTIONS.get(),
+            rejected_protocol: rejections_of(RejectClass::Protocol),
+            rejected_http: rejections_of(RejectClass::Http),
+            rejected_http_bare_lf: HTTP_BARE_LF_REJECTIONS.get(),
+            rejected_slowloris: rejections_of(RejectClass::Slowloris),
+            rejected_handshake_deadline: HANDSHAKE_DEADLINE_REJECTIONS.get(),
+            rejected_fragmented_connect: FRAGMENTED_CONNECT_REJECTIONS.get(),
+            rejected_region: total(&REGION_REJECTIONS),
+            rejected_subnet_cap: SUBNET_CAP_REJECTIONS.get(),
+            rejected_session_rate: SESSION_RATE_REJECTIONS.get(),
+            rejected_connect_ratio: CONNECT_RATIO_REJECTIONS.get(),
+            rejected_inspection_budget: INSPECTION_BUDGET_EXCEEDED.get(),
+            rejected_repeated_malformed: REPEATED_MALFORMED_REJECTIONS.get(),
+            rejected_in_flight: IN_FLIGHT_CONNECT_REJECTIONS.get(),
+            rejected_accept_filter: ACCEPT_FILTER_REJECTIONS.get(),
+            rejected_auth: AUTH_REJECTIONS.get(),
+            rejected_backend_unhealthy: total(&BACKEND_UNHEALTHY_REJECTIONS),
+            rejected_proxy_protocol: PROXY_PROTOCOL_REJECTIONS.get(),
+            rejected_concurrency: CONCURRENCY_REJECTIONS.get(),
+            rejected_inspection_saturated: INSPECTION_SATURATED.get(),
+            rejected_acl: ACL_REJECTIONS.get(),
+            rejected_tls_handshake: TLS_HANDSHAKE_FAILURES.get() + TLS_HANDSHAKE_TIMEOUTS.get(),
+            rejected_fd_pressure: FD_PRESSURE_REJECTIONS.get(),
+            rejected_draining: DRAINING_REJECTIONS.get(),
+            backend_unavailable: total(&BACKEND_UNAVAILABLE),
+        }
+    }
+
+    /// Increase of every counter since `earlier`.
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            accepted: self.accepted.saturating_sub(earlier.accepted),
+            bytes_forwarded: self.bytes_forwarded.saturating_sub(earlier.bytes_forwarded),
+            rejected_rate_limit: self
+                .rejected_rate_limit
+                .saturating_sub(earlier.rejected_rate_limit),
+            rejected_protocol: self
+                .rejected_protocol
+                .saturating_sub(earlier.rejected_protocol),
+            rejected_http: self.rejected_http.saturating_sub(earlier.rejected_http),
+            rejected_http_bare_lf: self
+                .rejected_http_bare_lf
+                .saturating_sub(earlier.rejected_http_bare_lf),
+            rejected_slowloris: self
+                .rejected_slowloris
+                .saturating_sub(earlier.rejected_slowloris),
+            rejected_handshake_deadline: self
+                .rejected_handshake_deadline
+                .saturating_sub(earlier.rejected_handshake_deadline),
+            rejected_fragmented_connect: self
+                .rejected_fragmented_connect
+                .saturating_sub(earlier.rejected_fragmented_connect),
+            rejected_region: self.rejected_region.saturating_sub(earlier.rejected_region),
+            rejected_subnet_cap: self
+                .rejected_subnet_cap
+                .saturating_sub(earlier.rejected_subnet_cap),
+            rejected_session_rate: self
+                .rejected_session_rate
+                .saturating_sub(earlier.rejected_session_rate),
+            rejected_connect_ratio: self
+                .rejected_connect_ratio
+                .saturating_sub(earlier.rejected_connect_ratio),
+            rejected_inspection_budget: self
+                .rejected_inspection_budget
+                .saturating_sub(earlier.rejected_inspection_budget),
+            rejected_repeated_malformed: self
+                .rejected_repeated_malformed
+                .saturating_sub(earlier.rejected_repeated_malformed),
+            rejected_in_flight: self
+                .rejected_in_flight
+                .saturating_sub(earlier.rejected_in_flight),
+            rejected_accept_filter: self
+                .rejected_accept_filter
+                .saturating_sub(earlier.rejected_accept_filter),
+            rejected_auth: self.rejected_auth.saturating_sub(earlier.rejected_auth),
+            rejected_backend_unhealthy: self
+                .rejected_backend_unhealthy
+                .saturating_sub(earlier.rejected_backend_unhealthy),
+            rejected_proxy_protocol: self
+                .rejected_proxy_protocol
+                .saturating_sub(earlier.rejected_proxy_protocol),
+            rejected_concurrency: self
+                .rejected_concurrency
+                .saturating_sub(earlier.rejected_concurrency),
+            rejected_inspection_saturated: self
+                .rejected_inspection_saturated
+                .saturating_sub(earlier.rejected_inspection_saturated),
+            rejected_acl: self.rejected_acl.saturating_sub(earlier.rejected_acl),
+            rejected_tls_handshake: self
+                .rejected_tls_handshake
+                .saturating_sub(earlier.rejected_tls_handshake),
+            rejected_fd_pressure: self
+                .rejected_fd_pressure
+                .saturating_sub(earlier.rejected_fd_pressure),
+            rejected_draining: self
+                .rejected_draining
+                .saturating_sub(earlier.rejected_draining),
+            backend_unavailable: self
+                .backend_unavailable
+                .saturating_sub(earlier.backend_unavailable),
+        }
+    }
+
+    /// Sum of all rejection counters.
+    pub fn rejected(&self) -> u64 {
+        self.rejected_rate_limit
+            + self.rejected_protocol
+            + self.rejected_http
+            + self.rejected_http_bare_lf
+            + self.rejected_slowloris
+            + self.rejected_handshake_deadline
+            + self.rejected_fragmented_connect
+            + self.rejected_region
+            + self.rejected_subnet_cap
+            + self.rejected_session_rate
+            + self.rejected_connect_ratio
+            + self.rejected_inspection_budget
+            + self.rejected_repeated_malformed
+            + self.rejected_in_flight
+            + self.rejected_accept_filter
+            + self.rejected_auth
+            + self.rejected_backend_unhealthy
+            + self.rejected_proxy_protocol
+            + self.rejected_concurrency
+            + self.rejected_inspection_saturated
+            + self.rejected_acl
+            + self.rejected_tls_handshake
+            + self.rejected_fd_pressure
+            + self.rejected_draining
+            + self.backend_unavailable
+    }
+}
+
+/// Logs one structured summary of counter deltas per `interval`, for
+/// deployments where nothing scrapes the Prometheus endpoint. With JSON
+/// logging each summary is a single JSON event.
+pub async fn log_summary_periodically(interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately; start counting from there.
+    ticker.tick().await;
+    let mut last = CounterTotals::now();
+    loop {
+        ticker.tick().await;
+        let current = CounterTotals::now();
+        let delta = current.since(&last);
+        last = current;
+        info!(
+            target: "aegis_metrics",
+            interval_secs = interval.as_secs(),
+            accepted = delta.accepted,
+            rejected = delta.rejected(),
+            rejected_rate_limit = delta.rejected_rate_limit,
+            rejected_protocol = delta.rejected_protocol,
+            rejected_http = delta.rejected_http,
+            rejected_http_bare_lf = delta.rejected_http_bare_lf,
+            rejected_slowloris = delta.rejected_slowloris,
+            rejected_handshake_deadline = delta.rejected_handshake_deadline,
+            rejected_fragmented_connect = delta.rejected_fragmented_connect,
+            rejected_region = delta.rejected_region,
+            rejected_subnet_cap = delta.rejected_subnet_cap,
+            rejected_session_rate = delta.rejected_session_rate,
+            rejected_connect_ratio = delta.rejected_connect_ratio,
+            rejected_inspection_budget = delta.rejected_inspection_budget,
+            rejected_repeated_malformed = delta.rejected_repeated_malformed,
+            rejected_in_flight = delta.rejected_in_flight,
+            rejected_accept_filter = delta.rejected_accept_filter,
+            rejected_auth = delta.rejected_auth,
+            rejected_backend_unhealthy = delta.rejected_backend_unhealthy,
+            rejected_proxy_protocol = delta.rejected_proxy_protocol,
+            rejected_concurrency = delta.rejected_concurrency,
+            rejected_inspection_saturated = delta.rejected_inspection_saturated,
+            rejected_acl = delta.rejected_acl,
+            rejected_tls_handshake = delta.rejected_tls_handshake,
+            rejected_fd_pressure = delta.rejected_fd_pressure,
+            rejected_draining = delta.rejected_draining,
+            backend_unavailable = delta.backend_unavailable,
+            active_connections = ACTIVE_CONNECTIONS.load(Ordering::SeqCst),
+            bytes_forwarded = delta.bytes_forwarded,
+            "Metrics summary"
+        );
+    }
+}
+
+/// Helpers for tests asserting exact counter values (`test-util` feature).
+///
+/// The metrics are process-wide statics, so tests in one binary share them
+/// and run in parallel. A test that checks exact values holds the guard from
+/// `exclusive`, which also starts it from zeroed counters; every test in the
+/// same file that touches those counters should do the same.
+#[cfg(feature = "test-util")]
+pub mod test_util {
+    use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    static EXCLUSIVE: Mutex<()> = Mutex::new(());
+
+    /// Serializes metrics-asserting tests and resets every counter.
+    pub fn exclusive() -> MutexGuard<'static, ()> {
+        let guard = EXCLUSIVE.lock().unwrap_or_else(|e| e.into_inner());
+        reset();
+        guard
+    }
+
+    /// Zeroes every counter (and every label of the counter vectors).
+    /// Gauges and histograms are left alone.
+    pub fn reset() {
+        for counter in [
+            &*REJECTED_CONNECTIONS,
+            &*HANDSHAKE_DEADLINE_REJECTIONS,
+            &*FRAGMENTED_CONNECT_REJECTIONS,
+            &*SUBNET_CAP_REJECTIONS,
+            &*SESSION_RATE_REJECTIONS,
+            &*HTTP_BARE_LF_REJECTIONS,
+            &*HTTP_EARLY_CLASSIFICATIONS,
+            &*REPEATED_MALFORMED_REJECTIONS,
+            &*IN_FLIGHT_CONNECT_REJECTIONS,
+            &*ACCEPT_FILTER_REJECTIONS,
+            &*AUTH_REJECTIONS,
+            &*PROXY_PROTOCOL_REJECTIONS,
+            &*CONCURRENCY_REJECTIONS,
+            &*INSPECTION_SATURATED,
+            &*ACL_REJECTIONS,
+            &*IDLE_DISCONNECTS,
+            &*BYTES_CLIENT_TO_BACKEND,
+            &*BYTES_BACKEND_TO_CLIENT,
+            &*LIFETIME_DISCONNECTS,
+            &*TLS_HANDSHAKE_FAILURES,
+            &*TLS_HANDSHAKE_TIMEOUTS,
+            &*TLS_HANDSHAKE_REJECTIONS,
+            &*INSPECTION_BUDGET_EXCEEDED,
+            &*FIRST_PACKET_PEEK_FILLED,
+            &*CONNECT_RATIO_BANS,
+            &*CONNECT_RATIO_REJECTIONS,
+            &*EVENTS_PUBLISHED,
+            &*EVENTS_DROPPED,
+            &*ADMIN_KILLS,
+            &*FD_PRESSURE_REJECTIONS,
+            &*DRAINING_REJECTIONS,
+            &*CAPTURES_WRITTEN,
+            &*CAPTURES_DROPPED,
+            &*REJECT_CONNACKS_SENT,
+            &*ACCEPTED_CONNECTIONS,
+            &*FORWARDED_BYTES,
+            &*SPLICED_BYTES,
+            &*OVERSIZED_PUBLISH,
+            &*RATE_LIMIT_BACKEND_ERRORS,
+            &*RATE_LIMIT_ALLOWED,
+        ] {
+            counter.reset();
+        }
+        for counter_vec in [
+            &*REGION_REJECTIONS,
+            &*POLICY_PROFILE_MATCHES,
+            &*SIGNATURE_FAST_PATH,
+            &*OVERSIZED_PACKET_REJECTIONS,
+            &*REPEATED_MALFORMED,
+            &*SESSION_IDLE_TIMEOUTS,
+            &*BACKEND_ERRORS,
+            &*PROTOCOL_LEVEL_REJECTIONS,
+            &*TAGGED_SESSIONS,
+            &*TLS_CONNECTIONS_BY_VERSION,
+            &*ROUTING_DECISIONS,
+            &*KEEP_ALIVE_ENFORCED,
+            &*LISTENER_ACCEPTED,
+            &*CONFIG_RELOADS,
+            &*REJECTIONS,
+            &*BACKEND_UNAVAILABLE,
+            &*BACKEND_UNHEALTHY_REJECTIONS,
+            &*BACKEND_SELECTIONS,
+        ] {
+            counter_vec.reset();
+        }
+    }
+}
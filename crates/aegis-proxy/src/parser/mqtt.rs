@@ -1,11 +1,38 @@
-#[derive(Debug, PartialEq)]
+/// MQTT control packet type, from the high nibble of the first byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MqttPacketType {
     Connect,
+    Connack,
     Publish,
-    Other,
+    Puback,
+    Pubrec,
+    Pubrel,
+    Pubcomp,
+    Subscribe,
+    Suback,
+    Unsubscribe,
+    Unsuback,
+    Pingreq,
+    Pingresp,
+    Disconnect,
+    /// MQTT 5 only; type 15 is reserved in v3.1.1 (see `valid_for_level`).
+    Auth,
+    /// Empty input or the reserved type 0.
     Malformed,
 }
 
+impl MqttPacketType {
+    /// Whether this packet type exists at CONNECT protocol level `level`
+    /// (4 = v3.1.1, 5 = v5).
+    pub fn valid_for_level(self, level: u8) -> bool {
+        match self {
+            MqttPacketType::Malformed => false,
+            MqttPacketType::Auth => level >= 5,
+            _ => true,
+        }
+    }
+}
+
 /// Decode the MQTT Remaining Length per the MQTT spec.
 ///
 /// Returns Ok((value, bytes_used)) on success, or Err(&'static str) on error:
@@ -43,11 +70,824 @@ pub fn inspect_packet(payload: &[u8]) -> MqttPacketType {
         return MqttPacketType::Malformed;
     }
 
-    let packet_type = payload[0] >> 4;
-
-    match packet_type {
+    match payload[0] >> 4 {
         1 => MqttPacketType::Connect,
+        2 => MqttPacketType::Connack,
         3 => MqttPacketType::Publish,
-        _ => MqttPacketType::Other,
+        4 => MqttPacketType::Puback,
+        5 => MqttPacketType::Pubrec,
+        6 => MqttPacketType::Pubrel,
+        7 => MqttPacketType::Pubcomp,
+        8 => MqttPacketType::Subscribe,
+        9 => MqttPacketType::Suback,
+        10 => MqttPacketType::Unsubscribe,
+        11 => MqttPacketType::Unsuback,
+        12 => MqttPacketType::Pingreq,
+        13 => MqttPacketType::Pingresp,
+        14 => MqttPacketType::Disconnect,
+        15 => MqttPacketType::Auth,
+        // Type 0 is reserved in every version.
+        _ => MqttPacketType::Malformed,
+    }
+}
+
+/// Minimal CONNECT variable-header validation (protocol name "MQTT").
+///
+/// `payload` starts right after the fixed header.
+pub fn validate_connect_variable_header(payload: &[u8]) -> bool {
+    payload.len() >= 6 && payload[0] == 0x00 && payload[1] == 0x04 && &payload[2..6] == b"MQTT"
+}
+
+/// Quick check if the first bytes of a stream look like an MQTT CONNECT.
+///
+/// Stricter than `inspect_packet`: the fixed-header flags must be zero, the
+/// Remaining Length must decode and the protocol name must be "MQTT", so
+/// unrelated protocols whose first nibble happens to be 1 (e.g. a TLS
+/// handshake record, 0x16) are not mistaken for a CONNECT.
+pub fn looks_like_mqtt_connect(buf: &[u8]) -> bool {
+    if buf.first() != Some(&0x10) {
+        return false;
+    }
+    match decode_remaining_length(&buf[1..]) {
+        Ok((_, rl_used)) => validate_connect_variable_header(&buf[1 + rl_used..]),
+        Err(_) => false,
+    }
+}
+
+/// Reads the protocol level byte from a (possibly partial) CONNECT packet.
+///
+/// `packet` starts at the fixed header. Returns `None` if the buffer is too
+/// short to reach the protocol level or the Remaining Length is malformed.
+pub fn connect_protocol_level(packet: &[u8]) -> Option<u8> {
+    let (_, rl_used) = decode_remaining_length(packet.get(1..)?).ok()?;
+    let var_header = packet.get(1 + rl_used..)?;
+    let name_len = u16::from_be_bytes([*var_header.first()?, *var_header.get(1)?]) as usize;
+    var_header.get(2 + name_len).copied()
+}
+
+/// Protocol levels defined by the MQTT specifications: 3 (v3.1, "MQIsdp"),
+/// 4 (v3.1.1) and 5 (v5).
+pub const KNOWN_PROTOCOL_LEVELS: &[u8] = &[3, 4, 5];
+
+/// Offset of the two-byte keep-alive field in a CONNECT frame (fixed header
+/// included): it follows the protocol name, level and connect flags.
+fn connect_keep_alive_offset(packet: &[u8]) -> Option<usize> {
+    let (_, rl_used) = decode_remaining_length(packet.get(1..)?).ok()?;
+    let var_at = 1 + rl_used;
+    let name_len = u16::from_be_bytes([*packet.get(var_at)?, *packet.get(var_at + 1)?]) as usize;
+    let at = var_at + 2 + name_len + 2;
+    packet.get(at..at + 2)?;
+    Some(at)
+}
+
+/// Reads the keep-alive (seconds) requested by a CONNECT frame.
+///
+/// `packet` starts at the fixed header. Returns `None` if it is too short.
+pub fn connect_keep_alive(packet: &[u8]) -> Option<u16> {
+    let at = connect_keep_alive_offset(packet)?;
+    Some(u16::from_be_bytes([packet[at], packet[at + 1]]))
+}
+
+/// Rewrites the keep-alive of a CONNECT frame in place. The field has a fixed
+/// width, so no lengths change. Returns `false` if the frame is too short.
+pub fn set_connect_keep_alive(packet: &mut [u8], keep_alive_secs: u16) -> bool {
+    match connect_keep_alive_offset(packet) {
+        Some(at) => {
+            packet[at..at + 2].copy_from_slice(&keep_alive_secs.to_be_bytes());
+            true
+        }
+        None => false,
+    }
+}
+
+/// MQTT 5.0 Maximum Packet Size identifier.
+const PROPERTY_MAXIMUM_PACKET_SIZE: u8 = 0x27;
+
+/// Length of an MQTT 5.0 property value starting at `value`, by identifier.
+/// Returns `None` for unknown identifiers or truncated values.
+fn property_value_len(id: u8, value: &[u8]) -> Option<usize> {
+    let prefixed = |at: usize| -> Option<usize> {
+        let len = u16::from_be_bytes([*value.get(at)?, *value.get(at + 1)?]) as usize;
+        Some(at + 2 + len)
+    };
+    let len = match id {
+        // Byte
+        0x01 | 0x17 | 0x19 | 0x24 | 0x25 | 0x28 | 0x29 | 0x2A => 1,
+        // Two Byte Integer
+        0x13 | 0x21 | 0x22 | 0x23 => 2,
+        // Four Byte Integer
+        0x02 | 0x11 | 0x18 | 0x27 => 4,
+        // Variable Byte Integer
+        0x0B => decode_remaining_length(value).ok()?.1,
+        // UTF-8 string or binary data
+        0x03 | 0x08 | 0x09 | 0x12 | 0x15 | 0x16 | 0x1A | 0x1C | 0x1F => prefixed(0)?,
+        // UTF-8 string pair
+        0x26 => prefixed(prefixed(0)?)?,
+        _ => return None,
+    };
+    (value.len() >= len).then_some(len)
+}
+
+/// Reads the Maximum Packet Size a v5 client advertised in its CONNECT.
+///
+/// `packet` starts at the fixed header. Returns `None` for earlier protocol
+/// levels, when the property is absent, or if the properties are malformed.
+pub fn connect_maximum_packet_size(packet: &[u8]) -> Option<u32> {
+    if connect_protocol_level(packet)? != 5 {
+        return None;
+    }
+    let props_at = connect_keep_alive_offset(packet)? + 2;
+    let (props_len, props_used) = decode_remaining_length(packet.get(props_at..)?).ok()?;
+    let start = props_at + props_used;
+    let mut props = packet.get(start..start + props_len)?;
+    while let Some((&id, rest)) = props.split_first() {
+        let len = property_value_len(id, rest)?;
+        if id == PROPERTY_MAXIMUM_PACKET_SIZE {
+            return Some(u32::from_be_bytes(rest[..4].try_into().ok()?));
+        }
+        props = &rest[len..];
+    }
+    None
+}
+
+/// Error decoding an MQTT structure from a byte buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MqttError {
+    /// The buffer ended before the structure did.
+    Incomplete,
+    /// The bytes violate the protocol.
+    Malformed(&'static str),
+}
+
+impl std::fmt::Display for MqttError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MqttError::Incomplete => f.write_str("incomplete"),
+            MqttError::Malformed(reason) => write!(f, "malformed: {}", reason),
+        }
+    }
+}
+
+/// MQTT 5.0 CONNECT properties relevant to admission policy. Every field is
+/// `None` when the property is absent or the CONNECT predates v5.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConnectProperties {
+    pub session_expiry_interval: Option<u32>,
+    pub receive_maximum: Option<u16>,
+    pub maximum_packet_size: Option<u32>,
+    pub topic_alias_maximum: Option<u16>,
+}
+
+const PROPERTY_SESSION_EXPIRY_INTERVAL: u8 = 0x11;
+const PROPERTY_RECEIVE_MAXIMUM: u8 = 0x21;
+const PROPERTY_TOPIC_ALIAS_MAXIMUM: u8 = 0x22;
+
+/// Identifiers MQTT 5.0 allows in a CONNECT property block.
+const CONNECT_PROPERTY_IDS: &[u8] = &[0x11, 0x15, 0x16, 0x17, 0x19, 0x21, 0x22, 0x26, 0x27];
+
+/// Decodes the property block of an MQTT 5.0 CONNECT.
+///
+/// `payload` starts right after the fixed header (as for
+/// `validate_connect_variable_header`). Earlier protocol levels have no
+/// properties and yield the default. Properties other than the four tracked
+/// ones are skipped after their length is checked. Duplicates, identifiers
+/// not allowed in a CONNECT, and a Receive Maximum or Maximum Packet Size of
+/// zero are protocol errors; a block running past the buffer is `Incomplete`.
+pub fn parse_connect_properties(payload: &[u8]) -> Result<ConnectProperties, MqttError> {
+    let name_len = match payload.get(..2) {
+        Some(len) => u16::from_be_bytes([len[0], len[1]]) as usize,
+        None => return Err(MqttError::Incomplete),
+    };
+    // Protocol name, level, connect flags, keep-alive
+    let level = *payload.get(2 + name_len).ok_or(MqttError::Incomplete)?;
+    if level != 5 {
+        return Ok(ConnectProperties::default());
+    }
+    let props_at = 2 + name_len + 4;
+    let (props_len, props_used) =
+        match decode_remaining_length(payload.get(props_at..).ok_or(MqttError::Incomplete)?) {
+            Ok(decoded) => decoded,
+            Err("Incomplete") => return Err(MqttError::Incomplete),
+            Err(_) => return Err(MqttError::Malformed("property length")),
+        };
+    let start = props_at + props_used;
+    let mut props = payload
+        .get(start..start + props_len)
+        .ok_or(MqttError::Incomplete)?;
+
+    let mut parsed = ConnectProperties::default();
+    while let Some((&id, rest)) = props.split_first() {
+        if !CONNECT_PROPERTY_IDS.contains(&id) {
+            return Err(MqttError::Malformed("property not allowed in CONNECT"));
+        }
+        // The block length is known, so a value running past it is malformed
+        // rather than incomplete.
+        let len =
+            property_value_len(id, rest).ok_or(MqttError::Malformed("truncated property value"))?;
+        let value = &rest[..len];
+        let duplicate = match id {
+            PROPERTY_SESSION_EXPIRY_INTERVAL => parsed
+                .session_expiry_interval
+                .replace(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+                .is_some(),
+            PROPERTY_RECEIVE_MAXIMUM => parsed
+                .receive_maximum
+                .replace(u16::from_be_bytes([value[0], value[1]]))
+                .is_some(),
+            PROPERTY_MAXIMUM_PACKET_SIZE => parsed
+                .maximum_packet_size
+                .replace(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+                .is_some(),
+            PROPERTY_TOPIC_ALIAS_MAXIMUM => parsed
+                .topic_alias_maximum
+                .replace(u16::from_be_bytes([value[0], value[1]]))
+                .is_some(),
+            _ => false,
+        };
+        if duplicate {
+            return Err(MqttError::Malformed("duplicate property"));
+        }
+        props = &rest[len..];
+    }
+
+    if parsed.receive_maximum == Some(0) {
+        return Err(MqttError::Malformed("receive maximum of zero"));
+    }
+    if parsed.maximum_packet_size == Some(0) {
+        return Err(MqttError::Malformed("maximum packet size of zero"));
+    }
+    Ok(parsed)
+}
+
+/// Offset of the CONNECT payload (client identifier first) in `payload`,
+/// which starts right after the fixed header.
+fn connect_payload_offset(payload: &[u8]) -> Option<usize> {
+    let name_len = u16::from_be_bytes([*payload.first()?, *payload.get(1)?]) as usize;
+    let level = *payload.get(2 + name_len)?;
+    // Protocol name, level, connect flags, keep-alive
+    let mut at = 2 + name_len + 4;
+    if level == 5 {
+        let (props_len, props_used) = decode_remaining_length(payload.get(at..)?).ok()?;
+        at += props_used + props_len;
+    }
+    Some(at)
+}
+
+/// Reads the client identifier from a CONNECT.
+///
+/// `payload` starts right after the fixed header. Returns `None` if the
+/// packet is too short or the identifier is not valid UTF-8.
+pub fn extract_client_id(payload: &[u8]) -> Option<&str> {
+    let at = connect_payload_offset(payload)?;
+    let len = u16::from_be_bytes([*payload.get(at)?, *payload.get(at + 1)?]) as usize;
+    std::str::from_utf8(payload.get(at + 2..at + 2 + len)?).ok()
+}
+
+/// The Connect Flags byte of a CONNECT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectFlags {
+    pub username: bool,
+    pub password: bool,
+    pub will_retain: bool,
+    pub will_qos: u8,
+    pub will: bool,
+    pub clean_session: bool,
+}
+
+/// Reads the raw Connect Flags byte of a CONNECT.
+///
+/// `payload` starts right after the fixed header.
+pub fn connect_flags_byte(payload: &[u8]) -> Option<u8> {
+    let name_len = u16::from_be_bytes([*payload.first()?, *payload.get(1)?]) as usize;
+    payload.get(2 + name_len + 1).copied()
+}
+
+/// Reads the Connect Flags of a CONNECT.
+///
+/// `payload` starts right after the fixed header.
+pub fn connect_flags(payload: &[u8]) -> Option<ConnectFlags> {
+    let flags = connect_flags_byte(payload)?;
+    Some(ConnectFlags {
+        username: flags & 0x80 != 0,
+        password: flags & 0x40 != 0,
+        will_retain: flags & 0x20 != 0,
+        will_qos: (flags >> 3) & 0x03,
+        will: flags & 0x04 != 0,
+        clean_session: flags & 0x02 != 0,
+    })
+}
+
+/// Checks a Connect Flags byte against the rules shared by MQTT 3.1.1 and
+/// 5.0: the reserved bit must be zero, the will QoS must not be 3, and the
+/// will QoS and retain bits must be zero without the will flag.
+pub fn validate_connect_flags(flags: u8) -> Result<(), &'static str> {
+    let will = flags & 0x04 != 0;
+    let will_qos = (flags >> 3) & 0x03;
+    let will_retain = flags & 0x20 != 0;
+    if flags & 0x01 != 0 {
+        return Err("reserved connect flag set");
+    }
+    if will_qos == 3 {
+        return Err("will QoS 3");
+    }
+    if !will && (will_qos != 0 || will_retain) {
+        return Err("will QoS or retain without the will flag");
+    }
+    Ok(())
+}
+
+/// Reads the Clean Session (v3.1.1) / Clean Start (v5) flag of a CONNECT.
+///
+/// `payload` starts right after the fixed header.
+pub fn connect_clean_session(payload: &[u8]) -> Option<bool> {
+    connect_flags(payload).map(|flags| flags.clean_session)
+}
+
+/// Reads the user name of a CONNECT, skipping the client identifier and any
+/// will fields before it.
+///
+/// `payload` starts right after the fixed header. Returns `None` if the
+/// username flag is unset, the packet is too short, or the name is not valid
+/// UTF-8.
+pub fn extract_username(payload: &[u8]) -> Option<&str> {
+    let flags = connect_flags(payload)?;
+    if !flags.username {
+        return None;
+    }
+    let name_len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+    let level = payload[2 + name_len];
+    let field_end = |at: usize| -> Option<usize> {
+        let len = u16::from_be_bytes([*payload.get(at)?, *payload.get(at + 1)?]) as usize;
+        Some(at + 2 + len)
+    };
+    // Client identifier
+    let mut at = field_end(connect_payload_offset(payload)?)?;
+    if flags.will {
+        if level == 5 {
+            let (props_len, props_used) = decode_remaining_length(payload.get(at..)?).ok()?;
+            at += props_used + props_len;
+        }
+        // Will topic, will payload
+        at = field_end(field_end(at)?)?;
+    }
+    let end = field_end(at)?;
+    std::str::from_utf8(payload.get(at + 2..end)?).ok()
+}
+
+/// A packet rejected by `PacketSizeTracker`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PacketSizeError {
+    /// Total packet size (fixed header included) above the limit.
+    TooLarge(usize),
+    /// Remaining Length longer than four bytes.
+    MalformedLength,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FrameState {
+    Header,
+    Length {
+        value: usize,
+        multiplier: usize,
+        used: usize,
+    },
+    Body {
+        remaining: usize,
+    },
+}
+
+/// Follows MQTT packet boundaries across a byte stream without buffering it,
+/// checking each packet's total size against a limit as soon as its
+/// Remaining Length is complete. The stream must start at a packet boundary.
+#[derive(Debug, Clone)]
+pub struct PacketSizeTracker {
+    max_packet_size: usize,
+    state: FrameState,
+}
+
+impl PacketSizeTracker {
+    pub fn new(max_packet_size: u32) -> Self {
+        Self {
+            max_packet_size: max_packet_size as usize,
+            state: FrameState::Header,
+        }
+    }
+
+    /// Feeds the next bytes of the stream.
+    pub fn observe(&mut self, mut bytes: &[u8]) -> Result<(), PacketSizeError> {
+        while let Some((&byte, rest)) = bytes.split_first() {
+            match self.state {
+                FrameState::Header => {
+                    self.state = FrameState::Length {
+                        value: 0,
+                        multiplier: 1,
+                        used: 0,
+                    };
+                    bytes = rest;
+                }
+                FrameState::Length {
+                    value,
+                    multiplier,
+                    used,
+                } => {
+                    let value = value + (byte & 0x7F) as usize * multiplier;
+                    let used = used + 1;
+                    if byte & 0x80 != 0 {
+                        if used == 4 {
+                            return Err(PacketSizeError::MalformedLength);
+                        }
+                        self.state = FrameState::Length {
+                            value,
+                            multiplier: multiplier * 128,
+                            used,
+                        };
+                    } else {
+                        let total = 1 + used + value;
+                        if total > self.max_packet_size {
+                            return Err(PacketSizeError::TooLarge(total));
+                        }
+                        self.state = if value == 0 {
+                            FrameState::Header
+                        } else {
+                            FrameState::Body { remaining: value }
+                        };
+                    }
+                    bytes = rest;
+                }
+                FrameState::Body { remaining } => {
+                    let take = remaining.min(bytes.len());
+                    bytes = &bytes[take..];
+                    self.state = if take == remaining {
+                        FrameState::Header
+                    } else {
+                        FrameState::Body {
+                            remaining: remaining - take,
+                        }
+                    };
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A PUBLISH rejected by `PublishTracker`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PublishError {
+    /// The topic name failed the caller's check.
+    Denied(String),
+    /// The payload is longer than the limit; carries its length.
+    PayloadTooLarge(usize),
+    /// A PUBLISH whose variable header overruns the packet or whose topic
+    /// name is not UTF-8, or a Remaining Length longer than four bytes.
+    Malformed,
+}
+
+#[derive(Debug, Clone)]
+enum PublishState {
+    Header,
+    /// Collecting the Remaining Length bytes; `publish` holds the fixed
+    /// header byte of a PUBLISH.
+    Length {
+        publish: Option<u8>,
+        len: Vec<u8>,
+    },
+    /// Collecting a PUBLISH variable header up to the end of its properties
+    /// length; `remaining` counts the packet body left including it.
+    Variable {
+        flags: u8,
+        header: Vec<u8>,
+        remaining: usize,
+    },
+    Body {
+        remaining: usize,
+    },
+}
+
+/// Where a partially collected PUBLISH variable header stands.
+enum VariableHeader {
+    /// More bytes are needed until the buffer is this long.
+    Needs(usize),
+    /// Complete: the variable header spans `len` bytes of the packet, the
+    /// topic name `2..topic_end` of the buffer.
+    Done { len: usize, topic_end: usize },
+}
+
+/// Follows MQTT packet boundaries across a byte stream, like
+/// `PacketSizeTracker`, and checks every PUBLISH as soon as its variable
+/// header is complete: the topic name against the caller's check and the
+/// payload length against `max_payload`. Only variable headers are buffered,
+/// never payloads. The stream must start at a packet boundary.
+///
+/// An empty topic name (an MQTT 5.0 topic alias) is not checked: the alias
+/// was bound by an earlier PUBLISH whose topic name was.
+#[derive(Debug, Clone)]
+pub struct PublishTracker {
+    v5: bool,
+    max_payload: Option<usize>,
+    state: PublishState,
+}
+
+impl PublishTracker {
+    /// `v5` selects the MQTT 5.0 variable header, which carries properties.
+    pub fn new(v5: bool, max_payload: Option<usize>) -> Self {
+        Self {
+            v5,
+            max_payload,
+            state: PublishState::Header,
+        }
+    }
+
+    /// Feeds the next bytes of the stream; `permits` is asked about each
+    /// complete topic name.
+    pub fn observe(
+        &mut self,
+        mut bytes: &[u8],
+        mut permits: impl FnMut(&str) -> bool,
+    ) -> Result<(), PublishError> {
+        loop {
+            // A variable header can complete exactly at the end of `bytes`.
+            if bytes.is_empty() && !matches!(self.state, PublishState::Variable { .. }) {
+                return Ok(());
+            }
+            match &mut self.state {
+                PublishState::Header => {
+                    let publish = inspect_packet(&bytes[..1]) == MqttPacketType::Publish;
+                    self.state = PublishState::Length {
+                        publish: publish.then_some(bytes[0]),
+                        len: Vec::with_capacity(4),
+                    };
+                    bytes = &bytes[1..];
+                }
+                PublishState::Length { publish, len } => {
+                    len.push(bytes[0]);
+                    bytes = &bytes[1..];
+                    let remaining = match decode_remaining_length(len) {
+                        Ok((value, _)) => value,
+                        Err("Incomplete") => continue,
+                        Err(_) => return Err(PublishError::Malformed),
+                    };
+                    self.state = match *publish {
+                        Some(flags) => PublishState::Variable {
+                            flags,
+                            header: Vec::new(),
+                            remaining,
+                        },
+                        None => body(remaining),
+                    };
+                }
+                PublishState::Variable {
+                    flags,
+                    header,
+                    remaining,
+                } => match variable_header(header, *flags, self.v5)? {
+                    VariableHeader::Needs(wanted) => {
+                        if wanted > *remaining {
+                            return Err(PublishError::Malformed);
+                        }
+                        if bytes.is_empty() {
+                            return Ok(());
+                        }
+                        let take = (wanted - header.len()).min(bytes.len());
+                        header.extend_from_slice(&bytes[..take]);
+                        bytes = &bytes[take..];
+                    }
+                    VariableHeader::Done { len, topic_end } => {
+                        if len > *remaining {
+                            return Err(PublishError::Malformed);
+                        }
+                        if topic_end > 2 {
+                            let topic = std::str::from_utf8(&header[2..topic_end])
+                                .map_err(|_| PublishError::Malformed)?;
+                            if !permits(topic) {
+                                return Err(PublishError::Denied(topic.to_string()));
+                            }
+                        }
+                        let payload = *remaining - len;
+                        if self.max_payload.is_some_and(|max| payload > max) {
+                            return Err(PublishError::PayloadTooLarge(payload));
+                        }
+                        // The properties themselves are skipped, not buffered.
+                        self.state = body(*remaining - header.len());
+                    }
+                },
+                PublishState::Body { remaining } => {
+                    let take = (*remaining).min(bytes.len());
+                    bytes = &bytes[take..];
+                    self.state = body(*remaining - take);
+                }
+            }
+        }
+    }
+}
+
+/// Parses as much of a PUBLISH variable header as `header` holds: the topic
+/// name, the packet identifier when QoS > 0, and (MQTT 5.0) the properties
+/// length.
+fn variable_header(header: &[u8], flags: u8, v5: bool) -> Result<VariableHeader, PublishError> {
+    let Some(len) = header.get(..2) else {
+        return Ok(VariableHeader::Needs(2));
+    };
+    let topic_end = 2 + u16::from_be_bytes([len[0], len[1]]) as usize;
+    let packet_id = if (flags >> 1) & 0x03 != 0 { 2 } else { 0 };
+    let properties = topic_end + packet_id;
+    if !v5 {
+        return Ok(if header.len() < properties {
+            VariableHeader::Needs(properties)
+        } else {
+            VariableHeader::Done {
+                len: properties,
+                topic_end,
+            }
+        });
+    }
+    if header.len() <= properties {
+        return Ok(VariableHeader::Needs(properties + 1));
+    }
+    match decode_remaining_length(&header[properties..]) {
+        Ok((value, used)) => Ok(VariableHeader::Done {
+            len: properties + used + value,
+            topic_end,
+        }),
+        Err("Incomplete") => Ok(VariableHeader::Needs(header.len() + 1)),
+        Err(_) => Err(PublishError::Malformed),
+    }
+}
+
+fn body(remaining: usize) -> PublishState {
+    if remaining == 0 {
+        PublishState::Header
+    } else {
+        PublishState::Body { remaining }
     }
 }
+
+/// Reasons AegisGate may refuse a CONNECT with, mapped to the right CONNACK
+/// code for the client's protocol version.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnackRefusal {
+    /// Temporary refusal while the proxy is shutting down.
+    ServerBusy,
+    /// The client was admitted but no backend could be reached.
+    ServerUnavailable,
+    /// The CONNECT was not well formed.
+    MalformedPacket,
+    /// The CONNECT was well formed but violates a configured policy.
+    PolicyViolation,
+    /// The CONNECT lacks credentials the proxy requires.
+    NotAuthorized,
+    /// The CONNECT's protocol level is not accepted.
+    UnsupportedProtocolVersion,
+}
+
+impl ConnackRefusal {
+    /// MQTT 3.1/3.1.1 CONNACK return code, if 3.1.1 has one for this refusal.
+    fn v3_return_code(self) -> Option<u8> {
+        match self {
+            ConnackRefusal::ServerBusy | ConnackRefusal::ServerUnavailable => Some(0x03), // Server unavailable
+            ConnackRefusal::NotAuthorized => Some(0x05), // Not authorized
+            ConnackRefusal::UnsupportedProtocolVersion => Some(0x01), // Unacceptable protocol version
+            ConnackRefusal::MalformedPacket | ConnackRefusal::PolicyViolation => None,
+        }
+    }
+
+    /// MQTT 5.0 CONNACK reason code.
+    fn v5_reason_code(self) -> u8 {
+        match self {
+            ConnackRefusal::ServerBusy => 0x89,
+            ConnackRefusal::ServerUnavailable => 0x88,
+            ConnackRefusal::MalformedPacket => 0x81,
+            ConnackRefusal::PolicyViolation => 0x83, // Implementation specific error
+            ConnackRefusal::NotAuthorized => 0x87,
+            ConnackRefusal::UnsupportedProtocolVersion => 0x84,
+        }
+    }
+}
+
+/// MQTT 5.0 Reason String identifier.
+const PROPERTY_REASON_STRING: u8 = 0x1F;
+
+/// Longest Reason String (in bytes) AegisGate sends; longer reasons are cut.
+pub const MAX_REASON_STRING_LEN: usize = 128;
+
+/// Encodes a refusing CONNACK for a client speaking `protocol_level`.
+///
+/// MQTT 5.0 clients receive a reason code plus an empty property block;
+/// all other levels get the 3.1.1 two-byte variable header. Returns `None`
+/// when 3.1.1 has no return code for `refusal`; such clients are just closed.
+pub fn encode_connack(protocol_level: u8, refusal: ConnackRefusal) -> Option<Vec<u8>> {
+    encode_connack_with_reason(protocol_level, refusal, None)
+}
+
+/// Like `encode_connack`, but MQTT 5.0 clients also get `reason` as a Reason
+/// String property (truncated to `MAX_REASON_STRING_LEN` bytes). Earlier
+/// levels have no reason string, so it is ignored for them.
+pub fn encode_connack_with_reason(
+    protocol_level: u8,
+    refusal: ConnackRefusal,
+    reason: Option<&str>,
+) -> Option<Vec<u8>> {
+    if protocol_level != 5 {
+        return Some(vec![0x20, 0x02, 0x00, refusal.v3_return_code()?]);
+    }
+
+    let mut properties = Vec::new();
+    if let Some(reason) = reason {
+        let mut end = reason.len().min(MAX_REASON_STRING_LEN);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        properties.push(PROPERTY_REASON_STRING);
+        properties.extend_from_slice(&(end as u16).to_be_bytes());
+        properties.extend_from_slice(&reason.as_bytes()[..end]);
+    }
+
+    let mut body = vec![0x00, refusal.v5_reason_code()];
+    body.extend_from_slice(&encode_remaining_length(properties.len()));
+    body.extend_from_slice(&properties);
+
+    let mut out = Vec::with_capacity(body.len() + 2);
+    out.push(0x20);
+    out.extend_from_slice(&encode_remaining_length(body.len()));
+    out.extend_from_slice(&body);
+    Some(out)
+}
+
+/// Encode a value using the MQTT variable byte integer scheme (the inverse of
+/// `decode_remaining_length`). Values above 268_435_455 are not representable.
+pub fn encode_remaining_length(mut value: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4);
+    loop {
+        let mut byte = (value % 128) as u8;
+        value /= 128;
+        if value > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if value == 0 {
+            return out;
+        }
+    }
+}
+
+/// MQTT 5.0 User Property identifier.
+const PROPERTY_USER_PROPERTY: u8 = 0x26;
+
+/// Append a User Property (`key`, `value`) to the property block of a complete
+/// MQTT 5.0 CONNECT frame (fixed header included), fixing up both the property
+/// length and the Remaining Length. User Properties the client already sent
+/// under `key` are dropped, so the broker only ever sees ours.
+///
+/// Returns `None` if the frame is not a well-formed v5 CONNECT or a string is
+/// too long to encode, in which case the caller should forward it untouched.
+pub fn inject_user_property(packet: &[u8], key: &str, value: &str) -> Option<Vec<u8>> {
+    if key.len() > u16::MAX as usize || value.len() > u16::MAX as usize {
+        return None;
+    }
+    let (remaining, rl_used) = decode_remaining_length(packet.get(1..)?).ok()?;
+    let body = packet.get(1 + rl_used..)?;
+    if body.len() != remaining {
+        return None;
+    }
+
+    // Protocol name, level, connect flags, keep-alive
+    let name_len = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+    if *body.get(2 + name_len)? != 5 {
+        return None;
+    }
+    let props_at = 2 + name_len + 4;
+    let (props_len, props_used) = decode_remaining_length(body.get(props_at..)?).ok()?;
+    let props_end = props_at + props_used + props_len;
+    let mut existing = body.get(props_at + props_used..props_end)?;
+    let mut kept = Vec::with_capacity(existing.len());
+    while let Some((&id, rest)) = existing.split_first() {
+        let len = property_value_len(id, rest)?;
+        let spoofed = id == PROPERTY_USER_PROPERTY
+            && rest.get(2..2 + key.len()) == Some(key.as_bytes())
+            && u16::from_be_bytes([rest[0], rest[1]]) as usize == key.len();
+        if !spoofed {
+            kept.extend_from_slice(&existing[..1 + len]);
+        }
+        existing = &rest[len..];
+    }
+
+    let mut property = Vec::with_capacity(5 + key.len() + value.len());
+    property.push(PROPERTY_USER_PROPERTY);
+    property.extend_from_slice(&(key.len() as u16).to_be_bytes());
+    property.extend_from_slice(key.as_bytes());
+    property.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    property.extend_from_slice(value.as_bytes());
+
+    let mut new_body = Vec::with_capacity(body.len() + property.len() + 4);
+    new_body.extend_from_slice(&body[..props_at]);
+    new_body.extend_from_slice(&encode_remaining_length(kept.len() + property.len()));
+    new_body.extend_from_slice(&kept);
+    new_body.extend_from_slice(&property);
+    new_body.extend_from_slice(&body[props_end..]);
+
+    let mut out = Vec::with_capacity(new_body.len() + 5);
+    out.push(packet[0]);
+    out.extend_from_slice(&encode_remaining_length(new_body.len()));
+    out.extend_from_slice(&new_body);
+    Some(out)
+}
@@ -0,0 +1,81 @@
+/// TLS record content type of a handshake record.
+pub const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+/// Bytes in a TLS record header: type, version and length.
+pub const RECORD_HEADER_LEN: usize = 5;
+/// Largest record fragment a peer may send (2^14 bytes, RFC 8446 5.1).
+pub const MAX_RECORD_LEN: usize = 16 * 1024;
+
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+const NAME_TYPE_HOST_NAME: u8 = 0x00;
+
+/// Fragment length announced by a TLS handshake record header.
+///
+/// Returns `None` unless `header` starts with a handshake record of a known
+/// version (major version 3) whose length fits in a single record.
+pub fn handshake_record_len(header: &[u8]) -> Option<usize> {
+    let header = header.get(..RECORD_HEADER_LEN)?;
+    if header[0] != CONTENT_TYPE_HANDSHAKE || header[1] != 3 {
+        return None;
+    }
+    let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+    (len > 0 && len <= MAX_RECORD_LEN).then_some(len)
+}
+
+/// Reads the server name (SNI) from a TLS ClientHello.
+///
+/// `buf` starts at the record header and must hold the whole ClientHello.
+/// Returns the first `host_name` entry, lowercased and without a trailing
+/// dot, or `None` if the record is not a ClientHello, is truncated, or
+/// carries no server name.
+pub fn parse_tls_client_hello(buf: &[u8]) -> Option<String> {
+    let len = handshake_record_len(buf)?;
+    let record = buf.get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + len)?;
+    if *record.first()? != HANDSHAKE_CLIENT_HELLO {
+        return None;
+    }
+    let hello_len = u32::from_be_bytes([0, *record.get(1)?, *record.get(2)?, *record.get(3)?]);
+    let hello = record.get(4..4 + hello_len as usize)?;
+
+    // Legacy version and random.
+    let mut at = 2 + 32;
+    // Legacy session id, cipher suites, legacy compression methods.
+    at += 1 + *hello.get(at)? as usize;
+    at += 2 + u16_at(hello, at)? as usize;
+    at += 1 + *hello.get(at)? as usize;
+
+    let extensions_len = u16_at(hello, at)? as usize;
+    let mut extensions = hello.get(at + 2..at + 2 + extensions_len)?;
+    while !extensions.is_empty() {
+        let kind = u16_at(extensions, 0)?;
+        let data_len = u16_at(extensions, 2)? as usize;
+        let data = extensions.get(4..4 + data_len)?;
+        if kind == EXTENSION_SERVER_NAME {
+            return server_name(data);
+        }
+        extensions = &extensions[4 + data_len..];
+    }
+    None
+}
+
+/// First `host_name` entry of a server_name extension body.
+fn server_name(data: &[u8]) -> Option<String> {
+    let list_len = u16_at(data, 0)? as usize;
+    let mut list = data.get(2..2 + list_len)?;
+    while !list.is_empty() {
+        let name_type = *list.first()?;
+        let name_len = u16_at(list, 1)? as usize;
+        let name = list.get(3..3 + name_len)?;
+        if name_type == NAME_TYPE_HOST_NAME {
+            let name = std::str::from_utf8(name).ok()?;
+            let name = name.strip_suffix('.').unwrap_or(name);
+            return (!name.is_empty() && name.is_ascii()).then(|| name.to_ascii_lowercase());
+        }
+        list = &list[3 + name_len..];
+    }
+    None
+}
+
+fn u16_at(buf: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(at)?, *buf.get(at + 1)?]))
+}
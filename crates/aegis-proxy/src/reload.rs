@@ -0,0 +1,106 @@
+//! Configuration reload.
+//!
+//! A reload re-reads the configuration file and swaps in a whole new set of
+//! per-listener settings at once, so a connection never mixes old and new
+//! values. Some settings are bound when the proxy starts: changing the set of
+//! listeners rejects the reload, while changes to the others are applied to
+//! nothing and logged as needing a restart.
+
+use aegis_common::{load_config, Config};
+use arc_swap::ArcSwap;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Re-reads the configuration at `path` and, if it is valid and keeps the
+/// listeners `startup` was started with, stores the per-listener settings
+/// `build` derives from it (given each listener's new and running
+/// configuration) in `live`. Otherwise the running settings stay in place
+/// and the error is returned.
+pub fn reload<T>(
+    path: &Path,
+    live: &ArcSwap<Vec<T>>,
+    startup: &Config,
+    build: impl Fn(Config, &Config) -> Result<T, String>,
+) -> Result<(), String> {
+    let started = startup.listener_configs();
+    let next = load_config(path)
+        .map_err(|e| e.to_string())
+        .and_then(|config| {
+            config.validate().map_err(|problems| {
+                let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+                problems.join("; ")
+            })?;
+            let listeners = config.listener_configs();
+            if listeners
+                .iter()
+                .map(|(name, _)| name)
+                .ne(started.iter().map(|(name, _)| name))
+            {
+                return Err("adding or removing proxy.listeners needs a restart".to_string());
+            }
+            let moved: Vec<&String> = listeners
+                .iter()
+                .zip(&started)
+                .filter(|((_, next), (_, running))| {
+                    next.proxy.listen_address != running.proxy.listen_address
+                })
+                .map(|((name, _), _)| name)
+                .collect();
+            let built = listeners
+                .iter()
+                .zip(&started)
+                .map(|((_, next), (_, running))| build(next.clone(), running))
+                .collect::<Result<Vec<_>, String>>()?;
+            for listener in moved {
+                warn!(
+                    listener = %listener,
+                    setting = "listen address",
+                    "Changed setting needs a restart to take effect"
+                );
+            }
+            for setting in restart_required(&config, startup) {
+                warn!(setting, "Changed setting needs a restart to take effect");
+            }
+            Ok(built)
+        });
+    match next {
+        Ok(next) => {
+            live.store(Arc::new(next));
+            crate::metrics::CONFIG_RELOADS
+                .with_label_values(&["applied"])
+                .inc();
+            info!("Configuration reloaded");
+            Ok(())
+        }
+        Err(e) => {
+            crate::metrics::CONFIG_RELOADS
+                .with_label_values(&["rejected"])
+                .inc();
+            error!(error = %e, "Configuration reload rejected; keeping the running configuration");
+            Err(e)
+        }
+    }
+}
+
+/// Settings that differ between `next` and `startup` but are only read at
+/// startup: the backend pool and its circuit breaker, the limiter backend
+/// and the admin endpoints.
+pub fn restart_required(next: &Config, startup: &Config) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if next.proxy.target_addresses != startup.proxy.target_addresses {
+        changed.push("proxy.target_addresses");
+    }
+    if next.proxy.backend_circuit != startup.proxy.backend_circuit {
+        changed.push("proxy.backend_circuit");
+    }
+    if next.limit.backend != startup.limit.backend
+        || next.limit.redis_url != startup.limit.redis_url
+    {
+        changed.push("limit.backend");
+    }
+    if next.metrics.expose_admin_endpoints != startup.metrics.expose_admin_endpoints {
+        changed.push("metrics.expose_admin_endpoints");
+    }
+    changed
+}
@@ -0,0 +1,32 @@
+use aegis_proxy::engine::accept_filter::{self, AcceptDecision};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+#[test]
+fn installed_filter_decides_and_cannot_be_replaced() {
+    let blocked: SocketAddr = "192.0.2.1:4000".parse().unwrap();
+    let trusted: SocketAddr = "192.0.2.2:4000".parse().unwrap();
+    let other: SocketAddr = "192.0.2.3:4000".parse().unwrap();
+
+    assert!(!accept_filter::is_installed());
+    assert_eq!(accept_filter::decide(blocked), AcceptDecision::Defer);
+    assert_eq!((accept_filter::no_op())(blocked), AcceptDecision::Defer);
+
+    let installed = accept_filter::set_accept_filter(Arc::new(move |addr| {
+        if addr == blocked {
+            AcceptDecision::Reject
+        } else if addr == trusted {
+            AcceptDecision::Accept
+        } else {
+            AcceptDecision::Defer
+        }
+    }));
+    assert!(installed.is_ok());
+    assert!(accept_filter::is_installed());
+    assert_eq!(accept_filter::decide(blocked), AcceptDecision::Reject);
+    assert_eq!(accept_filter::decide(trusted), AcceptDecision::Accept);
+    assert_eq!(accept_filter::decide(other), AcceptDecision::Defer);
+
+    assert!(accept_filter::set_accept_filter(accept_filter::no_op()).is_err());
+    assert_eq!(accept_filter::decide(blocked), AcceptDecision::Reject);
+}
@@ -0,0 +1,158 @@
+use aegis_common::BackendCircuitConfig;
+use aegis_proxy::engine::backend::{connect, BackendErrorClass, BackendHealth, BackendSelector};
+use aegis_proxy::metrics::BACKEND_HEALTHY;
+use std::io;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+#[test]
+fn connect_errors_are_classified_by_kind() {
+    let cases = [
+        (io::ErrorKind::ConnectionRefused, BackendErrorClass::Refused),
+        (io::ErrorKind::TimedOut, BackendErrorClass::Timeout),
+        (
+            io::ErrorKind::HostUnreachable,
+            BackendErrorClass::Unreachable,
+        ),
+        (
+            io::ErrorKind::NetworkUnreachable,
+            BackendErrorClass::Unreachable,
+        ),
+        (io::ErrorKind::PermissionDenied, BackendErrorClass::Other),
+    ];
+    for (kind, class) in cases {
+        assert_eq!(
+            BackendErrorClass::of_connect_error(&io::Error::from(kind)),
+            class
+        );
+    }
+    assert!(BackendErrorClass::Refused.is_retryable());
+    assert!(!BackendErrorClass::Dns.is_retryable());
+}
+
+#[tokio::test]
+async fn closed_port_is_refused() {
+    let addr = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    let err = connect(&addr, Duration::from_secs(5)).await.unwrap_err();
+    assert_eq!(err.class, BackendErrorClass::Refused);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn missing_unix_socket_is_refused() {
+    let path = std::env::temp_dir().join(format!("aegis-missing-{}.sock", std::process::id()));
+    let err = connect(&format!("unix:{}", path.display()), Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    assert_eq!(err.class, BackendErrorClass::Refused);
+}
+
+#[tokio::test]
+async fn unresolvable_name_is_a_dns_failure() {
+    // `.invalid` is reserved and never resolves (RFC 6761).
+    let err = connect("broker.invalid:1883", Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.class,
+            BackendErrorClass::Dns | BackendErrorClass::Timeout
+        ),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn selector_rotates_the_starting_backend() {
+    assert!(BackendSelector::new(Vec::new()).is_none());
+    let selector = BackendSelector::new(vec![
+        "a:1".to_string(),
+        "b:1".to_string(),
+        "c:1".to_string(),
+    ])
+    .unwrap();
+    let orders: Vec<Vec<&str>> = (0..4).map(|_| selector.rotation().collect()).collect();
+    assert_eq!(
+        orders,
+        [
+            ["a:1", "b:1", "c:1"],
+            ["b:1", "c:1", "a:1"],
+            ["c:1", "a:1", "b:1"],
+            ["a:1", "b:1", "c:1"],
+        ]
+    );
+}
+
+#[test]
+fn weighted_selector_interleaves_heavier_backends() {
+    let selector =
+        BackendSelector::weighted(vec![("a:1".to_string(), 1), ("b:1".to_string(), 3)]).unwrap();
+    let starts: Vec<&str> = (0..8)
+        .map(|_| selector.rotation().next().unwrap())
+        .collect();
+    assert_eq!(
+        starts,
+        ["b:1", "a:1", "b:1", "b:1", "b:1", "a:1", "b:1", "b:1"]
+    );
+    // Whatever the start, every backend is still tried once.
+    assert_eq!(selector.rotation().collect::<Vec<_>>(), ["b:1", "a:1"]);
+}
+
+#[tokio::test]
+async fn circuit_opens_after_repeated_failures_and_probes_after_cooldown() {
+    let health = BackendHealth::new(&BackendCircuitConfig {
+        failure_threshold: 2,
+        window_ms: 10_000,
+        cooldown_ms: 50,
+        reject_at_accept: false,
+        busy_signal: false,
+    });
+    let target = "circuit-test:1883";
+    assert!(health.allow(target));
+    health.record_failure(target);
+    assert!(!health.is_open(target));
+    health.record_failure(target);
+    assert!(health.is_open(target));
+    assert!(!health.allow(target));
+    assert_eq!(BACKEND_HEALTHY.with_label_values(&[target]).get(), 0);
+
+    // After the cooldown exactly one probe is let through; a failed probe
+    // reopens the circuit.
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert!(health.allow(target));
+    assert!(!health.allow(target));
+    health.record_failure(target);
+    assert!(!health.allow(target));
+
+    // A successful probe closes it.
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert!(health.allow(target));
+    health.record_success(target);
+    assert!(!health.is_open(target));
+    assert!(health.allow(target));
+    assert!(health.allow(target));
+    assert_eq!(BACKEND_HEALTHY.with_label_values(&[target]).get(), 1);
+}
+
+#[test]
+fn all_skipped_only_while_every_circuit_is_open() {
+    let health = BackendHealth::new(&BackendCircuitConfig {
+        failure_threshold: 1,
+        window_ms: 10_000,
+        cooldown_ms: 60_000,
+        reject_at_accept: true,
+        busy_signal: false,
+    });
+    let targets = ["skip-a:1883", "skip-b:1883"];
+    assert!(!health.all_skipped(targets));
+    health.record_failure(targets[0]);
+    assert!(!health.all_skipped(targets));
+    health.record_failure(targets[1]);
+    assert!(health.all_skipped(targets));
+    health.record_success(targets[1]);
+    assert!(!health.all_skipped(targets));
+}
@@ -0,0 +1,40 @@
+use aegis_common::CaptureConfig;
+use aegis_proxy::engine::capture::PacketCapture;
+
+#[tokio::test]
+async fn capture_writes_only_triggered_reasons_within_budget() {
+    let path = std::env::temp_dir().join(format!("aegis-capture-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let config = CaptureConfig {
+        enabled: true,
+        reasons: vec!["malformed_connect".to_string()],
+        max_bytes_per_connection: 4,
+        max_captures_per_minute: 1,
+        path: Some(path.to_string_lossy().into_owned()),
+    };
+    let (capture, writer) = PacketCapture::new(&config);
+    let writer = tokio::spawn(writer);
+
+    // Not a configured trigger
+    capture.record("http_detected", "10.0.0.1:1000", b"GET / HTTP/1.1");
+    // Captured, truncated to 4 bytes
+    capture.record(
+        "malformed_connect",
+        "10.0.0.2:2000",
+        b"\x10\x05\x00\x04XYZW",
+    );
+    // Over the per-minute budget
+    capture.record("malformed_connect", "10.0.0.3:3000", b"\x10\x05");
+
+    drop(capture);
+    writer.await.unwrap();
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 1, "unexpected captures: {:?}", lines);
+    assert!(lines[0].contains("client=10.0.0.2:2000"));
+    assert!(lines[0].contains("reason=malformed_connect"));
+    assert!(lines[0].contains("len=8 captured=4 hex=10050004"));
+}
@@ -0,0 +1,115 @@
+use aegis_common::{AccessControlConfig, RegionFilterConfig, RegionPolicy};
+use aegis_proxy::engine::cidr::{
+    AccessControl, AclRejection, CidrSet, RegionFilter, UNLISTED_REGION,
+};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn region_config(policy: RegionPolicy) -> RegionFilterConfig {
+    let mut regions = BTreeMap::new();
+    regions.insert(
+        "eu".to_string(),
+        vec!["192.0.2.0/24".to_string(), "2001:db8:1::/48".to_string()],
+    );
+    regions.insert("office".to_string(), vec!["198.51.100.7".to_string()]);
+    RegionFilterConfig { policy, regions }
+}
+
+#[test]
+fn cidr_set_matches_v4_v6_and_host_entries() {
+    let set = CidrSet::parse(&["10.0.0.0/8", "2001:db8::/32", "203.0.113.9"]).unwrap();
+    assert!(set.contains(ip("10.1.2.3")));
+    assert!(set.contains(ip("2001:db8::1")));
+    assert!(set.contains(ip("203.0.113.9")));
+    assert!(!set.contains(ip("203.0.113.10")));
+    assert!(!set.contains(ip("11.0.0.1")));
+}
+
+#[test]
+fn cidr_set_matches_ipv4_mapped_ipv6_clients() {
+    let set = CidrSet::parse(&["10.0.0.0/8"]).unwrap();
+    assert!(set.contains(ip("::ffff:10.0.0.1")));
+}
+
+#[test]
+fn cidr_set_rejects_invalid_entries() {
+    let err = CidrSet::parse(&["10.0.0.0/33"]).unwrap_err();
+    assert!(err.contains("10.0.0.0/33"));
+}
+
+#[test]
+fn allow_listed_policy_rejects_unlisted_clients() {
+    let filter = RegionFilter::from_config(&region_config(RegionPolicy::AllowListed)).unwrap();
+    assert_eq!(filter.check(ip("192.0.2.10")), Ok(()));
+    assert_eq!(filter.check(ip("2001:db8:1::5")), Ok(()));
+    assert_eq!(filter.check(ip("8.8.8.8")), Err(UNLISTED_REGION));
+}
+
+#[test]
+fn deny_listed_policy_reports_the_matching_region() {
+    let filter = RegionFilter::from_config(&region_config(RegionPolicy::DenyListed)).unwrap();
+    assert_eq!(filter.check(ip("198.51.100.7")), Err("office"));
+    assert_eq!(filter.check(ip("192.0.2.1")), Err("eu"));
+    assert_eq!(filter.check(ip("8.8.8.8")), Ok(()));
+}
+
+fn acl(allow: &[&str], deny: &[&str]) -> AccessControl {
+    AccessControl::from_config(&AccessControlConfig {
+        allow_cidrs: allow.iter().map(|s| s.to_string()).collect(),
+        deny_cidrs: deny.iter().map(|s| s.to_string()).collect(),
+    })
+    .unwrap()
+}
+
+#[test]
+fn access_control_deny_wins_over_overlapping_allow() {
+    let acl = acl(&["10.0.0.0/8"], &["10.1.0.0/16", "10.1.2.0/24"]);
+    assert_eq!(acl.check(ip("10.2.0.1")), Ok(()));
+    assert_eq!(
+        acl.check(ip("10.1.9.9")),
+        Err(AclRejection::Denied("10.1.0.0/16".parse().unwrap()))
+    );
+    // The most specific deny rule is reported.
+    assert_eq!(
+        acl.check(ip("10.1.2.3")),
+        Err(AclRejection::Denied("10.1.2.0/24".parse().unwrap()))
+    );
+    assert_eq!(acl.check(ip("192.0.2.1")), Err(AclRejection::NotAllowed));
+}
+
+#[test]
+fn access_control_empty_allow_list_admits_all_but_denied() {
+    let acl = acl(&[], &["203.0.113.0/24", "2001:db8:bad::/48"]);
+    assert_eq!(acl.check(ip("198.51.100.1")), Ok(()));
+    assert_eq!(acl.check(ip("2001:db8:1::1")), Ok(()));
+    assert!(acl.check(ip("203.0.113.5")).is_err());
+    assert!(acl.check(ip("2001:db8:bad::1")).is_err());
+}
+
+#[test]
+fn access_control_matches_ipv4_mapped_addresses() {
+    let acl = acl(&["192.0.2.0/24"], &["192.0.2.66"]);
+    assert_eq!(acl.check(ip("::ffff:192.0.2.10")), Ok(()));
+    assert!(matches!(
+        acl.check(ip("::ffff:192.0.2.66")),
+        Err(AclRejection::Denied(_))
+    ));
+    assert_eq!(
+        acl.check(ip("::ffff:198.51.100.1")),
+        Err(AclRejection::NotAllowed)
+    );
+}
+
+#[test]
+fn access_control_rejects_invalid_entries() {
+    let err = AccessControl::from_config(&AccessControlConfig {
+        allow_cidrs: vec![],
+        deny_cidrs: vec!["10.0.0.0/33".to_string()],
+    })
+    .unwrap_err();
+    assert!(err.contains("deny_cidrs"));
+}
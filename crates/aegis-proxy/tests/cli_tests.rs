@@ -0,0 +1,57 @@
+use aegis_proxy::cli::{config_path, CONFIG_PATH};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+fn path(args: &[&str], env: Option<&str>) -> Result<Option<PathBuf>, String> {
+    config_path(args.iter().map(OsString::from), env.map(OsString::from))
+}
+
+fn some(p: &str) -> Result<Option<PathBuf>, String> {
+    Ok(Some(PathBuf::from(p)))
+}
+
+#[test]
+fn config_flag_in_every_form() {
+    assert_eq!(path(&["--config", "a.yaml"], None), some("a.yaml"));
+    assert_eq!(path(&["-c", "b.toml"], None), some("b.toml"));
+    assert_eq!(path(&["--config=c.json"], None), some("c.json"));
+    // The last one wins.
+    assert_eq!(
+        path(&["-c", "a.yaml", "--config=b.yaml"], None),
+        some("b.yaml")
+    );
+}
+
+#[test]
+fn environment_then_default_without_a_flag() {
+    assert_eq!(
+        path(&[], Some("/etc/aegis/env.yaml")),
+        some("/etc/aegis/env.yaml")
+    );
+    assert_eq!(path(&[], Some("")), some(CONFIG_PATH));
+    assert_eq!(path(&[], None), some(CONFIG_PATH));
+}
+
+#[test]
+fn flag_takes_precedence_over_environment() {
+    assert_eq!(
+        path(&["--config", "flag.yaml"], Some("env.yaml")),
+        some("flag.yaml")
+    );
+    assert_eq!(
+        path(&["--config=flag.yaml"], Some("env.yaml")),
+        some("flag.yaml")
+    );
+}
+
+#[test]
+fn help_and_usage_errors() {
+    assert_eq!(path(&["--help"], None), Ok(None));
+    assert_eq!(path(&["-h", "--config", "a.yaml"], None), Ok(None));
+    assert!(path(&["--config"], None)
+        .unwrap_err()
+        .contains("needs a path"));
+    assert!(path(&["serve"], None)
+        .unwrap_err()
+        .contains("unexpected argument 'serve'"));
+}
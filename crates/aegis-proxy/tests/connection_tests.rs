@@ -0,0 +1,2236 @@
+use aegis_common::{
+    AccessControlConfig, BackendCircuitConfig, CaptureConfig, FeaturesConfig, HttpInspectionConfig,
+    KeepAliveAction, LimitConfig, MetricTagsConfig, MqttPolicyConfig, ProtocolBackends,
+    RegionFilterConfig, RegionPolicy, RejectCategory, SignatureFastPathConfig, SlowlorisConfig,
+    SourcePolicyConfig, TlsConfig,
+};
+use aegis_proxy::engine::access_log::AccessLog;
+use aegis_proxy::engine::backend::{BackendHealth, BackendSelector};
+use aegis_proxy::engine::capture::PacketCapture;
+use aegis_proxy::engine::cidr::{AccessControl, RegionFilter};
+use aegis_proxy::engine::connection::{
+    handle_connection, ConnectionConfig, ProxiedChecks, SourceChecks,
+};
+use aegis_proxy::engine::limiter::{concurrent_connections, InspectionLimiter};
+use aegis_proxy::engine::policy::SourcePolicy;
+use aegis_proxy::engine::proxy_protocol::V2_SIGNATURE;
+use aegis_proxy::engine::signature::SignatureSet;
+use aegis_proxy::engine::tags::{ConnectionTags, TagSet};
+use aegis_proxy::engine::tls::{load_acceptor, HandshakeLimit, TlsClient};
+use aegis_proxy::engine::topics::TopicRules;
+use aegis_proxy::engine::trace::DecisionTrace;
+use aegis_proxy::parser::mqtt::encode_remaining_length;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+/// Minimal MQTT 3.1.1 CONNECT (clean session, keep-alive 60, client id "c").
+const CONNECT: &[u8] = &[
+    0x10, 0x0d, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x02, 0x00, 0x3c, 0x00, 0x01, b'c',
+];
+
+fn slowloris_config() -> SlowlorisConfig {
+    SlowlorisConfig {
+        first_packet_timeout_ms: 1000,
+        packet_idle_timeout_ms: 1000,
+        connection_timeout_ms: 5000,
+        mqtt_connect_timeout_ms: 1000,
+        mqtt_packet_timeout_ms: 1000,
+        mqtt_fixed_header_timeout_ms: 3000,
+        mqtt_remaining_length_byte_timeout_ms: 1000,
+        mqtt_connect_payload_timeout_ms: 5000,
+        http_request_timeout_ms: 1000,
+        max_http_header_size: 8192,
+        max_http_header_count: 100,
+        handshake_deadline_ms: None,
+        single_segment_connect_timeout_ms: None,
+        inspection_budget_ms: 100,
+        proxy_idle_timeout_ms: None,
+        min_throughput_bytes_per_sec: None,
+    }
+}
+
+#[tokio::test]
+async fn forwarded_bytes_are_counted_per_direction() {
+    let upstream_before = aegis_proxy::metrics::BYTES_CLIENT_TO_BACKEND.get();
+    let downstream_before = aegis_proxy::metrics::BYTES_BACKEND_TO_CLIENT.get();
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let proxy_addr = spawn_proxy(backend_addr, connection_config()).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut broker, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    broker.read_exact(&mut connect).await.unwrap();
+
+    // The CONNECT is replayed during the handshake; only session traffic counts.
+    client.write_all(&[0xc0, 0x00]).await.unwrap();
+    broker
+        .write_all(&[0x20, 0x02, 0x00, 0x00, 0xd0, 0x00])
+        .await
+        .unwrap();
+    client.shutdown().await.unwrap();
+    broker.shutdown().await.unwrap();
+    let mut sink = Vec::new();
+    timeout(Duration::from_secs(2), client.read_to_end(&mut sink))
+        .await
+        .unwrap()
+        .unwrap();
+    timeout(Duration::from_secs(2), broker.read_to_end(&mut sink))
+        .await
+        .unwrap()
+        .unwrap();
+    // Counted once each direction has ended.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert!(aegis_proxy::metrics::BYTES_CLIENT_TO_BACKEND.get() >= upstream_before + 2);
+    assert!(aegis_proxy::metrics::BYTES_BACKEND_TO_CLIENT.get() >= downstream_before + 6);
+}
+
+fn with_access_log(mut config: ConnectionConfig) -> (ConnectionConfig, AccessLog) {
+    let access_log = AccessLog::new(true);
+    config.trace = DecisionTrace::disabled().with_access_log(access_log.clone());
+    config.access_log = access_log.clone();
+    (config, access_log)
+}
+
+#[tokio::test]
+async fn access_log_summarizes_an_admitted_session() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let (config, access_log) = with_access_log(connection_config());
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut broker, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    broker.read_exact(&mut connect).await.unwrap();
+    client.write_all(&[0xc0, 0x00]).await.unwrap();
+    broker.write_all(&[0x20, 0x02, 0x00, 0x00]).await.unwrap();
+    client.shutdown().await.unwrap();
+    broker.shutdown().await.unwrap();
+    let mut sink = Vec::new();
+    timeout(Duration::from_secs(2), client.read_to_end(&mut sink))
+        .await
+        .unwrap()
+        .unwrap();
+    timeout(Duration::from_secs(2), broker.read_to_end(&mut sink))
+        .await
+        .unwrap()
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // The replayed CONNECT counts toward the bytes sent by the client.
+    assert_eq!(
+        access_log.render(),
+        format!(
+            "client_ip=127.0.0.1 protocol=mqtt decision=allowed reason=none bytes_in={} bytes_out=4",
+            CONNECT.len() + 2
+        )
+    );
+}
+
+#[tokio::test]
+async fn access_log_names_the_check_that_rejected() {
+    let (config, access_log) = with_access_log(connection_config());
+    let mut connect = CONNECT.to_vec();
+    connect[8] = 9;
+    assert_eq!(forwarded_bytes(config, false, &[&connect]).await, None);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(
+        access_log.render(),
+        "client_ip=127.0.0.1 protocol=mqtt decision=rejected reason=mqtt_connect bytes_in=0 bytes_out=0"
+    );
+}
+
+#[tokio::test]
+async fn busy_sessions_are_closed_at_their_maximum_lifetime() {
+    let before = aegis_proxy::metrics::LIFETIME_DISCONNECTS.get();
+    let mut config = connection_config();
+    config.max_connection_lifetime = Some(Duration::from_secs(1));
+    assert!(session_ends_when_silent(config, true).await);
+    assert!(aegis_proxy::metrics::LIFETIME_DISCONNECTS.get() > before);
+}
+
+#[tokio::test]
+async fn session_silent_both_ways_hits_the_proxy_idle_timeout() {
+    let before = aegis_proxy::metrics::IDLE_DISCONNECTS.get();
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let mut config = connection_config();
+    config.slowloris_config.proxy_idle_timeout_ms = Some(200);
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(&connect_with_keep_alive(0)).await.unwrap();
+    let (mut broker, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    broker.read_exact(&mut connect).await.unwrap();
+
+    let mut sink = Vec::new();
+    let ended = timeout(Duration::from_secs(2), client.read_to_end(&mut sink)).await;
+    assert!(ended.is_ok());
+    assert!(aegis_proxy::metrics::IDLE_DISCONNECTS.get() > before);
+}
+
+#[tokio::test]
+async fn traffic_in_one_direction_keeps_the_proxy_idle_timeout_at_bay() {
+    let mut config = connection_config();
+    config.slowloris_config.proxy_idle_timeout_ms = Some(200);
+    assert!(!session_ends_when_silent(config, true).await);
+
+    let mut config = connection_config();
+    config.slowloris_config.proxy_idle_timeout_ms = Some(200);
+    assert!(!session_ends_when_silent(config, false).await);
+}
+
+fn connection_config() -> ConnectionConfig {
+    ConnectionConfig {
+        mqtt_inspect: true,
+        mqtt_full_inspect: true,
+        http_inspect: true,
+        slowloris_protect: true,
+        max_connect_remaining: 64 * 1024,
+        backend_write_timeout_ms: 1000,
+        slow_backend_connect_ms: 1000,
+        backend_unavailable_connack: false,
+        slowloris_config: slowloris_config(),
+        http_inspection: HttpInspectionConfig {
+            max_header_line_size: 8192,
+            allow_bare_lf: false,
+            max_headers_to_inspect: 1000,
+        },
+        mqtt_policy: MqttPolicyConfig::default(),
+        session_rate: None,
+        repeated_malformed: None,
+        in_flight_connects: None,
+        max_client_id_len: None,
+        require_client_id_for_persistent_session: false,
+        require_username: false,
+        mqtt_websocket: false,
+        tcp_user_timeout: None,
+        backend_pool: None,
+        backend_health: None,
+        tags: None,
+        send_proxy_protocol: false,
+        accept_proxy_protocol: false,
+        deferred_source_checks: None,
+        shutdown: None,
+        max_concurrent_per_ip: None,
+        inspection_slot: None,
+        fast_path: None,
+        splice_forwarding: false,
+        protocol_backends: None,
+        edge_instance_id: None,
+        capture: None,
+        backend_write_buffer: None,
+        half_close_grace: None,
+        client_idle_timeout: None,
+        backend_idle_timeout: None,
+        write_stall_timeout: None,
+        max_connection_lifetime: None,
+        sni_routes: None,
+        publish_topics: None,
+        phase_timings: false,
+        trace: DecisionTrace::disabled(),
+        access_log: AccessLog::disabled(),
+        listener: "default".to_string(),
+    }
+}
+
+/// Starts the proxy for a single client connection and returns its address.
+async fn spawn_proxy(backend: String, config: ConnectionConfig) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let _ = handle_connection(socket, backend, config).await;
+    });
+    addr
+}
+
+#[tokio::test]
+async fn client_half_close_keeps_backend_to_client_direction_open() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+
+    // Broker stand-in: read the CONNECT until the client half-closes, then
+    // deliver a "retained message" and close.
+    let broker = tokio::spawn(async move {
+        let (mut conn, _) = backend.accept().await.unwrap();
+        let mut received = Vec::new();
+        conn.read_to_end(&mut received).await.unwrap();
+        conn.write_all(b"retained").await.unwrap();
+        conn.shutdown().await.unwrap();
+        received
+    });
+
+    let proxy_addr = spawn_proxy(backend_addr, connection_config()).await;
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    client.shutdown().await.unwrap();
+
+    let mut reply = Vec::new();
+    timeout(Duration::from_secs(5), client.read_to_end(&mut reply))
+        .await
+        .expect("session should end once the broker closes")
+        .unwrap();
+
+    assert_eq!(reply, b"retained");
+    assert_eq!(broker.await.unwrap(), CONNECT);
+}
+
+#[tokio::test]
+async fn half_close_grace_bounds_the_remaining_direction() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+
+    // Broker stand-in that never answers or closes after the client's EOF.
+    let _broker = tokio::spawn(async move {
+        let (mut conn, _) = backend.accept().await.unwrap();
+        let mut received = Vec::new();
+        conn.read_to_end(&mut received).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        drop(conn);
+    });
+
+    let mut config = connection_config();
+    config.half_close_grace = Some(Duration::from_millis(200));
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    client.shutdown().await.unwrap();
+
+    let mut reply = Vec::new();
+    timeout(Duration::from_secs(5), client.read_to_end(&mut reply))
+        .await
+        .expect("grace should close the half-closed session")
+        .unwrap();
+    assert!(reply.is_empty());
+}
+
+/// Sends an HTTP request with one `X-Pad` header of `pad` bytes through a proxy
+/// that routes HTTP to a backend, returning whether the backend was reached.
+async fn http_request_reaches_backend(max_header_line_size: usize, pad: usize) -> bool {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+
+    let mut config = connection_config();
+    config.http_inspection.max_header_line_size = max_header_line_size;
+    config.protocol_backends = Some(ProtocolBackends {
+        http: Some(backend_addr.clone()),
+        ..Default::default()
+    });
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: example.com\r\nX-Pad: {}\r\n\r\n",
+        "a".repeat(pad)
+    );
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(request.as_bytes()).await.unwrap();
+
+    timeout(Duration::from_millis(500), backend.accept())
+        .await
+        .is_ok()
+}
+
+#[tokio::test]
+async fn configured_max_header_line_size_is_enforced() {
+    assert!(http_request_reaches_backend(256, 100).await);
+    assert!(!http_request_reaches_backend(256, 600).await);
+}
+
+#[tokio::test]
+async fn backend_connect_failure_after_inspection_is_counted() {
+    // Reserve a port, then close it so the backend connect is refused.
+    let backend_addr = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    let unavailable = aegis_proxy::metrics::BACKEND_UNAVAILABLE.with_label_values(&["mqtts"]);
+    let refused = aegis_proxy::metrics::BACKEND_ERRORS.with_label_values(&["refused", "mqtts"]);
+    let before = (unavailable.get(), refused.get());
+
+    let mut config = connection_config();
+    config.listener = "mqtts".to_string();
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+
+    let mut reply = Vec::new();
+    timeout(Duration::from_secs(5), client.read_to_end(&mut reply))
+        .await
+        .expect("client should be closed")
+        .unwrap();
+    assert!(reply.is_empty());
+    assert!(unavailable.get() > before.0);
+    assert!(refused.get() > before.1);
+}
+
+#[tokio::test]
+async fn rejections_are_counted_under_the_accepting_listener() {
+    let rejected = aegis_proxy::metrics::REJECTIONS
+        .with_label_values(&["unsupported_protocol_level", "internal"]);
+    let before = rejected.get();
+
+    let mut config = connection_config();
+    config.listener = "internal".to_string();
+    let mut connect = CONNECT.to_vec();
+    connect[8] = 9;
+    assert_eq!(forwarded_bytes(config, false, &[&connect]).await, None);
+    assert!(rejected.get() > before);
+}
+
+#[tokio::test]
+async fn unreachable_backend_can_be_reported_with_a_connack() {
+    let backend_addr = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    let unavailable = aegis_proxy::metrics::BACKEND_UNAVAILABLE.with_label_values(&["default"]);
+    let before = unavailable.get();
+
+    for full_inspect in [true, false] {
+        let mut config = connection_config();
+        config.mqtt_full_inspect = full_inspect;
+        config.backend_unavailable_connack = true;
+        let proxy_addr = spawn_proxy(backend_addr.clone(), config).await;
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        client.write_all(CONNECT).await.unwrap();
+
+        let mut reply = Vec::new();
+        timeout(Duration::from_secs(5), client.read_to_end(&mut reply))
+            .await
+            .expect("client should be closed")
+            .unwrap();
+        // Return code 0x03: server unavailable.
+        assert_eq!(reply, [0x20, 0x02, 0x00, 0x03]);
+    }
+    assert!(unavailable.get() >= before + 2);
+}
+
+/// Brokers that report the index of whichever accepted a connection.
+async fn counting_brokers(count: usize) -> (Vec<String>, tokio::sync::mpsc::Receiver<usize>) {
+    let (tx, rx) = tokio::sync::mpsc::channel(16);
+    let mut addrs = Vec::new();
+    for index in 0..count {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        addrs.push(listener.local_addr().unwrap().to_string());
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                held.push(conn);
+                let _ = tx.send(index).await;
+            }
+        });
+    }
+    (addrs, rx)
+}
+
+/// Sends one CONNECT through a proxy using `pool`; returns the broker index.
+async fn pooled_connect(
+    pool: &Arc<BackendSelector>,
+    accepted: &mut tokio::sync::mpsc::Receiver<usize>,
+) -> usize {
+    pooled_connect_with_health(pool, None, accepted).await
+}
+
+async fn pooled_connect_with_health(
+    pool: &Arc<BackendSelector>,
+    health: Option<&Arc<BackendHealth>>,
+    accepted: &mut tokio::sync::mpsc::Receiver<usize>,
+) -> usize {
+    let mut config = connection_config();
+    config.backend_pool = Some(Arc::clone(pool));
+    config.backend_health = health.cloned();
+    let proxy_addr = spawn_proxy(pool.targets()[0].clone(), config).await;
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    timeout(Duration::from_secs(5), accepted.recv())
+        .await
+        .expect("a broker should accept")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn backend_pool_spreads_connections_evenly() {
+    let (addrs, mut accepted) = counting_brokers(3).await;
+    let pool = Arc::new(BackendSelector::new(addrs).unwrap());
+    let mut counts = [0; 3];
+    for _ in 0..9 {
+        counts[pooled_connect(&pool, &mut accepted).await] += 1;
+    }
+    assert_eq!(counts, [3, 3, 3]);
+}
+
+#[tokio::test]
+async fn backend_pool_spreads_connections_by_weight() {
+    let (addrs, mut accepted) = counting_brokers(3).await;
+    let weighted = addrs.iter().cloned().zip([1, 3, 2]).collect();
+    let pool = Arc::new(BackendSelector::weighted(weighted).unwrap());
+    let mut counts = [0; 3];
+    for _ in 0..12 {
+        counts[pooled_connect(&pool, &mut accepted).await] += 1;
+    }
+    assert_eq!(counts, [2, 6, 4]);
+
+    // The brokers may see a session before the proxy counts it.
+    let selections = || {
+        addrs
+            .iter()
+            .map(|addr| {
+                aegis_proxy::metrics::BACKEND_SELECTIONS
+                    .with_label_values(&[addr.as_str()])
+                    .get()
+            })
+            .collect::<Vec<_>>()
+    };
+    timeout(Duration::from_secs(5), async {
+        while selections().iter().sum::<u64>() < 12 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("every session should be counted");
+    assert_eq!(selections(), [2, 6, 4]);
+}
+
+#[tokio::test]
+async fn drained_backend_gets_no_new_sessions_until_undrained() {
+    use aegis_proxy::engine::admin::execute;
+
+    let (addrs, mut accepted) = counting_brokers(2).await;
+    let pool = Arc::new(BackendSelector::new(addrs.clone()).unwrap());
+    let drain = format!("drain-backend {}", addrs[0]);
+    assert_eq!(
+        execute(&drain, Some(&pool)),
+        format!("draining {}", addrs[0])
+    );
+    assert_eq!(
+        aegis_proxy::metrics::BACKEND_DRAINING
+            .with_label_values(&[addrs[0].as_str()])
+            .get(),
+        1
+    );
+    let mut counts = [0; 2];
+    for _ in 0..4 {
+        counts[pooled_connect(&pool, &mut accepted).await] += 1;
+    }
+    assert_eq!(counts, [0, 4]);
+
+    let undrain = format!("undrain-backend {}", addrs[0]);
+    assert_eq!(
+        execute(&undrain, Some(&pool)),
+        format!("undrained {}", addrs[0])
+    );
+    assert!(!pool.is_draining(&addrs[0]));
+    let mut counts = [0; 2];
+    for _ in 0..4 {
+        counts[pooled_connect(&pool, &mut accepted).await] += 1;
+    }
+    assert_eq!(counts, [2, 2]);
+
+    assert!(execute("drain-backend 192.0.2.1:1883", Some(&pool)).starts_with("error"));
+    assert!(execute(&drain, None).starts_with("error"));
+    assert!(execute("drain-backend", Some(&pool)).starts_with("error"));
+}
+
+#[tokio::test]
+async fn backend_pool_falls_back_when_a_broker_is_down() {
+    let (mut addrs, mut accepted) = counting_brokers(2).await;
+    // A closed port first in the rotation: its connects are refused.
+    let down = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    addrs.insert(0, down);
+    let pool = Arc::new(BackendSelector::new(addrs).unwrap());
+    let mut counts = [0; 2];
+    for _ in 0..3 {
+        counts[pooled_connect(&pool, &mut accepted).await] += 1;
+    }
+    // The down broker's turn fell through to the next one.
+    assert_eq!(counts, [2, 1]);
+}
+
+#[tokio::test]
+async fn open_circuit_skips_a_dead_broker() {
+    let (mut addrs, mut accepted) = counting_brokers(1).await;
+    let down = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    addrs.insert(0, down.clone());
+    let pool = Arc::new(BackendSelector::new(addrs).unwrap());
+    let health = Arc::new(BackendHealth::new(&BackendCircuitConfig {
+        failure_threshold: 1,
+        window_ms: 10_000,
+        cooldown_ms: 60_000,
+        reject_at_accept: false,
+        busy_signal: false,
+    }));
+
+    // The first connection trips the circuit on its way to the live broker.
+    assert_eq!(
+        pooled_connect_with_health(&pool, Some(&health), &mut accepted).await,
+        0
+    );
+    assert!(health.is_open(&down));
+    for _ in 0..3 {
+        assert_eq!(
+            pooled_connect_with_health(&pool, Some(&health), &mut accepted).await,
+            0
+        );
+    }
+    assert!(health.is_open(&down));
+}
+
+#[tokio::test]
+async fn admitted_sessions_are_counted_by_tag() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let set = TagSet::from_config(
+        &MetricTagsConfig {
+            profile: vec!["tagged".to_string()],
+            protocol: vec!["mqtt".to_string()],
+            ..MetricTagsConfig::default()
+        },
+        &["tagged"],
+    )
+    .unwrap();
+    let mut config = connection_config();
+    config.tags = Some(ConnectionTags::new(Arc::new(set), "tagged"));
+
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut conn, _) = timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut received = vec![0u8; CONNECT.len()];
+    conn.read_exact(&mut received).await.unwrap();
+
+    // Counted once forwarding starts, just after the CONNECT is written.
+    let labels = ["tagged", "mqtt", aegis_proxy::engine::tags::ANY];
+    let sessions = aegis_proxy::metrics::TAGGED_SESSIONS.with_label_values(&labels);
+    timeout(Duration::from_secs(5), async {
+        while sessions.get() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("session should be counted");
+    assert_eq!(sessions.get(), 1);
+    assert_eq!(
+        aegis_proxy::metrics::TAGGED_HANDSHAKE_SECONDS
+            .with_label_values(&labels)
+            .get_sample_count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn proxy_protocol_header_precedes_the_connect() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let mut config = connection_config();
+    config.send_proxy_protocol = true;
+
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(&proxy_addr).await.unwrap();
+    let client_port = client.local_addr().unwrap().port();
+    let proxy_port = client.peer_addr().unwrap().port();
+    client.write_all(CONNECT).await.unwrap();
+
+    let (mut conn, _) = timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut expected = format!(
+        "PROXY TCP4 127.0.0.1 127.0.0.1 {} {}\r\n",
+        client_port, proxy_port
+    )
+    .into_bytes();
+    expected.extend_from_slice(CONNECT);
+    let mut received = vec![0u8; expected.len()];
+    conn.read_exact(&mut received).await.unwrap();
+    assert_eq!(received, expected);
+}
+
+#[tokio::test]
+async fn inbound_proxy_header_is_consumed_and_names_the_client() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let mut config = connection_config();
+    config.accept_proxy_protocol = true;
+    config.send_proxy_protocol = true;
+
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(&proxy_addr).await.unwrap();
+    let proxy_port = client.peer_addr().unwrap().port();
+    let mut header = V2_SIGNATURE.to_vec();
+    header.extend_from_slice(&[0x21, 0x11, 0x00, 0x0c]);
+    header.extend_from_slice(&[203, 0, 113, 7, 127, 0, 0, 1, 0x10, 0x92, 0x1f, 0x90]);
+    client.write_all(&header).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+
+    let (mut conn, _) = timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut expected =
+        format!("PROXY TCP4 203.0.113.7 127.0.0.1 4242 {}\r\n", proxy_port).into_bytes();
+    expected.extend_from_slice(CONNECT);
+    let mut received = vec![0u8; expected.len()];
+    conn.read_exact(&mut received).await.unwrap();
+    assert_eq!(received, expected);
+}
+
+/// PROXY v2 header naming 203.0.113.7:4242 as the client.
+fn proxy_v2_header() -> Vec<u8> {
+    let mut header = V2_SIGNATURE.to_vec();
+    header.extend_from_slice(&[0x21, 0x11, 0x00, 0x0c]);
+    header.extend_from_slice(&[203, 0, 113, 7, 127, 0, 0, 1, 0x10, 0x92, 0x1f, 0x90]);
+    header
+}
+
+/// Deferred checks as the accept loop builds them for a PROXY listener, with
+/// a `proxied-clients` profile for 203.0.113.0/24.
+fn proxied_source_checks() -> SourceChecks {
+    let features: FeaturesConfig = serde_yaml::from_str(
+        "enable_mqtt_inspection: true
+enable_mqtt_full_inspection: true
+enable_http_inspection: true
+enable_slowloris_protection: true
+enable_rate_limiter: false
+enable_ebpf: false
+enable_ml: false",
+    )
+    .unwrap();
+    let limit: LimitConfig = serde_yaml::from_str(
+        "max_tokens: 5.0
+refill_rate: 1.0
+cleanup_interval_secs: 60
+ip_idle_timeout_secs: 60",
+    )
+    .unwrap();
+    let policy: SourcePolicyConfig = serde_yaml::from_str(
+        "profiles:
+  proxied-clients:
+    cidrs: [\"203.0.113.0/24\"]
+",
+    )
+    .unwrap();
+    let source_policy = SourcePolicy::from_config(Some(&policy), &features, &limit).unwrap();
+    SourceChecks {
+        limit: Arc::new(limit),
+        rate_limit: false,
+        bans: true,
+        proxied: Some(ProxiedChecks {
+            region_filter: None,
+            access_control: None,
+            accept_filter: false,
+            source_policy: Arc::new(source_policy),
+            subnet_limiter: None,
+        }),
+    }
+}
+
+#[tokio::test]
+async fn proxied_clients_get_the_profile_of_the_header_address() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let mut config = connection_config();
+    config.accept_proxy_protocol = true;
+    config.deferred_source_checks = Some(proxied_source_checks());
+    let matches =
+        aegis_proxy::metrics::POLICY_PROFILE_MATCHES.with_label_values(&["proxied-clients"]);
+    let before = matches.get();
+
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(&proxy_addr).await.unwrap();
+    client.write_all(&proxy_v2_header()).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+
+    let (mut conn, _) = timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut received = vec![0u8; CONNECT.len()];
+    conn.read_exact(&mut received).await.unwrap();
+    assert_eq!(received, CONNECT);
+    assert!(matches.get() > before);
+}
+
+#[tokio::test]
+async fn proxied_region_filter_checks_the_header_address() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    // Denies the client the header names, not the load balancer on loopback.
+    let mut regions = BTreeMap::new();
+    regions.insert(
+        "proxied-region".to_string(),
+        vec!["203.0.113.0/24".to_string()],
+    );
+    let filter = RegionFilter::from_config(&RegionFilterConfig {
+        policy: RegionPolicy::DenyListed,
+        regions,
+    })
+    .unwrap();
+    let mut checks = proxied_source_checks();
+    if let Some(proxied) = &mut checks.proxied {
+        proxied.region_filter = Some(Arc::new(filter));
+    }
+    let mut config = connection_config();
+    config.accept_proxy_protocol = true;
+    config.deferred_source_checks = Some(checks);
+    let rejected = aegis_proxy::metrics::REGION_REJECTIONS.with_label_values(&["proxied-region"]);
+    let before = rejected.get();
+
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(&proxy_addr).await.unwrap();
+    client.write_all(&proxy_v2_header()).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+
+    let mut buf = [0u8; 1];
+    let read = timeout(Duration::from_secs(5), client.read(&mut buf))
+        .await
+        .unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+    assert!(timeout(Duration::from_millis(200), backend.accept())
+        .await
+        .is_err());
+    assert!(rejected.get() > before);
+}
+
+#[tokio::test]
+async fn proxied_access_control_checks_the_header_address() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let acl = AccessControl::from_config(&AccessControlConfig {
+        allow_cidrs: Vec::new(),
+        deny_cidrs: vec!["203.0.113.0/24".to_string()],
+    })
+    .unwrap();
+    let mut checks = proxied_source_checks();
+    if let Some(proxied) = &mut checks.proxied {
+        proxied.access_control = Some(Arc::new(acl));
+    }
+    let mut config = connection_config();
+    config.accept_proxy_protocol = true;
+    config.deferred_source_checks = Some(checks);
+    let before = aegis_proxy::metrics::ACL_REJECTIONS.get();
+
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(&proxy_addr).await.unwrap();
+    client.write_all(&proxy_v2_header()).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+
+    let mut buf = [0u8; 1];
+    let read = timeout(Duration::from_secs(5), client.read(&mut buf))
+        .await
+        .unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+    assert!(timeout(Duration::from_millis(200), backend.accept())
+        .await
+        .is_err());
+    assert!(aegis_proxy::metrics::ACL_REJECTIONS.get() > before);
+}
+
+#[tokio::test]
+async fn proxied_access_control_admits_clients_behind_a_denied_balancer() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    // The load balancer connects from loopback; only the header address counts.
+    let acl = AccessControl::from_config(&AccessControlConfig {
+        allow_cidrs: Vec::new(),
+        deny_cidrs: vec!["127.0.0.0/8".to_string()],
+    })
+    .unwrap();
+    let mut checks = proxied_source_checks();
+    if let Some(proxied) = &mut checks.proxied {
+        proxied.access_control = Some(Arc::new(acl));
+    }
+    let mut config = connection_config();
+    config.accept_proxy_protocol = true;
+    config.deferred_source_checks = Some(checks);
+
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(&proxy_addr).await.unwrap();
+    client.write_all(&proxy_v2_header()).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+
+    let (mut conn, _) = timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut received = vec![0u8; CONNECT.len()];
+    conn.read_exact(&mut received).await.unwrap();
+    assert_eq!(received, CONNECT);
+}
+
+#[tokio::test]
+async fn connections_without_a_proxy_header_are_dropped() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let mut config = connection_config();
+    config.accept_proxy_protocol = true;
+    let rejected =
+        aegis_proxy::metrics::REJECTIONS.with_label_values(&["proxy_protocol", "default"]);
+    let before = rejected.get();
+
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(&proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+
+    let mut buf = [0u8; 1];
+    let read = timeout(Duration::from_secs(5), client.read(&mut buf))
+        .await
+        .unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+    assert!(timeout(Duration::from_millis(200), backend.accept())
+        .await
+        .is_err());
+    assert!(rejected.get() > before);
+}
+
+#[tokio::test]
+async fn shutdown_aborts_a_stalled_initial_forward() {
+    // A broker that accepts but never reads.
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let _stalled = tokio::spawn(async move {
+        let (conn, _) = backend.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        drop(conn);
+    });
+
+    let shutdown = CancellationToken::new();
+    let mut config = connection_config();
+    config.max_connect_remaining = 16 * 1024 * 1024;
+    config.backend_write_timeout_ms = 60_000;
+    config.shutdown = Some(shutdown.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        handle_connection(socket, backend_addr, config).await
+    });
+
+    // An MQTT 5 CONNECT padded with user properties to far more than the
+    // socket buffers between proxy and broker hold.
+    let mut properties = Vec::new();
+    for _ in 0..128 {
+        properties.extend_from_slice(&[0x26, 0x00, 0x01, b'k']);
+        properties.extend_from_slice(&60_000u16.to_be_bytes());
+        properties.resize(properties.len() + 60_000, b'v');
+    }
+    let mut body = b"\x00\x04MQTT\x05\x02\x00\x3c".to_vec();
+    body.extend_from_slice(&encode_remaining_length(properties.len()));
+    body.extend_from_slice(&properties);
+    body.extend_from_slice(b"\x00\x01c");
+    let mut connect = vec![0x10];
+    connect.extend_from_slice(&encode_remaining_length(body.len()));
+    connect.extend_from_slice(&body);
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    let writer = tokio::spawn(async move {
+        let _ = client.write_all(&connect).await;
+        client
+    });
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(!proxy.is_finished());
+    shutdown.cancel();
+    timeout(Duration::from_secs(5), proxy)
+        .await
+        .expect("forward aborted on shutdown")
+        .unwrap()
+        .unwrap();
+    drop(writer);
+}
+
+#[tokio::test]
+async fn connections_over_the_per_ip_cap_are_refused() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let config = || {
+        let mut config = connection_config();
+        config.max_concurrent_per_ip = Some(1);
+        config
+    };
+
+    let held_addr = spawn_proxy(backend_addr.clone(), config()).await;
+    let mut held = TcpStream::connect(held_addr).await.unwrap();
+    held.write_all(CONNECT).await.unwrap();
+    let (session, _) = timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let refused_addr = spawn_proxy(backend_addr.clone(), config()).await;
+    let mut refused = TcpStream::connect(refused_addr).await.unwrap();
+    let mut buf = [0u8; 1];
+    let read = timeout(Duration::from_secs(5), refused.read(&mut buf))
+        .await
+        .unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+
+    // Ending the first session frees the slot.
+    drop(held);
+    drop(session);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while concurrent_connections("127.0.0.1".parse().unwrap()) > 0 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "slot never released"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let admitted_addr = spawn_proxy(backend_addr, config()).await;
+    let mut admitted = TcpStream::connect(admitted_addr).await.unwrap();
+    admitted.write_all(CONNECT).await.unwrap();
+    timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .expect("admitted once the slot is free")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn inspection_slot_is_released_once_forwarding_starts() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let limiter = InspectionLimiter::from_config(Some(1)).unwrap();
+    let mut config = connection_config();
+    config.inspection_slot = limiter.try_acquire();
+    assert!(limiter.try_acquire().is_none());
+
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut session, _) = timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    session.read_exact(&mut connect).await.unwrap();
+
+    // The session is still open, but no longer counts as inspecting.
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while limiter.active() > 0 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "slot never released"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(limiter.try_acquire().is_some());
+    drop(client);
+}
+
+#[tokio::test]
+async fn buffered_backend_writes_flush_when_client_goes_quiet() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+
+    let mut config = connection_config();
+    config.backend_write_buffer = Some(64 * 1024);
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+
+    let (mut conn, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    conn.read_exact(&mut connect).await.unwrap();
+
+    // A small PINGREQ must not sit in the 64 KiB buffer.
+    client.write_all(&[0xC0, 0x00]).await.unwrap();
+    let mut ping = [0u8; 2];
+    timeout(Duration::from_secs(2), conn.read_exact(&mut ping))
+        .await
+        .expect("buffered data should be flushed on idle")
+        .unwrap();
+    assert_eq!(ping, [0xC0, 0x00]);
+}
+
+/// Proxies `chunks` (sent with a short pause between them) and returns what
+/// the backend received, or `None` if the backend was never reached. With
+/// `route_http`, HTTP is routed to the same backend instead of rejected.
+async fn forwarded_bytes(
+    mut config: ConnectionConfig,
+    route_http: bool,
+    chunks: &[&[u8]],
+) -> Option<Vec<u8>> {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    if route_http {
+        config.protocol_backends = Some(ProtocolBackends {
+            http: Some(backend_addr.clone()),
+            ..Default::default()
+        });
+    }
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    for chunk in chunks {
+        client.write_all(chunk).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    client.shutdown().await.unwrap();
+
+    let (mut conn, _) = timeout(Duration::from_millis(500), backend.accept())
+        .await
+        .ok()?
+        .unwrap();
+    let mut received = Vec::new();
+    timeout(Duration::from_secs(2), conn.read_to_end(&mut received))
+        .await
+        .unwrap()
+        .unwrap();
+    Some(received)
+}
+
+#[tokio::test]
+async fn peeked_and_inspected_http_bytes_reach_backend_unchanged() {
+    // Fragmented so the first peek sees only part of the request line.
+    let chunks: [&[u8]; 4] = [
+        b"GET /st",
+        b"atus HTTP/1.1\r\nHost: a\r\n",
+        b"Content-Length: 4\r\n\r\n",
+        b"ping",
+    ];
+    let received = forwarded_bytes(connection_config(), true, &chunks).await;
+    assert_eq!(received, Some(chunks.concat()));
+}
+
+#[tokio::test]
+async fn http_false_positive_is_replayed_when_mqtt_inspection_is_off() {
+    let mut config = connection_config();
+    config.mqtt_inspect = false;
+    let chunks: [&[u8]; 2] = [b"GET nonsense\r\n", b"\x01\x02\x03"];
+    let received = forwarded_bytes(config, false, &chunks).await;
+    assert_eq!(received, Some(chunks.concat()));
+}
+
+#[tokio::test]
+async fn http_false_positive_is_rejected_under_mqtt_inspection() {
+    let chunks: [&[u8]; 2] = [b"GET nonsense\r\n", CONNECT];
+    assert_eq!(
+        forwarded_bytes(connection_config(), false, &chunks).await,
+        None
+    );
+}
+
+const MQTT_WS_UPGRADE: &[u8] = b"GET /mqtt HTTP/1.1\r\nHost: broker\r\nConnection: Upgrade\r\n\
+Upgrade: websocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+Sec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: mqtt\r\n\r\n";
+
+#[tokio::test]
+async fn mqtt_websocket_upgrade_reaches_the_mqtt_target_when_enabled() {
+    let mut config = connection_config();
+    config.mqtt_websocket = true;
+    let received = forwarded_bytes(config, false, &[MQTT_WS_UPGRADE]).await;
+    assert_eq!(received.as_deref(), Some(MQTT_WS_UPGRADE));
+}
+
+#[tokio::test]
+async fn mqtt_websocket_upgrade_is_rejected_when_disabled() {
+    assert_eq!(
+        forwarded_bytes(connection_config(), false, &[MQTT_WS_UPGRADE]).await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn plain_http_is_still_rejected_with_mqtt_websocket_enabled() {
+    let mut config = connection_config();
+    config.mqtt_websocket = true;
+    let request: &[u8] = b"GET /status HTTP/1.1\r\nHost: a\r\n\r\n";
+    assert_eq!(forwarded_bytes(config, false, &[request]).await, None);
+}
+
+#[tokio::test]
+async fn http_connect_tunnels_are_rejected_even_with_an_http_backend() {
+    let request: &[u8] = b"CONNECT broker.example:1883 HTTP/1.1\r\nHost: broker.example\r\n\r\n";
+    assert_eq!(
+        forwarded_bytes(connection_config(), true, &[request]).await,
+        None
+    );
+    assert!(
+        aegis_proxy::metrics::REJECTIONS
+            .with_label_values(&["http_connect_tunnel", "default"])
+            .get()
+            >= 1
+    );
+}
+
+/// CONNECT with the given keep-alive, otherwise identical to `CONNECT`.
+fn connect_with_keep_alive(keep_alive: u16) -> Vec<u8> {
+    let mut connect = CONNECT.to_vec();
+    connect[10..12].copy_from_slice(&keep_alive.to_be_bytes());
+    connect
+}
+
+fn keep_alive_config(max: u16, action: KeepAliveAction) -> ConnectionConfig {
+    let mut config = connection_config();
+    config.mqtt_policy = MqttPolicyConfig {
+        max_keep_alive_secs: Some(max),
+        keep_alive_action: action,
+        ..Default::default()
+    };
+    config
+}
+
+#[tokio::test]
+async fn keep_alive_over_limit_is_rejected() {
+    let config = || keep_alive_config(300, KeepAliveAction::Reject);
+    let within = connect_with_keep_alive(300);
+    assert_eq!(
+        forwarded_bytes(config(), false, &[&within]).await,
+        Some(within.clone())
+    );
+    let over = connect_with_keep_alive(301);
+    assert_eq!(forwarded_bytes(config(), false, &[&over]).await, None);
+    // Zero disables keep-alive entirely, which is longer than any limit.
+    let disabled = connect_with_keep_alive(0);
+    assert_eq!(forwarded_bytes(config(), false, &[&disabled]).await, None);
+}
+
+#[tokio::test]
+async fn keep_alive_over_limit_is_clamped() {
+    let config = || keep_alive_config(300, KeepAliveAction::Clamp);
+    for requested in [0, 3600] {
+        let received = forwarded_bytes(config(), false, &[&connect_with_keep_alive(requested)])
+            .await
+            .expect("clamped CONNECT is forwarded");
+        assert_eq!(received, connect_with_keep_alive(300));
+    }
+}
+
+#[tokio::test]
+async fn keep_alive_under_minimum_is_rejected_or_raised() {
+    let config = |action| {
+        let mut config = keep_alive_config(300, action);
+        config.mqtt_policy.min_keep_alive_secs = Some(10);
+        config
+    };
+    let within = connect_with_keep_alive(10);
+    assert_eq!(
+        forwarded_bytes(config(KeepAliveAction::Reject), false, &[&within]).await,
+        Some(within.clone())
+    );
+    let chatty = connect_with_keep_alive(2);
+    assert_eq!(
+        forwarded_bytes(config(KeepAliveAction::Reject), false, &[&chatty]).await,
+        None
+    );
+    assert_eq!(
+        forwarded_bytes(config(KeepAliveAction::Clamp), false, &[&chatty]).await,
+        Some(connect_with_keep_alive(10))
+    );
+}
+
+#[tokio::test]
+async fn keep_alive_zero_is_below_a_lone_minimum() {
+    let config = |action| {
+        let mut config = connection_config();
+        config.mqtt_policy = MqttPolicyConfig {
+            min_keep_alive_secs: Some(10),
+            keep_alive_action: action,
+            ..Default::default()
+        };
+        config
+    };
+    let disabled = connect_with_keep_alive(0);
+    assert_eq!(
+        forwarded_bytes(config(KeepAliveAction::Reject), false, &[&disabled]).await,
+        None
+    );
+    assert_eq!(
+        forwarded_bytes(config(KeepAliveAction::Clamp), false, &[&disabled]).await,
+        Some(connect_with_keep_alive(10))
+    );
+}
+
+/// Sends `connect` through a proxy that rejects keep-alives over 300s and
+/// returns what the client received before the connection closed.
+async fn reply_to_rejected_connect(connect: &[u8], send_connack: bool) -> Vec<u8> {
+    let mut config = keep_alive_config(300, KeepAliveAction::Reject);
+    config.mqtt_policy.send_connack_on_reject = send_connack;
+    reply_to_connect(connect, config).await
+}
+
+/// Sends `connect` through a proxy with `config` and returns what the client
+/// received before the connection closed.
+async fn reply_to_connect(connect: &[u8], config: ConnectionConfig) -> Vec<u8> {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = spawn_proxy(backend.local_addr().unwrap().to_string(), config).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(connect).await.unwrap();
+    let mut reply = Vec::new();
+    timeout(Duration::from_secs(5), client.read_to_end(&mut reply))
+        .await
+        .expect("rejected client should be closed")
+        .unwrap();
+    reply
+}
+
+#[tokio::test]
+async fn rejected_v5_client_gets_connack_with_reason_string() {
+    // v5 CONNECT, keep-alive 3600, empty properties, client id "c".
+    let connect = b"\x10\x0e\x00\x04MQTT\x05\x02\x0e\x10\x00\x00\x01c";
+    let reply = reply_to_rejected_connect(connect, true).await;
+    assert_eq!(&reply[..4], &[0x20, reply.len() as u8 - 2, 0x00, 0x83]);
+    assert_eq!(reply[5], 0x1F);
+    let reason = std::str::from_utf8(&reply[8..]).unwrap();
+    assert!(reason.contains("keep alive"), "{}", reason);
+
+    assert!(reply_to_rejected_connect(connect, false).await.is_empty());
+    // 3.1.1 has no reason string, so those clients are closed silently.
+    let v311 = connect_with_keep_alive(3600);
+    assert!(reply_to_rejected_connect(&v311, true).await.is_empty());
+}
+
+#[tokio::test]
+async fn listed_rejection_categories_get_a_connack_at_any_level() {
+    let config = |categories: Vec<RejectCategory>| {
+        let mut config = connection_config();
+        config.require_username = true;
+        config.mqtt_policy.allowed_protocol_levels = Some(vec![4]);
+        config.mqtt_policy.reject_connack_categories = categories;
+        config
+    };
+    let sent = aegis_proxy::metrics::REJECT_CONNACKS_SENT.get();
+
+    // 3.1.1 without a user name: 0x05 not authorized.
+    let listed = config(vec![RejectCategory::NotAuthorized]);
+    assert_eq!(
+        reply_to_connect(CONNECT, listed).await,
+        [0x20, 0x02, 0x00, 0x05]
+    );
+    let unlisted = config(vec![RejectCategory::UnsupportedProtocolVersion]);
+    assert!(reply_to_connect(CONNECT, unlisted).await.is_empty());
+
+    // v5 is not allowed here: 0x84 unsupported protocol version.
+    let v5 = b"\x10\x0e\x00\x04MQTT\x05\x02\x00\x3c\x00\x00\x01c";
+    let listed = config(vec![RejectCategory::UnsupportedProtocolVersion]);
+    let reply = reply_to_connect(v5, listed).await;
+    assert_eq!(&reply[..4], &[0x20, reply.len() as u8 - 2, 0x00, 0x84]);
+
+    // 3.1.1 has no code for a malformed CONNECT, so it is still closed.
+    let mut malformed = CONNECT.to_vec();
+    malformed[9] |= 0x01;
+    let listed = config(vec![RejectCategory::Malformed]);
+    assert!(reply_to_connect(&malformed, listed).await.is_empty());
+
+    assert!(aegis_proxy::metrics::REJECT_CONNACKS_SENT.get() >= sent + 2);
+}
+
+#[test]
+fn effective_summary_reports_resolved_settings() {
+    let mut config = connection_config();
+    config.mqtt_full_inspect = false;
+    config.half_close_grace = Some(Duration::from_millis(250));
+    let summary = config.effective_summary();
+    assert!(summary.contains("mqtt_full_inspect=false"), "{}", summary);
+    assert!(
+        summary.contains("max_connect_remaining=65536"),
+        "{}",
+        summary
+    );
+    assert!(summary.contains("half_close_grace_ms=250"), "{}", summary);
+    assert!(
+        summary.contains("handshake_deadline_ms=none"),
+        "{}",
+        summary
+    );
+}
+
+#[tokio::test]
+async fn signature_fast_path_skips_inspection() {
+    // Not a valid CONNECT (bad protocol name), but it carries the signature.
+    let spoofed: &[u8] = b"\x10\x0d\x00\x04MQXX\x04\x02\x00\x3c\x00\x01c";
+    let with_signature = |verify_every| {
+        let mut config = connection_config();
+        let signatures = SignatureSet::from_config(&SignatureFastPathConfig {
+            signatures: vec!["100d0004".to_string()],
+            verify_every,
+        })
+        .unwrap();
+        config.fast_path = Some(Arc::new(signatures));
+        config
+    };
+    assert_eq!(
+        forwarded_bytes(with_signature(100), false, &[spoofed]).await,
+        Some(spoofed.to_vec())
+    );
+    // Verified matches are fully inspected and the spoof is caught.
+    assert_eq!(
+        forwarded_bytes(with_signature(1), false, &[spoofed]).await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn oversized_client_packet_ends_the_session() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = connection_config();
+    config.mqtt_policy.max_packet_size = Some(16);
+    let proxy_addr = spawn_proxy(backend.local_addr().unwrap().to_string(), config).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut conn, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    conn.read_exact(&mut connect).await.unwrap();
+
+    // A PINGREQ passes; a 20-byte PUBLISH does not.
+    client.write_all(&[0xC0, 0x00]).await.unwrap();
+    let mut ping = [0u8; 2];
+    conn.read_exact(&mut ping).await.unwrap();
+    let mut publish = vec![0x30, 18];
+    publish.extend_from_slice(&[b'x'; 18]);
+    client.write_all(&publish).await.unwrap();
+
+    let mut rest = Vec::new();
+    let _ = timeout(Duration::from_secs(5), conn.read_to_end(&mut rest))
+        .await
+        .expect("backend side should be closed");
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn publish_to_a_denied_topic_ends_the_session() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = connection_config();
+    config.publish_topics = Some(Arc::new(
+        TopicRules::from_config(&MqttPolicyConfig {
+            publish_allow_topics: Some(vec!["devices/+/telemetry".to_string()]),
+            ..Default::default()
+        })
+        .unwrap(),
+    ));
+    let proxy_addr = spawn_proxy(backend.local_addr().unwrap().to_string(), config).await;
+    let publish = |topic: &[u8]| {
+        let mut packet = vec![0x30, 2 + topic.len() as u8 + 2, 0x00, topic.len() as u8];
+        packet.extend_from_slice(topic);
+        packet.extend_from_slice(b"hi");
+        packet
+    };
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut conn, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    conn.read_exact(&mut connect).await.unwrap();
+
+    let allowed = publish(b"devices/d1/telemetry");
+    client.write_all(&allowed).await.unwrap();
+    let mut received = vec![0u8; allowed.len()];
+    conn.read_exact(&mut received).await.unwrap();
+    assert_eq!(received, allowed);
+
+    client
+        .write_all(&publish(b"devices/d1/admin"))
+        .await
+        .unwrap();
+    let mut rest = Vec::new();
+    let _ = timeout(Duration::from_secs(5), conn.read_to_end(&mut rest))
+        .await
+        .expect("backend side should be closed");
+    assert!(rest.is_empty());
+    assert!(
+        aegis_proxy::metrics::REJECTIONS
+            .with_label_values(&["publish_topic_denied", "default"])
+            .get()
+            >= 1
+    );
+}
+
+#[tokio::test]
+async fn oversized_publish_payload_ends_the_session_before_forwarding() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = connection_config();
+    config.mqtt_policy.max_publish_payload = Some(64);
+    let proxy_addr = spawn_proxy(backend.local_addr().unwrap().to_string(), config).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut conn, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    conn.read_exact(&mut connect).await.unwrap();
+
+    let small = [0x30, 0x05, 0x00, 0x01, b't', b'h', b'i'];
+    client.write_all(&small).await.unwrap();
+    let mut received = [0u8; 7];
+    conn.read_exact(&mut received).await.unwrap();
+    assert_eq!(received, small);
+
+    // Only the header of a 1000-byte payload: refused before the payload.
+    let mut header = vec![0x30];
+    header.extend_from_slice(&encode_remaining_length(3 + 1000));
+    header.extend_from_slice(&[0x00, 0x01, b't']);
+    client.write_all(&header).await.unwrap();
+    let mut rest = Vec::new();
+    let _ = timeout(Duration::from_secs(5), conn.read_to_end(&mut rest))
+        .await
+        .expect("backend side should be closed");
+    assert!(rest.is_empty());
+    assert!(aegis_proxy::metrics::OVERSIZED_PUBLISH.get() >= 1);
+    assert!(
+        aegis_proxy::metrics::REJECTIONS
+            .with_label_values(&["oversized_publish", "default"])
+            .get()
+            >= 1
+    );
+}
+
+#[tokio::test]
+async fn trickled_connect_below_the_throughput_floor_is_rejected() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = connection_config();
+    config.slowloris_config.min_throughput_bytes_per_sec = Some(50);
+    let proxy_addr = spawn_proxy(backend.local_addr().unwrap().to_string(), config).await;
+
+    let client_id = vec![b'x'; 40];
+    let mut body = b"\x00\x04MQTT\x04\x02\x00\x3c".to_vec();
+    body.extend_from_slice(&(client_id.len() as u16).to_be_bytes());
+    body.extend_from_slice(&client_id);
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(&[0x10, body.len() as u8]).await.unwrap();
+    // 10 bytes/s: each byte lands well inside the idle timeout.
+    for byte in body {
+        if client.write_all(&[byte]).await.is_err() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert!(timeout(Duration::from_millis(100), backend.accept())
+        .await
+        .is_err());
+    assert!(
+        aegis_proxy::metrics::REJECTIONS
+            .with_label_values(&["connect_throughput_too_low", "default"])
+            .get()
+            >= 1
+    );
+}
+
+#[tokio::test]
+async fn connect_larger_than_the_first_packet_peek_is_admitted() {
+    // 200-byte client id: the 16-byte peek sees only the start of the CONNECT.
+    let client_id = vec![b'x'; 200];
+    let mut body = b"\x00\x04MQTT\x04\x02\x00\x3c".to_vec();
+    body.extend_from_slice(&(client_id.len() as u16).to_be_bytes());
+    body.extend_from_slice(&client_id);
+    let mut connect = vec![0x10];
+    connect.extend_from_slice(&encode_remaining_length(body.len()));
+    connect.extend_from_slice(&body);
+
+    let before = aegis_proxy::metrics::FIRST_PACKET_PEEK_FILLED.get();
+    let received = forwarded_bytes(connection_config(), false, &[&connect]).await;
+    assert_eq!(received, Some(connect));
+    assert!(aegis_proxy::metrics::FIRST_PACKET_PEEK_FILLED.get() > before);
+}
+
+/// Runs a forwarded session where one side keeps talking every 50ms while the
+/// other stays silent, and returns whether the proxy ended it. The CONNECT
+/// disables keep-alive, so a configured client idle timeout applies as is.
+async fn session_ends_when_silent(config: ConnectionConfig, chatty_client: bool) -> bool {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(&connect_with_keep_alive(0)).await.unwrap();
+    let (mut broker, _) = backend.accept().await.unwrap();
+
+    let (mut talker, mut listener) = if chatty_client {
+        (client, broker)
+    } else {
+        let mut connect = vec![0u8; CONNECT.len()];
+        broker.read_exact(&mut connect).await.unwrap();
+        (broker, client)
+    };
+    let chatter = tokio::spawn(async move {
+        // PINGREQ / a small PUBLISH stand-in; stops once the proxy closes.
+        while talker.write_all(&[0xc0, 0x00]).await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    });
+
+    // The silent side sees the session end as EOF (or a reset).
+    let mut sink = Vec::new();
+    let ended = timeout(Duration::from_secs(2), listener.read_to_end(&mut sink))
+        .await
+        .is_ok();
+    chatter.abort();
+    ended
+}
+
+#[tokio::test]
+async fn silent_client_hits_the_client_idle_timeout() {
+    let before = aegis_proxy::metrics::SESSION_IDLE_TIMEOUTS
+        .with_label_values(&["upstream"])
+        .get();
+    let mut config = connection_config();
+    config.client_idle_timeout = Some(Duration::from_millis(200));
+    assert!(session_ends_when_silent(config, false).await);
+    assert!(
+        aegis_proxy::metrics::SESSION_IDLE_TIMEOUTS
+            .with_label_values(&["upstream"])
+            .get()
+            > before
+    );
+
+    // The client timeout does not apply to a silent backend.
+    let mut config = connection_config();
+    config.client_idle_timeout = Some(Duration::from_millis(200));
+    assert!(!session_ends_when_silent(config, true).await);
+}
+
+#[tokio::test]
+async fn client_pinging_at_keep_alive_cadence_outlives_a_shorter_idle_timeout() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let mut config = connection_config();
+    config.client_idle_timeout = Some(Duration::from_millis(300));
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(&connect_with_keep_alive(1)).await.unwrap();
+    let (mut broker, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    broker.read_exact(&mut connect).await.unwrap();
+
+    // The idle timeout follows the 1 s keep-alive (1.5 s), not the 300 ms
+    // configured one.
+    for _ in 0..3 {
+        tokio::time::sleep(Duration::from_millis(900)).await;
+        client.write_all(&[0xc0, 0x00]).await.unwrap();
+        let mut ping = [0u8; 2];
+        timeout(Duration::from_secs(1), broker.read_exact(&mut ping))
+            .await
+            .expect("session still forwarding")
+            .unwrap();
+    }
+
+    // Silence past 1.5x the keep-alive still ends the session.
+    let mut sink = Vec::new();
+    let ended = timeout(Duration::from_secs(3), broker.read_to_end(&mut sink)).await;
+    assert!(ended.is_ok());
+}
+
+#[tokio::test]
+async fn backend_that_stops_reading_hits_the_write_stall_timeout() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = connection_config();
+    config.write_stall_timeout = Some(Duration::from_millis(200));
+    let proxy_addr = spawn_proxy(backend.local_addr().unwrap().to_string(), config).await;
+
+    let client = TcpStream::connect(proxy_addr).await.unwrap();
+    let (mut client_read, mut client_write) = client.into_split();
+    client_write.write_all(CONNECT).await.unwrap();
+    // Accept but never read: the socket buffers fill and the proxy's writes
+    // to the backend stop making progress.
+    let (_conn, _) = backend.accept().await.unwrap();
+    tokio::spawn(async move {
+        let chunk = vec![0u8; 64 * 1024];
+        while client_write.write_all(&chunk).await.is_ok() {}
+    });
+
+    let mut rest = Vec::new();
+    let _ = timeout(Duration::from_secs(10), client_read.read_to_end(&mut rest))
+        .await
+        .expect("session should end once the backend write stalls");
+}
+
+#[tokio::test]
+async fn silent_backend_hits_the_backend_idle_timeout() {
+    let before = aegis_proxy::metrics::SESSION_IDLE_TIMEOUTS
+        .with_label_values(&["downstream"])
+        .get();
+    let mut config = connection_config();
+    config.backend_idle_timeout = Some(Duration::from_millis(200));
+    assert!(session_ends_when_silent(config, true).await);
+    assert!(
+        aegis_proxy::metrics::SESSION_IDLE_TIMEOUTS
+            .with_label_values(&["downstream"])
+            .get()
+            > before
+    );
+
+    let mut config = connection_config();
+    config.backend_idle_timeout = Some(Duration::from_millis(200));
+    assert!(!session_ends_when_silent(config, false).await);
+}
+
+#[tokio::test]
+async fn admin_kill_closes_matching_live_sessions() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let proxy_addr = spawn_proxy(backend_addr, connection_config()).await;
+
+    // A dedicated loopback source, so other tests' sessions never match.
+    let socket = tokio::net::TcpSocket::new_v4().unwrap();
+    socket.bind("127.0.0.42:0".parse().unwrap()).unwrap();
+    let mut client = socket.connect(proxy_addr.parse().unwrap()).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut broker, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    broker.read_exact(&mut connect).await.unwrap();
+
+    assert_eq!(
+        aegis_proxy::engine::admin::execute("kill 10.0.0.0/8", None),
+        "killed 0"
+    );
+    assert_eq!(
+        aegis_proxy::engine::admin::execute("kill 127.0.0.42", None),
+        "killed 1"
+    );
+
+    let mut sink = Vec::new();
+    let closed = timeout(Duration::from_secs(2), client.read_to_end(&mut sink)).await;
+    assert!(closed.is_ok(), "killed session should be closed");
+    assert!(aegis_proxy::engine::admin::execute("kill not-a-cidr", None).starts_with("error"));
+}
+
+#[tokio::test]
+async fn handshake_phases_are_timed() {
+    let samples = |phase: &str| {
+        aegis_proxy::metrics::HANDSHAKE_PHASE_SECONDS
+            .with_label_values(&[phase])
+            .get_sample_count()
+    };
+    let before = ["peek", "payload", "backend_connect", "initial_forward"].map(samples);
+
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let proxy_addr = spawn_proxy(backend_addr, connection_config()).await;
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut broker, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    broker.read_exact(&mut connect).await.unwrap();
+
+    let after = ["peek", "payload", "backend_connect", "initial_forward"].map(samples);
+    for (before, after) in before.iter().zip(after) {
+        assert!(after > *before);
+    }
+}
+
+/// MQTT 3.1.1 CONNECT with the given client identifier and clean session flag.
+fn connect_with_client_id(client_id: &[u8], clean_session: bool) -> Vec<u8> {
+    let mut body = b"\x00\x04MQTT\x04".to_vec();
+    body.push(if clean_session { 0x02 } else { 0x00 });
+    body.extend_from_slice(&[0x00, 0x3c]);
+    body.extend_from_slice(&(client_id.len() as u16).to_be_bytes());
+    body.extend_from_slice(client_id);
+    let mut connect = vec![0x10];
+    connect.extend_from_slice(&encode_remaining_length(body.len()));
+    connect.extend_from_slice(&body);
+    connect
+}
+
+#[tokio::test]
+async fn client_identifier_limits_are_enforced() {
+    let config = || {
+        let mut config = connection_config();
+        config.max_client_id_len = Some(8);
+        config.require_client_id_for_persistent_session = true;
+        config
+    };
+    let within = connect_with_client_id(b"sensor-1", false);
+    assert_eq!(
+        forwarded_bytes(config(), false, &[&within]).await,
+        Some(within.clone())
+    );
+    let long = connect_with_client_id(b"sensor-10", true);
+    assert_eq!(forwarded_bytes(config(), false, &[&long]).await, None);
+    let not_utf8 = connect_with_client_id(b"\xff\xfe", true);
+    assert_eq!(forwarded_bytes(config(), false, &[&not_utf8]).await, None);
+
+    // An empty identifier needs a clean session.
+    let anonymous = connect_with_client_id(b"", true);
+    assert_eq!(
+        forwarded_bytes(config(), false, &[&anonymous]).await,
+        Some(anonymous.clone())
+    );
+    let resuming = connect_with_client_id(b"", false);
+    assert_eq!(forwarded_bytes(config(), false, &[&resuming]).await, None);
+}
+
+#[tokio::test]
+async fn anonymous_connects_are_rejected_when_a_username_is_required() {
+    let config = || {
+        let mut config = connection_config();
+        config.require_username = true;
+        config
+    };
+    let anonymous = connect_with_client_id(b"c1", true);
+    assert_eq!(
+        forwarded_bytes(connection_config(), false, &[&anonymous]).await,
+        Some(anonymous.clone())
+    );
+    assert_eq!(forwarded_bytes(config(), false, &[&anonymous]).await, None);
+
+    let mut named = connect_with_client_id(b"c1", true);
+    named[9] |= 0x80;
+    named.extend_from_slice(b"\x00\x05alice");
+    named[1] += 7;
+    assert_eq!(
+        forwarded_bytes(config(), false, &[&named]).await,
+        Some(named.clone())
+    );
+}
+
+#[tokio::test]
+async fn connects_with_unsupported_protocol_levels_are_rejected() {
+    let with_level = |level: u8| {
+        let mut connect = CONNECT.to_vec();
+        connect[8] = level;
+        connect
+    };
+    // Level 9 is not a known MQTT version.
+    assert_eq!(
+        forwarded_bytes(connection_config(), false, &[&with_level(9)]).await,
+        None
+    );
+
+    let v5_only = || {
+        let mut config = connection_config();
+        config.mqtt_policy.allowed_protocol_levels = Some(vec![5]);
+        config
+    };
+    assert_eq!(forwarded_bytes(v5_only(), false, &[CONNECT]).await, None);
+    let v5 = b"\x10\x0e\x00\x04MQTT\x05\x02\x00\x3c\x00\x00\x01c";
+    assert_eq!(
+        forwarded_bytes(v5_only(), false, &[v5]).await,
+        Some(v5.to_vec())
+    );
+    assert!(
+        aegis_proxy::metrics::PROTOCOL_LEVEL_REJECTIONS
+            .with_label_values(&["4"])
+            .get()
+            >= 1
+    );
+    assert!(
+        aegis_proxy::metrics::REJECTIONS
+            .with_label_values(&["unsupported_protocol_level", "default"])
+            .get()
+            >= 2
+    );
+}
+
+#[tokio::test]
+async fn connects_with_invalid_flags_are_rejected() {
+    let with_flags = |flags: u8| {
+        let mut connect = CONNECT.to_vec();
+        connect[9] = flags;
+        connect
+    };
+    // Reserved bit set; will QoS without the will flag.
+    for flags in [0x03, 0x0a] {
+        assert_eq!(
+            forwarded_bytes(connection_config(), false, &[&with_flags(flags)]).await,
+            None
+        );
+    }
+    assert!(
+        aegis_proxy::metrics::REJECTIONS
+            .with_label_values(&["invalid_connect_flags", "default"])
+            .get()
+            >= 2
+    );
+}
+
+fn tls_fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn tls_config() -> TlsConfig {
+    TlsConfig {
+        enabled: true,
+        cert_path: tls_fixture("cert.pem"),
+        key_path: tls_fixture("key.pem"),
+        handshake_timeout_ms: 1000,
+        max_concurrent_handshakes: None,
+        handshake_queue_timeout_ms: 1000,
+    }
+}
+
+#[tokio::test]
+async fn tls_is_terminated_and_inspected_in_plaintext() {
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let acceptor = load_acceptor(&tls_config()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let tls13 = aegis_proxy::metrics::TLS_CONNECTIONS_BY_VERSION.with_label_values(&["tls1.3"]);
+    let tls13_before = tls13.get();
+    let (params_tx, params_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let client = TlsClient::accept(&acceptor, socket, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let _ = params_tx.send(client.parameters());
+        let _ = handle_connection(client, backend_addr, connection_config()).await;
+    });
+
+    // The fixture certificate is self-signed for localhost.
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_file(tls_fixture("cert.pem")).unwrap())
+        .unwrap();
+    let connector = TlsConnector::from(Arc::new(
+        ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    ));
+    let tcp = TcpStream::connect(proxy_addr).await.unwrap();
+    let mut client = connector
+        .connect(ServerName::try_from("localhost").unwrap(), tcp)
+        .await
+        .unwrap();
+    client.write_all(CONNECT).await.unwrap();
+
+    let (mut broker, _) = backend.accept().await.unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    timeout(Duration::from_secs(2), broker.read_exact(&mut connect))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(connect, CONNECT);
+
+    let params = params_rx.await.unwrap();
+    assert_eq!(params.version, "tls1.3");
+    assert!(params.cipher.starts_with("TLS13_"), "{}", params.cipher);
+    assert_eq!(params.sni.as_deref(), Some("localhost"));
+    assert!(tls13.get() > tls13_before);
+
+    broker.write_all(&[0x20, 0x02, 0x00, 0x00]).await.unwrap();
+    let mut connack = [0u8; 4];
+    timeout(Duration::from_secs(2), client.read_exact(&mut connack))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(connack, [0x20, 0x02, 0x00, 0x00]);
+}
+
+#[tokio::test]
+async fn tls_handshake_slots_are_bounded_and_released() {
+    let handshakes = HandshakeLimit::new(1, Duration::from_millis(50));
+    let first = handshakes.acquire().await.expect("a slot is free");
+    // The only slot is taken: the next connection gives up after the wait.
+    assert!(handshakes.acquire().await.is_none());
+
+    let waiting = {
+        let handshakes = handshakes.clone();
+        tokio::spawn(async move { handshakes.acquire().await.is_some() })
+    };
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    drop(first);
+    assert!(waiting.await.unwrap(), "a released slot goes to a waiter");
+    assert!(handshakes.acquire().await.is_some());
+}
+
+#[tokio::test]
+async fn tls_handshake_held_open_times_out_apart_from_failures() {
+    let timeouts = aegis_proxy::metrics::TLS_HANDSHAKE_TIMEOUTS.get();
+    let acceptor = load_acceptor(&tls_config()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        TlsClient::accept(&acceptor, socket, Duration::from_millis(300))
+            .await
+            .err()
+            .map(|e| e.kind())
+    });
+
+    // Trickle a ClientHello a few bytes at a time and never finish it.
+    let hello = tls_client_hello("localhost");
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    for chunk in hello[..hello.len() / 2].chunks(8) {
+        client.write_all(chunk).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let kind = timeout(Duration::from_secs(2), server)
+        .await
+        .expect("handshake cut off at its timeout")
+        .unwrap();
+    assert_eq!(kind, Some(std::io::ErrorKind::TimedOut));
+    assert!(aegis_proxy::metrics::TLS_HANDSHAKE_TIMEOUTS.get() > timeouts);
+}
+
+#[test]
+fn tls_acceptor_names_unreadable_files() {
+    let mut config = tls_config();
+    config.key_path = tls_fixture("missing.pem");
+    let err = load_acceptor(&config).err().unwrap();
+    assert!(err.contains("missing.pem"));
+}
+
+/// Minimal TLS ClientHello record naming `server_name`.
+fn tls_client_hello(server_name: &str) -> Vec<u8> {
+    let name = server_name.as_bytes();
+    let mut sni = vec![0x00, 0x00];
+    sni.extend_from_slice(&(name.len() as u16 + 5).to_be_bytes());
+    sni.extend_from_slice(&(name.len() as u16 + 3).to_be_bytes());
+    sni.push(0x00);
+    sni.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    sni.extend_from_slice(name);
+
+    let mut hello = vec![0x03, 0x03];
+    hello.extend_from_slice(&[0x2a; 32]);
+    hello.extend_from_slice(&[0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+    hello.extend_from_slice(&(sni.len() as u16).to_be_bytes());
+    hello.extend_from_slice(&sni);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&(hello.len() as u16 + 4).to_be_bytes());
+    record.push(0x01);
+    record.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+    record.extend_from_slice(&hello);
+    record
+}
+
+/// Sends a ClientHello for `server_name` plus trailing bytes through a proxy
+/// with one SNI route, and returns which backend received what.
+async fn sni_routed(server_name: &str) -> (Option<Vec<u8>>, Option<Vec<u8>>, Vec<u8>) {
+    let routed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let fallback = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = connection_config();
+    config.sni_routes = Some(Arc::new(
+        [(
+            "broker.example".to_string(),
+            routed.local_addr().unwrap().to_string(),
+        )]
+        .into_iter()
+        .collect(),
+    ));
+    let proxy_addr = spawn_proxy(fallback.local_addr().unwrap().to_string(), config).await;
+
+    let mut sent = tls_client_hello(server_name);
+    sent.extend_from_slice(b"\x17\x03\x03\x00\x01z");
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(&sent).await.unwrap();
+    client.shutdown().await.unwrap();
+
+    async fn received(listener: &TcpListener) -> Option<Vec<u8>> {
+        let (mut conn, _) = timeout(Duration::from_millis(500), listener.accept())
+            .await
+            .ok()?
+            .unwrap();
+        let mut buf = Vec::new();
+        timeout(Duration::from_secs(2), conn.read_to_end(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        Some(buf)
+    }
+    let (to_routed, to_fallback) = tokio::join!(received(&routed), received(&fallback));
+    (to_routed, to_fallback, sent)
+}
+
+#[tokio::test]
+async fn tls_client_hello_is_routed_by_server_name_unchanged() {
+    let (to_routed, to_fallback, sent) = sni_routed("Broker.Example").await;
+    assert_eq!(to_routed, Some(sent));
+    assert_eq!(to_fallback, None);
+}
+
+#[tokio::test]
+async fn unlisted_server_name_falls_back_to_the_default_target() {
+    let (to_routed, to_fallback, sent) = sni_routed("other.example").await;
+    assert_eq!(to_routed, None);
+    assert_eq!(to_fallback, Some(sent));
+}
+
+#[tokio::test]
+async fn mqtt_is_still_inspected_with_sni_routes() {
+    let mut config = connection_config();
+    config.sni_routes = Some(Arc::new(Default::default()));
+    let received = forwarded_bytes(config, false, &[CONNECT]).await;
+    assert_eq!(received.as_deref(), Some(CONNECT));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_socket_client_reaches_a_unix_socket_backend() {
+    use aegis_proxy::engine::unix::{self, UnixClient};
+    use tokio::net::UnixStream;
+
+    let dir = std::env::temp_dir();
+    let pid = std::process::id();
+    let backend_path = dir.join(format!("aegis-backend-{}.sock", pid));
+    let proxy_path = dir.join(format!("aegis-proxy-{}.sock", pid));
+    let backend = unix::bind(backend_path.to_str().unwrap()).unwrap();
+    let listener = unix::bind(proxy_path.to_str().unwrap()).unwrap();
+    let target = format!("unix:{}", backend_path.display());
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let _ = handle_connection(UnixClient::new(stream), target, connection_config()).await;
+    });
+
+    let mut client = UnixStream::connect(&proxy_path).await.unwrap();
+    client.write_all(CONNECT).await.unwrap();
+    let (mut broker, _) = timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .expect("the CONNECT should be forwarded")
+        .unwrap();
+    let mut connect = vec![0u8; CONNECT.len()];
+    broker.read_exact(&mut connect).await.unwrap();
+    broker.write_all(&[0x20, 0x02, 0x00, 0x00]).await.unwrap();
+    let mut connack = [0u8; 4];
+    client.read_exact(&mut connack).await.unwrap();
+
+    let _ = std::fs::remove_file(&backend_path);
+    let _ = std::fs::remove_file(&proxy_path);
+    assert_eq!(connect, CONNECT);
+    assert_eq!(connack, [0x20, 0x02, 0x00, 0x00]);
+}
+
+#[tokio::test]
+async fn handshake_deadline_cuts_off_clients_pacing_under_each_phase_timeout() {
+    let before = aegis_proxy::metrics::HANDSHAKE_DEADLINE_REJECTIONS.get();
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let mut config = connection_config();
+    config.slowloris_config.handshake_deadline_ms = Some(400);
+    config.slowloris_config.inspection_budget_ms = 5000;
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+
+    // One byte every 150ms: well inside every per-phase timeout (1s and up),
+    // but the CONNECT as a whole takes over 2s.
+    let started = std::time::Instant::now();
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    for byte in CONNECT {
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        if client.write_all(&[*byte]).await.is_err() {
+            break;
+        }
+    }
+    let mut sink = Vec::new();
+    let _ = timeout(Duration::from_secs(2), client.read_to_end(&mut sink)).await;
+    assert!(sink.is_empty());
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "cut off at the deadline, not after the trickle finished"
+    );
+
+    timeout(Duration::from_secs(2), async {
+        while aegis_proxy::metrics::HANDSHAKE_DEADLINE_REJECTIONS.get() == before {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("deadline rejection recorded");
+    assert!(
+        timeout(Duration::from_millis(100), backend.accept())
+            .await
+            .is_err(),
+        "no backend connection for a client cut off mid-handshake"
+    );
+}
+
+fn single_segment_config() -> ConnectionConfig {
+    let mut config = connection_config();
+    config.slowloris_config.single_segment_connect_timeout_ms = Some(1000);
+    config
+}
+
+#[tokio::test]
+async fn single_segment_connect_is_admitted_whatever_its_size() {
+    // A CONNECT larger than the first read of the segment.
+    let client_id = vec![b'c'; 600];
+    let mut body = b"\x00\x04MQTT\x04\x02\x00\x3c".to_vec();
+    body.extend_from_slice(&(client_id.len() as u16).to_be_bytes());
+    body.extend_from_slice(&client_id);
+    let mut large = vec![0x10];
+    large.extend_from_slice(&encode_remaining_length(body.len()));
+    large.extend_from_slice(&body);
+
+    for connect in [CONNECT.to_vec(), large] {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap().to_string();
+        let proxy_addr = spawn_proxy(backend_addr, single_segment_config()).await;
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        client.write_all(&connect).await.unwrap();
+
+        let (mut broker, _) = timeout(Duration::from_secs(2), backend.accept())
+            .await
+            .expect("single-segment CONNECT forwarded")
+            .unwrap();
+        let mut forwarded = vec![0u8; connect.len()];
+        broker.read_exact(&mut forwarded).await.unwrap();
+        assert_eq!(forwarded, connect);
+    }
+}
+
+#[tokio::test]
+async fn fragmented_connect_is_rejected_and_captured() {
+    let before = aegis_proxy::metrics::FRAGMENTED_CONNECT_REJECTIONS.get();
+    let path = std::env::temp_dir().join(format!(
+        "aegis-fragmented-capture-{}.log",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let (capture, writer) = PacketCapture::new(&CaptureConfig {
+        enabled: true,
+        reasons: vec!["fragmented_connect".to_string()],
+        max_bytes_per_connection: 64,
+        max_captures_per_minute: 10,
+        path: Some(path.to_string_lossy().into_owned()),
+    });
+    let writer = tokio::spawn(writer);
+
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap().to_string();
+    let mut config = single_segment_config();
+    config.capture = Some(Arc::new(capture));
+    let proxy_addr = spawn_proxy(backend_addr, config).await;
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(&CONNECT[..5]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let _ = client.write_all(&CONNECT[5..]).await;
+    let mut sink = Vec::new();
+    let _ = timeout(Duration::from_secs(2), client.read_to_end(&mut sink)).await;
+    assert!(sink.is_empty());
+    assert!(
+        timeout(Duration::from_millis(100), backend.accept())
+            .await
+            .is_err(),
+        "fragmented CONNECT never reaches the backend"
+    );
+    assert!(aegis_proxy::metrics::FRAGMENTED_CONNECT_REJECTIONS.get() > before);
+
+    // The handler dropped its capture handle, so the writer drains and ends.
+    timeout(Duration::from_secs(2), writer)
+        .await
+        .expect("capture writer finishes")
+        .unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert!(contents.contains("reason=fragmented_connect"));
+    assert!(contents.contains("hex=100d00044d"), "{}", contents);
+}
@@ -0,0 +1,128 @@
+//! Protocol detection against recorded byte streams.
+//!
+//! Fixtures live in `tests/fixtures/detection/<expected>/`, where `<expected>`
+//! is one of `mqtt_connect`, `http`, `websocket` or `other`. Each fixture is
+//! the first bytes a real client sent, either as a `.hex` dump (whitespace is
+//! ignored, `#` starts a comment) or as a raw `.bin` file extracted from a pcap.
+//! Adding a sample is just dropping a file into the right directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use aegis_common::HttpInspectionConfig;
+use aegis_proxy::engine::http::{inspect_http, looks_like_http, HttpInspectionResult};
+use aegis_proxy::parser::mqtt::looks_like_mqtt_connect;
+
+const CLASSES: &[&str] = &["mqtt_connect", "http", "websocket", "other"];
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/detection")
+}
+
+/// Loads a fixture as raw bytes, decoding `.hex` dumps.
+fn load_fixture(path: &Path) -> Vec<u8> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("bin") => fs::read(path).unwrap(),
+        Some("hex") => {
+            let text = fs::read_to_string(path).unwrap();
+            let digits: String = text
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or(""))
+                .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+                .collect();
+            assert!(
+                digits.len().is_multiple_of(2),
+                "{}: odd hex length",
+                path.display()
+            );
+            (0..digits.len())
+                .step_by(2)
+                .map(|i| {
+                    u8::from_str_radix(&digits[i..i + 2], 16)
+                        .unwrap_or_else(|_| panic!("{}: invalid hex", path.display()))
+                })
+                .collect()
+        }
+        _ => panic!("{}: unsupported fixture extension", path.display()),
+    }
+}
+
+/// Every fixture as `(expected class, path, bytes)`.
+fn load_fixtures() -> Vec<(&'static str, PathBuf, Vec<u8>)> {
+    let mut fixtures = Vec::new();
+    for class in CLASSES {
+        let mut paths: Vec<PathBuf> = fs::read_dir(fixtures_dir().join(class))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        for path in paths {
+            let bytes = load_fixture(&path);
+            fixtures.push((*class, path, bytes));
+        }
+    }
+    fixtures
+}
+
+/// Classifies a stream the way the proxy's detectors see it.
+async fn classify(bytes: &[u8]) -> &'static str {
+    if looks_like_http(bytes) {
+        let mut reader = bytes;
+        let result = inspect_http(
+            &mut reader,
+            Duration::from_secs(1),
+            Duration::from_millis(100),
+            8192,
+            100,
+            &HttpInspectionConfig {
+                max_header_line_size: 8192,
+                allow_bare_lf: false,
+                max_headers_to_inspect: 1000,
+            },
+        )
+        .await;
+        return match result {
+            Ok(HttpInspectionResult::HttpDetected | HttpInspectionResult::HttpConnectTunnel(_)) => {
+                "http"
+            }
+            Ok(HttpInspectionResult::WebSocketUpgrade | HttpInspectionResult::MqttWebSocket) => {
+                "websocket"
+            }
+            _ => "other",
+        };
+    }
+    if looks_like_mqtt_connect(bytes) {
+        return "mqtt_connect";
+    }
+    "other"
+}
+
+#[test]
+fn every_class_has_fixtures() {
+    let fixtures = load_fixtures();
+    for class in CLASSES {
+        assert!(
+            fixtures.iter().any(|(c, _, _)| c == class),
+            "no fixtures for {}",
+            class
+        );
+    }
+}
+
+#[tokio::test]
+async fn recorded_streams_are_classified_as_expected() {
+    let mut mismatches = Vec::new();
+    for (expected, path, bytes) in load_fixtures() {
+        let actual = classify(&bytes).await;
+        if actual != expected {
+            mismatches.push(format!(
+                "{}: expected {}, got {}",
+                path.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
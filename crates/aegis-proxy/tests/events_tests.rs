@@ -0,0 +1,50 @@
+#![cfg(feature = "nats-events")]
+
+use aegis_common::EventsConfig;
+use aegis_proxy::engine::events;
+use aegis_proxy::engine::trace::DecisionTrace;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn decision_records_are_published_to_nats() {
+    let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let publisher = events::init(&EventsConfig {
+        nats_url: format!("nats://{}", server.local_addr().unwrap()),
+        subject: "aegis.test".to_string(),
+        queue_depth: 16,
+    });
+    tokio::spawn(publisher);
+
+    let (conn, _) = server.accept().await.unwrap();
+    let (read, mut write) = conn.into_split();
+    write.write_all(b"INFO {}\r\nPING\r\n").await.unwrap();
+    let mut lines = BufReader::new(read).lines();
+
+    {
+        let trace = DecisionTrace::new(true, "192.0.2.7:4000");
+        trace.check("rate_limit");
+    }
+
+    let mut seen = Vec::new();
+    while seen.len() < 4 {
+        let line = timeout(Duration::from_secs(5), lines.next_line())
+            .await
+            .expect("publisher should write")
+            .unwrap()
+            .unwrap();
+        seen.push(line);
+    }
+    assert!(seen[0].starts_with("CONNECT "));
+    // The PING reply may be interleaved before or after the publish.
+    assert!(seen.iter().any(|line| line == "PONG"));
+    let publish = seen
+        .iter()
+        .position(|line| line.starts_with("PUB aegis.test "))
+        .expect("event should be published");
+    let payload = &seen[publish + 1];
+    assert!(payload.contains("\"event\":\"rejected\""), "{}", payload);
+    assert!(payload.contains("rate_limit=reject"), "{}", payload);
+}
@@ -1,7 +1,16 @@
 use std::time::Duration;
 
+use aegis_common::HttpInspectionConfig;
 use aegis_proxy::engine::http::{inspect_http, looks_like_http, HttpInspectionResult};
 
+fn options(max_header_line_size: usize, allow_bare_lf: bool) -> HttpInspectionConfig {
+    HttpInspectionConfig {
+        max_header_line_size,
+        allow_bare_lf,
+        max_headers_to_inspect: 1000,
+    }
+}
+
 #[tokio::test]
 async fn test_parse_valid_http_request() {
     let data = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nUser-Agent: test\r\n\r\n";
@@ -13,7 +22,7 @@ async fn test_parse_valid_http_request() {
         Duration::from_millis(100),
         8192,
         100,
-        8192,
+        &options(8192, false),
     )
     .await
     .unwrap();
@@ -32,7 +41,7 @@ async fn test_parse_post_request() {
         Duration::from_millis(100),
         8192,
         100,
-        8192,
+        &options(8192, false),
     )
     .await
     .unwrap();
@@ -52,7 +61,7 @@ async fn test_not_http() {
         Duration::from_millis(100),
         8192,
         100,
-        8192,
+        &options(8192, false),
     )
     .await;
 
@@ -90,7 +99,7 @@ async fn test_header_count_limit() {
         Duration::from_millis(100),
         100000,
         100,
-        8192,
+        &options(8192, false),
     )
     .await
     .unwrap();
@@ -114,7 +123,7 @@ async fn test_header_size_limit() {
         Duration::from_millis(100),
         8192,
         100,
-        20000,
+        &options(20000, false),
     )
     .await
     .unwrap();
@@ -133,7 +142,7 @@ async fn test_malformed_header() {
         Duration::from_millis(100),
         8192,
         100,
-        8192,
+        &options(8192, false),
     )
     .await
     .unwrap();
@@ -141,6 +150,61 @@ async fn test_malformed_header() {
     assert!(matches!(result, HttpInspectionResult::SlowlorisDetected(_)));
 }
 
+#[tokio::test]
+async fn test_websocket_upgrade_detected() {
+    let data =
+        b"GET /mqtt HTTP/1.1\r\nHost: broker\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n";
+    let mut reader = &data[..];
+
+    let result = inspect_http(
+        &mut reader,
+        Duration::from_secs(1),
+        Duration::from_millis(100),
+        8192,
+        100,
+        &options(8192, false),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result, HttpInspectionResult::WebSocketUpgrade);
+}
+
+async fn inspect_upgrade(subprotocol: &str) -> HttpInspectionResult {
+    let data = format!(
+        "GET /mqtt HTTP/1.1\r\nHost: broker\r\nConnection: Upgrade\r\n\
+         Upgrade: websocket\r\nSec-WebSocket-Protocol: {}\r\n\r\n",
+        subprotocol
+    );
+    let mut reader = data.as_bytes();
+    inspect_http(
+        &mut reader,
+        Duration::from_secs(1),
+        Duration::from_millis(100),
+        8192,
+        100,
+        &options(8192, false),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn mqtt_subprotocol_upgrade_is_mqtt_websocket() {
+    assert_eq!(
+        inspect_upgrade("mqtt").await,
+        HttpInspectionResult::MqttWebSocket
+    );
+    assert_eq!(
+        inspect_upgrade("wamp, MQTTv3.1").await,
+        HttpInspectionResult::MqttWebSocket
+    );
+    assert_eq!(
+        inspect_upgrade("graphql-ws").await,
+        HttpInspectionResult::WebSocketUpgrade
+    );
+}
+
 #[test]
 fn test_looks_like_http() {
     assert!(looks_like_http(b"GET /"));
@@ -150,3 +214,115 @@ fn test_looks_like_http() {
     assert!(!looks_like_http(b"\x10\x0f\x00"));
     assert!(!looks_like_http(b"GET")); // No space after
 }
+
+async fn inspect_with_bare_lf(data: &[u8], allow_bare_lf: bool) -> HttpInspectionResult {
+    let mut reader = data;
+    inspect_http(
+        &mut reader,
+        Duration::from_secs(1),
+        Duration::from_millis(100),
+        8192,
+        100,
+        &options(8192, allow_bare_lf),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn bare_lf_request_is_detected_when_allowed() {
+    let data = b"GET / HTTP/1.1\nHost: x\n\n";
+    assert_eq!(
+        inspect_with_bare_lf(data, true).await,
+        HttpInspectionResult::HttpDetected
+    );
+}
+
+#[tokio::test]
+async fn bare_lf_request_is_flagged_when_not_allowed() {
+    let data = b"GET / HTTP/1.1\nHost: x\n\n";
+    assert_eq!(
+        inspect_with_bare_lf(data, false).await,
+        HttpInspectionResult::BareLineFeed
+    );
+    // Only in a header line.
+    let data = b"GET / HTTP/1.1\r\nHost: x\n\r\n";
+    assert_eq!(
+        inspect_with_bare_lf(data, false).await,
+        HttpInspectionResult::BareLineFeed
+    );
+    // Not a request line at all: still just not HTTP.
+    assert_eq!(
+        inspect_with_bare_lf(b"GET nonsense\n", false).await,
+        HttpInspectionResult::NotHttp
+    );
+}
+
+#[tokio::test]
+async fn stops_after_max_headers_to_inspect() {
+    // Only the first two headers arrive; the rest never would. With the cap
+    // the request is classified without waiting for them.
+    let data = b"GET / HTTP/1.1\r\nHost: a\r\nAccept: */*\r\n";
+    let mut reader = &data[..];
+    let mut capped = options(8192, false);
+    capped.max_headers_to_inspect = 2;
+    let before = aegis_proxy::metrics::HTTP_EARLY_CLASSIFICATIONS.get();
+
+    let result = inspect_http(
+        &mut reader,
+        Duration::from_secs(1),
+        Duration::from_millis(100),
+        8192,
+        100,
+        &capped,
+    )
+    .await
+    .unwrap();
+    assert_eq!(result, HttpInspectionResult::HttpDetected);
+    assert!(aegis_proxy::metrics::HTTP_EARLY_CLASSIFICATIONS.get() > before);
+
+    // Without the cap the same bytes are an incomplete request.
+    let mut reader = &data[..];
+    let result = inspect_http(
+        &mut reader,
+        Duration::from_secs(1),
+        Duration::from_millis(100),
+        8192,
+        100,
+        &options(8192, false),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(result, HttpInspectionResult::SlowlorisDetected(_)));
+}
+
+#[tokio::test]
+async fn connect_method_is_classified_as_a_tunnel() {
+    let inspect = |data: &'static [u8]| async move {
+        let mut reader = data;
+        inspect_http(
+            &mut reader,
+            Duration::from_secs(1),
+            Duration::from_millis(100),
+            8192,
+            100,
+            &options(8192, false),
+        )
+        .await
+        .unwrap()
+    };
+
+    assert_eq!(
+        inspect(b"CONNECT broker.example:8883 HTTP/1.1\r\nHost: broker.example:8883\r\n\r\n").await,
+        HttpInspectionResult::HttpConnectTunnel("broker.example:8883".to_string())
+    );
+    assert_eq!(
+        inspect(b"CONNECT [2001:db8::1]:443 HTTP/1.1\r\n\r\n").await,
+        HttpInspectionResult::HttpConnectTunnel("[2001:db8::1]:443".to_string())
+    );
+    // Not in authority form: an ordinary request.
+    assert_eq!(
+        inspect(b"CONNECT /path HTTP/1.1\r\n\r\n").await,
+        HttpInspectionResult::HttpDetected
+    );
+}
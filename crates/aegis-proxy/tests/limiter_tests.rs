@@ -0,0 +1,506 @@
+use aegis_common::{
+    ConnectRatioConfig, InFlightConnectConfig, LimitConfig, RateLimiterKind,
+    RepeatedMalformedConfig, SessionRateConfig, SubnetCapConfig,
+};
+use aegis_proxy::engine::limiter::{
+    acquire_in_flight, check_connect_ratio, check_rate_limit, check_session_rate, client_key,
+    concurrent_connections, malformed_banned, packet_hash, rate_limit_snapshot, rate_limit_subnet,
+    record_connect_completed, record_malformed, reset_rate_limit, start_cleanup_task,
+    try_acquire_concurrent, InspectionLimiter, SubnetLimiter, CONCURRENT_TRACKER, IP_TRACKER,
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn limiter(max_connections: usize, exempt: &[&str]) -> SubnetLimiter {
+    SubnetLimiter::from_config(&SubnetCapConfig {
+        max_connections,
+        ipv4_prefix: 24,
+        ipv6_prefix: 48,
+        exempt_cidrs: exempt.iter().map(|s| s.to_string()).collect(),
+    })
+    .unwrap()
+}
+
+#[test]
+fn subnet_cap_aggregates_addresses_in_one_prefix() {
+    let limiter = limiter(2, &[]);
+    let a = limiter.try_acquire(ip("192.0.2.1")).unwrap();
+    let _b = limiter.try_acquire(ip("192.0.2.200")).unwrap();
+    assert!(limiter.try_acquire(ip("192.0.2.77")).is_none());
+
+    // Other subnets are unaffected.
+    assert!(limiter.try_acquire(ip("192.0.3.1")).is_some());
+
+    drop(a);
+    assert_eq!(limiter.active_in_subnet(ip("192.0.2.5")), 1);
+    assert!(limiter.try_acquire(ip("192.0.2.77")).is_some());
+}
+
+#[test]
+fn subnet_cap_groups_ipv6_by_prefix() {
+    let limiter = limiter(1, &[]);
+    let _a = limiter.try_acquire(ip("2001:db8:1:2::1")).unwrap();
+    assert!(limiter.try_acquire(ip("2001:db8:1:ffff::9")).is_none());
+    assert!(limiter.try_acquire(ip("2001:db8:2::1")).is_some());
+}
+
+#[test]
+fn exempt_networks_bypass_the_subnet_cap() {
+    let limiter = limiter(1, &["10.0.0.0/8"]);
+    let _a = limiter.try_acquire(ip("10.1.1.1")).unwrap();
+    let _b = limiter.try_acquire(ip("10.1.1.2")).unwrap();
+    assert_eq!(limiter.active_in_subnet(ip("10.1.1.1")), 0);
+}
+
+#[test]
+fn subnet_cap_rejects_invalid_config() {
+    assert!(SubnetLimiter::from_config(&SubnetCapConfig {
+        max_connections: 0,
+        ipv4_prefix: 24,
+        ipv6_prefix: 48,
+        exempt_cidrs: Vec::new(),
+    })
+    .is_err());
+    assert!(SubnetLimiter::from_config(&SubnetCapConfig {
+        max_connections: 1,
+        ipv4_prefix: 33,
+        ipv6_prefix: 48,
+        exempt_cidrs: Vec::new(),
+    })
+    .is_err());
+}
+
+#[test]
+fn session_rate_is_tracked_separately_from_accept_rate() {
+    let config = SessionRateConfig {
+        max_tokens: 2.0,
+        refill_rate: 0.001,
+    };
+    let client = ip("198.51.100.40");
+    assert!(check_session_rate(client, &config));
+    assert!(check_session_rate(client, &config));
+    assert!(!check_session_rate(client, &config));
+
+    // Other clients and the accept-rate buckets are unaffected.
+    assert!(check_session_rate(ip("198.51.100.41"), &config));
+    assert!(!IP_TRACKER.contains_key(&client));
+}
+
+#[test]
+fn skewed_connect_ratio_bans_the_client() {
+    let config = ConnectRatioConfig {
+        window_secs: 60,
+        min_opens: 6,
+        max_ratio: 2.0,
+        ban_secs: 60,
+    };
+
+    // Completing most connections keeps the ratio in bounds.
+    let honest = ip("198.51.100.50");
+    for _ in 0..10 {
+        assert!(check_connect_ratio(honest, &config));
+        record_connect_completed(honest);
+    }
+
+    // Opens that never complete trip the ban once `min_opens` is reached,
+    // and the ban holds for later opens.
+    let attacker = ip("198.51.100.51");
+    for _ in 0..5 {
+        assert!(check_connect_ratio(attacker, &config));
+    }
+    assert!(!check_connect_ratio(attacker, &config));
+    record_connect_completed(attacker);
+    assert!(!check_connect_ratio(attacker, &config));
+}
+
+#[test]
+fn scoped_link_local_peers_share_one_key() {
+    // Same link-local address on two interfaces, and twice on the same one.
+    let eth0: SocketAddr = "[fe80::1%2]:40000".parse().unwrap();
+    let eth0_again: SocketAddr = "[fe80::1%2]:40001".parse().unwrap();
+    let eth1: SocketAddr = "[fe80::1%3]:40000".parse().unwrap();
+    assert_eq!(client_key(eth0.ip()), client_key(eth0_again.ip()));
+    assert_eq!(client_key(eth0.ip()), client_key(eth1.ip()));
+    assert_eq!(client_key(eth0.ip()), ip("fe80::1"));
+
+    let config = SessionRateConfig {
+        max_tokens: 1.0,
+        refill_rate: 0.001,
+    };
+    assert!(check_session_rate(eth0.ip(), &config));
+    assert!(!check_session_rate(eth0_again.ip(), &config));
+    assert!(!check_session_rate(eth1.ip(), &config));
+}
+
+#[test]
+fn ipv4_mapped_peers_share_the_ipv4_bucket() {
+    assert_eq!(client_key(ip("::ffff:198.51.100.60")), ip("198.51.100.60"));
+
+    let config = SessionRateConfig {
+        max_tokens: 1.0,
+        refill_rate: 0.001,
+    };
+    assert!(check_session_rate(ip("198.51.100.61"), &config));
+    assert!(!check_session_rate(ip("::ffff:198.51.100.61"), &config));
+
+    // Subnet grouping agrees with the per-IP key.
+    let limiter = limiter(1, &[]);
+    let _slot = limiter.try_acquire(ip("::ffff:192.0.2.9")).unwrap();
+    assert!(limiter.try_acquire(ip("192.0.2.10")).is_none());
+}
+
+#[test]
+fn repeating_the_same_malformed_packet_bans_the_client() {
+    let config = RepeatedMalformedConfig {
+        threshold: 3,
+        window_secs: 60,
+        max_hashes_per_ip: 2,
+        ban_secs: 60,
+    };
+    let client = ip("198.51.100.70");
+
+    // Varied malformed traffic never repeats enough, and the oldest hash is
+    // forgotten once the per-IP set is full.
+    assert_eq!(record_malformed(client, b"junk-a", &config), None);
+    assert_eq!(record_malformed(client, b"junk-b", &config), None);
+    assert_eq!(record_malformed(client, b"junk-c", &config), None);
+    assert_eq!(record_malformed(client, b"junk-a", &config), None);
+    assert!(!malformed_banned(client));
+
+    assert_eq!(record_malformed(client, b"junk-c", &config), None);
+    assert_eq!(
+        record_malformed(client, b"junk-c", &config),
+        Some(packet_hash(b"junk-c"))
+    );
+    assert!(malformed_banned(client));
+    assert!(!malformed_banned(ip("198.51.100.71")));
+}
+
+#[tokio::test]
+async fn in_flight_connects_wait_for_a_free_slot() {
+    let config = InFlightConnectConfig {
+        max_per_ip: 1,
+        max_wait_ms: 50,
+    };
+    let client = ip("198.51.100.80");
+
+    let first = acquire_in_flight(client, &config).await.unwrap();
+    // Same IP via its mapped form: no slot frees up in time.
+    assert!(acquire_in_flight(ip("::ffff:198.51.100.80"), &config)
+        .await
+        .is_none());
+    // Other IPs are unaffected.
+    let _other = acquire_in_flight(ip("198.51.100.81"), &config)
+        .await
+        .unwrap();
+
+    let waiter = tokio::spawn(async move { acquire_in_flight(client, &config).await.is_some() });
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    drop(first);
+    assert!(waiter.await.unwrap());
+}
+
+#[test]
+fn concurrent_cap_counts_open_slots_per_ip() {
+    let client = ip("198.51.100.91");
+    let first = try_acquire_concurrent(client, 2).expect("under the cap");
+    let second = try_acquire_concurrent(client, 2).expect("at the cap");
+    assert!(try_acquire_concurrent(client, 2).is_none());
+    // A rejected attempt does not hold a slot.
+    assert_eq!(concurrent_connections(client), 2);
+    // The mapped form of the address shares the count.
+    assert!(try_acquire_concurrent(ip("::ffff:198.51.100.91"), 2).is_none());
+
+    drop(first);
+    let third = try_acquire_concurrent(client, 2).expect("a slot was released");
+    drop(second);
+    drop(third);
+    assert_eq!(concurrent_connections(client), 0);
+    assert!(!CONCURRENT_TRACKER.contains_key(&client));
+}
+
+#[test]
+fn inspection_cap_rejects_excess_until_a_slot_frees() {
+    assert!(InspectionLimiter::from_config(Some(0)).is_err());
+    let limiter = InspectionLimiter::from_config(Some(2)).unwrap();
+    let first = limiter.try_acquire().expect("under the cap");
+    let _second = limiter.try_acquire().expect("at the cap");
+    assert!(limiter.try_acquire().is_none());
+    assert!(limiter.try_acquire().is_none());
+    assert_eq!(limiter.active(), 2);
+
+    drop(first);
+    assert_eq!(limiter.active(), 1);
+    assert!(limiter.try_acquire().is_some());
+
+    let uncapped = InspectionLimiter::from_config(None).unwrap();
+    let slots: Vec<_> = (0..100).filter_map(|_| uncapped.try_acquire()).collect();
+    assert_eq!(uncapped.active(), slots.len());
+    assert_eq!(slots.len(), 100);
+}
+
+fn rate_limit(kind: &str) -> LimitConfig {
+    serde_yaml::from_str(&format!(
+        "max_tokens: 3.0
+refill_rate: 20.0
+rate_limiter: {}
+window_secs: 60
+cleanup_interval_secs: 60
+ip_idle_timeout_secs: 60",
+        kind
+    ))
+    .unwrap()
+}
+
+async fn admitted(addr: IpAddr, config: &LimitConfig, attempts: usize) -> usize {
+    let mut admitted = 0;
+    for _ in 0..attempts {
+        if check_rate_limit(addr, config).await {
+            admitted += 1;
+        }
+    }
+    admitted
+}
+
+#[test]
+fn rate_limiter_defaults_to_the_token_bucket() {
+    let config: LimitConfig = serde_yaml::from_str(
+        "max_tokens: 3.0
+refill_rate: 1.0
+cleanup_interval_secs: 60
+ip_idle_timeout_secs: 60",
+    )
+    .unwrap();
+    assert_eq!(config.rate_limiter, RateLimiterKind::TokenBucket);
+}
+
+#[tokio::test]
+async fn sliding_window_does_not_refill_bursts_within_the_window() {
+    let bucket = rate_limit("token_bucket");
+    let window = rate_limit("sliding_window");
+    let (bucket_ip, window_ip) = (ip("198.51.100.101"), ip("198.51.100.102"));
+
+    // Both admit the same initial burst.
+    assert_eq!(admitted(bucket_ip, &bucket, 5).await, 3);
+    assert_eq!(admitted(window_ip, &window, 5).await, 3);
+
+    // The bucket refills (20 tokens/s) and admits another burst; the window
+    // still holds the first three accepts.
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(admitted(bucket_ip, &bucket, 5).await, 3);
+    assert_eq!(admitted(window_ip, &window, 5).await, 0);
+}
+
+#[tokio::test]
+async fn sliding_window_admits_again_once_accepts_age_out() {
+    let mut config = rate_limit("sliding_window");
+    config.window_secs = 1;
+    let client = ip("198.51.100.103");
+    assert_eq!(admitted(client, &config, 4).await, 3);
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    assert_eq!(admitted(client, &config, 4).await, 3);
+}
+
+#[tokio::test]
+async fn rate_limit_snapshot_lists_and_resets_one_bucket() {
+    let config = rate_limit("token_bucket");
+    let (throttled, other) = (ip("198.51.100.104"), ip("198.51.100.105"));
+    assert_eq!(admitted(throttled, &config, 4).await, 3);
+    assert_eq!(admitted(other, &config, 1).await, 1);
+
+    let snapshot = rate_limit_snapshot();
+    let bucket = |addr| snapshot.iter().find(|b| b.addr == addr).unwrap();
+    assert!(bucket(throttled).tokens < 1.0);
+    assert!(bucket(other).tokens >= 1.0);
+
+    assert!(reset_rate_limit(throttled));
+    assert!(!reset_rate_limit(throttled));
+    assert!(!IP_TRACKER.contains_key(&throttled));
+    assert!(IP_TRACKER.contains_key(&other));
+    assert_eq!(admitted(throttled, &config, 4).await, 3);
+}
+
+/// Reads one RESP command (an array of bulk strings) sent to a fake server.
+async fn read_command(
+    reader: &mut tokio::io::BufReader<tokio::net::tcp::OwnedReadHalf>,
+) -> Option<Vec<String>> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    let mut line = String::new();
+    reader.read_line(&mut line).await.ok()?;
+    let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::new();
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).await.ok()?;
+        let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+        let mut arg = vec![0u8; len + 2];
+        reader.read_exact(&mut arg).await.ok()?;
+        arg.truncate(len);
+        args.push(String::from_utf8(arg).ok()?);
+    }
+    Some(args)
+}
+
+#[tokio::test]
+async fn redis_backend_runs_the_script_again_after_a_cache_flush() {
+    use aegis_proxy::engine::limiter::RateLimitBackend;
+    use aegis_proxy::engine::redis_limiter::RedisBackend;
+    use tokio::io::AsyncWriteExt;
+
+    let server = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = rate_limit("token_bucket");
+    config.redis_url = Some(format!(
+        "redis://:secret@{}/2",
+        server.local_addr().unwrap()
+    ));
+    let commands = tokio::spawn(async move {
+        let (socket, _) = server.accept().await.unwrap();
+        let (read, mut write) = socket.into_split();
+        let mut reader = tokio::io::BufReader::new(read);
+        let mut seen = Vec::new();
+        while let Some(command) = read_command(&mut reader).await {
+            let reply: &[u8] = match command[0].as_str() {
+                "AUTH" | "SELECT" => b"+OK\r\n",
+                "SCRIPT" => b"$4\r\nsha1\r\n",
+                "EVALSHA" => b"-NOSCRIPT No matching script\r\n",
+                _ => b"*2\r\n:1\r\n$3\r\n2.5\r\n",
+            };
+            write.write_all(reply).await.unwrap();
+            seen.push(command);
+            if seen.len() == 5 {
+                break;
+            }
+        }
+        seen
+    });
+
+    let backend = RedisBackend::from_config(&config).unwrap();
+    let subnet = "192.0.2.0/24".parse().unwrap();
+    let taken = backend
+        .take_token(subnet, 3.0, 20.0, &config)
+        .await
+        .unwrap();
+    assert_eq!(taken, (true, 2.5));
+
+    let commands = commands.await.unwrap();
+    let names: Vec<&str> = commands.iter().map(|c| c[0].as_str()).collect();
+    assert_eq!(names, ["AUTH", "SELECT", "SCRIPT", "EVALSHA", "EVAL"]);
+    assert_eq!(commands[0][1], "secret");
+    assert_eq!(commands[1][1], "2");
+    assert_eq!(commands[3][1], "sha1");
+    assert_eq!(
+        &commands[4][2..],
+        ["1", "aegis:rate:192.0.2.0/24", "3", "20", "60000"]
+    );
+}
+
+#[tokio::test]
+async fn unreachable_redis_applies_the_failure_policy() {
+    use aegis_common::{RateLimitBackendKind, RedisFailurePolicy};
+    use aegis_proxy::engine::limiter::RateLimitBackend;
+    use aegis_proxy::engine::redis_limiter::RedisBackend;
+    use aegis_proxy::metrics::RATE_LIMIT_BACKEND_ERRORS;
+
+    let closed = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let mut config = rate_limit("token_bucket");
+    config.backend = RateLimitBackendKind::Redis;
+    config.redis_url = Some(format!("redis://{}", closed));
+    let backend = RedisBackend::from_config(&config).unwrap();
+    let client = IpNet::from(ip("198.51.100.110"));
+    assert!(backend
+        .take_token(client, 3.0, 20.0, &config)
+        .await
+        .is_err());
+
+    // No backend is installed in this test binary, which also fails.
+    let errors = RATE_LIMIT_BACKEND_ERRORS.get();
+    assert!(check_rate_limit(ip("198.51.100.110"), &config).await);
+    config.redis_failure_policy = RedisFailurePolicy::FailClosed;
+    assert!(!check_rate_limit(ip("198.51.100.110"), &config).await);
+    assert!(RATE_LIMIT_BACKEND_ERRORS.get() >= errors + 2);
+}
+
+#[test]
+fn redis_url_defaults_the_port_and_rejects_other_schemes() {
+    use aegis_proxy::engine::redis_limiter::RedisBackend;
+
+    let mut config = rate_limit("token_bucket");
+    config.redis_url = Some("redis://cache.internal".to_string());
+    let backend = RedisBackend::from_config(&config).unwrap();
+    assert_eq!(backend.address(), "cache.internal:6379");
+    config.redis_url = Some("redis://[::1]:7000/".to_string());
+    let backend = RedisBackend::from_config(&config).unwrap();
+    assert_eq!(backend.address(), "[::1]:7000");
+    config.redis_url = Some("rediss://cache.internal:6380".to_string());
+    assert!(RedisBackend::from_config(&config).is_err());
+}
+
+#[tokio::test]
+async fn ipv4_clients_in_one_slash_24_share_a_subnet_bucket() {
+    let mut config = rate_limit("token_bucket");
+    config.refill_rate = 0.001;
+    config.subnet_prefix_v4 = Some(24);
+    config.subnet_max_tokens = Some(4.0);
+    config.subnet_refill_rate = Some(0.001);
+
+    // The first address is held to its own 3 tokens, leaving one for its
+    // neighbour; the next /24 is unaffected.
+    assert_eq!(admitted(ip("203.0.113.1"), &config, 5).await, 3);
+    assert_eq!(admitted(ip("203.0.113.77"), &config, 5).await, 1);
+    assert_eq!(admitted(ip("::ffff:203.0.113.200"), &config, 5).await, 0);
+    assert_eq!(admitted(ip("203.0.114.1"), &config, 5).await, 3);
+    assert_eq!(
+        rate_limit_subnet(ip("203.0.113.77"), &config),
+        Some("203.0.113.0/24".parse().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn ipv6_clients_rotating_within_a_slash_64_share_a_subnet_bucket() {
+    let mut config = rate_limit("token_bucket");
+    config.refill_rate = 0.001;
+    config.subnet_prefix_v6 = Some(64);
+    config.subnet_refill_rate = Some(0.001);
+
+    // One connection from each of many addresses: the /64 admits its
+    // max_tokens between them.
+    let mut rotated = 0;
+    for host in 1..=10 {
+        let addr = ip(&format!("2001:db8:64:1::{:x}", host));
+        rotated += admitted(addr, &config, 1).await;
+    }
+    assert_eq!(rotated, 3);
+    assert_eq!(admitted(ip("2001:db8:64:2::1"), &config, 1).await, 1);
+    // IPv4 clients have no prefix configured and keep per-IP limits only.
+    assert_eq!(rate_limit_subnet(ip("198.51.100.120"), &config), None);
+}
+
+#[tokio::test]
+async fn rate_limit_decisions_feed_the_utilization_metrics() {
+    use aegis_proxy::metrics::{RATE_LIMIT_ALLOWED, RATE_LIMIT_TOKENS_REMAINING, TRACKED_IPS};
+
+    let mut config = rate_limit("token_bucket");
+    config.refill_rate = 0.001;
+    config.cleanup_interval_secs = 3600;
+    config.ip_idle_timeout_secs = 3600;
+    let allowed = RATE_LIMIT_ALLOWED.get();
+    let observed = RATE_LIMIT_TOKENS_REMAINING.get_sample_count();
+
+    assert_eq!(admitted(ip("198.51.100.130"), &config, 4).await, 3);
+    assert!(RATE_LIMIT_ALLOWED.get() >= allowed + 3);
+    assert!(RATE_LIMIT_TOKENS_REMAINING.get_sample_count() >= observed + 4);
+
+    // The first interval tick is immediate.
+    let cleanup = tokio::spawn(start_cleanup_task(std::sync::Arc::new(config)));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    cleanup.abort();
+    assert!(TRACKED_IPS.get() >= 1);
+}
@@ -0,0 +1,65 @@
+use aegis_proxy::metrics::{self, CounterTotals};
+
+#[test]
+fn summary_reports_counter_deltas() {
+    let _metrics = metrics::test_util::exclusive();
+    let before = CounterTotals::now();
+    metrics::ACCEPTED_CONNECTIONS.inc_by(3);
+    metrics::record_rejection(metrics::RejectReason::MalformedConnect, "default");
+    metrics::record_rejection(metrics::RejectReason::MalformedConnect, "mqtts");
+    metrics::REGION_REJECTIONS
+        .with_label_values(&["unlisted"])
+        .inc_by(2);
+    metrics::FORWARDED_BYTES.inc_by(1024);
+
+    let delta = CounterTotals::now().since(&before);
+    assert_eq!(delta.accepted, 3);
+    assert_eq!(delta.rejected_protocol, 2);
+    assert_eq!(delta.rejected_region, 2);
+    assert_eq!(delta.rejected(), 4);
+    assert_eq!(delta.bytes_forwarded, 1024);
+}
+
+#[test]
+fn itemized_rejections_are_summarized_once() {
+    let _metrics = metrics::test_util::exclusive();
+    let before = CounterTotals::now();
+    metrics::HTTP_BARE_LF_REJECTIONS.inc();
+    metrics::record_rejection(metrics::RejectReason::HttpBareLf, "default");
+    metrics::record_rejection(metrics::RejectReason::KeepAlive, "default");
+    metrics::SESSION_RATE_REJECTIONS.inc();
+    metrics::record_rejection(metrics::RejectReason::SessionRate, "default");
+
+    let delta = CounterTotals::now().since(&before);
+    assert_eq!(delta.rejected_http_bare_lf, 1);
+    assert_eq!(delta.rejected_session_rate, 1);
+    // Keep-alive rejections have no field of their own.
+    assert_eq!(delta.rejected_protocol, 1);
+    assert_eq!(delta.rejected(), 3);
+    assert_eq!(
+        metrics::REJECTIONS
+            .with_label_values(&["http_bare_lf", "default"])
+            .get(),
+        1
+    );
+}
+
+#[test]
+fn exclusive_access_starts_from_zeroed_counters() {
+    let _metrics = metrics::test_util::exclusive();
+    metrics::SESSION_RATE_REJECTIONS.inc_by(4);
+    metrics::OVERSIZED_PACKET_REJECTIONS
+        .with_label_values(&["upstream"])
+        .inc();
+    metrics::test_util::reset();
+
+    assert_eq!(metrics::SESSION_RATE_REJECTIONS.get(), 0);
+    assert_eq!(
+        metrics::OVERSIZED_PACKET_REJECTIONS
+            .with_label_values(&["upstream"])
+            .get(),
+        0
+    );
+    metrics::SESSION_RATE_REJECTIONS.inc();
+    assert_eq!(CounterTotals::now().rejected_session_rate, 1);
+}
@@ -1,4 +1,12 @@
-use aegis_proxy::parser::mqtt::{decode_remaining_length, inspect_packet, MqttPacketType};
+use aegis_proxy::parser::mqtt::{
+    connect_clean_session, connect_flags, connect_flags_byte, connect_keep_alive,
+    connect_maximum_packet_size, connect_protocol_level, decode_remaining_length, encode_connack,
+    encode_connack_with_reason, encode_remaining_length, extract_client_id, extract_username,
+    inject_user_property, inspect_packet, looks_like_mqtt_connect, parse_connect_properties,
+    set_connect_keep_alive, validate_connect_flags, ConnackRefusal, ConnectFlags,
+    ConnectProperties, MqttError, MqttPacketType, PacketSizeError, PacketSizeTracker, PublishError,
+    PublishTracker, MAX_REASON_STRING_LEN,
+};
 
 #[test]
 fn remaining_length_decodes_single_byte_127() {
@@ -49,11 +57,483 @@ fn inspect_packet_detects_connect_and_publish_and_malformed() {
     let publish_buf = [0x30u8];
     assert_eq!(inspect_packet(&publish_buf), MqttPacketType::Publish);
 
-    // Other packet type (e.g., SUBSCRIBE is 8 -> 0x80 >> 4 == 8 -> falls into Other)
-    let other_buf = [0x80u8];
-    assert_eq!(inspect_packet(&other_buf), MqttPacketType::Other);
+    // SUBSCRIBE -> high nibble = 8 (flags 0b0010) -> byte 0x82
+    let subscribe_buf = [0x82u8];
+    assert_eq!(inspect_packet(&subscribe_buf), MqttPacketType::Subscribe);
 
     // Empty payload -> Malformed
     let empty: [u8; 0] = [];
     assert_eq!(inspect_packet(&empty), MqttPacketType::Malformed);
 }
+
+#[test]
+fn inspect_packet_maps_every_control_packet_type() {
+    let expected = [
+        MqttPacketType::Malformed,
+        MqttPacketType::Connect,
+        MqttPacketType::Connack,
+        MqttPacketType::Publish,
+        MqttPacketType::Puback,
+        MqttPacketType::Pubrec,
+        MqttPacketType::Pubrel,
+        MqttPacketType::Pubcomp,
+        MqttPacketType::Subscribe,
+        MqttPacketType::Suback,
+        MqttPacketType::Unsubscribe,
+        MqttPacketType::Unsuback,
+        MqttPacketType::Pingreq,
+        MqttPacketType::Pingresp,
+        MqttPacketType::Disconnect,
+        MqttPacketType::Auth,
+    ];
+    for (nibble, packet_type) in expected.iter().enumerate() {
+        assert_eq!(inspect_packet(&[(nibble as u8) << 4]), *packet_type);
+    }
+
+    // AUTH is reserved before MQTT 5.
+    assert!(!MqttPacketType::Auth.valid_for_level(4));
+    assert!(MqttPacketType::Auth.valid_for_level(5));
+    assert!(MqttPacketType::Pingreq.valid_for_level(4));
+}
+
+#[test]
+fn connect_protocol_level_reads_partial_connect() {
+    // First 16 bytes of a v3.1.1 CONNECT (level 4) and a v5 CONNECT (level 5)
+    let v311 = b"\x10\x0f\x00\x04MQTT\x04\x02\x00\x3c\x00\x05te";
+    assert_eq!(connect_protocol_level(v311), Some(4));
+    let v5 = b"\x10\x10\x00\x04MQTT\x05\x02\x00\x3c\x00\x00\x05t";
+    assert_eq!(connect_protocol_level(v5), Some(5));
+
+    // Truncated before the level byte
+    assert_eq!(connect_protocol_level(b"\x10\x0f\x00\x04MQ"), None);
+}
+
+#[test]
+fn connect_is_detected_from_a_16_byte_prefix() {
+    // Two-byte Remaining Length (a long client id) and the largest four-byte
+    // one: the packet continues well past the peek in both cases.
+    let two_byte = b"\x10\xd0\x01\x00\x04MQTT\x04\x02\x00\x3c\x00\xc4c";
+    let four_byte = b"\x10\xff\xff\xff\x7f\x00\x04MQTT\x05\x02\x00\x3c\x00";
+    for prefix in [&two_byte[..], &four_byte[..]] {
+        assert_eq!(prefix.len(), 16);
+        assert!(looks_like_mqtt_connect(prefix));
+        assert!(!aegis_proxy::engine::http::looks_like_http(prefix));
+    }
+
+    // Too short to reach the protocol name: not (yet) a CONNECT.
+    assert!(!looks_like_mqtt_connect(&two_byte[..8]));
+}
+
+#[test]
+fn encode_connack_uses_version_specific_codes() {
+    assert_eq!(
+        encode_connack(4, ConnackRefusal::ServerBusy),
+        Some(vec![0x20, 0x02, 0x00, 0x03])
+    );
+    assert_eq!(
+        encode_connack(5, ConnackRefusal::ServerBusy),
+        Some(vec![0x20, 0x03, 0x00, 0x89, 0x00])
+    );
+    assert_eq!(encode_connack(4, ConnackRefusal::MalformedPacket), None);
+    assert_eq!(
+        encode_connack(4, ConnackRefusal::NotAuthorized),
+        Some(vec![0x20, 0x02, 0x00, 0x05])
+    );
+    assert_eq!(
+        encode_connack(5, ConnackRefusal::NotAuthorized),
+        Some(vec![0x20, 0x03, 0x00, 0x87, 0x00])
+    );
+    assert_eq!(
+        encode_connack(4, ConnackRefusal::ServerUnavailable),
+        Some(vec![0x20, 0x02, 0x00, 0x03])
+    );
+    assert_eq!(
+        encode_connack(5, ConnackRefusal::ServerUnavailable),
+        Some(vec![0x20, 0x03, 0x00, 0x88, 0x00])
+    );
+    assert_eq!(
+        encode_connack(3, ConnackRefusal::UnsupportedProtocolVersion),
+        Some(vec![0x20, 0x02, 0x00, 0x01])
+    );
+    assert_eq!(
+        encode_connack(5, ConnackRefusal::UnsupportedProtocolVersion),
+        Some(vec![0x20, 0x03, 0x00, 0x84, 0x00])
+    );
+}
+
+#[test]
+fn connack_reason_string_is_v5_only_and_bounded() {
+    assert_eq!(
+        encode_connack_with_reason(5, ConnackRefusal::MalformedPacket, Some("bad")),
+        Some(vec![
+            0x20, 0x09, 0x00, 0x81, 0x06, 0x1F, 0x00, 0x03, b'b', b'a', b'd'
+        ])
+    );
+    assert_eq!(
+        encode_connack_with_reason(4, ConnackRefusal::ServerBusy, Some("bad")),
+        encode_connack(4, ConnackRefusal::ServerBusy)
+    );
+    assert_eq!(
+        encode_connack_with_reason(4, ConnackRefusal::PolicyViolation, Some("bad")),
+        None
+    );
+
+    // Truncated to the limit without splitting a multi-byte character.
+    let long = format!("a{}", "é".repeat(MAX_REASON_STRING_LEN));
+    let connack =
+        encode_connack_with_reason(5, ConnackRefusal::PolicyViolation, Some(&long)).unwrap();
+    // Two-byte Remaining Length and property length, then 0x1F and the length.
+    assert_eq!(connack[7], 0x1F);
+    let len = u16::from_be_bytes([connack[8], connack[9]]) as usize;
+    assert_eq!(len, MAX_REASON_STRING_LEN - 1);
+    assert_eq!(connack.len(), 10 + len);
+    assert!(std::str::from_utf8(&connack[10..]).is_ok());
+}
+
+#[test]
+fn encode_remaining_length_round_trips() {
+    for value in [0usize, 127, 128, 16_383, 16_384, 2_097_151, 268_435_455] {
+        let encoded = encode_remaining_length(value);
+        assert_eq!(
+            decode_remaining_length(&encoded),
+            Ok((value, encoded.len()))
+        );
+    }
+}
+
+#[test]
+fn inject_user_property_appends_to_v5_properties() {
+    // v5 CONNECT, empty property block, client id "c1"
+    let connect = b"\x10\x0f\x00\x04MQTT\x05\x02\x00\x3c\x00\x00\x02c1";
+    let tagged = inject_user_property(connect, "k", "edge").expect("v5 CONNECT is tagged");
+
+    let mut expected = b"\x10\x19\x00\x04MQTT\x05\x02\x00\x3c\x0a".to_vec();
+    expected.extend_from_slice(b"\x26\x00\x01k\x00\x04edge");
+    expected.extend_from_slice(b"\x00\x02c1");
+    assert_eq!(tagged, expected);
+}
+
+#[test]
+fn inject_user_property_replaces_client_supplied_values() {
+    // v5 CONNECT claiming k=fake, with another user property and a session
+    // expiry around it.
+    let mut props = b"\x11\x00\x00\x00\x0a".to_vec();
+    props.extend_from_slice(b"\x26\x00\x01k\x00\x04fake");
+    props.extend_from_slice(b"\x26\x00\x02kk\x00\x01v");
+    let mut connect = b"\x10\x00\x00\x04MQTT\x05\x02\x00\x3c".to_vec();
+    connect.push(props.len() as u8);
+    connect.extend_from_slice(&props);
+    connect.extend_from_slice(b"\x00\x02c1");
+    connect[1] = (connect.len() - 2) as u8;
+
+    let tagged = inject_user_property(&connect, "k", "edge").expect("v5 CONNECT is tagged");
+
+    let mut expected_props = b"\x11\x00\x00\x00\x0a".to_vec();
+    expected_props.extend_from_slice(b"\x26\x00\x02kk\x00\x01v");
+    expected_props.extend_from_slice(b"\x26\x00\x01k\x00\x04edge");
+    let mut expected = b"\x10\x00\x00\x04MQTT\x05\x02\x00\x3c".to_vec();
+    expected.push(expected_props.len() as u8);
+    expected.extend_from_slice(&expected_props);
+    expected.extend_from_slice(b"\x00\x02c1");
+    expected[1] = (expected.len() - 2) as u8;
+    assert_eq!(tagged, expected);
+}
+
+#[test]
+fn inject_user_property_skips_v311() {
+    let connect = b"\x10\x0e\x00\x04MQTT\x04\x02\x00\x3c\x00\x02c1";
+    assert_eq!(inject_user_property(connect, "k", "edge"), None);
+}
+
+#[test]
+fn connect_keep_alive_reads_and_rewrites_in_place() {
+    let mut connect = b"\x10\x0e\x00\x04MQTT\x04\x02\x00\x3c\x00\x02c1".to_vec();
+    assert_eq!(connect_keep_alive(&connect), Some(60));
+
+    assert!(set_connect_keep_alive(&mut connect, 1800));
+    assert_eq!(connect_keep_alive(&connect), Some(1800));
+    assert_eq!(&connect[10..12], &[0x07, 0x08]);
+    assert_eq!(connect.len(), 16);
+
+    // Truncated before the keep-alive field.
+    assert_eq!(connect_keep_alive(&connect[..10]), None);
+    assert!(!set_connect_keep_alive(&mut connect[..9], 10));
+}
+
+#[test]
+fn maximum_packet_size_is_read_from_v5_connect_properties() {
+    // Properties: Session Expiry Interval (0x11), User Property, Maximum Packet Size 1024.
+    let mut connect = b"\x10\x00\x00\x04MQTT\x05\x02\x00\x3c".to_vec();
+    let props = b"\x11\x00\x00\x00\x0a\x26\x00\x01k\x00\x01v\x27\x00\x00\x04\x00";
+    connect.push(props.len() as u8);
+    connect.extend_from_slice(props);
+    connect.extend_from_slice(b"\x00\x01c");
+    connect[1] = (connect.len() - 2) as u8;
+    assert_eq!(connect_maximum_packet_size(&connect), Some(1024));
+
+    // v3.1.1 has no properties; v5 without the property.
+    assert_eq!(
+        connect_maximum_packet_size(b"\x10\x0e\x00\x04MQTT\x04\x02\x00\x3c\x00\x02c1"),
+        None
+    );
+    assert_eq!(
+        connect_maximum_packet_size(b"\x10\x0f\x00\x04MQTT\x05\x02\x00\x3c\x00\x00\x02c1"),
+        None
+    );
+}
+
+/// CONNECT payload (after the fixed header) with the given v5 properties.
+fn v5_connect_payload(props: &[u8]) -> Vec<u8> {
+    let mut payload = b"\x00\x04MQTT\x05\x02\x00\x3c".to_vec();
+    payload.extend_from_slice(&encode_remaining_length(props.len()));
+    payload.extend_from_slice(props);
+    payload.extend_from_slice(b"\x00\x01c");
+    payload
+}
+
+#[test]
+fn connect_properties_are_parsed_from_v5_payload() {
+    // Session Expiry 60, Receive Maximum 10, User Property, Maximum Packet
+    // Size 1024, Topic Alias Maximum 5.
+    let props =
+        b"\x11\x00\x00\x00\x3c\x21\x00\x0a\x26\x00\x01k\x00\x01v\x27\x00\x00\x04\x00\x22\x00\x05";
+    assert_eq!(
+        parse_connect_properties(&v5_connect_payload(props)),
+        Ok(ConnectProperties {
+            session_expiry_interval: Some(60),
+            receive_maximum: Some(10),
+            maximum_packet_size: Some(1024),
+            topic_alias_maximum: Some(5),
+        })
+    );
+
+    // v3.1.1 has no properties.
+    assert_eq!(
+        parse_connect_properties(b"\x00\x04MQTT\x04\x02\x00\x3c\x00\x02c1"),
+        Ok(ConnectProperties::default())
+    );
+}
+
+#[test]
+fn invalid_connect_properties_are_rejected() {
+    // Maximum Packet Size and Receive Maximum of zero are protocol errors.
+    assert!(matches!(
+        parse_connect_properties(&v5_connect_payload(b"\x27\x00\x00\x00\x00")),
+        Err(MqttError::Malformed(_))
+    ));
+    assert!(matches!(
+        parse_connect_properties(&v5_connect_payload(b"\x21\x00\x00")),
+        Err(MqttError::Malformed(_))
+    ));
+    // Duplicates, and identifiers that only appear in other packets.
+    assert!(matches!(
+        parse_connect_properties(&v5_connect_payload(b"\x21\x00\x01\x21\x00\x02")),
+        Err(MqttError::Malformed(_))
+    ));
+    assert!(matches!(
+        parse_connect_properties(&v5_connect_payload(b"\x01\x01")),
+        Err(MqttError::Malformed(_))
+    ));
+}
+
+#[test]
+fn truncated_connect_properties_are_incomplete() {
+    let payload = v5_connect_payload(b"\x11\x00\x00\x00\x3c\x21\x00\x0a");
+    // Cut inside the property block, at the property length, and before the level.
+    for end in [14, 11, 6] {
+        assert_eq!(
+            parse_connect_properties(&payload[..end]),
+            Err(MqttError::Incomplete),
+            "cut at {}",
+            end
+        );
+    }
+}
+
+#[test]
+fn packet_size_tracker_follows_boundaries_across_reads() {
+    let mut tracker = PacketSizeTracker::new(8);
+    // PINGREQ, then a 6-byte PUBLISH split mid-body, then a SUBSCRIBE split mid-length.
+    assert_eq!(tracker.observe(&[0xC0, 0x00, 0x30, 0x04, b't']), Ok(()));
+    assert_eq!(tracker.observe(&[b'o', b'p', b'!', 0x82]), Ok(()));
+    assert_eq!(tracker.observe(&[0x07]), Err(PacketSizeError::TooLarge(9)));
+
+    // Multi-byte Remaining Length.
+    let mut tracker = PacketSizeTracker::new(1024);
+    assert_eq!(
+        tracker.observe(&[0x30, 0x80, 0x08]),
+        Err(PacketSizeError::TooLarge(1027))
+    );
+    let mut tracker = PacketSizeTracker::new(u32::MAX);
+    assert_eq!(
+        tracker.observe(&[0x30, 0xFF, 0xFF, 0xFF, 0xFF]),
+        Err(PacketSizeError::MalformedLength)
+    );
+}
+
+#[test]
+fn publish_tracker_checks_topics_split_across_reads() {
+    let mut tracker = PublishTracker::new(false, None);
+    let mut seen = Vec::new();
+    let mut observe = |tracker: &mut PublishTracker, bytes: &[u8]| {
+        tracker.observe(bytes, |topic| {
+            seen.push(topic.to_string());
+            topic != "denied"
+        })
+    };
+    // PINGREQ, then a PUBLISH to "a/b" split inside the topic length and name.
+    assert_eq!(
+        observe(&mut tracker, &[0xC0, 0x00, 0x30, 0x07, 0x00]),
+        Ok(())
+    );
+    assert_eq!(observe(&mut tracker, &[0x03, b'a', b'/']), Ok(()));
+    assert_eq!(observe(&mut tracker, b"bhi"), Ok(()));
+    // An empty topic name (a v5 topic alias) is not checked.
+    assert_eq!(
+        observe(&mut tracker, &[0x30, 0x05, 0x00, 0x00, 0x02, 0x23, 0x01]),
+        Ok(())
+    );
+    // A SUBSCRIBE body is not a topic name.
+    assert_eq!(
+        observe(&mut tracker, &[0x82, 0x03, 0x00, 0x01, b'x']),
+        Ok(())
+    );
+    assert_eq!(observe(&mut tracker, &[0x32, 0x0a, 0x00, 0x06]), Ok(()));
+    assert_eq!(
+        observe(&mut tracker, b"denied\x00\x01"),
+        Err(PublishError::Denied("denied".to_string()))
+    );
+    assert_eq!(seen, ["a/b", "denied"]);
+
+    // Topic name longer than the packet.
+    let mut tracker = PublishTracker::new(false, None);
+    assert_eq!(
+        tracker.observe(&[0x30, 0x03, 0x00, 0x05, b'a'], |_| true),
+        Err(PublishError::Malformed)
+    );
+}
+
+#[test]
+fn publish_tracker_limits_payloads_split_across_reads() {
+    let mut tracker = PublishTracker::new(true, Some(4));
+    let mut observe = |bytes: &[u8]| tracker.observe(bytes, |_| true);
+    // v5 PUBLISH to "t" with no properties and a 4-byte payload, in pieces.
+    for piece in [
+        &[0x30][..],
+        &[0x08, 0x00],
+        &[0x01, b't'],
+        &[0x00, b'a', b'b'],
+        b"cd",
+    ] {
+        assert_eq!(observe(piece), Ok(()));
+    }
+    // Three bytes of properties, then a 5-byte payload: refused as soon as
+    // the properties length is read, before any payload arrives.
+    assert_eq!(observe(&[0x30, 0x0c, 0x00, 0x01, b't']), Ok(()));
+    assert_eq!(observe(&[0x03]), Err(PublishError::PayloadTooLarge(5)));
+
+    // v3.1.1 QoS 1: the packet identifier is not payload; a Remaining Length
+    // split across reads.
+    let mut tracker = PublishTracker::new(false, Some(100));
+    assert_eq!(
+        tracker.observe(&[0x32, 0x69, 0x00, 0x01, b't', 0x00, 0x01], |_| true),
+        Ok(())
+    );
+    assert_eq!(tracker.observe(&[0u8; 100], |_| true), Ok(()));
+    assert_eq!(tracker.observe(&[0x30, 0xCB], |_| true), Ok(()));
+    assert_eq!(
+        tracker.observe(&[0x01, 0x00, 0x01, b't'], |_| true),
+        Err(PublishError::PayloadTooLarge(200))
+    );
+}
+
+#[test]
+fn client_id_is_read_after_the_variable_header() {
+    // v3.1.1: no properties.
+    assert_eq!(
+        extract_client_id(b"\x00\x04MQTT\x04\x02\x00\x3c\x00\x02c1"),
+        Some("c1")
+    );
+    // v5: skips the property block.
+    let payload = v5_connect_payload(b"\x21\x00\x0a");
+    assert_eq!(extract_client_id(&payload), Some("c"));
+    assert_eq!(connect_clean_session(&payload), Some(true));
+
+    // Truncated or not UTF-8.
+    assert_eq!(
+        extract_client_id(b"\x00\x04MQTT\x04\x00\x00\x3c\x00\x05c1"),
+        None
+    );
+    assert_eq!(
+        extract_client_id(b"\x00\x04MQTT\x04\x00\x00\x3c\x00\x02\xc3\x28"),
+        None
+    );
+    assert_eq!(
+        connect_clean_session(b"\x00\x04MQTT\x04\x00\x00\x3c\x00\x00"),
+        Some(false)
+    );
+}
+
+#[test]
+fn connect_flags_are_decoded() {
+    // User name, password, will retain, will QoS 1, will, clean session.
+    assert_eq!(
+        connect_flags(b"\x00\x04MQTT\x04\xee\x00\x3c"),
+        Some(ConnectFlags {
+            username: true,
+            password: true,
+            will_retain: true,
+            will_qos: 1,
+            will: true,
+            clean_session: true,
+        })
+    );
+    assert_eq!(connect_flags(b"\x00\x04MQTT\x04"), None);
+}
+
+#[test]
+fn connect_flags_are_validated() {
+    assert_eq!(
+        connect_flags_byte(b"\x00\x04MQTT\x04\xee\x00\x3c"),
+        Some(0xee)
+    );
+    for legal in [0x00, 0x02, 0xc2, 0x04, 0x0c, 0x14, 0x34] {
+        assert_eq!(validate_connect_flags(legal), Ok(()), "{:#04x}", legal);
+    }
+    assert_eq!(
+        validate_connect_flags(0x03),
+        Err("reserved connect flag set")
+    );
+    assert_eq!(validate_connect_flags(0x1c), Err("will QoS 3"));
+    for orphaned in [0x08, 0x10, 0x20] {
+        assert_eq!(
+            validate_connect_flags(orphaned),
+            Err("will QoS or retain without the will flag")
+        );
+    }
+}
+
+#[test]
+fn username_is_read_after_client_id_and_will() {
+    let plain = b"\x00\x04MQTT\x04\xc2\x00\x3c\x00\x01c\x00\x05alice\x00\x02pw";
+    assert_eq!(extract_username(plain), Some("alice"));
+
+    // v3.1.1 will topic and payload come first.
+    let with_will = b"\x00\x04MQTT\x04\x86\x00\x3c\x00\x01c\x00\x01t\x00\x02wp\x00\x03bob";
+    assert_eq!(extract_username(with_will), Some("bob"));
+
+    // v5 will properties precede the will topic.
+    let mut v5 = b"\x00\x04MQTT\x05\x86\x00\x3c\x00\x00\x01c\x02\x01\x01".to_vec();
+    v5.extend_from_slice(b"\x00\x01t\x00\x00\x00\x03eve");
+    assert_eq!(extract_username(&v5), Some("eve"));
+
+    // Flag unset, or the name runs past the packet.
+    assert_eq!(
+        extract_username(b"\x00\x04MQTT\x04\x02\x00\x3c\x00\x01c"),
+        None
+    );
+    assert_eq!(
+        extract_username(b"\x00\x04MQTT\x04\x82\x00\x3c\x00\x01c\x00\x05al"),
+        None
+    );
+}
@@ -0,0 +1,80 @@
+use aegis_common::RepeatedMalformedConfig;
+use aegis_proxy::engine::limiter::{malformed_banned, record_malformed, HeldState};
+use aegis_proxy::engine::persist::{decode, encode, load, save};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn state_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("aegis-{}-{}.state", name, std::process::id()))
+}
+
+#[test]
+fn state_round_trips_through_the_file_format() {
+    let until = UNIX_EPOCH + Duration::from_secs(1_767_225_600);
+    let held = vec![
+        HeldState::ConnectRatioBan(ip("203.0.113.7"), until),
+        HeldState::MalformedBan(ip("2001:db8::1"), until),
+        HeldState::Throttled(ip("198.51.100.2")),
+    ];
+    let text = encode(&held);
+    assert!(text.contains("connect_ratio_ban 203.0.113.7 1767225600\n"));
+    assert_eq!(decode(&text), Ok(held));
+}
+
+#[test]
+fn corrupt_state_is_rejected_whole() {
+    assert!(decode("").is_err());
+    assert!(decode("throttled 198.51.100.2\n").is_err());
+    assert!(decode("# aegis limiter state v1\nthrottled 198.51.100.2\nbanned forever\n").is_err());
+    assert!(decode("# aegis limiter state v1\nmalformed_ban 2001:db8::1 soon\n").is_err());
+}
+
+#[test]
+fn missing_or_corrupt_files_start_fresh() {
+    let missing = state_path("missing");
+    let _ = std::fs::remove_file(&missing);
+    assert_eq!(load(&missing), 0);
+
+    let corrupt = state_path("corrupt");
+    std::fs::write(&corrupt, "not a state file").unwrap();
+    assert_eq!(load(&corrupt), 0);
+    let _ = std::fs::remove_file(&corrupt);
+}
+
+#[test]
+fn bans_survive_a_save_and_reload() {
+    let client = ip("198.51.100.81");
+    let config = RepeatedMalformedConfig {
+        threshold: 1,
+        window_secs: 60,
+        max_hashes_per_ip: 4,
+        ban_secs: 600,
+    };
+    record_malformed(client, b"junk", &config);
+    assert!(malformed_banned(client));
+
+    let path = state_path("bans");
+    save(&path).unwrap();
+    let saved = decode(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert!(saved
+        .iter()
+        .any(|s| matches!(s, HeldState::MalformedBan(addr, _) if *addr == client)));
+
+    // An expired ban in the file is not restored.
+    let expired = ip("198.51.100.82");
+    let past = SystemTime::now() - Duration::from_secs(60);
+    std::fs::write(&path, encode(&[HeldState::MalformedBan(expired, past)])).unwrap();
+    assert_eq!(load(&path), 0);
+    assert!(!malformed_banned(expired));
+
+    let restored = ip("198.51.100.83");
+    let future = SystemTime::now() + Duration::from_secs(600);
+    std::fs::write(&path, encode(&[HeldState::MalformedBan(restored, future)])).unwrap();
+    assert_eq!(load(&path), 1);
+    assert!(malformed_banned(restored));
+    let _ = std::fs::remove_file(&path);
+}
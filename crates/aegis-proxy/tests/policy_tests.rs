@@ -0,0 +1,123 @@
+use aegis_common::{FeaturesConfig, LimitConfig, SourcePolicyConfig};
+use aegis_proxy::engine::policy::{SourcePolicy, GLOBAL_PROFILE};
+use std::net::IpAddr;
+
+fn features() -> FeaturesConfig {
+    serde_yaml::from_str(
+        "enable_mqtt_inspection: true
+enable_mqtt_full_inspection: true
+enable_http_inspection: true
+enable_slowloris_protection: true
+enable_rate_limiter: false
+enable_ebpf: false
+enable_ml: false",
+    )
+    .unwrap()
+}
+
+fn limit() -> LimitConfig {
+    serde_yaml::from_str(
+        "max_tokens: 5.0
+refill_rate: 1.0
+cleanup_interval_secs: 60
+ip_idle_timeout_secs: 60",
+    )
+    .unwrap()
+}
+
+fn policy(yaml: &str) -> Result<SourcePolicy, String> {
+    let config: SourcePolicyConfig = serde_yaml::from_str(yaml).unwrap();
+    SourcePolicy::from_config(Some(&config), &features(), &limit())
+}
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+const POLICY: &str = "
+default_profile: unknown
+profiles:
+  internal:
+    cidrs: [\"10.0.0.0/8\"]
+    enable_mqtt_full_inspection: false
+  lab:
+    cidrs: [\"10.20.0.0/16\"]
+    enable_slowloris_protection: false
+  unknown:
+    enable_rate_limiter: true
+    max_tokens: 2.0
+";
+
+#[test]
+fn longest_prefix_match_wins() {
+    let policy = policy(POLICY).unwrap();
+    assert_eq!(policy.select(ip("10.1.2.3")).name, "internal");
+    assert_eq!(policy.select(ip("10.20.1.1")).name, "lab");
+    assert_eq!(policy.select(ip("::ffff:10.20.1.1")).name, "lab");
+}
+
+#[test]
+fn unmatched_clients_get_the_default_profile() {
+    let policy = policy(POLICY).unwrap();
+    let profile = policy.select(ip("198.51.100.1"));
+    assert_eq!(profile.name, "unknown");
+    assert!(profile.features.enable_rate_limiter);
+    assert_eq!(profile.limit.max_tokens, 2.0);
+    assert_eq!(profile.limit.refill_rate, 1.0);
+    assert!(policy.rate_limiting_enabled());
+}
+
+#[test]
+fn overrides_inherit_unset_global_settings() {
+    let policy = policy(POLICY).unwrap();
+    let internal = policy.select(ip("10.1.2.3"));
+    assert!(!internal.features.enable_mqtt_full_inspection);
+    assert!(internal.features.enable_mqtt_inspection);
+    assert!(internal.features.enable_slowloris_protection);
+    assert_eq!(internal.limit.max_tokens, 5.0);
+}
+
+#[test]
+fn global_settings_apply_without_a_policy() {
+    let policy = SourcePolicy::from_config(None, &features(), &limit()).unwrap();
+    let profile = policy.select(ip("10.1.2.3"));
+    assert_eq!(profile.name, GLOBAL_PROFILE);
+    assert!(profile.features.enable_mqtt_full_inspection);
+    assert!(!policy.rate_limiting_enabled());
+}
+
+#[test]
+fn undefined_default_profile_is_rejected() {
+    let err = policy("default_profile: missing\nprofiles: {}\n").unwrap_err();
+    assert!(err.contains("missing"));
+}
+
+#[test]
+fn zero_refill_rate_is_rejected_when_rate_limiting() {
+    let err = policy(
+        "profiles:
+  partners:
+    cidrs: [\"192.0.2.0/24\"]
+    enable_rate_limiter: true
+    refill_rate: 0.0
+",
+    )
+    .unwrap_err();
+    assert!(err.contains("partners") && err.contains("refill_rate"));
+
+    let mut limit = limit();
+    limit.refill_rate = 0.0;
+    let mut features = features();
+    assert!(SourcePolicy::from_config(None, &features, &limit).is_ok());
+    features.enable_rate_limiter = true;
+    let err = SourcePolicy::from_config(None, &features, &limit).unwrap_err();
+    assert!(err.starts_with("limit: refill_rate"));
+}
+
+#[test]
+fn connect_ratio_below_one_is_rejected() {
+    let mut limit = limit();
+    limit.connect_ratio = Some(serde_yaml::from_str("max_ratio: 0.5").unwrap());
+    let err = SourcePolicy::from_config(None, &features(), &limit).unwrap_err();
+    assert!(err.starts_with("limit: connect_ratio.max_ratio"));
+}
@@ -0,0 +1,182 @@
+use aegis_proxy::engine::proxy_protocol::{
+    find_tlv, parse_proxy_v2, tlv_label, v1_header, ProxyError, V2_MAX_TLVS, V2_SIGNATURE,
+};
+use std::net::SocketAddr;
+
+fn addr(s: &str) -> Option<SocketAddr> {
+    Some(s.parse().unwrap())
+}
+
+/// A v2 header with the given command/version byte, family and address block.
+fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+    let mut header = V2_SIGNATURE.to_vec();
+    header.push(command);
+    header.push(family);
+    header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+    header.extend_from_slice(addresses);
+    header
+}
+
+const IPV4_BLOCK: [u8; 12] = [192, 0, 2, 10, 198, 51, 100, 1, 0xdc, 0x04, 0x1f, 0x90];
+
+#[test]
+fn v1_header_for_ipv4_and_ipv6() {
+    assert_eq!(
+        v1_header(addr("192.0.2.10:56324"), addr("198.51.100.1:8080")),
+        "PROXY TCP4 192.0.2.10 198.51.100.1 56324 8080\r\n"
+    );
+    assert_eq!(
+        v1_header(addr("[2001:db8::1]:40000"), addr("[2001:db8::2]:8883")),
+        "PROXY TCP6 2001:db8::1 2001:db8::2 40000 8883\r\n"
+    );
+}
+
+#[test]
+fn v1_header_reports_mapped_addresses_as_ipv4() {
+    assert_eq!(
+        v1_header(
+            addr("[::ffff:192.0.2.10]:1234"),
+            addr("[::ffff:192.0.2.1]:8080")
+        ),
+        "PROXY TCP4 192.0.2.10 192.0.2.1 1234 8080\r\n"
+    );
+}
+
+#[test]
+fn v1_header_falls_back_to_unknown() {
+    assert_eq!(v1_header(None, addr("192.0.2.1:8080")), "PROXY UNKNOWN\r\n");
+    assert_eq!(
+        v1_header(addr("192.0.2.10:1234"), addr("[2001:db8::2]:8080")),
+        "PROXY UNKNOWN\r\n"
+    );
+}
+
+#[test]
+fn v2_header_yields_the_ipv4_client() {
+    let mut buf = v2(0x21, 0x11, &IPV4_BLOCK);
+    let len = buf.len();
+    buf.extend_from_slice(b"\x10\x0c");
+    assert_eq!(
+        parse_proxy_v2(&buf),
+        Ok(("192.0.2.10:56324".parse().unwrap(), len))
+    );
+}
+
+#[test]
+fn v2_header_yields_the_ipv6_client_and_skips_tlvs() {
+    let mut block = Vec::new();
+    block.extend_from_slice(
+        &"2001:db8::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    block.extend_from_slice(
+        &"2001:db8::2"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    block.extend_from_slice(&[0x9c, 0x40, 0x22, 0xb3]);
+    // A trailing TLV (PP2_TYPE_AUTHORITY) is covered by the length and skipped.
+    block.extend_from_slice(&[0x02, 0x00, 0x03, b'a', b'b', b'c']);
+    let buf = v2(0x21, 0x21, &block);
+    assert_eq!(
+        parse_proxy_v2(&buf),
+        Ok(("[2001:db8::1]:40000".parse().unwrap(), buf.len()))
+    );
+}
+
+#[test]
+fn v2_local_and_unspec_carry_no_client() {
+    let local = v2(0x20, 0x00, &[]);
+    assert_eq!(
+        parse_proxy_v2(&local),
+        Err(ProxyError::Local { header_len: 16 })
+    );
+    let unix = v2(0x21, 0x31, &[0u8; 216]);
+    assert_eq!(
+        parse_proxy_v2(&unix),
+        Err(ProxyError::Local { header_len: 232 })
+    );
+}
+
+#[test]
+fn v2_rejects_malformed_headers() {
+    let mut bad_signature = v2(0x21, 0x11, &IPV4_BLOCK);
+    bad_signature[0] = b'X';
+    assert!(matches!(
+        parse_proxy_v2(&bad_signature),
+        Err(ProxyError::Malformed(_))
+    ));
+    assert!(matches!(
+        parse_proxy_v2(&v2(0x11, 0x11, &IPV4_BLOCK)),
+        Err(ProxyError::Malformed(_))
+    ));
+    assert!(matches!(
+        parse_proxy_v2(&v2(0x22, 0x11, &IPV4_BLOCK)),
+        Err(ProxyError::Malformed(_))
+    ));
+    assert!(matches!(
+        parse_proxy_v2(&v2(0x21, 0x11, &IPV4_BLOCK[..8])),
+        Err(ProxyError::Malformed(_))
+    ));
+    assert!(matches!(
+        parse_proxy_v2(&v2(0x21, 0x11, &[0u8; 600])),
+        Err(ProxyError::Malformed(_))
+    ));
+}
+
+#[test]
+fn v2_needs_the_whole_header() {
+    let buf = v2(0x21, 0x11, &IPV4_BLOCK);
+    assert_eq!(parse_proxy_v2(&buf[..10]), Err(ProxyError::Incomplete));
+    assert_eq!(
+        parse_proxy_v2(&buf[..buf.len() - 1]),
+        Err(ProxyError::Incomplete)
+    );
+}
+
+/// `IPV4_BLOCK` followed by the given TLVs.
+fn v2_with_tlvs(tlvs: &[(u8, &[u8])]) -> Vec<u8> {
+    let mut block = IPV4_BLOCK.to_vec();
+    for (kind, value) in tlvs {
+        block.push(*kind);
+        block.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        block.extend_from_slice(value);
+    }
+    v2(0x21, 0x11, &block)
+}
+
+#[test]
+fn v2_tlvs_are_found_by_type() {
+    let header = v2_with_tlvs(&[(0x04, b"\0\0\0\0"), (0xEA, b"\x01vpce-0a1b")]);
+    let value = find_tlv(&header, 0xEA).unwrap().unwrap();
+    assert_eq!(value, b"\x01vpce-0a1b");
+    // The AWS subtype byte is a control character and left out of the label.
+    assert_eq!(tlv_label(value), "vpce-0a1b");
+    assert_eq!(find_tlv(&header, 0x05), Ok(None));
+    assert_eq!(find_tlv(&v2(0x21, 0x11, &IPV4_BLOCK), 0xEA), Ok(None));
+}
+
+#[test]
+fn malformed_v2_tlvs_are_rejected() {
+    // A TLV claiming more bytes than the header holds.
+    let mut block = IPV4_BLOCK.to_vec();
+    block.extend_from_slice(b"\xEA\x00\x0avpce");
+    let truncated = v2(0x21, 0x11, &block);
+    assert!(matches!(
+        find_tlv(&truncated, 0xEA),
+        Err(ProxyError::Malformed(_))
+    ));
+
+    let noop: (u8, &[u8]) = (0x04, b"");
+    let many = v2_with_tlvs(&vec![noop; V2_MAX_TLVS + 1]);
+    assert!(matches!(
+        find_tlv(&many, 0xEA),
+        Err(ProxyError::Malformed(_))
+    ));
+
+    let long = "x".repeat(200);
+    assert_eq!(tlv_label(long.as_bytes()).len(), 64);
+}
@@ -0,0 +1,92 @@
+use aegis_common::{load_config, BackendCircuitConfig, BackendTarget, Config};
+use aegis_proxy::reload::{reload, restart_required};
+use arc_swap::ArcSwap;
+use std::path::{Path, PathBuf};
+
+fn shipped_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../config/aegis_config.yaml")
+}
+
+/// The shipped configuration with `edit` applied, written to a file of its
+/// own named after `test`.
+fn config_file(test: &str, edit: impl FnOnce(&mut serde_yaml::Value)) -> PathBuf {
+    let mut value: serde_yaml::Value =
+        serde_yaml::from_str(&std::fs::read_to_string(shipped_path()).unwrap()).unwrap();
+    edit(&mut value);
+    let path =
+        std::env::temp_dir().join(format!("aegis-reload-{}-{}.yaml", test, std::process::id()));
+    std::fs::write(&path, serde_yaml::to_string(&value).unwrap()).unwrap();
+    path
+}
+
+/// Per-listener settings standing in for the proxy's: the CONNECT cap.
+fn build(config: Config, _running: &Config) -> Result<Option<usize>, String> {
+    Ok(config.proxy.max_connect_remaining)
+}
+
+fn startup() -> Config {
+    load_config(shipped_path()).unwrap()
+}
+
+#[test]
+fn valid_reload_swaps_every_listener_setting_at_once() {
+    let startup = startup();
+    let live = ArcSwap::from_pointee(vec![startup.proxy.max_connect_remaining]);
+    let path = config_file("applied", |value| {
+        value["proxy"]["max_connect_remaining"] = 1234.into();
+    });
+
+    let result = reload(&path, &live, &startup, build);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(result, Ok(()));
+    assert_eq!(**live.load(), [Some(1234)]);
+}
+
+#[test]
+fn reload_changing_the_listener_set_is_rejected() {
+    let startup = startup();
+    let live = ArcSwap::from_pointee(vec![Some(1)]);
+    let path = config_file("listeners", |value| {
+        value["proxy"]["listeners"] =
+            serde_yaml::from_str("[{name: extra, address: \"127.0.0.1:18830\"}]").unwrap();
+    });
+
+    let result = reload(&path, &live, &startup, build);
+    let _ = std::fs::remove_file(&path);
+    assert!(result.unwrap_err().contains("restart"));
+    assert_eq!(**live.load(), [Some(1)], "running settings kept");
+}
+
+#[test]
+fn reload_that_fails_to_build_keeps_the_running_settings() {
+    let startup = startup();
+    let live = ArcSwap::from_pointee(vec![Some(1)]);
+    let path = config_file("build", |_| {});
+
+    let result = reload(&path, &live, &startup, |_, _| {
+        Err::<Option<usize>, _>("bad policy".to_string())
+    });
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(result, Err("bad policy".to_string()));
+    assert_eq!(**live.load(), [Some(1)]);
+}
+
+#[test]
+fn startup_only_settings_are_reported() {
+    let startup = startup();
+    assert!(restart_required(&startup, &startup).is_empty());
+
+    let mut next = startup.clone();
+    next.proxy.target_addresses = Some(vec![BackendTarget::Address("broker:1883".to_string())]);
+    next.proxy.backend_circuit = Some(BackendCircuitConfig {
+        failure_threshold: 3,
+        window_ms: 1000,
+        cooldown_ms: 1000,
+        reject_at_accept: false,
+        busy_signal: false,
+    });
+    assert_eq!(
+        restart_required(&next, &startup),
+        ["proxy.target_addresses", "proxy.backend_circuit"]
+    );
+}
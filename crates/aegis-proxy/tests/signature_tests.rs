@@ -0,0 +1,37 @@
+use aegis_common::SignatureFastPathConfig;
+use aegis_proxy::engine::signature::{FastPath, SignatureSet};
+
+fn signatures(signatures: &[&str], verify_every: u64) -> Result<SignatureSet, String> {
+    SignatureSet::from_config(&SignatureFastPathConfig {
+        signatures: signatures.iter().map(|s| s.to_string()).collect(),
+        verify_every,
+    })
+}
+
+const CONNECT: &[u8] = &[
+    0x10, 0x0d, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x02, 0x00, 0x3c, 0x00, 0x01, b'c',
+];
+
+#[test]
+fn matching_prefix_takes_fast_path_with_sampled_verification() {
+    let set = signatures(&["100d0004 4d515454 0402"], 3).unwrap();
+    assert_eq!(set.max_len(), 10);
+    assert_eq!(set.classify(CONNECT), FastPath::Hit);
+    assert_eq!(set.classify(CONNECT), FastPath::Hit);
+    assert_eq!(set.classify(CONNECT), FastPath::Verify);
+    assert_eq!(set.classify(b"GET / HTTP/1.1\r\n"), FastPath::Miss);
+    // A peek shorter than the signature never matches.
+    assert_eq!(set.classify(&CONNECT[..6]), FastPath::Miss);
+}
+
+#[test]
+fn signatures_are_bounded() {
+    assert!(signatures(&[], 100).is_err());
+    assert!(signatures(&["1010"], 100).is_err());
+    assert!(signatures(&[&"ab".repeat(65)], 100).is_err());
+    assert!(signatures(&["100d0"], 100).is_err());
+    assert!(signatures(&["zz0d0004"], 100).is_err());
+    assert!(signatures(&["100d0004"; 17], 100).is_err());
+    assert!(signatures(&["100d0004"], 0).is_err());
+    assert!(signatures(&["100d0004"; 16], 1).is_ok());
+}
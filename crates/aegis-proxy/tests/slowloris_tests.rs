@@ -1,7 +1,10 @@
 use std::time::Duration;
 
-use aegis_proxy::engine::slowloris::{read_with_idle_timeout, read_with_timeout, TimeoutReader};
-use tokio::io::AsyncReadExt;
+use aegis_proxy::engine::slowloris::{
+    read_with_idle_timeout, read_with_throughput_floor, read_with_timeout, BudgetExceeded,
+    InspectionBudget, ThroughputTooLow, TimeoutReader, TimeoutWriter, WriteStalled,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 #[tokio::test]
 async fn test_read_with_timeout_success() {
@@ -48,3 +51,113 @@ async fn test_read_with_idle_timeout_success() {
     assert_eq!(n, 10);
     assert_eq!(&buf, &data[..10]);
 }
+
+#[tokio::test]
+async fn read_with_throughput_floor_rejects_a_trickle_inside_the_idle_timeout() {
+    let (mut tx, mut rx) = tokio::io::duplex(64);
+    // 5 bytes/s, each byte well inside the 500ms idle timeout.
+    tokio::spawn(async move {
+        for _ in 0..64 {
+            if tx.write_all(b"x").await.is_err() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    });
+
+    let started = std::time::Instant::now();
+    let mut buf = [0u8; 64];
+    let err = read_with_throughput_floor(
+        &mut rx,
+        &mut buf,
+        Duration::from_millis(500),
+        Duration::from_secs(30),
+        20,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    assert!(ThroughputTooLow::is(&err));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // Data that is already there clears any floor.
+    let data = [7u8; 32];
+    let mut buf = [0u8; 32];
+    let n = read_with_throughput_floor(
+        &mut &data[..],
+        &mut buf,
+        Duration::from_millis(500),
+        Duration::from_secs(1),
+        1_000_000,
+    )
+    .await
+    .unwrap();
+    assert_eq!(n, 32);
+}
+
+#[tokio::test]
+async fn test_timeout_writer_passes_through_when_peer_reads() {
+    let (client, mut server) = tokio::io::duplex(64);
+    let mut writer = TimeoutWriter::new(client, Duration::from_millis(200));
+
+    writer.write_all(b"hello").await.unwrap();
+    let mut buf = [0u8; 5];
+    server.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+
+    let _client = writer.into_inner();
+}
+
+#[tokio::test]
+async fn test_timeout_writer_times_out_when_peer_stops_reading() {
+    // The duplex buffer holds 16 bytes; nobody drains the other end.
+    let (client, _server) = tokio::io::duplex(16);
+    let mut writer = TimeoutWriter::new(client, Duration::from_millis(100));
+
+    let err = writer.write_all(&[0u8; 64]).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    assert!(WriteStalled::is(&err));
+}
+
+#[tokio::test]
+async fn inspection_budget_stops_a_busy_parser() {
+    let mut budget = InspectionBudget::new(Duration::from_millis(30));
+
+    // Cheap work finishes and is charged.
+    assert_eq!(budget.run(async { 7 }).await, Ok(7));
+
+    // A "parser" burning 10ms per step between awaits is cut off at a poll
+    // boundary once the budget is spent.
+    let busy = async {
+        loop {
+            std::thread::sleep(Duration::from_millis(10));
+            tokio::task::yield_now().await;
+        }
+    };
+    assert_eq!(budget.run(busy).await, Err(BudgetExceeded));
+    assert!(budget.spent() >= Duration::from_millis(30));
+
+    // The budget is per connection: later phases see it exhausted.
+    assert_eq!(budget.run(async { 1 }).await, Err(BudgetExceeded));
+}
+
+#[tokio::test]
+async fn inspection_budget_ignores_time_waiting_for_data() {
+    let mut budget = InspectionBudget::new(Duration::from_millis(20));
+    let waiting = tokio::time::sleep(Duration::from_millis(60));
+    assert_eq!(budget.run(waiting).await, Ok(()));
+    assert!(budget.spent() < Duration::from_millis(20));
+}
+
+#[tokio::test]
+async fn timeout_reader_fails_after_idle_timeout() {
+    let (mut tx, rx) = tokio::io::duplex(64);
+    let mut reader = TimeoutReader::new(rx, Duration::from_millis(100));
+    let mut buf = [0u8; 8];
+
+    tx.write_all(b"ab").await.unwrap();
+    assert_eq!(reader.read(&mut buf).await.unwrap(), 2);
+
+    let err = reader.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+}
@@ -0,0 +1,18 @@
+use aegis_proxy::engine::sockopt::{set_tcp_user_timeout, tcp_user_timeout};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+#[tokio::test]
+async fn tcp_user_timeout_is_applied_where_supported() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+
+    set_tcp_user_timeout(&stream, Duration::from_millis(1500)).unwrap();
+    if cfg!(target_os = "linux") {
+        assert_eq!(tcp_user_timeout(&stream), Some(Duration::from_millis(1500)));
+    } else {
+        assert_eq!(tcp_user_timeout(&stream), None);
+    }
+}
@@ -0,0 +1,37 @@
+use aegis_proxy::engine::splice::splice_copy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Returns both ends of a connected loopback TCP pair.
+async fn tcp_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    (client.unwrap(), server.unwrap().0)
+}
+
+#[tokio::test]
+async fn splice_copy_forwards_all_bytes_until_eof() {
+    let (mut sender, upstream) = tcp_pair().await;
+    let (downstream, mut receiver) = tcp_pair().await;
+
+    let payload: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let expected = payload.clone();
+
+    let writer = tokio::spawn(async move {
+        sender.write_all(&payload).await.unwrap();
+        sender.shutdown().await.unwrap();
+    });
+
+    let (mut up_read, _up_write) = upstream.into_split();
+    let (_down_read, mut down_write) = downstream.into_split();
+    let forwarded = splice_copy(&mut up_read, &mut down_write).await.unwrap();
+    drop(down_write);
+    writer.await.unwrap();
+
+    let mut received = Vec::new();
+    receiver.read_to_end(&mut received).await.unwrap();
+
+    assert_eq!(forwarded, expected.len() as u64);
+    assert_eq!(received, expected);
+}
@@ -0,0 +1,69 @@
+use aegis_common::MetricTagsConfig;
+use aegis_proxy::engine::tags::{ConnectionTags, TagSet, ANY, OTHER};
+use std::sync::Arc;
+
+fn tags(yaml: &str) -> MetricTagsConfig {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+const PROFILES: &[&str] = &["default", "iot_fleet", "partners"];
+
+#[test]
+fn unlisted_values_fold_into_other_and_unlisted_dimensions_into_any() {
+    let set = Arc::new(TagSet::from_config(&tags("profile: [iot_fleet]"), PROFILES).unwrap());
+    assert_eq!(set.cardinality(), 2);
+
+    let fleet = ConnectionTags::new(Arc::clone(&set), "iot_fleet");
+    assert_eq!(fleet.labels("mqtt"), ["iot_fleet", ANY, ANY]);
+    let partner = ConnectionTags::new(Arc::clone(&set), "partners");
+    assert_eq!(partner.labels("http"), [OTHER, ANY, ANY]);
+
+    let set =
+        Arc::new(TagSet::from_config(&tags("protocol: [mqtt, websocket]"), PROFILES).unwrap());
+    let tags = ConnectionTags::new(set, "default");
+    assert_eq!(tags.labels("websocket"), [ANY, "websocket", ANY]);
+    assert_eq!(tags.labels("http"), [ANY, OTHER, ANY]);
+}
+
+#[test]
+fn endpoints_come_from_the_configured_proxy_tlv() {
+    let config = tags("proxy_tlv_type: 0xEA\nendpoint: [vpce-0a1b]");
+    let set = Arc::new(TagSet::from_config(&config, PROFILES).unwrap());
+    assert_eq!(set.proxy_tlv_type(), Some(0xEA));
+    assert_eq!(set.cardinality(), 2);
+
+    let mut tags = ConnectionTags::new(set, "default");
+    // No TLV seen (yet): folded into `other`.
+    assert_eq!(tags.labels("mqtt"), [ANY, ANY, OTHER]);
+    tags.set_endpoint("vpce-0a1b");
+    assert_eq!(tags.labels("mqtt"), [ANY, ANY, "vpce-0a1b"]);
+    tags.set_endpoint("vpce-ffff");
+    assert_eq!(tags.labels("mqtt"), [ANY, ANY, OTHER]);
+}
+
+#[test]
+fn config_that_could_grow_label_sets_is_rejected() {
+    // Values must be ones the dimension can actually take.
+    assert!(TagSet::from_config(&tags("profile: [unknown]"), PROFILES).is_err());
+    assert!(TagSet::from_config(&tags("protocol: [ftp]"), PROFILES).is_err());
+    assert!(TagSet::from_config(&tags("protocol: [mqtt, mqtt]"), PROFILES).is_err());
+
+    // No more than 16 values per dimension.
+    let many: Vec<String> = (0..17).map(|i| format!("p{}", i)).collect();
+    let names: Vec<&str> = many.iter().map(String::as_str).collect();
+    let config = MetricTagsConfig {
+        profile: many.clone(),
+        protocol: Vec::new(),
+        ..MetricTagsConfig::default()
+    };
+    assert!(TagSet::from_config(&config, &names).is_err());
+
+    // Endpoints are only known from a PROXY protocol TLV.
+    assert!(TagSet::from_config(&tags("endpoint: [vpce-0a1b]"), PROFILES).is_err());
+    assert!(
+        TagSet::from_config(&tags("proxy_tlv_type: 0xEA\nendpoint: [a, a]"), PROFILES).is_err()
+    );
+
+    // Only known dimensions exist.
+    assert!(serde_yaml::from_str::<MetricTagsConfig>("client_id: [a]").is_err());
+}
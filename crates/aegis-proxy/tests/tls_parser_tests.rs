@@ -0,0 +1,99 @@
+use aegis_proxy::parser::tls::{handshake_record_len, parse_tls_client_hello};
+
+/// ClientHello record with the given server_name entries (type, name) and,
+/// ahead of them, an unrelated extension.
+fn client_hello(names: &[(u8, &str)]) -> Vec<u8> {
+    let mut extensions = vec![0x00, 0x0b, 0x00, 0x02, 0x01, 0x00]; // ec_point_formats
+    if !names.is_empty() {
+        let mut list = Vec::new();
+        for (kind, name) in names {
+            list.push(*kind);
+            list.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            list.extend_from_slice(name.as_bytes());
+        }
+        extensions.extend_from_slice(&[0x00, 0x00]);
+        extensions.extend_from_slice(&(list.len() as u16 + 2).to_be_bytes());
+        extensions.extend_from_slice(&(list.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&list);
+    }
+
+    let mut hello = vec![0x03, 0x03];
+    hello.extend_from_slice(&[0x2a; 32]); // random
+    hello.extend_from_slice(&[0x00]); // session id
+    hello.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // cipher suites
+    hello.extend_from_slice(&[0x01, 0x00]); // compression methods
+    hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    hello.extend_from_slice(&extensions);
+
+    let mut handshake = vec![0x01];
+    handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(&hello);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+    record
+}
+
+#[test]
+fn server_name_is_read_from_a_client_hello() {
+    let record = client_hello(&[(0, "broker.example.com")]);
+    assert_eq!(
+        parse_tls_client_hello(&record).as_deref(),
+        Some("broker.example.com")
+    );
+}
+
+#[test]
+fn server_name_is_lowercased_without_trailing_dot() {
+    let record = client_hello(&[(0, "Broker.Example.COM.")]);
+    assert_eq!(
+        parse_tls_client_hello(&record).as_deref(),
+        Some("broker.example.com")
+    );
+}
+
+#[test]
+fn first_host_name_entry_is_used() {
+    let record = client_hello(&[(7, "ignored"), (0, "a.example"), (0, "b.example")]);
+    assert_eq!(
+        parse_tls_client_hello(&record).as_deref(),
+        Some("a.example")
+    );
+}
+
+#[test]
+fn client_hello_without_server_name_yields_none() {
+    assert_eq!(parse_tls_client_hello(&client_hello(&[])), None);
+}
+
+#[test]
+fn truncated_client_hello_yields_none() {
+    let record = client_hello(&[(0, "broker.example.com")]);
+    for len in [0, 4, 5, 40, record.len() - 1] {
+        assert_eq!(parse_tls_client_hello(&record[..len]), None, "len {}", len);
+    }
+}
+
+#[test]
+fn non_client_hello_records_yield_none() {
+    let mut record = client_hello(&[(0, "broker.example.com")]);
+    record[5] = 0x02; // ServerHello
+    assert_eq!(parse_tls_client_hello(&record), None);
+    record[5] = 0x01;
+    record[0] = 0x17; // application data
+    assert_eq!(parse_tls_client_hello(&record), None);
+}
+
+#[test]
+fn record_length_comes_from_handshake_headers_only() {
+    assert_eq!(
+        handshake_record_len(&[0x16, 0x03, 0x01, 0x00, 0xf8]),
+        Some(0xf8)
+    );
+    assert_eq!(handshake_record_len(&[0x16, 0x03, 0x01, 0x00]), None);
+    // MQTT CONNECT shares the first nibble but is not a handshake record.
+    assert_eq!(handshake_record_len(&[0x10, 0x0d, 0x00, 0x04, b'M']), None);
+    // Longer than any single record may be.
+    assert_eq!(handshake_record_len(&[0x16, 0x03, 0x03, 0x40, 0x01]), None);
+}
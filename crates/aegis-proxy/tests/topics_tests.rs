@@ -0,0 +1,50 @@
+use aegis_common::MqttPolicyConfig;
+use aegis_proxy::engine::topics::{topic_matches, TopicRules};
+
+fn rules(allow: Option<&[&str]>, deny: &[&str]) -> Result<TopicRules, String> {
+    TopicRules::from_config(&MqttPolicyConfig {
+        publish_allow_topics: allow.map(|topics| topics.iter().map(|t| t.to_string()).collect()),
+        publish_deny_topics: deny.iter().map(|t| t.to_string()).collect(),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn wildcards_match_levels() {
+    assert!(topic_matches("devices/+/telemetry", "devices/d1/telemetry"));
+    assert!(topic_matches("devices/+/telemetry", "devices//telemetry"));
+    assert!(!topic_matches(
+        "devices/+/telemetry",
+        "devices/d1/x/telemetry"
+    ));
+    assert!(!topic_matches("devices/+", "devices"));
+    // `#` also matches the parent level.
+    assert!(topic_matches("devices/#", "devices"));
+    assert!(topic_matches("devices/#", "devices/d1/events/boot"));
+    assert!(topic_matches("#", "anything/at/all"));
+    assert!(!topic_matches("devices/d1", "devices/d1/extra"));
+    // Leading wildcards do not reach `$` topics.
+    assert!(!topic_matches("#", "$SYS/broker"));
+    assert!(!topic_matches("+/broker", "$SYS/broker"));
+    assert!(topic_matches("$SYS/#", "$SYS/broker"));
+}
+
+#[test]
+fn deny_wins_over_allow() {
+    let allow_and_deny = rules(Some(&["devices/#"]), &["devices/+/admin"]).unwrap();
+    assert!(allow_and_deny.permits("devices/d1/telemetry"));
+    assert!(!allow_and_deny.permits("devices/d1/admin"));
+    assert!(!allow_and_deny.permits("other/topic"));
+
+    let deny_only = rules(None, &["$SYS/#"]).unwrap();
+    assert!(deny_only.permits("any/topic"));
+    assert!(!deny_only.permits("$SYS/broker"));
+}
+
+#[test]
+fn invalid_patterns_are_rejected() {
+    for pattern in ["", "a/#/b", "a/b#", "a+/b"] {
+        assert!(rules(Some(&[pattern]), &[]).is_err(), "{:?}", pattern);
+        assert!(rules(None, &[pattern]).is_err(), "{:?}", pattern);
+    }
+}
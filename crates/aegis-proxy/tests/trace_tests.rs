@@ -0,0 +1,63 @@
+use aegis_proxy::engine::trace::DecisionTrace;
+use std::time::Duration;
+
+#[test]
+fn next_check_marks_the_previous_one_passed() {
+    let trace = DecisionTrace::new(true, "192.0.2.1:5000");
+    trace.check("fd_pressure");
+    trace.skip("region_filter");
+    trace.pass("source_profile", "internal");
+    trace.check("rate_limit");
+    assert_eq!(
+        trace.render(),
+        "fd_pressure=pass region_filter=skip source_profile=pass(internal) rate_limit=pending"
+    );
+}
+
+#[test]
+fn admit_closes_the_last_check() {
+    let trace = DecisionTrace::new(true, "192.0.2.1:5000");
+    trace.check("mqtt_connect");
+    trace.check("backend_connect");
+    trace.admit();
+    assert_eq!(trace.render(), "mqtt_connect=pass backend_connect=pass");
+}
+
+#[test]
+fn disabled_trace_records_nothing() {
+    let trace = DecisionTrace::disabled();
+    trace.check("fd_pressure");
+    trace.pass("source_profile", "default");
+    assert!(!trace.is_enabled());
+    assert_eq!(trace.render(), "");
+}
+
+#[test]
+fn effective_config_is_only_described_when_enabled() {
+    let trace = DecisionTrace::new(true, "192.0.2.1:5000");
+    trace.record_config(|| "mqtt_inspect=true".to_string());
+    assert_eq!(
+        trace.effective_config().as_deref(),
+        Some("mqtt_inspect=true")
+    );
+
+    let disabled = DecisionTrace::disabled();
+    disabled.record_config(|| unreachable!("not described when disabled"));
+    assert_eq!(disabled.effective_config(), None);
+}
+
+#[test]
+fn phase_timings_are_rendered_in_order() {
+    let trace = DecisionTrace::new(true, "192.0.2.1:5000");
+    assert_eq!(trace.phase_timings(), None);
+    trace.record_phase("peek", Duration::from_micros(1500));
+    trace.record_phase("backend_connect", Duration::from_millis(12));
+    assert_eq!(
+        trace.phase_timings().as_deref(),
+        Some("peek=1.500ms backend_connect=12.000ms")
+    );
+
+    let disabled = DecisionTrace::disabled();
+    disabled.record_phase("peek", Duration::from_millis(1));
+    assert_eq!(disabled.phase_timings(), None);
+}
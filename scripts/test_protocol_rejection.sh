@@ -5,8 +5,8 @@
 # Purpose:
 # - Sends a deliberately malformed MQTT CONNECT frame to the AegisGate proxy
 #   to trigger protocol-level rejection logic.
-# - Verifies that the Prometheus metric `aegis_protocol_rejections_total`
-#   increments as a result.
+# - Verifies that the Prometheus metric `aegis_rejections_total`, summed over
+#   the protocol reasons, increments as a result.
 #
 # Notes:
 # - This is a quick integration-style test intended to run on a machine where
@@ -34,7 +34,9 @@ PROXY_PORT="${PROXY_PORT:-8080}"
 METRICS_HOST="${METRICS_HOST:-127.0.0.1}"
 METRICS_PORT="${METRICS_PORT:-9090}"
 
-METRIC_NAME="aegis_protocol_rejections_total"
+METRIC_NAME="aegis_rejections_total"
+# Reasons the malformed CONNECT below can be counted under.
+REASONS="fixed_header_read|not_http_or_mqtt|unexpected_packet_type|first_byte_timeout|remaining_length_too_large|malformed_remaining_length|remaining_length_read|connect_payload_read|connect_segment|malformed_connect|unsupported_protocol_level|invalid_connect_properties|client_id|malformed_tls_client_hello"
 POLL_INTERVAL=1            # seconds between metric polls
 POLL_TIMEOUT=10            # total seconds to wait for metric to increase
 
@@ -45,16 +47,16 @@ fetch_metric_value() {
         echo ""
         return
     fi
-    # Use grep anchored to exact metric name; allow optional help/type lines around it.
+    # Match only the series labelled with one of the protocol reasons.
     # Extract the numeric value from the metric line.
-    local line
-    line="$(echo "$out" | grep -E "^${METRIC_NAME}[[:space:]]+" || true)"
-    if [ -z "$line" ]; then
+    local lines
+    lines="$(echo "$out" | grep -E "^${METRIC_NAME}\{([^}]*,)?reason=\"(${REASONS})\"[^}]*\}[[:space:]]+" || true)"
+    if [ -z "$lines" ]; then
         echo ""
         return
     fi
-    # Get last token which should be the value (handles integers and floats)
-    echo "$line" | awk '{print $NF}'
+    # Sum the last token of each series (handles integers and floats)
+    echo "$lines" | awk '{s += $NF} END {print s}'
 }
 
 # Helper: send malformed MQTT CONNECT bytes to the proxy
@@ -91,6 +91,18 @@ get_metric() {
     curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "^$metric_name " | awk '{print $2}' || echo "0"
 }
 
+# aegis_rejections_total is labelled by reason; these group the reasons the
+# way the old per-kind counters did.
+HTTP_REASONS="http_detected"
+SLOWLORIS_REASONS="closed_before_data|first_packet_error|first_packet_timeout|http_request_timeout|http_header_count_exceeded|http_header_size_exceeded|http_incomplete_headers|http_malformed_header|http_read_error|fixed_header_timeout|connect_throughput_too_low|tls_client_hello_timeout"
+PROTOCOL_REASONS="fixed_header_read|not_http_or_mqtt|unexpected_packet_type|first_byte_timeout|remaining_length_too_large|malformed_remaining_length|remaining_length_read|connect_payload_read|connect_segment|malformed_connect|unsupported_protocol_level|invalid_connect_properties|client_id|malformed_tls_client_hello"
+
+# Function to sum aegis_rejections_total over the reasons matching a regex
+get_rejections() {
+    local reasons=$1
+    curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep -E "^aegis_rejections_total\{([^}]*,)?reason=\"($reasons)\"[^}]*\} " | awk '{s += $2} END {print s + 0}'
+}
+
 # Function to print metric delta
 print_metric_delta() {
     local name=$1
@@ -112,9 +124,9 @@ echo -e "${BLUE}═════════════════════
 echo ""
 
 # Get initial metrics
-INITIAL_HTTP=$(get_metric "aegis_http_rejections_total")
-INITIAL_SLOWLORIS=$(get_metric "aegis_slowloris_rejections_total")
-INITIAL_PROTOCOL=$(get_metric "aegis_protocol_rejections_total")
+INITIAL_HTTP=$(get_rejections "$HTTP_REASONS")
+INITIAL_SLOWLORIS=$(get_rejections "$SLOWLORIS_REASONS")
+INITIAL_PROTOCOL=$(get_rejections "$PROTOCOL_REASONS")
 INITIAL_REJECTED=$(get_metric "aegis_rejected_connections_total")
 
 echo -e "${YELLOW}📊 Initial Metrics:${NC}"
@@ -145,8 +157,8 @@ done
 wait
 
 sleep 2
-AFTER_SLOWLORIS=$(get_metric "aegis_slowloris_rejections_total")
-AFTER_HTTP=$(get_metric "aegis_http_rejections_total")
+AFTER_SLOWLORIS=$(get_rejections "$SLOWLORIS_REASONS")
+AFTER_HTTP=$(get_rejections "$HTTP_REASONS")
 print_metric_delta "Slowloris rejections" $INITIAL_SLOWLORIS $AFTER_SLOWLORIS
 print_metric_delta "HTTP rejections" $INITIAL_HTTP $AFTER_HTTP
 echo ""
@@ -176,8 +188,8 @@ if [ $SLOWLORIS_PY_AVAILABLE -eq 1 ]; then
         timeout 20 $SLOWLORIS_CMD $PROXY_HOST -p $PROXY_PORT -s $CONNECTIONS --sleeptime 2 > /dev/null 2>&1 || true
 
         sleep 2
-        AFTER_SLOWLORIS=$(get_metric "aegis_slowloris_rejections_total")
-        AFTER_HTTP=$(get_metric "aegis_http_rejections_total")
+        AFTER_SLOWLORIS=$(get_rejections "$SLOWLORIS_REASONS")
+        AFTER_HTTP=$(get_rejections "$HTTP_REASONS")
         print_metric_delta "Slowloris rejections" $INITIAL_SLOWLORIS $AFTER_SLOWLORIS
         print_metric_delta "HTTP rejections" $INITIAL_HTTP $AFTER_HTTP
 
@@ -207,8 +219,8 @@ if [ $SLOWHTTPTEST_AVAILABLE -eq 1 ]; then
     timeout 20 slowhttptest -c 100 -H -i 15 -r 50 -t GET -u http://$PROXY_HOST:$PROXY_PORT -x 200 -p 3 > /tmp/slowhttp_test.log 2>&1 || true
 
     sleep 2
-    AFTER_SLOWLORIS=$(get_metric "aegis_slowloris_rejections_total")
-    AFTER_HTTP=$(get_metric "aegis_http_rejections_total")
+    AFTER_SLOWLORIS=$(get_rejections "$SLOWLORIS_REASONS")
+    AFTER_HTTP=$(get_rejections "$HTTP_REASONS")
     print_metric_delta "Slowloris rejections" $INITIAL_SLOWLORIS $AFTER_SLOWLORIS
     print_metric_delta "HTTP rejections" $INITIAL_HTTP $AFTER_HTTP
 
@@ -234,8 +246,8 @@ if [ $SLOWHTTPTEST_AVAILABLE -eq 1 ]; then
     timeout 20 slowhttptest -c 100 -B -i 15 -r 50 -t POST -u http://$PROXY_HOST:$PROXY_PORT -x 200 -p 3 > /tmp/slowpost_test.log 2>&1 || true
 
     sleep 2
-    AFTER_SLOWLORIS=$(get_metric "aegis_slowloris_rejections_total")
-    AFTER_HTTP=$(get_metric "aegis_http_rejections_total")
+    AFTER_SLOWLORIS=$(get_rejections "$SLOWLORIS_REASONS")
+    AFTER_HTTP=$(get_rejections "$HTTP_REASONS")
     print_metric_delta "Slowloris rejections" $INITIAL_SLOWLORIS $AFTER_SLOWLORIS
     print_metric_delta "HTTP rejections" $INITIAL_HTTP $AFTER_HTTP
     echo ""
@@ -257,7 +269,7 @@ done
 echo "Waiting for first_packet_timeout (30s)..."
 sleep 32
 
-AFTER_SLOWLORIS=$(get_metric "aegis_slowloris_rejections_total")
+AFTER_SLOWLORIS=$(get_rejections "$SLOWLORIS_REASONS")
 print_metric_delta "Slowloris rejections" $INITIAL_SLOWLORIS $AFTER_SLOWLORIS
 
 # Clean up background processes
@@ -284,8 +296,8 @@ done
 wait
 
 sleep 2
-AFTER_SLOWLORIS=$(get_metric "aegis_slowloris_rejections_total")
-AFTER_HTTP=$(get_metric "aegis_http_rejections_total")
+AFTER_SLOWLORIS=$(get_rejections "$SLOWLORIS_REASONS")
+AFTER_HTTP=$(get_rejections "$HTTP_REASONS")
 print_metric_delta "Slowloris rejections" $INITIAL_SLOWLORIS $AFTER_SLOWLORIS
 print_metric_delta "HTTP rejections" $INITIAL_HTTP $AFTER_HTTP
 echo ""
@@ -328,9 +340,9 @@ echo -e "${BLUE}  Final Results${NC}"
 echo -e "${BLUE}════════════════════════════════════════════════════════════${NC}"
 echo ""
 
-FINAL_HTTP=$(get_metric "aegis_http_rejections_total")
-FINAL_SLOWLORIS=$(get_metric "aegis_slowloris_rejections_total")
-FINAL_PROTOCOL=$(get_metric "aegis_protocol_rejections_total")
+FINAL_HTTP=$(get_rejections "$HTTP_REASONS")
+FINAL_SLOWLORIS=$(get_rejections "$SLOWLORIS_REASONS")
+FINAL_PROTOCOL=$(get_rejections "$PROTOCOL_REASONS")
 FINAL_REJECTED=$(get_metric "aegis_rejected_connections_total")
 
 TOTAL_HTTP_BLOCKED=$((FINAL_HTTP - INITIAL_HTTP))
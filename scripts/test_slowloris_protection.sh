@@ -7,6 +7,16 @@ PROXY_HOST="localhost"
 PROXY_PORT="8080"
 METRICS_PORT="9090"
 
+# aegis_rejections_total is labelled by reason; these group the reasons the
+# way the old per-kind counters did.
+HTTP_REASONS="http_detected"
+SLOWLORIS_REASONS="closed_before_data|first_packet_error|first_packet_timeout|http_request_timeout|http_header_count_exceeded|http_header_size_exceeded|http_incomplete_headers|http_malformed_header|http_read_error|fixed_header_timeout|connect_throughput_too_low|tls_client_hello_timeout"
+PROTOCOL_REASONS="fixed_header_read|not_http_or_mqtt|unexpected_packet_type|first_byte_timeout|remaining_length_too_large|malformed_remaining_length|remaining_length_read|connect_payload_read|connect_segment|malformed_connect|unsupported_protocol_level|invalid_connect_properties|client_id|malformed_tls_client_hello"
+
+rejections() {
+    curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep -E "^aegis_rejections_total\{([^}]*,)?reason=\"($1)\"[^}]*\} " | awk '{s += $2} END {print s + 0}'
+}
+
 echo "🧪 Testing HTTP Inspection and Slowloris Protection"
 echo "=================================================="
 echo ""
@@ -23,8 +33,8 @@ echo ""
 
 # Get initial metrics
 echo "2️⃣  Getting initial metrics..."
-INITIAL_HTTP=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_http_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
-INITIAL_SLOWLORIS=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_slowloris_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
+INITIAL_HTTP=$(rejections "$HTTP_REASONS")
+INITIAL_SLOWLORIS=$(rejections "$SLOWLORIS_REASONS")
 echo "   Initial HTTP rejections: $INITIAL_HTTP"
 echo "   Initial Slowloris rejections: $INITIAL_SLOWLORIS"
 echo ""
@@ -34,7 +44,7 @@ echo "3️⃣  Test 1: Sending valid HTTP GET request..."
 echo "   (Should be rejected as wrong protocol for MQTT broker)"
 (echo -e "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n" | nc -w 2 $PROXY_HOST $PROXY_PORT > /dev/null 2>&1) || true
 sleep 1
-HTTP_COUNT=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_http_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
+HTTP_COUNT=$(rejections "$HTTP_REASONS")
 if [ "$HTTP_COUNT" -gt "$INITIAL_HTTP" ]; then
     echo "✅ HTTP request detected and rejected (count: $INITIAL_HTTP → $HTTP_COUNT)"
 else
@@ -46,7 +56,7 @@ echo ""
 echo "4️⃣  Test 2: Sending HTTP POST request..."
 (echo -e "POST /api HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n" | nc -w 2 $PROXY_HOST $PROXY_PORT > /dev/null 2>&1) || true
 sleep 1
-HTTP_COUNT2=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_http_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
+HTTP_COUNT2=$(rejections "$HTTP_REASONS")
 if [ "$HTTP_COUNT2" -gt "$HTTP_COUNT" ]; then
     echo "✅ HTTP POST detected and rejected (count: $HTTP_COUNT → $HTTP_COUNT2)"
 else
@@ -73,7 +83,7 @@ echo "   Sending headers very slowly (one byte every 2 seconds)..."
 ) | nc -w 1 $PROXY_HOST $PROXY_PORT > /dev/null 2>&1 || true
 
 sleep 1
-SLOWLORIS_COUNT=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_slowloris_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
+SLOWLORIS_COUNT=$(rejections "$SLOWLORIS_REASONS")
 if [ "$SLOWLORIS_COUNT" -gt "$INITIAL_SLOWLORIS" ]; then
     echo "✅ Slowloris attack detected and rejected (count: $INITIAL_SLOWLORIS → $SLOWLORIS_COUNT)"
 else
@@ -92,8 +102,8 @@ echo "6️⃣  Test 4: Sending request with excessive headers..."
 ) | nc -w 2 $PROXY_HOST $PROXY_PORT > /dev/null 2>&1 || true
 
 sleep 1
-SLOWLORIS_COUNT2=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_slowloris_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
-HTTP_COUNT3=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_http_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
+SLOWLORIS_COUNT2=$(rejections "$SLOWLORIS_REASONS")
+HTTP_COUNT3=$(rejections "$HTTP_REASONS")
 if [ "$SLOWLORIS_COUNT2" -gt "$SLOWLORIS_COUNT" ]; then
     echo "✅ Excessive headers detected as Slowloris (count: $SLOWLORIS_COUNT → $SLOWLORIS_COUNT2)"
 elif [ "$HTTP_COUNT3" -gt "$HTTP_COUNT2" ]; then
@@ -116,11 +126,11 @@ echo ""
 
 # Test 6: First packet timeout
 echo "8️⃣  Test 6: Testing first packet timeout (connection without sending data)..."
-BEFORE_SLOWLORIS=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_slowloris_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
+BEFORE_SLOWLORIS=$(rejections "$SLOWLORIS_REASONS")
 # Connect but don't send anything for 35 seconds (exceeds first_packet_timeout of 30s)
 timeout 35 nc $PROXY_HOST $PROXY_PORT > /dev/null 2>&1 || true
 sleep 1
-AFTER_SLOWLORIS=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_slowloris_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
+AFTER_SLOWLORIS=$(rejections "$SLOWLORIS_REASONS")
 if [ "$AFTER_SLOWLORIS" -gt "$BEFORE_SLOWLORIS" ]; then
     echo "✅ First packet timeout enforced (count: $BEFORE_SLOWLORIS → $AFTER_SLOWLORIS)"
 else
@@ -132,9 +142,9 @@ echo ""
 echo "=================================================="
 echo "📊 Test Summary"
 echo "=================================================="
-FINAL_HTTP=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_http_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
-FINAL_SLOWLORIS=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_slowloris_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
-FINAL_PROTOCOL=$(curl -s http://$PROXY_HOST:$METRICS_PORT/metrics | grep "aegis_protocol_rejections_total" | grep -v "#" | awk '{print $2}' || echo "0")
+FINAL_HTTP=$(rejections "$HTTP_REASONS")
+FINAL_SLOWLORIS=$(rejections "$SLOWLORIS_REASONS")
+FINAL_PROTOCOL=$(rejections "$PROTOCOL_REASONS")
 
 HTTP_DELTA=$((FINAL_HTTP - INITIAL_HTTP))
 SLOWLORIS_DELTA=$((FINAL_SLOWLORIS - INITIAL_SLOWLORIS))